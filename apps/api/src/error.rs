@@ -0,0 +1,267 @@
+use axum::{
+    Json,
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use types::{error::ChainError, validation::BlockRejection};
+
+/// SQLite's primary result code for a constraint violation, including a
+/// trigger aborting the statement like `limit_votes_per_station`
+const SQLITE_CONSTRAINT: i32 = 19;
+
+/// Why a request failed, answered with a status and an [`ErrorBody`]
+#[derive(Debug)]
+pub enum ApiError {
+    /// The request itself is malformed
+    BadRequest(String),
+    /// The requested record does not exist
+    NotFound(String),
+    /// An extractor rejected the request, with the status it chose, e.g.
+    /// `415` for a body that is not JSON
+    Rejected(StatusCode, String),
+    Chain(ChainError),
+}
+
+/// JSON body of every failed request
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    /// Stable, machine readable kind of the error
+    pub code: &'static str,
+    pub message: String,
+    /// The rejection or database error behind it, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl From<ChainError> for ApiError {
+    fn from(error: ChainError) -> Self {
+        ApiError::Chain(error)
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(error: sqlx::Error) -> Self {
+        ApiError::Chain(ChainError::DatabaseError(error))
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(error: std::io::Error) -> Self {
+        ApiError::Chain(ChainError::IoError(error))
+    }
+}
+
+impl From<BlockRejection> for ApiError {
+    fn from(rejection: BlockRejection) -> Self {
+        ApiError::Chain(ChainError::BlockRejected(rejection))
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::Rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        ApiError::Rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::Rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl ApiError {
+    fn status_and_code(&self) -> (StatusCode, &'static str) {
+        match self {
+            ApiError::BadRequest(_) => (StatusCode::BAD_REQUEST, "bad_request"),
+            ApiError::NotFound(_) => (StatusCode::NOT_FOUND, "not_found"),
+            ApiError::Rejected(status, _) => (*status, "invalid_request"),
+            ApiError::Chain(ChainError::BlockRejected(
+                BlockRejection::UnauthorisedSubmission { .. }
+                | BlockRejection::InvalidSubmissionSignature(_)
+                | BlockRejection::UnsignedResults,
+            )) => (StatusCode::FORBIDDEN, "unauthorised_submission"),
+            ApiError::Chain(ChainError::BlockRejected(_)) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "block_rejected")
+            }
+            ApiError::Chain(ChainError::DatabaseError(sqlx::Error::RowNotFound)) => {
+                (StatusCode::NOT_FOUND, "not_found")
+            }
+            ApiError::Chain(ChainError::DatabaseError(sqlx::Error::Database(e)))
+                if e.code()
+                    .and_then(|code| code.parse::<i32>().ok())
+                    .is_some_and(|code| code & 0xff == SQLITE_CONSTRAINT) =>
+            {
+                (StatusCode::UNPROCESSABLE_ENTITY, "constraint_violation")
+            }
+            ApiError::Chain(ChainError::DatabaseError(_)) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "database_error")
+            }
+            ApiError::Chain(ChainError::ReadOnly) => (StatusCode::FORBIDDEN, "read_only"),
+            ApiError::Chain(ChainError::TimeoutError(_)) => {
+                (StatusCode::GATEWAY_TIMEOUT, "timeout")
+            }
+            ApiError::Chain(ChainError::ConsensusError(_)) => {
+                (StatusCode::SERVICE_UNAVAILABLE, "consensus_error")
+            }
+            ApiError::Chain(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error"),
+        }
+    }
+
+    fn body(&self) -> ErrorBody {
+        let (_, code) = self.status_and_code();
+        let (message, details) = match self {
+            ApiError::BadRequest(message)
+            | ApiError::NotFound(message)
+            | ApiError::Rejected(_, message) => (message.clone(), None),
+            ApiError::Chain(ChainError::BlockRejected(rejection)) => {
+                (rejection.to_string(), serde_json::to_value(rejection).ok())
+            }
+            // The message of a trigger abort is all the client needs
+            ApiError::Chain(ChainError::DatabaseError(sqlx::Error::Database(e))) => (
+                e.message().to_string(),
+                e.code()
+                    .map(|code| serde_json::json!({ "sqlite_code": code })),
+            ),
+            ApiError::Chain(e) => (e.to_string(), None),
+        };
+        ErrorBody {
+            code,
+            message,
+            details,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, _) = self.status_and_code();
+        if status.is_server_error() {
+            log::error!("Request failed: {self:?}");
+        }
+        (status, Json(self.body())).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_map_to_statuses() {
+        let rejection = BlockRejection::UnauthorisedSubmission {
+            agent: "f00d".into(),
+            station_id: 1,
+        };
+        let cases = [
+            (ApiError::from(rejection), StatusCode::FORBIDDEN),
+            (
+                ApiError::from(BlockRejection::InvalidElection(1)),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                ApiError::from(sqlx::Error::RowNotFound),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                ApiError::BadRequest("No empty results".into()),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                ApiError::from(ChainError::Other("boom".into())),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ];
+        for (error, status) in cases {
+            assert_eq!(error.status_and_code().0, status);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extractor_rejections_are_api_errors() {
+        use axum::{
+            body::Body,
+            extract::{FromRequest, FromRequestParts},
+            http::{Request, header},
+        };
+
+        use crate::extract::{Json, Query};
+
+        let request = |content_type, body| {
+            Request::post("/")
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let cases = [
+            (request("application/json", "[1,"), StatusCode::BAD_REQUEST),
+            (
+                request("application/json", "[\"one\"]"),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                request("text/plain", "[1]"),
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ),
+        ];
+        for (request, status) in cases {
+            let error = Json::<Vec<i64>>::from_request(request, &())
+                .await
+                .unwrap_err();
+            assert_eq!(error.status_and_code(), (status, "invalid_request"));
+            assert!(!error.body().message.is_empty());
+        }
+
+        #[derive(Debug, serde::Deserialize)]
+        struct Page {
+            limit: i64,
+        }
+        let parts = |uri| Request::get(uri).body(()).unwrap().into_parts().0;
+        let page = Query::<Page>::from_request_parts(&mut parts("/?limit=10"), &())
+            .await
+            .unwrap();
+        assert_eq!(page.limit, 10);
+        let error = Query::<Page>::from_request_parts(&mut parts("/?limit=ten"), &())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.status_and_code(),
+            (StatusCode::BAD_REQUEST, "invalid_request")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trigger_abort_is_unprocessable() {
+        let db = database::Database::new_in_memory();
+        sqlx::query(
+            "CREATE TABLE results (votes int);
+            CREATE TRIGGER limit_votes BEFORE INSERT ON results BEGIN
+                SELECT RAISE(ABORT, 'Vote count exceeds registered voters for this station');
+            END;",
+        )
+        .execute(&db.chain_db)
+        .await
+        .unwrap();
+        let error = sqlx::query("INSERT INTO results VALUES (600)")
+            .execute(&db.chain_db)
+            .await
+            .unwrap_err();
+
+        let error = ApiError::from(error);
+        assert_eq!(
+            error.status_and_code(),
+            (StatusCode::UNPROCESSABLE_ENTITY, "constraint_violation")
+        );
+        assert_eq!(
+            error.body().message,
+            "Vote count exceeds registered voters for this station"
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use axum::{Extension, response::IntoResponse};
+use blockchain::BlockChain;
+use database::Database;
+use types::Block;
+
+use crate::{
+    ApiError, ApiResult,
+    extract::{Json, Path, Query},
+};
+
+/// Blocks listed when a request does not ask for a number
+const DEFAULT_PAGE_SIZE: i64 = 20;
+
+/// Most blocks listed by a single request
+const MAX_PAGE_SIZE: i64 = 100;
+
+/// Which slice of a listing to return, e.g. `?offset=40&limit=20`
+#[derive(Debug, Default, serde::Deserialize)]
+pub(crate) struct PageQuery {
+    offset: Option<i64>,
+    limit: Option<i64>,
+}
+
+impl PageQuery {
+    fn bounds(&self) -> (i64, i64) {
+        (
+            self.offset.unwrap_or(0).max(0),
+            self.limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        )
+    }
+}
+
+/// Looks a block up by its hash, or by its height when `id` is a number
+async fn find_block(db: &Database, id: &str) -> ApiResult<Block> {
+    let block = match id.parse::<i64>() {
+        Ok(height) => db.get_block_by_height(height).await,
+        Err(_) => db.get_block_by_hash(id).await,
+    };
+    block.map_err(|e| match e {
+        sqlx::Error::RowNotFound => ApiError::NotFound(format!("No block {id}")),
+        e => e.into(),
+    })
+}
+
+pub(crate) async fn blocks(
+    blockchain: Extension<BlockChain>,
+    page: Query<PageQuery>,
+) -> ApiResult<impl IntoResponse> {
+    let (offset, limit) = page.bounds();
+
+    let blocks = blockchain.db.block_summaries(offset, limit).await?;
+
+    Ok(Json(blocks))
+}
+
+pub(crate) async fn block(
+    blockchain: Extension<BlockChain>,
+    id: Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let block = find_block(&blockchain.db, &id).await?;
+
+    Ok(Json(block))
+}
+
+/// The results a block recorded, with the height of any correction that
+/// replaced them since
+pub(crate) async fn block_results(
+    blockchain: Extension<BlockChain>,
+    id: Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let db = &blockchain.db;
+    let block = find_block(db, &id).await?;
+
+    let results = db.results_at_height(block.height as i64).await?;
+
+    Ok(Json(results))
+}
+
+pub(crate) async fn station_history(
+    blockchain: Extension<BlockChain>,
+    station: Path<i64>,
+    page: Query<PageQuery>,
+) -> ApiResult<impl IntoResponse> {
+    let db = &blockchain.db;
+    let (offset, limit) = page.bounds();
+
+    if db.registered_voters(*station).await?.is_none() {
+        return Err(ApiError::NotFound(format!("No station {}", *station)));
+    }
+    let blocks = db.station_history(*station, offset, limit).await?;
+
+    Ok(Json(blocks))
+}
+
+pub(crate) async fn signer_blocks(
+    blockchain: Extension<BlockChain>,
+    pubkey_hash: Path<String>,
+    page: Query<PageQuery>,
+) -> ApiResult<impl IntoResponse> {
+    let (offset, limit) = page.bounds();
+
+    let blocks = blockchain
+        .db
+        .blocks_by_signer(&pubkey_hash, offset, limit)
+        .await?;
+
+    Ok(Json(blocks))
+}
+
+pub(crate) async fn chain_summary(
+    blockchain: Extension<BlockChain>,
+) -> ApiResult<impl IntoResponse> {
+    let summary = blockchain.db.chain_summary().await?;
+
+    Ok(Json(summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_query_is_bounded() {
+        let page = |offset, limit| PageQuery { offset, limit }.bounds();
+
+        assert_eq!(page(None, None), (0, DEFAULT_PAGE_SIZE));
+        assert_eq!(page(Some(-5), Some(0)), (0, 1));
+        assert_eq!(page(Some(40), Some(10_000)), (40, MAX_PAGE_SIZE));
+    }
+}
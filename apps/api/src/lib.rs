@@ -1,175 +1,515 @@
+mod error;
+mod explorer;
+mod extract;
+mod stream;
+
+pub use error::{ApiError, ErrorBody};
+
 use std::path::PathBuf;
 
 use axum::{
-    Extension, Json, Router,
-    extract::Path,
+    Extension, Router,
+    body::Bytes,
+    extract::DefaultBodyLimit,
+    http::{HeaderMap, StatusCode, header},
     response::IntoResponse,
     routing::{get, post},
 };
 use blockchain::BlockChain;
+use serde::Serialize;
 use tower_http::services::{ServeDir, ServeFile};
-use types::{Block, CandidateResult, merkle::MerkleTree};
+use types::{
+    Block, BlockType, Correction,
+    agents::{AgentSignature, Submission},
+    crypto::attachment_digest,
+    election::Election,
+    reference::ReferenceData,
+};
+
+use crate::extract::{Json, Path, Query};
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// Largest attachment accepted for upload, a scanned form or a photo of one
+const MAX_ATTACHMENT_SIZE: usize = 8 * 1024 * 1024;
+
+/// Hash of the agent uploading an attachment
+pub const AGENT_HEADER: &str = "x-agent";
+/// The agent's signature over the attachment's digest, see [`AgentSignature`]
+pub const SIGNATURE_HEADER: &str = "x-agent-signature";
+
+/// Most headers returned by a single `/headers` request
+const MAX_HEADERS: i64 = 500;
 
-async fn submit_result(mut blockchain: Extension<BlockChain>, result: Json<Block>) -> String {
-    let block = blockchain.add_block(&result.0).await.unwrap();
-    blockchain.announce_block(result.0).await.unwrap();
-    format!("Block with index {} submitted successfully!", block)
+/// Whether a submitted block made it onto the chain yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubmissionStatus {
+    /// A quorum of validators committed the block
+    Committed,
+    /// Proposed to the validators, which have not committed it yet
+    Pending,
 }
 
-async fn submit_raw_result(
+/// Where a submitted block was placed, answered with `202 Accepted` until
+/// the block is committed
+#[derive(Debug, Serialize)]
+pub struct Submitted {
+    pub height: usize,
+    pub hash: String,
+    pub status: SubmissionStatus,
+}
+
+impl Submitted {
+    /// Proposes `block`, answering as soon as it is committed or waits for
+    /// other validators
+    async fn propose(blockchain: &mut BlockChain, block: Block) -> ApiResult<Self> {
+        let (height, hash) = (block.height, block.hash.clone());
+        blockchain.propose_block(block).await?;
+        let committed = match blockchain.db.get_block_by_height(height as i64).await {
+            Ok(block) => block.hash == hash,
+            Err(sqlx::Error::RowNotFound) => false,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Submitted {
+            height,
+            hash,
+            status: if committed {
+                SubmissionStatus::Committed
+            } else {
+                SubmissionStatus::Pending
+            },
+        })
+    }
+}
+
+impl IntoResponse for Submitted {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self.status {
+            SubmissionStatus::Committed => StatusCode::OK,
+            SubmissionStatus::Pending => StatusCode::ACCEPTED,
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+/// A block the caller signed with a validator key, such as the key and agent
+/// registrations of the CLI
+async fn submit_result(
     mut blockchain: Extension<BlockChain>,
-    results: Json<Vec<CandidateResult>>,
-) -> String {
+    result: Json<Block>,
+) -> ApiResult<Submitted> {
+    Submitted::propose(&mut blockchain, result.0).await
+}
+
+/// Results signed by a registered agent, forbidden unless the agent may
+/// submit for every station in them. Tally form summaries and attachments
+/// are optional, the attachments have to be uploaded beforehand.
+async fn submit_agent_results(
+    mut blockchain: Extension<BlockChain>,
+    submission: Json<Submission>,
+) -> ApiResult<Submitted> {
+    if submission.results.is_empty() {
+        return Err(ApiError::BadRequest("No empty results".into()));
+    }
+    blockchain
+        .validator()
+        .authorise_submission(&submission)
+        .await?;
+    for hash in &submission.attachments {
+        // Peers can only replicate what we hold ourselves
+        if !blockchain.db.attachments.contains(hash).await? {
+            return Err(ApiError::BadRequest(format!(
+                "Attachment {hash} was not uploaded"
+            )));
+        }
+    }
+    submit_inner(&mut blockchain, BlockType::Submission(submission.0)).await
+}
+
+/// Signs `inner` with the node key and proposes it on top of the tip. Only
+/// for what agents signed, or behind [`run_admin_api_server`].
+async fn submit_inner(blockchain: &mut BlockChain, inner: BlockType) -> ApiResult<Submitted> {
     let db = &blockchain.db;
-    let height = db.get_height().await.unwrap();
+    let height = db.get_height().await?;
+
+    let signer = db.get_private_key().await?;
+    let prev_hash = db.get_block_by_height(height).await?.hash;
+
+    let block = Block::with_inner(&signer, &prev_hash, inner, (height + 1) as usize);
+    Submitted::propose(blockchain, block).await
+}
 
-    assert!(results.len() > 0, "No empty results");
-    let signer = db.get_private_key().await.unwrap();
-    let prev_hash = db.get_block_by_height(height).await.unwrap().hash;
+/// Stores an attachment to reference in a submission, returning its digest.
+/// The uploading agent signs the digest, sent in the [`AGENT_HEADER`] and
+/// [`SIGNATURE_HEADER`] headers. Attachments no block references in time are
+/// deleted again.
+async fn upload_attachment(
+    blockchain: Extension<BlockChain>,
+    headers: HeaderMap,
+    data: Bytes,
+) -> ApiResult<String> {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+            .ok_or_else(|| ApiError::BadRequest(format!("Missing {name} header")))
+    };
+    let signed_by = AgentSignature {
+        agent: header(AGENT_HEADER)?,
+        signature: header(SIGNATURE_HEADER)?,
+    };
+    let digest = attachment_digest(&data);
+    blockchain
+        .validator()
+        .authorise_upload(&signed_by, &digest)
+        .await?;
+    Ok(blockchain.db.attachments.put(&data).await?)
+}
 
-    let tree = MerkleTree::from_election_results_proper(&results);
-    let root = tree.get_root_hash();
+async fn attachment(
+    blockchain: Extension<BlockChain>,
+    hash: Path<String>,
+) -> ApiResult<impl IntoResponse> {
+    let db = &blockchain.db;
 
-    let block = Block::new(
-        &signer,
-        &prev_hash,
-        results.0,
-        (height + 1) as usize,
-        root.unwrap(),
-    );
-    let height = blockchain.add_block(&block).await.unwrap();
-    blockchain.announce_block(block).await.unwrap();
-    format!("Block with index {} submitted successfully!", height)
+    let data = db
+        .attachments
+        .get(&hash)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No attachment {}", *hash)))?;
+    let content_type = match data.as_slice() {
+        [b'%', b'P', b'D', b'F', ..] => "application/pdf",
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [0xff, 0xd8, 0xff, ..] => "image/jpeg",
+        _ => "application/octet-stream",
+    };
+    Ok(([(header::CONTENT_TYPE, content_type)], data))
+}
+
+async fn submit_corrections(
+    mut blockchain: Extension<BlockChain>,
+    corrections: Json<Vec<Correction>>,
+) -> ApiResult<Submitted> {
+    if corrections.is_empty() {
+        return Err(ApiError::BadRequest("No empty corrections".into()));
+    }
+    submit_inner(&mut blockchain, BlockType::Correction(corrections.0)).await
+}
+
+async fn submit_election(
+    mut blockchain: Extension<BlockChain>,
+    election: Json<Election>,
+) -> ApiResult<Submitted> {
+    submit_inner(&mut blockchain, BlockType::Election(election.0)).await
+}
+
+async fn submit_reference_data(
+    mut blockchain: Extension<BlockChain>,
+    records: Json<Vec<ReferenceData>>,
+) -> ApiResult<Submitted> {
+    if records.is_empty() {
+        return Err(ApiError::BadRequest("No empty reference data".into()));
+    }
+    submit_inner(&mut blockchain, BlockType::ReferenceData(records.0)).await
 }
 
 async fn block_by_height(
     blockchain: Extension<BlockChain>,
     height: Path<i64>,
-) -> impl IntoResponse {
+) -> ApiResult<impl IntoResponse> {
+    let db = &blockchain.db;
+
+    let block = db.get_block_by_height(*height).await?;
+
+    Ok(Json(block))
+}
+
+/// Signed headers from `start`, enough for a client to check the chain
+/// links up without downloading the blocks
+async fn headers(
+    blockchain: Extension<BlockChain>,
+    Path((start, count)): Path<(i64, i64)>,
+) -> ApiResult<impl IntoResponse> {
+    let db = &blockchain.db;
+    let end = start + count.clamp(1, MAX_HEADERS) - 1;
+
+    let blocks = db.get_blocks_in_range(start, end).await?;
+
+    Ok(Json(
+        blocks
+            .iter()
+            .map(|block| block.signed_header())
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[derive(serde::Deserialize)]
+struct ProofQuery {
+    election_id: Option<i64>,
+}
+
+async fn inclusion_proof(
+    blockchain: Extension<BlockChain>,
+    Path((station, candidate)): Path<(i64, i64)>,
+    query: Query<ProofQuery>,
+) -> ApiResult<impl IntoResponse> {
     let db = &blockchain.db;
 
-    let block = db.get_block_by_height(*height).await.unwrap();
+    let proof = db
+        .inclusion_proof(query.election_id, station, candidate)
+        .await?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "No result for candidate {candidate} at station {station}"
+            ))
+        })?;
 
-    Json(block)
+    Ok(Json(proof))
 }
 
-async fn positions(blockchain: Extension<BlockChain>) -> impl IntoResponse {
+async fn agents(blockchain: Extension<BlockChain>) -> ApiResult<impl IntoResponse> {
     let db = &blockchain.db;
 
-    let positions = db.positions().await.unwrap();
+    let agents = db.agents().await?;
 
-    Json(positions)
+    Ok(Json(agents))
 }
 
-async fn parties(blockchain: Extension<BlockChain>) -> impl IntoResponse {
+async fn reference_data(blockchain: Extension<BlockChain>) -> ApiResult<impl IntoResponse> {
     let db = &blockchain.db;
 
-    let parties = db.parties().await.unwrap();
+    let records = db.export_reference_data().await?;
 
-    Json(parties)
+    Ok(Json(records))
 }
 
-async fn counties(blockchain: Extension<BlockChain>) -> impl IntoResponse {
+async fn positions(blockchain: Extension<BlockChain>) -> ApiResult<impl IntoResponse> {
     let db = &blockchain.db;
 
-    let counties = db.counties().await.unwrap();
+    let positions = db.positions().await?;
 
-    Json(counties)
+    Ok(Json(positions))
+}
+
+async fn parties(blockchain: Extension<BlockChain>) -> ApiResult<impl IntoResponse> {
+    let db = &blockchain.db;
+
+    let parties = db.parties().await?;
+
+    Ok(Json(parties))
+}
+
+async fn counties(blockchain: Extension<BlockChain>) -> ApiResult<impl IntoResponse> {
+    let db = &blockchain.db;
+
+    let counties = db.counties().await?;
+
+    Ok(Json(counties))
 }
 
 async fn constituencies_by_county(
     blockchain: Extension<BlockChain>,
     county: Path<u32>,
-) -> impl IntoResponse {
+) -> ApiResult<impl IntoResponse> {
     let db = &blockchain.db;
 
-    let constituencies = db.constituencies_by_county(&county).await.unwrap();
+    let constituencies = db.constituencies_by_county(&county).await?;
 
-    Json(constituencies)
+    Ok(Json(constituencies))
 }
 
-pub async fn constituencies(blockchain: Extension<BlockChain>) -> impl IntoResponse {
+pub async fn constituencies(blockchain: Extension<BlockChain>) -> ApiResult<impl IntoResponse> {
     let db = &blockchain.db;
 
-    let constituencies = db.constituencies().await.unwrap();
+    let constituencies = db.constituencies().await?;
 
-    Json(constituencies)
+    Ok(Json(constituencies))
 }
 
 async fn wards_by_constituency(
     blockchain: Extension<BlockChain>,
     constituency: Path<u32>,
-) -> impl IntoResponse {
+) -> ApiResult<impl IntoResponse> {
+    let db = &blockchain.db;
+
+    let wards = db.wards_by_constituency(&constituency).await?;
+
+    Ok(Json(wards))
+}
+
+async fn stations_by_ward(
+    blockchain: Extension<BlockChain>,
+    ward: Path<u32>,
+) -> ApiResult<impl IntoResponse> {
+    let db = &blockchain.db;
+
+    let stations = db.stations_by_ward(&ward).await?;
+
+    Ok(Json(stations))
+}
+
+async fn attestations_by_station(
+    blockchain: Extension<BlockChain>,
+    station: Path<i64>,
+) -> ApiResult<impl IntoResponse> {
+    let db = &blockchain.db;
+
+    let attestations = db.attestations_by_station(*station).await?;
+
+    Ok(Json(attestations))
+}
+
+async fn corrections_by_station(
+    blockchain: Extension<BlockChain>,
+    station: Path<i64>,
+) -> ApiResult<impl IntoResponse> {
+    let db = &blockchain.db;
+
+    let corrections = db.corrections_by_station(*station).await?;
+    let history = db.result_history(*station).await?;
+
+    Ok(Json(
+        serde_json::json!({ "corrections": corrections, "history": history }),
+    ))
+}
+
+async fn elections(blockchain: Extension<BlockChain>) -> ApiResult<impl IntoResponse> {
     let db = &blockchain.db;
 
-    let wards = db.wards_by_constituency(&constituency).await.unwrap();
+    let elections = db.elections().await?;
 
-    Json(wards)
+    Ok(Json(elections))
 }
 
-async fn stations_by_ward(blockchain: Extension<BlockChain>, ward: Path<u32>) -> impl IntoResponse {
+async fn election(
+    blockchain: Extension<BlockChain>,
+    election: Path<i64>,
+) -> ApiResult<impl IntoResponse> {
     let db = &blockchain.db;
 
-    let stations = db.stations_by_ward(&ward).await.unwrap();
+    let election = db
+        .election(*election)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No election {}", *election)))?;
 
-    Json(stations)
+    Ok(Json(election))
 }
 
 async fn candidates_by_position_type(
     blockchain: Extension<BlockChain>,
-    Path((position_type, area_id)): Path<(String, i32)>,
-) -> impl IntoResponse {
+    Path((election, position_type, area_id)): Path<(i64, String, i32)>,
+) -> ApiResult<impl IntoResponse> {
     let db = &blockchain.db;
 
     let res = match position_type.as_str() {
-        "Mca" => db.candidates_by_ward(&area_id).await.unwrap(),
-        "Governor" => db.candidates_by_county(&area_id, "Governor").await.unwrap(),
-        "Senator" => db.candidates_by_county(&area_id, "Senator").await.unwrap(),
-        "Mp" => db.candidates_by_constituency(&area_id, "Mp").await.unwrap(),
-        "WomenRep" => db
-            .candidates_by_constituency(&area_id, "WomenRep")
-            .await
-            .unwrap(),
-        _ => db.candidates_national().await.unwrap(),
+        "Mca" => db.candidates_by_ward(election, &area_id).await?,
+        "Governor" => {
+            db.candidates_by_county(election, &area_id, "Governor")
+                .await?
+        }
+        "Senator" => {
+            db.candidates_by_county(election, &area_id, "Senator")
+                .await?
+        }
+        "Mp" => {
+            db.candidates_by_constituency(election, &area_id, "Mp")
+                .await?
+        }
+        "WomenRep" => {
+            db.candidates_by_constituency(election, &area_id, "WomenRep")
+                .await?
+        }
+        _ => db.candidates_national(election).await?,
     };
 
-    Json(res)
+    Ok(Json(res))
 }
 
 async fn candidates_by_result(
     blockchain: Extension<BlockChain>,
-    Path((position_type, area_id)): Path<(String, i32)>,
-) -> impl IntoResponse {
+    Path((election, position_type, area_id)): Path<(i64, String, i32)>,
+) -> ApiResult<impl IntoResponse> {
     let db = &blockchain.db;
 
     let res = match position_type.as_str() {
-        "Mca" => db.results_by_ward(&area_id).await.unwrap(),
-        "Governor" => db.results_by_county(&area_id, "Governor").await.unwrap(),
-        "Senator" => db.results_by_county(&area_id, "Senator").await.unwrap(),
-        "Mp" => db.results_by_constituency(&area_id, "Mp").await.unwrap(),
-        "WomenRep" => db
-            .results_by_constituency(&area_id, "WomenRep")
-            .await
-            .unwrap(),
+        "Mca" => db.results_by_ward(election, &area_id).await?,
+        "Governor" => db.results_by_county(election, &area_id, "Governor").await?,
+        "Senator" => db.results_by_county(election, &area_id, "Senator").await?,
+        "Mp" => db.results_by_constituency(election, &area_id, "Mp").await?,
+        "WomenRep" => {
+            db.results_by_constituency(election, &area_id, "WomenRep")
+                .await?
+        }
         _ => vec![],
     };
 
-    Json(res)
+    Ok(Json(res))
+}
+
+async fn results_by_station(
+    blockchain: Extension<BlockChain>,
+    Path((election, station)): Path<(i64, i64)>,
+) -> ApiResult<impl IntoResponse> {
+    let db = &blockchain.db;
+
+    let res = db.results_by_station(election, station).await?;
+
+    Ok(Json(res))
 }
 
-async fn live(blockchain: Extension<BlockChain>) -> impl IntoResponse {
+async fn live(blockchain: Extension<BlockChain>) -> ApiResult<impl IntoResponse> {
     let db = &blockchain.db;
 
-    let res = db.last_five_results().await.unwrap();
+    let res = db.last_five_results().await?;
+
+    Ok(Json(res))
+}
 
-    Json(res)
+async fn sync_status(blockchain: Extension<BlockChain>) -> impl IntoResponse {
+    Json(blockchain.sync_status().await)
 }
 
 pub fn run_api_server() -> Router {
-    let router = Router::new()
+    Router::new()
+        .route("/submit/raw", post(submit_agent_results))
+        .route("/submit/tally", post(submit_agent_results))
+        .route(
+            "/attachments",
+            post(upload_attachment).layer(DefaultBodyLimit::max(MAX_ATTACHMENT_SIZE)),
+        )
+        .merge(run_read_only_api_server())
+}
+
+/// The routes that sign what they are sent with the node key, or take
+/// blocks signed with a validator key. They do not authenticate the caller,
+/// so serve them on a private interface only.
+pub fn run_admin_api_server() -> Router {
+    Router::new()
         .route("/submit", post(submit_result))
-        .route("/submit/raw", post(submit_raw_result))
+        .route("/submit/correction", post(submit_corrections))
+        .route("/submit/election", post(submit_election))
+        .route("/submit/reference", post(submit_reference_data))
+}
+
+/// The routes that only read the chain, as served by observer nodes
+pub fn run_read_only_api_server() -> Router {
+    Router::new()
         .route("/block/{height}", get(block_by_height))
+        .route("/blocks", get(explorer::blocks))
+        .route("/blocks/{block}", get(explorer::block))
+        .route("/blocks/{block}/results", get(explorer::block_results))
+        .route("/chain", get(explorer::chain_summary))
+        .route(
+            "/signers/{pubkey_hash}/blocks",
+            get(explorer::signer_blocks),
+        )
+        .route("/headers/{start}/{count}", get(headers))
+        .route("/reference", get(reference_data))
+        .route("/agents", get(agents))
+        .route("/attachments/{hash}", get(attachment))
+        .route("/proof/{station}/{candidate}", get(inclusion_proof))
         .route("/positions", get(positions))
         .route("/parties", get(parties))
         .route("/counties", get(counties))
@@ -183,15 +523,35 @@ pub fn run_api_server() -> Router {
         )
         .route("/wards/{ward}/stations", get(stations_by_ward))
         .route(
-            "/candidates/{position_type}/{area_id}",
+            "/stations/{station}/attestations",
+            get(attestations_by_station),
+        )
+        .route(
+            "/stations/{station}/corrections",
+            get(corrections_by_station),
+        )
+        .route(
+            "/stations/{station}/history",
+            get(explorer::station_history),
+        )
+        .route("/elections", get(elections))
+        .route("/elections/{election}", get(election))
+        .route(
+            "/elections/{election}/candidates/{position_type}/{area_id}",
             get(candidates_by_position_type),
         )
         .route(
-            "/candidates/{position_type}/{area_id}/results",
+            "/elections/{election}/candidates/{position_type}/{area_id}/results",
             get(candidates_by_result),
         )
-        .route("/live", get(live));
-    router
+        .route(
+            "/elections/{election}/stations/{station}/results",
+            get(results_by_station),
+        )
+        .route("/live", get(live))
+        .route("/stream", get(stream::sse))
+        .route("/stream/ws", get(stream::websocket))
+        .route("/sync", get(sync_status))
 }
 
 fn workspace_dir() -> Result<PathBuf, std::io::Error> {
@@ -204,22 +564,16 @@ fn workspace_dir() -> Result<PathBuf, std::io::Error> {
     let cargo_path = std::path::Path::new(std::str::from_utf8(&output).unwrap().trim());
     Ok(cargo_path
         .parent()
-        .ok_or(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "No parent directory",
-        ))?
+        .ok_or(std::io::Error::other("No parent directory"))?
         .to_path_buf())
 }
 
 pub fn ui_handler() -> ServeDir<tower_http::set_status::SetStatus<ServeFile>> {
-    ServeDir::new(
-        workspace_dir()
-            .unwrap_or(PathBuf::new())
-            .join("apps/web/dist"),
+    ServeDir::new(workspace_dir().unwrap_or_default().join("apps/web/dist")).not_found_service(
+        ServeFile::new(
+            workspace_dir()
+                .unwrap_or_default()
+                .join("apps/web/dist/index.html"),
+        ),
     )
-    .not_found_service(ServeFile::new(
-        workspace_dir()
-            .unwrap_or(PathBuf::new())
-            .join("apps/web/dist/index.html"),
-    ))
 }
@@ -0,0 +1,185 @@
+use std::convert::Infallible;
+
+use axum::{
+    Extension,
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use blockchain::BlockChain;
+use futures_util::{Stream, stream};
+use tokio::sync::broadcast::{Receiver, error::RecvError};
+use types::{election::Area, events::ChainEvent};
+
+use crate::{ApiError, ApiResult, extract::Query};
+
+/// Narrows the tally deltas pushed to a dashboard, e.g.
+/// `?position=Mp&area=constituency:113`. Blocks, reorgs, peer and sync events
+/// are always pushed.
+#[derive(Debug, Default, serde::Deserialize)]
+pub(crate) struct StreamFilter {
+    position: Option<String>,
+    area: Option<String>,
+}
+
+/// [`StreamFilter`] with the area parsed
+struct Filter {
+    position: Option<String>,
+    area: Option<Area>,
+}
+
+impl Filter {
+    fn new(query: StreamFilter) -> ApiResult<Self> {
+        let area = query
+            .area
+            .map(|area| {
+                Area::parse(&area)
+                    .ok_or_else(|| ApiError::BadRequest(format!("Invalid area {area}")))
+            })
+            .transpose()?;
+        Ok(Filter {
+            position: query.position,
+            area,
+        })
+    }
+
+    fn apply(&self, mut event: ChainEvent) -> ChainEvent {
+        if let ChainEvent::BlockAdded { deltas, .. } = &mut event {
+            deltas.retain(|delta| {
+                self.position
+                    .as_ref()
+                    .is_none_or(|position| *position == delta.position_type)
+                    && self.area.is_none_or(|area| area == delta.area)
+            });
+        }
+        event
+    }
+}
+
+/// Name the event is pushed under, its data is the event as JSON
+fn event_name(event: &ChainEvent) -> &'static str {
+    match event {
+        ChainEvent::Reorg { .. } => "reorg",
+        ChainEvent::BlockAdded { .. } => "block_added",
+        ChainEvent::PeerConnected { .. } => "peer_connected",
+        ChainEvent::PeerDisconnected { .. } => "peer_disconnected",
+        ChainEvent::SyncStarted { .. } => "sync_started",
+        ChainEvent::SyncFinished { .. } => "sync_finished",
+    }
+}
+
+/// The next event for a subscriber, `Err` with the number of events it
+/// missed when it fell behind, `None` once the chain is gone
+async fn next_event(events: &mut Receiver<ChainEvent>) -> Option<Result<ChainEvent, u64>> {
+    match events.recv().await {
+        Ok(event) => Some(Ok(event)),
+        Err(RecvError::Lagged(missed)) => Some(Err(missed)),
+        Err(RecvError::Closed) => None,
+    }
+}
+
+/// Server-Sent Events feed of chain events. A `lagged` event tells the client
+/// it missed some and should refetch what it shows.
+pub(crate) async fn sse(
+    blockchain: Extension<BlockChain>,
+    query: Query<StreamFilter>,
+) -> ApiResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let filter = Filter::new(query.0)?;
+    let events = blockchain.subscribe_events();
+
+    let stream = stream::unfold((events, filter), |(mut events, filter)| async move {
+        let event = match next_event(&mut events).await? {
+            Ok(event) => {
+                let event = filter.apply(event);
+                Event::default()
+                    .event(event_name(&event))
+                    .json_data(&event)
+                    .unwrap_or_default()
+            }
+            Err(missed) => Event::default().event("lagged").data(missed.to_string()),
+        };
+        Some((Ok(event), (events, filter)))
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// The same feed over a WebSocket, one JSON text message per event
+pub(crate) async fn websocket(
+    blockchain: Extension<BlockChain>,
+    query: Query<StreamFilter>,
+    upgrade: WebSocketUpgrade,
+) -> ApiResult<impl IntoResponse> {
+    let filter = Filter::new(query.0)?;
+    let events = blockchain.subscribe_events();
+    Ok(upgrade.on_upgrade(move |socket| push_events(socket, events, filter)))
+}
+
+async fn push_events(mut socket: WebSocket, mut events: Receiver<ChainEvent>, filter: Filter) {
+    loop {
+        let message = tokio::select! {
+            event = next_event(&mut events) => match event {
+                Some(Ok(event)) => serde_json::json!({
+                    "event": event_name(&event),
+                    "data": filter.apply(event),
+                }),
+                Some(Err(missed)) => serde_json::json!({ "event": "lagged", "data": missed }),
+                None => break,
+            },
+            // Clients only ever close the socket
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        if socket
+            .send(Message::Text(message.to_string().into()))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::events::TallyDelta;
+
+    #[test]
+    fn test_filter_narrows_tally_deltas() {
+        let delta = |area, position_type: &str| TallyDelta {
+            election_id: 1,
+            area,
+            position_type: position_type.into(),
+            candidate_id: 1,
+            votes: 66,
+        };
+        let event = ChainEvent::BlockAdded {
+            height: 2,
+            hash: "f00d".into(),
+            deltas: vec![
+                delta(Area::Ward(563), "Mp"),
+                delta(Area::Ward(563), "Mca"),
+                delta(Area::Constituency(113), "Mp"),
+            ],
+        };
+        let filter = Filter::new(StreamFilter {
+            position: Some("Mp".into()),
+            area: Some("ward:563".into()),
+        })
+        .unwrap();
+        assert!(matches!(
+            filter.apply(event),
+            ChainEvent::BlockAdded { deltas, .. } if deltas == vec![delta(Area::Ward(563), "Mp")]
+        ));
+
+        let invalid = StreamFilter {
+            area: Some("ward".into()),
+            ..Default::default()
+        };
+        assert!(matches!(Filter::new(invalid), Err(ApiError::BadRequest(_))));
+    }
+}
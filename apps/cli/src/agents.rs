@@ -0,0 +1,31 @@
+use database::Database;
+use types::{Block, BlockType, agents::Agent, crypto, election::Area};
+
+/// Prints a fresh agent key. The secret stays with the agent, the public key
+/// is what gets registered.
+pub(crate) fn new_key() {
+    let key = crypto::get_private_key();
+    let pub_key = bincode::serialize(&crypto::get_public_key(&key)).unwrap();
+    println!("secret:  {}", hex::encode(key.to_bytes()));
+    println!("key:     {}", hex::encode(&pub_key));
+    println!("hash:    {}", types::keys::key_hash(&pub_key).unwrap());
+}
+
+/// Parses an area given as `national` or `<type>:<code>`, e.g. `ward:563`
+pub(crate) fn parse_area(area: &str) -> Result<Area, String> {
+    Area::parse(area).ok_or_else(|| format!("Invalid area {area}"))
+}
+
+/// Registers an agent, or replaces its scope, in a block signed with our key
+pub(crate) async fn submit_agent(db: &Database, node_addr: &str, agent: Agent) {
+    let signer = db.get_private_key().await.unwrap();
+    let tip = db.last_block().await.unwrap();
+
+    let block = Block::with_inner(
+        &signer,
+        &tip.hash,
+        BlockType::Agents(vec![agent]),
+        tip.height + 1,
+    );
+    crate::submit_block(node_addr, &block).await;
+}
@@ -1,14 +1,9 @@
 use std::str::FromStr;
 
-use bincode::serialize;
 use blockchain::BlockChain;
 use database::Database;
 use sqlx::{SqlitePool, sqlite::SqliteConnectOptions};
-use types::{
-    Block,
-    config::Config,
-    crypto::{self, sha256_digest},
-};
+use types::{Block, PubKey, config::Config, crypto};
 
 pub async fn init_blockchain(config: Config, init_query_path: &str) {
     let pool_cfg = SqliteConnectOptions::from_str(&config.main_db)
@@ -33,21 +28,16 @@ pub async fn init_blockchain(config: Config, init_query_path: &str) {
         .await
         .unwrap();
 
-    let db = Database::new(main_db.clone(), private_db);
+    let db = Database::new(main_db, private_db);
 
     // Create keypair
     let private_key = crypto::get_private_key();
     let verify_key = *private_key.verifying_key();
-    let pub_key_hash = sha256_digest(&verify_key);
-    let pub_key_bytes = serialize(&verify_key).unwrap();
+    let pub_key = PubKey::from_verifying_key("genesis", &verify_key);
     let priv_key_bytes = private_key.to_bytes().as_slice().to_vec();
 
-    // Save keypair to db
-    db.add_public_key(&pub_key_bytes, "genesis", &pub_key_hash, -1)
-        .await
-        .unwrap();
-
-    db.add_private_key(&priv_key_bytes, &pub_key_hash)
+    // Save the private key, the public key is registered by the genesis block
+    db.add_private_key(&priv_key_bytes, &pub_key.hash)
         .await
         .unwrap();
 
@@ -55,15 +45,17 @@ pub async fn init_blockchain(config: Config, init_query_path: &str) {
 
     assert_eq!(my_keys.len(), 1);
 
-    //assert_eq!(&my_keys.get(0).unwrap(), &pub_key_hash);
-    let pub_key = db.get_public_key(&pub_key_hash).await.unwrap();
+    // The gazette travels in the genesis block so other nodes can load it
     let init_query = std::fs::read_to_string(init_query_path).unwrap();
-    let init_query_hash = sha256_digest(&init_query);
+    let reference_data = Database::reference_data_from_sql(&init_query)
+        .await
+        .unwrap();
+    log::info!("Gazette has {} records", reference_data.len());
     // let there be light
-    let genesis_block = Block::genesis(&(private_key, verify_key, pub_key), init_query_hash);
+    let genesis_block = Block::genesis(&(private_key, verify_key, pub_key), reference_data);
     let mut blockchain = BlockChain::new(db, None);
-    sqlx::query(&init_query).execute(&main_db).await.unwrap();
-    blockchain.add_block(&genesis_block).await.unwrap();
+    blockchain.bootstrap(&genesis_block).await.unwrap();
+    log::info!("Genesis block: {}", genesis_block.hash);
     assert!(blockchain.is_valid().await.unwrap());
     log::info!("Blockchain was successfully initialized!");
 }
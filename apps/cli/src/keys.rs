@@ -0,0 +1,27 @@
+use database::Database;
+use types::{
+    Block, BlockType,
+    keys::{KeyOperation, KeyTransaction},
+};
+
+pub(crate) async fn show_key(db: &Database) {
+    let (_, _, pub_key) = db.get_private_key().await.unwrap();
+    println!("creator: {}", pub_key.creator);
+    println!("hash:    {}", pub_key.hash);
+    println!("key:     {}", hex::encode(&pub_key.bytes));
+}
+
+/// Signs a key operation with our key and submits it to a node as a new block
+pub(crate) async fn submit_key_operation(db: &Database, node_addr: &str, operation: KeyOperation) {
+    let signer = db.get_private_key().await.unwrap();
+    let tip = db.last_block().await.unwrap();
+
+    let tx = KeyTransaction::new(&signer, operation);
+    let block = Block::with_inner(
+        &signer,
+        &tip.hash,
+        BlockType::KeyManagement(vec![tx]),
+        tip.height + 1,
+    );
+    crate::submit_block(node_addr, &block).await;
+}
@@ -1,19 +1,25 @@
 use std::path::PathBuf;
+mod agents;
 mod init;
+mod keys;
+mod peers;
+mod proof;
 mod query;
 mod validate;
 
 use clap::{CommandFactory, Parser, Subcommand};
 use database::Database;
 use sqlx::SqlitePool;
-use types::{Block, config::Config, merkle::MerkleTree};
+use types::{
+    Block, agents::Agent, config::Config, election::Area, keys::KeyOperation, merkle::MerkleTree,
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    /// Sets a custom config file
+    /// Sets a custom config file, needed by every command but `verify-proof`
     #[arg(short, long, value_name = "FILE")]
-    config: PathBuf,
+    config: Option<PathBuf>,
 
     #[command(subcommand)]
     command: Option<Commands>,
@@ -37,24 +43,128 @@ enum Commands {
         source: String,
     },
 
-    /// Submit a block to a submission node
+    /// Submit results signed with our key to the admin API of a submission
+    /// node, only accepted if our key signed the genesis block
     Submit {
         node_addr: String,
 
+        election_id: i64,
         station_id: i64,
         candidate_id: i64,
         votes: i64,
     },
+
+    /// Manage the on-chain signer registry
+    Keys {
+        #[command(subcommand)]
+        command: KeyCommands,
+    },
+
+    /// Manage the agents allowed to submit results
+    Agents {
+        #[command(subcommand)]
+        command: AgentCommands,
+    },
+
+    /// Manage the peer book
+    Peers {
+        #[command(subcommand)]
+        command: PeerCommands,
+    },
+
+    /// Check that a result belongs to a signed block, without the chain
+    VerifyProof {
+        /// Proof file or URL, as served by `/api/v1/proof/{station}/{candidate}`
+        proof: String,
+        /// Hash of the key the block has to be signed with
+        #[clap(long)]
+        signer: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum PeerCommands {
+    /// List known peers
+    List,
+    /// Add a peer, in the format "address:port"
+    Add {
+        address: String,
+        /// Keep reconnecting to this peer
+        #[clap(long)]
+        permanent: bool,
+    },
+    /// Forget a peer
+    Remove { address: String },
+    /// List banned peers
+    Bans,
+    /// Lift the ban on a peer, by node id
+    Unban { node_id: String },
+}
+
+#[derive(Subcommand)]
+enum AgentCommands {
+    /// Generate a key for a new agent
+    NewKey,
+    /// Register an agent, or replace the areas it may submit for. An agent
+    /// registered without areas is revoked.
+    Register {
+        node_addr: String,
+        /// Party or observer organisation the agent reports for
+        organisation: String,
+        /// Hex encoded public key, as printed by `agents new-key`
+        pub_key: String,
+        /// Area the agent may submit for, e.g. `ward:563` or `national`
+        #[clap(long = "area", value_parser = agents::parse_area)]
+        areas: Vec<Area>,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeyCommands {
+    /// Show our signing key
+    Show,
+    /// Register a new signer
+    Register {
+        node_addr: String,
+        creator: String,
+        /// Hex encoded public key, as printed by `keys show`
+        pub_key: String,
+    },
+    /// Revoke a signer
+    Revoke {
+        node_addr: String,
+        pubkey_hash: String,
+    },
+    /// Replace a signer's key with a new one
+    Rotate {
+        node_addr: String,
+        pubkey_hash: String,
+        /// Hex encoded public key, as printed by `keys show`
+        new_pub_key: String,
+    },
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
 
-    let config: Config = toml::from_str(
-        &std::fs::read_to_string(cli.config.as_path()).expect("Failed to read config file"),
-    )
-    .expect("Failed to parse config file");
+    // Proofs stand on their own, no node or database is involved
+    if let Some(Commands::VerifyProof { proof, signer }) = &cli.command {
+        proof::verify_proof(proof, signer.as_deref()).await;
+        return;
+    }
+    if let Some(Commands::Agents {
+        command: AgentCommands::NewKey,
+    }) = &cli.command
+    {
+        agents::new_key();
+        return;
+    }
+
+    let config_path = cli.config.as_ref().expect("Missing --config");
+    let config: Config =
+        toml::from_str(&std::fs::read_to_string(config_path).expect("Failed to read config file"))
+            .expect("Failed to parse config file");
 
     // You can check for the existence of subcommands, and if found use their
     // matches just as you would the top level cmd
@@ -73,13 +183,15 @@ async fn main() {
         }
         Some(Commands::Submit {
             node_addr,
+            election_id,
             station_id,
             candidate_id,
             votes,
         }) => {
             log::debug!(
-                "Submitting to node at {}: station_id={}, candidate_id={}, votes={}",
+                "Submitting to node at {}: election_id={}, station_id={}, candidate_id={}, votes={}",
                 node_addr,
+                election_id,
                 station_id,
                 candidate_id,
                 votes
@@ -93,12 +205,13 @@ async fn main() {
             let height = db.get_height().await.unwrap();
 
             let results = vec![types::CandidateResult {
+                election_id: *election_id,
                 station_id: *station_id,
                 candidate_id: *candidate_id,
                 votes: *votes,
             }];
 
-            assert!(results.len() > 0, "No empty results");
+            assert!(!results.is_empty(), "No empty results");
             let signer = db.get_private_key().await.unwrap();
             let prev_hash = db.get_block_by_height(height).await.unwrap().hash;
 
@@ -112,23 +225,86 @@ async fn main() {
                 (height + 1) as usize,
                 root.unwrap(),
             );
-            let client = reqwest::Client::new();
-            match client
-                .post(&format!("{}/submit", node_addr))
-                .json(&block)
-                .send()
-                .await
-            {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        println!("Block submitted successfully");
-                    } else {
-                        eprintln!("Failed to submit block: {}", response.status());
-                    }
+            submit_block(node_addr, &block).await;
+        }
+        Some(Commands::Keys { command }) => {
+            let db = Database::new(
+                SqlitePool::connect(&config.main_db).await.unwrap(),
+                SqlitePool::connect(&config.private_db).await.unwrap(),
+            );
+            match command {
+                KeyCommands::Show => keys::show_key(&db).await,
+                KeyCommands::Register {
+                    node_addr,
+                    creator,
+                    pub_key,
+                } => {
+                    let operation = KeyOperation::Register {
+                        creator: creator.clone(),
+                        pub_key: hex::decode(pub_key).expect("Invalid public key"),
+                    };
+                    keys::submit_key_operation(&db, node_addr, operation).await;
+                }
+                KeyCommands::Revoke {
+                    node_addr,
+                    pubkey_hash,
+                } => {
+                    let operation = KeyOperation::Revoke {
+                        pubkey_hash: pubkey_hash.clone(),
+                    };
+                    keys::submit_key_operation(&db, node_addr, operation).await;
+                }
+                KeyCommands::Rotate {
+                    node_addr,
+                    pubkey_hash,
+                    new_pub_key,
+                } => {
+                    let operation = KeyOperation::Rotate {
+                        pubkey_hash: pubkey_hash.clone(),
+                        new_pub_key: hex::decode(new_pub_key).expect("Invalid public key"),
+                    };
+                    keys::submit_key_operation(&db, node_addr, operation).await;
+                }
+            }
+        }
+        Some(Commands::Agents { command }) => {
+            let db = Database::new(
+                SqlitePool::connect(&config.main_db).await.unwrap(),
+                SqlitePool::connect(&config.private_db).await.unwrap(),
+            );
+            match command {
+                AgentCommands::NewKey => unreachable!(),
+                AgentCommands::Register {
+                    node_addr,
+                    organisation,
+                    pub_key,
+                    areas,
+                } => {
+                    let agent = Agent {
+                        pub_key: hex::decode(pub_key).expect("Invalid public key"),
+                        organisation: organisation.clone(),
+                        scope: areas.clone(),
+                    };
+                    agents::submit_agent(&db, node_addr, agent).await;
+                }
+            }
+        }
+        Some(Commands::Peers { command }) => {
+            let db = Database::new(
+                SqlitePool::connect(&config.main_db).await.unwrap(),
+                SqlitePool::connect(&config.private_db).await.unwrap(),
+            );
+            match command {
+                PeerCommands::List => peers::list_peers(&db).await,
+                PeerCommands::Add { address, permanent } => {
+                    peers::add_peer(&db, address, *permanent).await
                 }
-                Err(e) => eprintln!("Error submitting block: {}", e),
+                PeerCommands::Remove { address } => peers::remove_peer(&db, address).await,
+                PeerCommands::Bans => peers::list_bans(&db).await,
+                PeerCommands::Unban { node_id } => peers::unban_peer(&db, node_id).await,
             }
         }
+        Some(Commands::VerifyProof { .. }) => unreachable!(),
         None => {
             clap::Command::print_long_help(&mut Cli::command()).unwrap();
         }
@@ -136,3 +312,26 @@ async fn main() {
 
     // Continued program logic goes here...
 }
+
+/// Submit a signed block to the admin API of a submission node
+async fn submit_block(node_addr: &str, block: &Block) {
+    let client = reqwest::Client::new();
+    match client
+        .post(format!("{}/submit", node_addr))
+        .json(block)
+        .send()
+        .await
+    {
+        // Either the height and hash the block was given or the error body
+        Ok(response) => {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            if status.is_success() {
+                println!("Block submitted successfully: {body}");
+            } else {
+                eprintln!("Failed to submit block: {status} {body}");
+            }
+        }
+        Err(e) => eprintln!("Error submitting block: {}", e),
+    }
+}
@@ -0,0 +1,84 @@
+use chrono::DateTime;
+use database::Database;
+use tabled::{Table, Tabled};
+
+#[derive(Tabled)]
+struct PeerRow {
+    address: String,
+    permanent: bool,
+    last_seen: String,
+    failures: i64,
+}
+
+#[derive(Tabled)]
+struct BanRow {
+    node_id: String,
+    address: String,
+    reason: String,
+    bans: i64,
+    until: String,
+}
+
+pub(crate) async fn add_peer(db: &Database, address: &str, permanent: bool) {
+    let address: std::net::SocketAddr = address.parse().expect("Invalid peer address");
+    db.add_peer(&address.to_string(), permanent).await.unwrap();
+    println!("Added peer {address}");
+}
+
+pub(crate) async fn remove_peer(db: &Database, address: &str) {
+    if db.remove_peer(address).await.unwrap() {
+        println!("Removed peer {address}");
+    } else {
+        eprintln!("Unknown peer {address}");
+    }
+}
+
+pub(crate) async fn list_peers(db: &Database) {
+    let rows: Vec<PeerRow> = db
+        .peers()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|peer| PeerRow {
+            address: peer.address,
+            permanent: peer.permanent,
+            last_seen: peer
+                .last_seen
+                .and_then(|t| DateTime::from_timestamp(t, 0))
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_else(|| "never".to_string()),
+            failures: peer.failures,
+        })
+        .collect();
+    println!("{}", Table::new(&rows));
+}
+
+pub(crate) async fn list_bans(db: &Database) {
+    let rows: Vec<BanRow> = db
+        .bans()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|ban| BanRow {
+            node_id: ban.node_id,
+            address: ban.address,
+            reason: ban.reason,
+            bans: ban.bans,
+            until: match ban.banned_until {
+                Some(t) => DateTime::from_timestamp(t, 0)
+                    .map(|t| t.to_rfc3339())
+                    .unwrap_or_default(),
+                None => "permanent".to_string(),
+            },
+        })
+        .collect();
+    println!("{}", Table::new(&rows));
+}
+
+pub(crate) async fn unban_peer(db: &Database, node_id: &str) {
+    if db.unban_peer(node_id).await.unwrap() {
+        println!("Unbanned peer {node_id}");
+    } else {
+        eprintln!("Peer {node_id} is not banned");
+    }
+}
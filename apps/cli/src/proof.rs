@@ -0,0 +1,43 @@
+use types::proof::InclusionProof;
+
+/// Verifies an inclusion proof read from a file, or fetched from a node when
+/// `source` is a URL such as `http://localhost:9091/api/v1/proof/{station}/{candidate}`
+pub(crate) async fn verify_proof(source: &str, signer: Option<&str>) {
+    let proof: InclusionProof = if source.starts_with("http://") || source.starts_with("https://") {
+        reqwest::get(source)
+            .await
+            .and_then(|response| response.error_for_status())
+            .expect("Failed to fetch proof")
+            .json()
+            .await
+            .expect("Invalid proof")
+    } else {
+        let json = std::fs::read_to_string(source).expect("Failed to read proof");
+        serde_json::from_str(&json).expect("Invalid proof")
+    };
+
+    if let Err(e) = proof.verify() {
+        eprintln!("Proof is INVALID: {e}");
+        std::process::exit(1);
+    }
+    let signer_hash = proof.signer_hash().unwrap_or_default();
+    if let Some(expected) = signer
+        && expected != signer_hash
+    {
+        eprintln!("Proof is valid but signed by {signer_hash}, not {expected}");
+        std::process::exit(1);
+    }
+
+    let result = &proof.result;
+    println!(
+        "Proof is VALID: {} votes for candidate {} at station {} (election {})",
+        result.votes, result.candidate_id, result.station_id, result.election_id
+    );
+    println!(
+        "Included in block {} at height {}, signed by {signer_hash}",
+        proof.header.hash, proof.header.height
+    );
+    if signer.is_none() {
+        println!("Compare the signer with the validators published for the chain");
+    }
+}
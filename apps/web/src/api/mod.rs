@@ -1,9 +1,19 @@
 use types::{
-    CandidateResult,
-    models::{Constituency, County, Station, Ward},
+    Block, SignedHeader,
+    agents::Submission,
+    election::Election,
+    explorer::{BlockSummary, ChainSummary, Page},
+    models::{Constituency, County, ResultRevision, Station, Ward},
+    proof::InclusionProof,
     results::{Candidate, GeneralResult, LastResultSummary},
 };
 
+/// The part of the API's error body shown to the user
+#[derive(serde::Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
 pub async fn positions() -> Result<Vec<String>, String> {
     let res = gloo_net::http::Request::get("/api/v1/positions")
         .send()
@@ -15,6 +25,17 @@ pub async fn positions() -> Result<Vec<String>, String> {
     Ok(res)
 }
 
+pub async fn elections() -> Result<Vec<Election>, String> {
+    let res = gloo_net::http::Request::get("/api/v1/elections")
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(res)
+}
+
 pub async fn counties() -> Result<Vec<County>, String> {
     let res = gloo_net::http::Request::get("/api/v1/counties")
         .send()
@@ -60,21 +81,30 @@ pub async fn stations(ward_id: &str) -> Result<Vec<Station>, String> {
     Ok(res)
 }
 
-pub async fn candidates(position_type: &str, area_id: &str) -> Result<Vec<Candidate>, String> {
-    let res =
-        gloo_net::http::Request::get(&format!("/api/v1/candidates/{position_type}/{area_id}"))
-            .send()
-            .await
-            .map_err(|e| e.to_string())?
-            .json()
-            .await
-            .map_err(|e| e.to_string())?;
+pub async fn candidates(
+    election_id: i64,
+    position_type: &str,
+    area_id: &str,
+) -> Result<Vec<Candidate>, String> {
+    let res = gloo_net::http::Request::get(&format!(
+        "/api/v1/elections/{election_id}/candidates/{position_type}/{area_id}"
+    ))
+    .send()
+    .await
+    .map_err(|e| e.to_string())?
+    .json()
+    .await
+    .map_err(|e| e.to_string())?;
     Ok(res)
 }
 
-pub async fn results(position_type: &str, area_id: &str) -> Result<Vec<GeneralResult>, String> {
+pub async fn results(
+    election_id: i64,
+    position_type: &str,
+    area_id: &str,
+) -> Result<Vec<GeneralResult>, String> {
     let res = gloo_net::http::Request::get(&format!(
-        "/api/v1/candidates/{position_type}/{area_id}/results"
+        "/api/v1/elections/{election_id}/candidates/{position_type}/{area_id}/results"
     ))
     .send()
     .await
@@ -96,12 +126,98 @@ pub async fn live() -> Result<Vec<LastResultSummary>, String> {
     Ok(res)
 }
 
-pub async fn submit(results: Vec<CandidateResult>) -> Result<(), String> {
-    gloo_net::http::Request::post(&format!("/api/v1/submit/raw"))
-        .json(&results)
+/// Submits results signed by an agent, failing with the node's reason when
+/// the agent is not allowed to submit them
+pub async fn submit(submission: Submission) -> Result<(), String> {
+    let res = gloo_net::http::Request::post("/api/v1/submit/raw")
+        .json(&submission)
         .map_err(|e| e.to_string())?
         .send()
         .await
         .map_err(|e| e.to_string())?;
+    if !res.ok() {
+        let error: ErrorBody = res.json().await.map_err(|e| e.to_string())?;
+        return Err(error.message);
+    }
     Ok(())
 }
+
+pub async fn proof(
+    election_id: i64,
+    station_id: i64,
+    candidate_id: i64,
+) -> Result<InclusionProof, String> {
+    let res = gloo_net::http::Request::get(&format!(
+        "/api/v1/proof/{station_id}/{candidate_id}?election_id={election_id}"
+    ))
+    .send()
+    .await
+    .map_err(|e| e.to_string())?;
+    if res.status() == 404 {
+        return Err("No result for this candidate at this station".into());
+    }
+    res.json().await.map_err(|e| e.to_string())
+}
+
+pub async fn headers(start: usize, count: usize) -> Result<Vec<SignedHeader>, String> {
+    let res = gloo_net::http::Request::get(&format!("/api/v1/headers/{start}/{count}"))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(res)
+}
+
+/// Fetches `url`, failing with the node's message when it answers with an error
+async fn get_json<T: serde::de::DeserializeOwned>(url: &str) -> Result<T, String> {
+    let res = gloo_net::http::Request::get(url)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !res.ok() {
+        let error: ErrorBody = res.json().await.map_err(|e| e.to_string())?;
+        return Err(error.message);
+    }
+    res.json().await.map_err(|e| e.to_string())
+}
+
+pub async fn chain_summary() -> Result<ChainSummary, String> {
+    get_json("/api/v1/chain").await
+}
+
+pub async fn blocks(offset: i64, limit: i64) -> Result<Page<BlockSummary>, String> {
+    get_json(&format!("/api/v1/blocks?offset={offset}&limit={limit}")).await
+}
+
+/// A block by its hash or height
+pub async fn block(id: &str) -> Result<Block, String> {
+    get_json(&format!("/api/v1/blocks/{id}")).await
+}
+
+pub async fn block_results(id: &str) -> Result<Vec<ResultRevision>, String> {
+    get_json(&format!("/api/v1/blocks/{id}/results")).await
+}
+
+pub async fn station_history(
+    station_id: i64,
+    offset: i64,
+    limit: i64,
+) -> Result<Page<BlockSummary>, String> {
+    get_json(&format!(
+        "/api/v1/stations/{station_id}/history?offset={offset}&limit={limit}"
+    ))
+    .await
+}
+
+pub async fn signer_blocks(
+    pubkey_hash: &str,
+    offset: i64,
+    limit: i64,
+) -> Result<Page<BlockSummary>, String> {
+    get_json(&format!(
+        "/api/v1/signers/{pubkey_hash}/blocks?offset={offset}&limit={limit}"
+    ))
+    .await
+}
@@ -19,7 +19,12 @@ pub fn ElectionHeader() -> impl IntoView {
                         <div>
                             <A href="/">
                                 <h1 class="text-xl md:text-2xl font-bold text-foreground">
-                                    "By-election Results 2025"
+                                    {move || {
+                                        app_data
+                                            .election()
+                                            .map(|e| format!("{} Results", e.name))
+                                            .unwrap_or_else(|| "Election Results".into())
+                                    }}
                                 </h1>
                             </A>
                             <p class="text-xs md:text-sm text-muted-foreground">
@@ -61,6 +66,18 @@ pub fn ElectionHeader() -> impl IntoView {
                             })
                             .collect::<Vec<_>>()}
                     </div>
+                    <A
+                        href="/explorer"
+                        attr:class="px-3 py-1 rounded border text-sm text-foreground border-border hover:bg-muted flex-none"
+                    >
+                        "Explorer"
+                    </A>
+                    <A
+                        href="/verify"
+                        attr:class="px-3 py-1 rounded border text-sm text-foreground border-border hover:bg-muted flex-none"
+                    >
+                        "Verify"
+                    </A>
                     <A
                         href="/submit"
                         attr:class="px-3 py-1 rounded border text-sm bg-green-900 text-white hover:bg-secondary/90 flex-none"
@@ -11,6 +11,8 @@ pub struct ResultData {
     pub opponent_percentage: f32,
     pub votes: String,
     pub polls_centers_reporting: String,
+    /// Submitters disagree on the station's results
+    pub disputed: bool,
 }
 
 #[component]
@@ -85,7 +87,11 @@ pub fn ResultCard(result: ResultData) -> impl IntoView {
                     {format!("{} votes", result.votes)}
                 </span>
 
-                <span class="text-xs font-medium text-primary">"Live"</span>
+                {if result.disputed {
+                    view! { <span class="text-xs font-medium text-red-700">"Disputed"</span> }
+                } else {
+                    view! { <span class="text-xs font-medium text-primary">"Live"</span> }
+                }}
             </div>
         </div>
     }
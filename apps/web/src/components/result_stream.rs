@@ -1,4 +1,7 @@
+use futures::StreamExt;
+use gloo_net::eventsource::futures::EventSource;
 use leptos::prelude::*;
+use types::results::Confirmation;
 
 use crate::components::result_card::{ResultCard, ResultData};
 
@@ -6,13 +9,26 @@ use crate::components::result_card::{ResultCard, ResultData};
 pub fn ResultsStream() -> impl IntoView {
     let results = LocalResource::new(move || async move { crate::api::live().await });
 
+    // Refetch when a block lands instead of polling
+    leptos::task::spawn_local(async move {
+        let Ok(mut source) = EventSource::new("/api/v1/stream") else {
+            return;
+        };
+        let Ok(mut blocks) = source.subscribe("block_added") else {
+            return;
+        };
+        while let Some(Ok(_)) = blocks.next().await {
+            results.refetch();
+        }
+    });
+
     view! {
         <div class="h-full overflow-y-auto">
             <div class="p-6 space-y-4">
 
                 <div class="mb-6">
                     <h2 class="text-xl font-bold text-foreground mb-1">"Live Results"</h2>
-                    <p class="text-sm text-muted-foreground">"Updated every 30 seconds"</p>
+                    <p class="text-sm text-muted-foreground">"Updated as blocks are added"</p>
                 </div>
 
                 <div class="space-y-3">
@@ -20,7 +36,7 @@ pub fn ResultsStream() -> impl IntoView {
                         each=move || results.get()
                             .unwrap_or(Ok(vec![]))
                             .unwrap_or_default()
-                            .into_iter().map(|s| ResultData { id: s.candidate1_id as i32, county: s.station_name, leading: s.candidate1_name, percentage: s.candidate1_percentage as f32, opponent: s.candidate2_name.unwrap_or_default(), opponent_percentage: s.candidate2_percentage.unwrap_or_default() as f32, votes: (s.candidate1_votes + s.candidate2_votes.unwrap_or_default()).to_string(), polls_centers_reporting: 0.to_string() })
+                            .into_iter().map(|s| ResultData { id: s.candidate1_id as i32, county: s.station_name, leading: s.candidate1_name, percentage: s.candidate1_percentage as f32, opponent: s.candidate2_name.unwrap_or_default(), opponent_percentage: s.candidate2_percentage.unwrap_or_default() as f32, votes: (s.candidate1_votes + s.candidate2_votes.unwrap_or_default()).to_string(), polls_centers_reporting: 0.to_string(), disputed: s.confirmation == Confirmation::Disputed.as_str() })
                         key=|res| res.id
                         children=move |res: ResultData| {
                             view! { <ResultCard result=res /> }
@@ -3,11 +3,14 @@ use leptos_meta::*;
 use leptos_router::components::{Outlet, ParentRoute, Route, Router, Routes};
 
 use leptos_router::path;
+use types::election::Election;
 
 use crate::components::election_header::ElectionHeader;
+use crate::pages::explorer::ExplorerPage;
 use crate::pages::home::HomePage;
 use crate::pages::results::ResultsPage;
 use crate::pages::submit::SubmissionPage;
+use crate::pages::verify::VerifyPage;
 
 mod api;
 mod components;
@@ -17,6 +20,8 @@ mod pages;
 pub struct AppState {
     pub result_type: RwSignal<String>,
     pub positions: LocalResource<Result<Vec<String>, String>>,
+    /// Registered elections, the most recent first
+    pub elections: LocalResource<Result<Vec<Election>, String>>,
 }
 
 impl AppState {
@@ -24,8 +29,17 @@ impl AppState {
         Self {
             result_type,
             positions: LocalResource::new(|| crate::api::positions()),
+            elections: LocalResource::new(crate::api::elections),
         }
     }
+
+    /// The election being shown, the most recent one
+    pub fn election(&self) -> Option<Election> {
+        self.elections
+            .get()
+            .and_then(Result::ok)
+            .and_then(|elections| elections.into_iter().next())
+    }
 }
 
 #[component]
@@ -67,6 +81,10 @@ pub fn App() -> impl IntoView {
                             <Route path=path!("results") view=ResultsPage />
 
                             <Route path=path!("submit") view=SubmissionPage />
+
+                            <Route path=path!("verify") view=VerifyPage />
+
+                            <Route path=path!("explorer") view=ExplorerPage />
                         </ParentRoute>
                     </Routes>
                 </Router>
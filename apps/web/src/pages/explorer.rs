@@ -0,0 +1,294 @@
+use leptos::ev::SubmitEvent;
+use leptos::prelude::*;
+use types::explorer::{BlockSummary, Page};
+
+use crate::api;
+
+/// Blocks shown per page
+const PAGE_SIZE: i64 = 20;
+
+/// Which blocks are listed
+#[derive(Clone, Debug, PartialEq)]
+enum Listing {
+    All,
+    /// Blocks that touched a station
+    Station(i64),
+    /// Blocks signed by a key, by its hash
+    Signer(String),
+}
+
+impl Listing {
+    fn title(&self) -> String {
+        match self {
+            Listing::All => "Latest Blocks".into(),
+            Listing::Station(station) => format!("Blocks touching station {station}"),
+            Listing::Signer(signer) => format!("Blocks signed by {}", short(signer)),
+        }
+    }
+}
+
+/// The first characters of a hash, enough to tell blocks apart in a list
+fn short(hash: &str) -> String {
+    format!("{}…", hash.get(..12).unwrap_or(hash))
+}
+
+async fn list(listing: Listing, offset: i64) -> Result<Page<BlockSummary>, String> {
+    match listing {
+        Listing::All => api::blocks(offset, PAGE_SIZE).await,
+        Listing::Station(station) => api::station_history(station, offset, PAGE_SIZE).await,
+        Listing::Signer(signer) => api::signer_blocks(&signer, offset, PAGE_SIZE).await,
+    }
+}
+
+#[component]
+pub fn ExplorerPage() -> impl IntoView {
+    let listing = RwSignal::new(Listing::All);
+    let offset = RwSignal::new(0_i64);
+    // Hash or height of the block being inspected
+    let selected = RwSignal::new(None::<String>);
+
+    let search_kind = RwSignal::new("block".to_string());
+    let query = RwSignal::new(String::new());
+    let search_error = RwSignal::new(None::<String>);
+
+    let summary = LocalResource::new(api::chain_summary);
+    let blocks = LocalResource::new(move || list(listing.get(), offset.get()));
+
+    let handle_search = move |ev: SubmitEvent| {
+        ev.prevent_default();
+        let value = query.get().trim().to_string();
+        search_error.set(None);
+        offset.set(0);
+        match search_kind.get().as_str() {
+            "station" => match value.parse() {
+                Ok(station) => listing.set(Listing::Station(station)),
+                Err(_) => search_error.set(Some("A station is searched by its number".into())),
+            },
+            "signer" => listing.set(Listing::Signer(value)),
+            _ => selected.set(Some(value)),
+        }
+    };
+
+    view! {
+        <div class="flex-1 bg-background">
+            <div class="p-6 space-y-6 max-w-5xl">
+                <div>
+                    <h2 class="text-xl font-bold text-foreground mb-1">"Block Explorer"</h2>
+                    {move || match summary.get() {
+                        Some(Ok(summary)) => {
+                            view! {
+                                <p class="text-sm text-gray-600">
+                                    {format!(
+                                        "Height {} • {} signers • {} elections • {} results • {} confirmed, {} pending and {} disputed stations",
+                                        summary.height,
+                                        summary.signers,
+                                        summary.elections,
+                                        summary.results,
+                                        summary.stations.confirmed,
+                                        summary.stations.pending,
+                                        summary.stations.disputed,
+                                    )}
+                                </p>
+                                <p class="text-xs font-mono text-gray-500 break-all">
+                                    {format!("Genesis: {}", summary.genesis_hash)}
+                                </p>
+                            }
+                                .into_any()
+                        }
+                        Some(Err(e)) => view! { <p class="text-sm text-red-700">{e}</p> }.into_any(),
+                        None => view! { <p class="text-sm text-gray-600">"Loading..."</p> }.into_any(),
+                    }}
+                </div>
+                <form on:submit=handle_search class="flex gap-2 items-end">
+                    <select
+                        prop:value=move || search_kind.get()
+                        on:change=move |ev| search_kind.set(event_target_value(&ev))
+                        class="px-4 py-2 rounded-lg border border-gray-300 bg-white text-gray-900"
+                    >
+                        <option value="block">"Block hash or height"</option>
+                        <option value="station">"Station"</option>
+                        <option value="signer">"Signer key hash"</option>
+                    </select>
+                    <input
+                        type="text"
+                        prop:value=move || query.get()
+                        on:input=move |ev| query.set(event_target_value(&ev))
+                        required
+                        class="flex-1 px-4 py-2 rounded-lg border border-gray-300 bg-white text-gray-900 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent"
+                    />
+                    <button
+                        type="submit"
+                        class="px-4 py-2 rounded-lg bg-green-900 text-white hover:bg-secondary/90"
+                    >
+                        "Search"
+                    </button>
+                    <button
+                        type="button"
+                        on:click=move |_| {
+                            listing.set(Listing::All);
+                            offset.set(0);
+                            selected.set(None);
+                        }
+                        class="px-4 py-2 rounded-lg border border-gray-300 text-gray-900 hover:bg-muted"
+                    >
+                        "Latest"
+                    </button>
+                </form>
+                {move || search_error.get().map(|e| view! { <p class="text-sm text-red-700">{e}</p> })}
+                {move || selected.get().map(|id| view! { <BlockDetails id /> })}
+                <div>
+                    <h3 class="text-lg font-semibold text-foreground mb-2">
+                        {move || listing.get().title()}
+                    </h3>
+                    {move || match blocks.get() {
+                        Some(Ok(page)) => {
+                            let has_more = page.has_more();
+                            view! {
+                                <BlockList blocks=page.items selected />
+                                <div class="flex gap-2 items-center mt-2 text-sm text-gray-600">
+                                    <button
+                                        disabled=move || offset.get() == 0
+                                        on:click=move |_| offset.update(|o| *o = (*o - PAGE_SIZE).max(0))
+                                        class="px-3 py-1 rounded border border-border hover:bg-muted disabled:opacity-50"
+                                    >
+                                        "Newer"
+                                    </button>
+                                    <button
+                                        disabled=!has_more
+                                        on:click=move |_| offset.update(|o| *o += PAGE_SIZE)
+                                        class="px-3 py-1 rounded border border-border hover:bg-muted disabled:opacity-50"
+                                    >
+                                        "Older"
+                                    </button>
+                                    <span>{format!("{} blocks", page.total)}</span>
+                                </div>
+                            }
+                                .into_any()
+                        }
+                        Some(Err(e)) => view! { <p class="text-sm text-red-700">{e}</p> }.into_any(),
+                        None => view! { <p class="text-sm text-gray-600">"Loading..."</p> }.into_any(),
+                    }}
+                </div>
+            </div>
+        </div>
+    }
+}
+
+#[component]
+fn BlockList(blocks: Vec<BlockSummary>, selected: RwSignal<Option<String>>) -> impl IntoView {
+    view! {
+        <table class="w-full text-sm text-left text-gray-900">
+            <thead class="text-xs uppercase text-gray-600 border-b border-border">
+                <tr>
+                    <th class="py-2">"Height"</th>
+                    <th>"Hash"</th>
+                    <th>"Type"</th>
+                    <th>"Transactions"</th>
+                    <th>"Signer"</th>
+                    <th>"Time"</th>
+                </tr>
+            </thead>
+            <tbody>
+                {blocks
+                    .into_iter()
+                    .map(|block| {
+                        let hash = block.hash.clone();
+                        view! {
+                            <tr
+                                class="border-b border-border hover:bg-muted cursor-pointer"
+                                on:click=move |_| selected.set(Some(hash.clone()))
+                            >
+                                <td class="py-2">{block.height}</td>
+                                <td class="font-mono">{short(&block.hash)}</td>
+                                <td>{block.kind}</td>
+                                <td>{block.transactions}</td>
+                                <td class="font-mono">{short(&block.signer)}</td>
+                                <td>{block.timestamp.format("%Y-%m-%d %H:%M:%S").to_string()}</td>
+                            </tr>
+                        }
+                    })
+                    .collect::<Vec<_>>()}
+            </tbody>
+        </table>
+    }
+}
+
+/// The header of a block and the results it recorded
+#[component]
+fn BlockDetails(id: String) -> impl IntoView {
+    let details = LocalResource::new(move || {
+        let id = id.clone();
+        async move {
+            let block = api::block(&id).await?;
+            let results = api::block_results(&block.hash).await?;
+            Ok::<_, String>((block, results))
+        }
+    });
+
+    view! {
+        <div class="rounded-lg border border-border bg-card p-4 space-y-2 text-sm text-gray-900">
+            {move || match details.get() {
+                None => view! { <p class="text-gray-600">"Loading..."</p> }.into_any(),
+                Some(Err(e)) => view! { <p class="text-red-700">{e}</p> }.into_any(),
+                Some(Ok((block, results))) => {
+                    view! {
+                        <h3 class="text-lg font-semibold">
+                            {format!("Block {} • {}", block.height, block.inner.kind())}
+                        </h3>
+                        <p class="font-mono break-all">{format!("Hash: {}", block.hash)}</p>
+                        <p class="font-mono break-all">{format!("Previous: {}", block.prev_hash)}</p>
+                        <p class="font-mono break-all">
+                            {format!("Signer: {}", block.signature_pub_key_hash)}
+                        </p>
+                        <p>
+                            {format!(
+                                "{} transactions • {} • {}",
+                                block.inner.transaction_count(),
+                                block.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                                if block.certificate.is_some() { "committed" } else { "uncommitted" },
+                            )}
+                        </p>
+                        {(!results.is_empty())
+                            .then(|| {
+                                view! {
+                                    <table class="w-full text-left">
+                                        <thead class="text-xs uppercase text-gray-600 border-b border-border">
+                                            <tr>
+                                                <th class="py-1">"Station"</th>
+                                                <th>"Candidate"</th>
+                                                <th>"Votes"</th>
+                                                <th>"Organisation"</th>
+                                                <th>"Status"</th>
+                                            </tr>
+                                        </thead>
+                                        <tbody>
+                                            {results
+                                                .into_iter()
+                                                .map(|result| {
+                                                    view! {
+                                                        <tr class="border-b border-border">
+                                                            <td class="py-1">{result.station_id}</td>
+                                                            <td>{result.candidate_id}</td>
+                                                            <td>{result.votes}</td>
+                                                            <td>{result.organisation}</td>
+                                                            <td>
+                                                                {result
+                                                                    .superseded_by
+                                                                    .map(|height| format!("Corrected at {height}"))
+                                                                    .unwrap_or_else(|| "Current".into())}
+                                                            </td>
+                                                        </tr>
+                                                    }
+                                                })
+                                                .collect::<Vec<_>>()}
+                                        </tbody>
+                                    </table>
+                                }
+                            })}
+                    }
+                        .into_any()
+                }
+            }}
+        </div>
+    }
+}
@@ -1,3 +1,5 @@
+pub mod explorer;
 pub mod home;
 pub mod results;
 pub mod submit;
+pub mod verify;
@@ -1,7 +1,7 @@
 use leptos::ev::SubmitEvent;
 use leptos::prelude::*;
 use std::collections::HashMap;
-use types::CandidateResult;
+use types::{CandidateResult, agents::Submission, crypto::signing_key_from_hex};
 
 use crate::components::election_map::ElectionMap;
 use crate::{AppState, api};
@@ -62,6 +62,9 @@ pub fn SubmissionForm() -> impl IntoView {
     let form_data = RwSignal::new(FormData::default());
     let candidate_votes = RwSignal::new(HashMap::<u32, String>::new());
     let submitted = RwSignal::new(false);
+    // Hex secret of the agent key, results are signed in the browser
+    let agent_key = RwSignal::new(String::new());
+    let error = RwSignal::new(None::<String>);
 
     // Signals to track selected IDs for API calls
     let selected_county_id = RwSignal::new(String::new());
@@ -106,20 +109,24 @@ pub fn SubmissionForm() -> impl IntoView {
         let ward_id = selected_ward_id.get();
         let constituency_id = selected_constituency_id.get();
         let county_id = selected_county_id.get();
+        let election = app_state.election().map(|e| e.id);
         async move {
+            let Some(election) = election else {
+                return Ok(vec![]);
+            };
             match form_data.get().result_type.as_str() {
-                "Mca" if !ward_id.is_empty() => crate::api::candidates("Mca", &ward_id).await,
+                "Mca" if !ward_id.is_empty() => crate::api::candidates(election, "Mca", &ward_id).await,
                 "Mp" if !constituency_id.is_empty() => {
-                    crate::api::candidates("Mp", &constituency_id).await
+                    crate::api::candidates(election, "Mp", &constituency_id).await
                 },
                 "Senator" if !county_id.is_empty() => {
-                    crate::api::candidates("Senator", &county_id).await
+                    crate::api::candidates(election, "Senator", &county_id).await
                 }
                 "Governor" if !county_id.is_empty() => {
-                    crate::api::candidates("Governor", &county_id).await
+                    crate::api::candidates(election, "Governor", &county_id).await
                 }
                 "WomenRep" if !constituency_id.is_empty() => {  
-                    crate::api::candidates("WomenRep", &constituency_id).await
+                    crate::api::candidates(election, "WomenRep", &constituency_id).await
                 }
                 _ => Ok(vec![]),
             }
@@ -132,6 +139,9 @@ pub fn SubmissionForm() -> impl IntoView {
 
         let data = form_data.get();
         let votes = candidate_votes.get();
+        let Some(election) = app_state.election() else {
+            return;
+        };
 
         // Build submission data
         let results = c
@@ -145,15 +155,25 @@ pub fn SubmissionForm() -> impl IntoView {
                     .and_then(|v| v.parse::<u32>().ok())
                     .unwrap_or(0);
                 CandidateResult {
+                    election_id: election.id,
                     station_id: data.station.parse().unwrap(),
                     votes: vote_count as i64,
                     candidate_id: c.id as i64,
                 }
             })
             .collect::<Vec<_>>();
+        let Some(key) = signing_key_from_hex(&agent_key.get()) else {
+            error.set(Some("Invalid agent key".into()));
+            return;
+        };
+        let submission = Submission::new(&key, results, vec![], vec![]);
 
         leptos::task::spawn_local(async move {
-            api::submit(results).await.unwrap();
+            if let Err(e) = api::submit(submission).await {
+                error.set(Some(e));
+                return;
+            }
+            error.set(None);
             submitted.set(true);
             set_timeout(
                 move || {
@@ -412,6 +432,23 @@ pub fn SubmissionForm() -> impl IntoView {
                     </div>
                 </div>
 
+                <div>
+                    <label for="agentKey" class="block text-sm font-semibold text-gray-900 mb-2">
+                        "Agent Key"
+                    </label>
+                    <input
+                        id="agentKey"
+                        type="password"
+                        placeholder="Secret printed by `agents new-key`"
+                        prop:value=move || agent_key.get()
+                        on:input=move |ev| agent_key.set(event_target_value(&ev))
+                        required
+                        class="w-full px-4 py-2 rounded-lg border border-gray-300 bg-white text-gray-900 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent"
+                    />
+                </div>
+
+                {move || error.get().map(|e| view! { <p class="text-sm text-red-700">{e}</p> })}
+
                 // Submit Button
                 <button
                     type="submit"
@@ -0,0 +1,176 @@
+use leptos::ev::SubmitEvent;
+use leptos::prelude::*;
+use types::{SignedHeader, proof::InclusionProof};
+
+use crate::{AppState, api};
+
+/// Headers fetched per request, the most the API serves at once
+const HEADER_BATCH: usize = 500;
+
+/// What the browser established about a result, independently of the node
+#[derive(Clone, Debug)]
+struct Verified {
+    proof: InclusionProof,
+    genesis_hash: String,
+    signer_hash: String,
+    /// Validators that committed the block, of those replayed from genesis
+    signers: usize,
+    validators: usize,
+}
+
+/// Checks that `headers` link up from genesis and end at the block the proof
+/// is anchored in
+fn check_headers(headers: &[SignedHeader], proof: &InclusionProof) -> Result<(), String> {
+    for (height, header) in headers.iter().enumerate() {
+        if header.height != height {
+            return Err(format!("Header {height} is missing"));
+        }
+        if !header.hash_matches() {
+            return Err(format!("Header {height} does not hash to {}", header.hash));
+        }
+        if height > 0 && header.prev_hash != headers[height - 1].hash {
+            return Err(format!("Header {height} does not link to the previous block"));
+        }
+    }
+    match headers.get(proof.header.height) {
+        Some(header) if header.hash == proof.header.hash => Ok(()),
+        _ => Err("The proof is not anchored in this chain".into()),
+    }
+}
+
+async fn verify(election_id: i64, station: i64, candidate: i64) -> Result<Verified, String> {
+    let proof = api::proof(election_id, station, candidate).await?;
+    proof.verify().map_err(|e| e.to_string())?;
+
+    let mut headers = Vec::new();
+    while headers.len() <= proof.header.height {
+        let batch = api::headers(headers.len(), HEADER_BATCH).await?;
+        if batch.is_empty() {
+            break;
+        }
+        headers.extend(batch);
+    }
+    check_headers(&headers, &proof)?;
+    let validators = proof.validators(&headers).map_err(|e| e.to_string())?;
+    let signers = proof
+        .verify_certificate(&validators)
+        .map_err(|e| e.to_string())?;
+
+    Ok(Verified {
+        signers,
+        validators: validators.len(),
+        genesis_hash: headers[0].hash.clone(),
+        signer_hash: proof.header.signature_pub_key_hash.clone(),
+        proof,
+    })
+}
+
+#[component]
+pub fn VerifyPage() -> impl IntoView {
+    let app_state = use_context::<AppState>().unwrap();
+
+    let station = RwSignal::new(String::new());
+    let candidate = RwSignal::new(String::new());
+    let outcome = RwSignal::new(None::<Result<Verified, String>>);
+
+    let handle_submit = move |ev: SubmitEvent| {
+        ev.prevent_default();
+        let Some(election) = app_state.election() else {
+            return;
+        };
+        let (Ok(station), Ok(candidate)) = (station.get().parse(), candidate.get().parse()) else {
+            outcome.set(Some(Err("Station and candidate must be numbers".into())));
+            return;
+        };
+        outcome.set(None);
+        leptos::task::spawn_local(async move {
+            outcome.set(Some(verify(election.id, station, candidate).await));
+        });
+    };
+
+    view! {
+        <div class="flex-1 bg-background">
+            <div class="p-6 space-y-4 max-w-3xl">
+                <div class="mb-6">
+                    <h2 class="text-xl font-bold text-foreground mb-1">"Verify a Result"</h2>
+                    <p class="text-sm text-gray-600">
+                        "The proof, the chain headers and the validators' commit signatures are checked in your browser, so you only have to trust the genesis hash, not the node serving this page"
+                    </p>
+                </div>
+                <form on:submit=handle_submit class="flex gap-2 items-end">
+                    <div>
+                        <label for="station" class="block text-sm font-semibold text-gray-900 mb-2">
+                            "Station"
+                        </label>
+                        <input
+                            id="station"
+                            type="number"
+                            prop:value=move || station.get()
+                            on:input=move |ev| station.set(event_target_value(&ev))
+                            required
+                            class="w-full px-4 py-2 rounded-lg border border-gray-300 bg-white text-gray-900 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent"
+                        />
+                    </div>
+                    <div>
+                        <label for="candidate" class="block text-sm font-semibold text-gray-900 mb-2">
+                            "Candidate"
+                        </label>
+                        <input
+                            id="candidate"
+                            type="number"
+                            prop:value=move || candidate.get()
+                            on:input=move |ev| candidate.set(event_target_value(&ev))
+                            required
+                            class="w-full px-4 py-2 rounded-lg border border-gray-300 bg-white text-gray-900 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:border-transparent"
+                        />
+                    </div>
+                    <button
+                        type="submit"
+                        class="px-4 py-2 rounded-lg bg-green-900 text-white hover:bg-secondary/90"
+                    >
+                        "Verify"
+                    </button>
+                </form>
+                {move || match outcome.get() {
+                    None => ().into_any(),
+                    Some(Err(e)) => {
+                        view! {
+                            <p class="text-sm text-red-700">{format!("Verification failed: {e}")}</p>
+                        }
+                            .into_any()
+                    }
+                    Some(Ok(verified)) => {
+                        view! {
+                            <div class="space-y-2 text-sm text-gray-900">
+                                <p class="font-semibold text-green-900">
+                                    {format!(
+                                        "{} votes for candidate {} at station {} are in block {}",
+                                        verified.proof.result.votes,
+                                        verified.proof.result.candidate_id,
+                                        verified.proof.result.station_id,
+                                        verified.proof.header.height,
+                                    )}
+                                </p>
+                                <p>
+                                    {format!(
+                                        "Committed by {} of the {} validators registered since genesis",
+                                        verified.signers,
+                                        verified.validators,
+                                    )}
+                                </p>
+                                <p>"Compare these with a source you trust:"</p>
+                                <p class="font-mono break-all">
+                                    {format!("Genesis: {}", verified.genesis_hash)}
+                                </p>
+                                <p class="font-mono break-all">
+                                    {format!("Signer: {}", verified.signer_hash)}
+                                </p>
+                            </div>
+                        }
+                            .into_any()
+                    }
+                }}
+            </div>
+        </div>
+    }
+}
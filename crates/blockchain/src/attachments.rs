@@ -0,0 +1,247 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+use types::{
+    crypto::attachment_digest,
+    error::ChainError,
+    p2p::{Capabilities, Misbehaviour, P2PMessage},
+};
+
+use crate::{BlockChain, SecureStream};
+
+/// Room left in a message for everything but the attachment itself
+const MESSAGE_OVERHEAD: usize = 1024;
+
+/// How long an uploaded attachment may wait for a block referencing it
+/// before it is deleted
+pub const UNREFERENCED_ATTACHMENT_TTL: Duration = Duration::from_secs(60 * 60);
+
+impl BlockChain {
+    /// Attachments referenced on chain that are not in our store yet, oldest first
+    pub async fn missing_attachments(&self) -> Result<Vec<String>, ChainError> {
+        let mut missing = Vec::new();
+        for hash in self.db.attachment_hashes().await? {
+            if !self.db.attachments.contains(&hash).await? {
+                missing.push(hash);
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Asks peers serving attachments for the ones we are missing, spreading
+    /// the requests over them. Unanswered requests are repeated next round.
+    pub(crate) async fn request_missing_attachments(&self) -> Result<(), ChainError> {
+        let peers: Vec<SocketAddr> = self
+            .peers
+            .read()
+            .await
+            .values()
+            .filter(|peer| peer.capabilities.contains(Capabilities::ATTACHMENTS))
+            .map(|peer| peer.addr)
+            .collect();
+        if peers.is_empty() {
+            return Ok(());
+        }
+        let missing = self.missing_attachments().await?;
+        for (i, hash) in missing
+            .into_iter()
+            .take(self.config.sync_batch_size as usize)
+            .enumerate()
+        {
+            self.send_to_peer(
+                peers[i % peers.len()],
+                P2PMessage::AttachmentRequest { hash },
+            )
+            .await;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn handle_attachment_request(
+        &self,
+        hash: String,
+        stream: &mut SecureStream,
+    ) -> Result<(), ChainError> {
+        // Blobs the peer could not receive are not offered at all
+        let data = self
+            .db
+            .attachments
+            .get(&hash)
+            .await?
+            .filter(|data| data.len() + MESSAGE_OVERHEAD <= self.config.max_message_size);
+        let response = P2PMessage::AttachmentResponse { hash, data };
+        self.send_message(stream, &response).await
+    }
+
+    pub(crate) async fn handle_attachment_response(
+        &self,
+        hash: String,
+        data: Option<Vec<u8>>,
+        peer_addr: SocketAddr,
+        stream: &mut SecureStream,
+    ) -> Result<(), ChainError> {
+        let Some(data) = data else {
+            log::debug!("Peer {peer_addr} does not have attachment {hash}");
+            return Ok(());
+        };
+        if attachment_digest(&data) != hash {
+            return self
+                .penalize(
+                    peer_addr,
+                    &stream.remote_node_id(),
+                    Misbehaviour::InvalidAttachment,
+                )
+                .await;
+        }
+        self.store_attachment(&data).await?;
+        Ok(())
+    }
+
+    /// Stores an attachment referenced on chain, returning its digest. Blobs
+    /// nobody referenced are dropped, so peers cannot fill up the store.
+    pub async fn store_attachment(&self, data: &[u8]) -> Result<Option<String>, ChainError> {
+        let hash = attachment_digest(data);
+        if !self.db.has_attachment(&hash).await? {
+            return Ok(None);
+        }
+        Ok(Some(self.db.attachments.put(data).await?))
+    }
+
+    /// Deletes blobs no block on the chain references that were stored more
+    /// than `ttl` ago, returning how many were deleted
+    pub async fn collect_attachments(&self, ttl: Duration) -> Result<usize, ChainError> {
+        let now = SystemTime::now();
+        let mut removed = 0;
+        for (hash, stored) in self.db.attachments.list().await? {
+            let expired = now.duration_since(stored).is_ok_and(|age| age >= ttl);
+            if expired && !self.db.has_attachment(&hash).await? {
+                self.db.attachments.remove(&hash).await?;
+                removed += 1;
+            }
+        }
+        if removed > 0 {
+            log::info!("🧹 Deleted {removed} attachments no block references");
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use types::{BlockType, validation::BlockRejection};
+
+    #[tokio::test]
+    async fn test_attachments_are_committed_by_digest() {
+        let mut chain = election_chain().await;
+        let key = register_agent(&mut chain, "Juja Observers").await;
+        let form = b"%PDF form 34A, Athi Primary School".to_vec();
+        let digest = types::crypto::attachment_digest(&form);
+        let tally = |attachments| {
+            tally(
+                &key,
+                vec![result(1, 66), result(2, 21)],
+                vec![],
+                attachments,
+            )
+        };
+
+        assert!(matches!(
+            propose(&mut chain, tally(vec!["../../etc/passwd".to_string()])).await,
+            Err(ChainError::BlockRejected(
+                BlockRejection::InvalidAttachment(_)
+            ))
+        ));
+        propose(&mut chain, tally(vec![digest.clone()]))
+            .await
+            .unwrap();
+
+        // Only blobs referenced on chain are taken from peers
+        assert_eq!(
+            chain.missing_attachments().await.unwrap(),
+            vec![digest.clone()]
+        );
+        assert_eq!(chain.store_attachment(b"spam").await.unwrap(), None);
+        assert_eq!(
+            chain.store_attachment(&form).await.unwrap(),
+            Some(digest.clone())
+        );
+        assert!(chain.missing_attachments().await.unwrap().is_empty());
+        assert_eq!(chain.attachments.get(&digest).await.unwrap(), Some(form));
+    }
+
+    #[tokio::test]
+    async fn test_uploads_need_an_agent_and_expire_unreferenced() {
+        use types::agents::{Agent, AgentSignature};
+
+        let mut chain = election_chain().await;
+        let key = register_agent(&mut chain, "Juja Observers").await;
+        let form = b"%PDF form 34A, Athi Primary School".to_vec();
+        let digest = types::crypto::attachment_digest(&form);
+
+        let stranger = types::crypto::get_private_key();
+        for signed_by in [
+            AgentSignature::sign(&stranger, &digest),
+            AgentSignature::sign(&key, &"another digest"),
+        ] {
+            assert!(
+                chain
+                    .validator()
+                    .authorise_upload(&signed_by, &digest)
+                    .await
+                    .is_err()
+            );
+        }
+        let signed_by = AgentSignature::sign(&key, &digest);
+        chain
+            .validator()
+            .authorise_upload(&signed_by, &digest)
+            .await
+            .unwrap();
+
+        // Blobs no block references are deleted once they are old enough
+        chain.attachments.put(&form).await.unwrap();
+        let spam = chain.attachments.put(b"spam").await.unwrap();
+        propose(
+            &mut chain,
+            tally(
+                &key,
+                vec![result(1, 66), result(2, 21)],
+                vec![],
+                vec![digest.clone()],
+            ),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            chain
+                .collect_attachments(Duration::from_secs(60))
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(chain.collect_attachments(Duration::ZERO).await.unwrap(), 1);
+        assert!(!chain.attachments.contains(&spam).await.unwrap());
+        assert!(chain.attachments.contains(&digest).await.unwrap());
+
+        // Revoked agents can no longer upload
+        let revoked = Agent {
+            pub_key: bincode::serialize(&types::crypto::get_public_key(&key)).unwrap(),
+            organisation: "Juja Observers".into(),
+            scope: vec![],
+        };
+        propose(&mut chain, BlockType::Agents(vec![revoked]))
+            .await
+            .unwrap();
+        assert!(
+            chain
+                .validator()
+                .authorise_upload(&signed_by, &digest)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::net::SocketAddr;
+
+use tokio::net::TcpStream;
+use types::{
+    Block,
+    error::ChainError,
+    p2p::{P2PMessage, PROTOCOL_VERSION},
+};
+
+use crate::{BlockChain, BlockValidator, SecureStream};
+
+impl BlockChain {
+    /// Downloads the genesis block from `addr` and replays the gazette records
+    /// and validator keys it carries, unless we already have it. Nothing signs
+    /// the genesis block, so it must hash to the `genesis_hash` the operator
+    /// trusts.
+    pub async fn bootstrap_from_peer(
+        &mut self,
+        addr: SocketAddr,
+        genesis_hash: &str,
+    ) -> Result<(), ChainError> {
+        match self.db.get_block_by_height(0).await {
+            Ok(genesis) if genesis.hash == genesis_hash => return Ok(()),
+            Ok(genesis) => {
+                return Err(ChainError::Other(format!(
+                    "Database holds genesis block {}, expected {genesis_hash}",
+                    genesis.hash
+                )));
+            }
+            Err(sqlx::Error::RowNotFound) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        log::info!("🌱 Fetching genesis block {genesis_hash} from {addr}");
+        let genesis = self.fetch_genesis(addr).await?;
+        if genesis.hash != genesis_hash {
+            return Err(ChainError::PeerError(format!(
+                "Peer {addr} sent genesis block {}, expected {genesis_hash}",
+                genesis.hash
+            )));
+        }
+        BlockValidator::validate_genesis(&genesis)?;
+        self.db.bootstrap(&genesis).await?;
+        log::info!("🌱 Loaded reference data from genesis block {genesis_hash}");
+        Ok(())
+    }
+
+    /// Asks `addr` for block 0 over a short lived connection
+    async fn fetch_genesis(&self, addr: SocketAddr) -> Result<Block, ChainError> {
+        tokio::time::timeout(self.config.connection_timeout, async {
+            let stream = TcpStream::connect(addr).await?;
+            let mut stream = SecureStream::connect(stream, &self.node_key).await?;
+            let hello = P2PMessage::Hello {
+                node_id: self.node_id.clone(),
+                version: PROTOCOL_VERSION,
+                // We have no blocks yet
+                chain_height: -1,
+                capabilities: self.capabilities(),
+            };
+            self.send_message(&mut stream, &hello).await?;
+            let request = P2PMessage::GetBlocks {
+                start_height: 0,
+                count: 1,
+            };
+            self.send_message(&mut stream, &request).await?;
+
+            loop {
+                match self.read_message(&mut stream).await? {
+                    P2PMessage::BlocksResponse { blocks } => {
+                        return blocks.into_iter().next().ok_or_else(|| {
+                            ChainError::PeerError(format!("Peer {addr} has no genesis block"))
+                        });
+                    }
+                    P2PMessage::HelloResponse {
+                        accepted: false, ..
+                    }
+                    | P2PMessage::Disconnect { .. } => {
+                        return Err(ChainError::PeerError(format!(
+                            "Peer {addr} refused the connection"
+                        )));
+                    }
+                    _ => {}
+                }
+            }
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::Database;
+    use tokio::net::TcpListener;
+    use types::{events::ChainEvent, p2p::Capabilities};
+
+    use crate::testing::*;
+
+    #[tokio::test]
+    async fn test_observer_bootstraps_from_genesis() {
+        let source = single_validator_chain().await;
+        let genesis = source.last_block().await.unwrap();
+        let signer = source.db.get_private_key().await.unwrap();
+        let gazette = source.export_reference_data().await.unwrap();
+        let mut events = source.subscribe_events();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer_addr) = listener.accept().await.unwrap();
+                let mut source = source.clone();
+                tokio::spawn(async move {
+                    let stream = SecureStream::accept(stream, &source.node_key)
+                        .await
+                        .unwrap();
+                    let _ = source.handle_peer_connection(stream, peer_addr).await;
+                });
+            }
+        });
+
+        let db = Database::new_in_memory();
+        sqlx::query(database::MAIN_SETUP)
+            .execute(&db.chain_db)
+            .await
+            .unwrap();
+        let mut observer = BlockChain::new(db, None).read_only();
+
+        let wrong_hash = "00".repeat(32);
+        assert!(
+            observer
+                .bootstrap_from_peer(addr, &wrong_hash)
+                .await
+                .is_err()
+        );
+        assert!(observer.get_block_by_height(0).await.is_err());
+
+        observer
+            .bootstrap_from_peer(addr, &genesis.hash)
+            .await
+            .unwrap();
+        assert_eq!(observer.last_block().await.unwrap().hash, genesis.hash);
+        assert_eq!(observer.positions().await.unwrap(), vec!["Mp".to_string()]);
+        assert_eq!(observer.export_reference_data().await.unwrap(), gazette);
+        // Subscribers learn how many peers there are, not who they are
+        assert_eq!(
+            events.recv().await.unwrap(),
+            ChainEvent::PeerConnected { peers: 1 }
+        );
+        // Bootstrapping again is a no-op
+        observer
+            .bootstrap_from_peer(addr, &genesis.hash)
+            .await
+            .unwrap();
+
+        // The observer never proposes, even with the validator key
+        let block = Block::new(&signer, &genesis.hash, vec![], 1, [0u8; 32]);
+        assert!(matches!(
+            observer.propose_block(block).await,
+            Err(ChainError::ReadOnly)
+        ));
+        assert!(observer.capabilities().contains(Capabilities::OBSERVER));
+    }
+}
@@ -0,0 +1,715 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+};
+
+use types::{
+    Block, BlockSigner,
+    consensus::{CommitCertificate, ValidatorSignature, Vote, VotePhase, quorum},
+    error::ChainError,
+    p2p::P2PMessage,
+};
+
+use crate::BlockChain;
+
+/// Signatures by validator hash, for one block hash or for nil
+type Votes = HashMap<Option<String>, HashMap<String, String>>;
+
+/// Proposals and votes collected for the height currently being decided
+#[derive(Debug, Default)]
+pub struct RoundState {
+    pub height: usize,
+    /// The round being voted in, moved on when it times out or splits
+    pub round: u32,
+    proposals: HashMap<String, Block>,
+    /// Hashes of `proposals` in the order they arrived
+    arrival: Vec<String>,
+    votes: HashMap<(u32, VotePhase), Votes>,
+    /// Last rounds we prevoted and precommitted in
+    prevoted: Option<u32>,
+    precommitted: Option<u32>,
+    /// The block we last precommitted, prevoted in later rounds until
+    /// another block gets a polka
+    locked: Option<String>,
+    /// Round the timeout is running for
+    timer: Option<u32>,
+    committed: bool,
+}
+
+impl RoundState {
+    pub fn new(height: usize) -> Self {
+        Self {
+            height,
+            ..Default::default()
+        }
+    }
+
+    /// Returns `false` if the proposal was already known
+    pub fn add_proposal(&mut self, block: Block) -> bool {
+        if self.proposals.contains_key(&block.hash) {
+            return false;
+        }
+        self.arrival.push(block.hash.clone());
+        self.proposals.insert(block.hash.clone(), block);
+        true
+    }
+
+    /// Returns `false` if the vote was already counted
+    pub fn add_vote(&mut self, vote: &Vote) -> bool {
+        self.votes
+            .entry((vote.round, vote.phase))
+            .or_default()
+            .entry(vote.block_hash.clone())
+            .or_default()
+            .insert(vote.validator.clone(), vote.signature.clone())
+            .is_none()
+    }
+
+    /// Distinct validators that voted in `round`, for a block or nil
+    fn voters(&self, round: u32, phases: &[VotePhase]) -> usize {
+        phases
+            .iter()
+            .filter_map(|phase| self.votes.get(&(round, *phase)))
+            .flat_map(|votes| votes.values().flat_map(|by| by.keys()))
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// The proposed block that more than 2/3 of the validators prevoted for in `round`
+    pub fn polka(&self, round: u32, quorum: usize) -> Option<String> {
+        self.votes
+            .get(&(round, VotePhase::Prevote))?
+            .iter()
+            .find(|(hash, votes)| {
+                votes.len() >= quorum
+                    && hash
+                        .as_ref()
+                        .is_some_and(|hash| self.proposals.contains_key(hash))
+            })
+            .and_then(|(hash, _)| hash.clone())
+    }
+
+    /// Whether `round` can no longer see a polka: a quorum prevoted nil, or
+    /// every validator prevoted without enough agreeing on a block
+    fn polka_failed(&self, round: u32, validator_count: usize) -> bool {
+        let quorum = quorum(validator_count);
+        let nil = self
+            .votes
+            .get(&(round, VotePhase::Prevote))
+            .and_then(|by_hash| by_hash.get(&None))
+            .map_or(0, |votes| votes.len());
+        self.polka(round, quorum).is_none()
+            && (nil >= quorum || self.voters(round, &[VotePhase::Prevote]) >= validator_count)
+    }
+
+    /// The block and its certificate once precommits of a round reach a quorum
+    pub fn commit(&self, quorum: usize) -> Option<(Block, CommitCertificate)> {
+        let ((round, _), hash, votes) = self
+            .votes
+            .iter()
+            .filter(|((_, phase), _)| *phase == VotePhase::Precommit)
+            .flat_map(|(key, by_hash)| {
+                by_hash
+                    .iter()
+                    .filter_map(move |(hash, votes)| Some((key, hash.as_ref()?, votes)))
+            })
+            .find(|(_, hash, votes)| votes.len() >= quorum && self.proposals.contains_key(*hash))?;
+
+        let mut signatures: Vec<ValidatorSignature> = votes
+            .iter()
+            .map(|(validator, signature)| ValidatorSignature {
+                validator: validator.clone(),
+                signature: signature.clone(),
+            })
+            .collect();
+        signatures.sort_by(|a, b| a.validator.cmp(&b.validator));
+
+        let certificate = CommitCertificate {
+            height: self.height,
+            round: *round,
+            block_hash: hash.clone(),
+            signatures,
+        };
+        Some((self.proposals[hash].clone(), certificate))
+    }
+
+    /// The round to move to without waiting for the timeout: the next one
+    /// once a quorum precommitted in this round without agreeing on a block,
+    /// or a later one enough validators already vote in that some are honest
+    fn next_round(&self, validator_count: usize) -> Option<u32> {
+        let quorum = quorum(validator_count);
+        let split = self.voters(self.round, &[VotePhase::Precommit]) >= quorum
+            && self
+                .votes
+                .get(&(self.round, VotePhase::Precommit))
+                .is_none_or(|by_hash| {
+                    by_hash
+                        .iter()
+                        .all(|(hash, votes)| hash.is_none() || votes.len() < quorum)
+                });
+        if split {
+            return Some(self.round + 1);
+        }
+        let honest = validator_count - quorum + 1;
+        self.votes
+            .keys()
+            .map(|(round, _)| *round)
+            .filter(|round| *round > self.round)
+            .filter(|round| {
+                self.voters(*round, &[VotePhase::Prevote, VotePhase::Precommit]) >= honest
+            })
+            .max()
+    }
+
+    /// The proposal to prevote in the current round: the block we are locked
+    /// on, otherwise the one from the validator whose turn it is to propose or
+    /// from the next validator after it that proposed
+    fn prevote_target(&self, validators: &[String]) -> Option<String> {
+        if let Some(hash) = &self.locked {
+            return Some(hash.clone());
+        }
+        let n = validators.len().max(1);
+        let turn = (self.height + self.round as usize) % n;
+        self.arrival
+            .iter()
+            .min_by_key(|hash| {
+                let signer = &self.proposals[*hash].signature_pub_key_hash;
+                validators
+                    .iter()
+                    .position(|validator| validator == signer)
+                    .map_or(n, |index| (index + n - turn) % n)
+            })
+            .cloned()
+    }
+}
+
+/// The validator whose turn it is to propose in `round`, from the sorted key hashes
+fn proposer(validators: &[String], height: usize, round: u32) -> Option<&String> {
+    validators.get((height + round as usize) % validators.len().max(1))
+}
+
+impl BlockChain {
+    /// Proposes a block to the validator set. The block is only added to the
+    /// chain once more than 2/3 of the validators have precommitted it.
+    pub async fn propose_block(&mut self, block: Block) -> Result<(), ChainError> {
+        if self.read_only {
+            return Err(ChainError::ReadOnly);
+        }
+        log::info!(
+            "📝 Proposing block: {} (height: {})",
+            block.hash,
+            block.height
+        );
+        let round = {
+            let state = self.round.read().await;
+            if state.height == block.height {
+                state.round
+            } else {
+                0
+            }
+        };
+        self.handle_proposal(block, round, None).await
+    }
+
+    /// `origin` is the peer the proposal came from, `None` for our own
+    pub(crate) async fn handle_proposal(
+        &mut self,
+        block: Block,
+        round: u32,
+        origin: Option<SocketAddr>,
+    ) -> Result<(), ChainError> {
+        let tip = self.db.last_block().await?;
+        if block.height <= tip.height {
+            log::debug!(
+                "Ignoring stale proposal {} at height {} (tip: {})",
+                block.hash,
+                block.height,
+                tip.height
+            );
+            return Ok(());
+        }
+        self.validator().validate(&block, &tip).await?;
+
+        let is_new = {
+            let mut state = self.current_round(block.height).await;
+            state.height == block.height && state.add_proposal(block.clone())
+        };
+        if !is_new {
+            return Ok(());
+        }
+
+        // Relay so validators that are not directly connected to the proposer see it
+        self.relay_message(P2PMessage::Proposal { block, round }, origin)
+            .await?;
+
+        self.prevote().await?;
+        self.advance_round().await
+    }
+
+    pub(crate) async fn handle_vote(
+        &mut self,
+        vote: Vote,
+        origin: Option<SocketAddr>,
+    ) -> Result<(), ChainError> {
+        let height = self.get_chain_height().await?;
+        if vote.height as i64 != height + 1 {
+            return Ok(());
+        }
+
+        let validators = self.db.validators().await?;
+        let Some(pub_key) = validators.iter().find(|key| key.hash == vote.validator) else {
+            log::warn!("Vote from unknown validator {}", vote.validator);
+            return Ok(());
+        };
+        if !vote.verify(pub_key) {
+            log::warn!("Invalid vote signature from {}", vote.validator);
+            return Ok(());
+        }
+
+        let is_new = {
+            let mut state = self.current_round(vote.height).await;
+            state.height == vote.height && state.add_vote(&vote)
+        };
+        if !is_new {
+            return Ok(());
+        }
+
+        self.relay_message(P2PMessage::Vote(vote), origin).await?;
+        self.advance_round().await
+    }
+
+    /// Moves the round forward: precommits once a proposal has a prevote
+    /// quorum, or nil once it cannot get one, commits once the precommits reach a quorum and moves to a later
+    /// round when this one cannot commit anymore.
+    async fn advance_round(&mut self) -> Result<(), ChainError> {
+        let validators = self.validator_hashes().await?;
+        let quorum = quorum(validators.len());
+        let signer = self.local_validator().await;
+
+        loop {
+            let precommit = {
+                let mut state = self.round.write().await;
+                let round = state.round;
+                if signer.is_none() || state.precommitted == Some(round) || state.committed {
+                    None
+                } else if let Some(hash) = state.polka(round, quorum) {
+                    state.precommitted = Some(round);
+                    state.locked = Some(hash.clone());
+                    Some((state.height, round, Some(hash)))
+                } else if state.polka_failed(round, validators.len()) {
+                    state.precommitted = Some(round);
+                    Some((state.height, round, None))
+                } else {
+                    None
+                }
+            };
+            if let (Some(signer), Some((height, round, hash))) = (&signer, precommit) {
+                self.cast_vote(signer, height, round, hash.as_deref(), VotePhase::Precommit)
+                    .await?;
+            }
+
+            let commit = {
+                let mut state = self.round.write().await;
+                if state.committed {
+                    None
+                } else {
+                    let commit = state.commit(quorum);
+                    state.committed = commit.is_some();
+                    commit
+                }
+            };
+
+            if let Some((mut block, certificate)) = commit {
+                log::info!(
+                    "✅ Block {} committed in round {} with {} signatures",
+                    block.hash,
+                    certificate.round,
+                    certificate.signatures.len()
+                );
+                block.certificate = Some(certificate);
+                self.append_block(&block).await?;
+                *self.round.write().await = RoundState::new(block.height + 1);
+                // Peers that missed the votes fetch the block from the inventory
+                self.relay_blocks(vec![block.hash], None).await?;
+                return Ok(());
+            }
+
+            let next = {
+                let state = self.round.read().await;
+                if state.committed {
+                    None
+                } else {
+                    state
+                        .next_round(validators.len())
+                        .map(|round| (state.height, round))
+                }
+            };
+            match next {
+                Some((height, round)) => self.enter_round(height, round).await?,
+                None => break,
+            }
+        }
+
+        self.start_round_timer().await;
+        Ok(())
+    }
+
+    /// Starts `round` of `height`, proposing again if it is our turn and
+    /// prevoting its proposal
+    async fn enter_round(&mut self, height: usize, round: u32) -> Result<(), ChainError> {
+        {
+            let mut state = self.round.write().await;
+            if state.height != height || state.round >= round {
+                return Ok(());
+            }
+            log::info!("🔁 Moving to round {round} at height {height}");
+            state.round = round;
+        }
+        self.repropose().await?;
+        self.prevote().await
+    }
+
+    /// Prevotes the proposal of the current round, if we have not yet
+    async fn prevote(&mut self) -> Result<(), ChainError> {
+        let Some(signer) = self.local_validator().await else {
+            return Ok(());
+        };
+        let validators = self.validator_hashes().await?;
+        let prevote = {
+            let mut state = self.round.write().await;
+            if state.committed || state.prevoted == Some(state.round) {
+                None
+            } else {
+                let target = state.prevote_target(&validators);
+                if target.is_some() {
+                    state.prevoted = Some(state.round);
+                }
+                target.map(|hash| (state.height, state.round, hash))
+            }
+        };
+        if let Some((height, round, hash)) = prevote {
+            self.cast_vote(&signer, height, round, Some(&hash), VotePhase::Prevote)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Proposes the round's block again when it is our turn, for validators
+    /// that missed the first proposal
+    async fn repropose(&self) -> Result<(), ChainError> {
+        let Some(signer) = self.local_validator().await else {
+            return Ok(());
+        };
+        let validators = self.validator_hashes().await?;
+        let proposal = {
+            let state = self.round.read().await;
+            let our_turn = proposer(&validators, state.height, state.round) == Some(&signer.2.hash);
+            state
+                .prevote_target(&validators)
+                .filter(|_| our_turn)
+                .map(|hash| (state.proposals[&hash].clone(), state.round))
+        };
+        if let Some((block, round)) = proposal {
+            self.broadcast_message(P2PMessage::Proposal { block, round })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Times the current round out once it has seen a proposal or vote
+    async fn start_round_timer(&self) {
+        let (height, round) = {
+            let mut state = self.round.write().await;
+            let active = !state.proposals.is_empty() || !state.votes.is_empty();
+            if state.committed || !active || state.timer == Some(state.round) {
+                return;
+            }
+            state.timer = Some(state.round);
+            (state.height, state.round)
+        };
+        self.schedule_timeout(height, round);
+    }
+
+    /// Not async, so the spawned task is not part of the round's own future
+    fn schedule_timeout(&self, height: usize, round: u32) {
+        // Later rounds wait longer, in case the network is just slow
+        let delay = self.config.round_timeout * (round + 1);
+        let mut blockchain = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            if let Err(e) = blockchain.round_timed_out(height, round).await {
+                log::warn!("Round {round} at height {height} could not move on: {e}");
+            }
+        });
+    }
+
+    /// Votes nil for whatever we have not voted for in the round and moves
+    /// to the next round, so a crashed proposer or split vote does not halt
+    /// the height
+    async fn round_timed_out(&mut self, height: usize, round: u32) -> Result<(), ChainError> {
+        let signer = self.local_validator().await;
+        let (prevote, precommit) = {
+            let mut state = self.round.write().await;
+            if state.height != height || state.round != round || state.committed {
+                return Ok(());
+            }
+            let prevote = state.prevoted != Some(round);
+            let precommit = state.precommitted != Some(round);
+            state.prevoted = Some(round);
+            state.precommitted = Some(round);
+            (prevote, precommit)
+        };
+        log::info!("⏱️ Round {round} at height {height} timed out");
+
+        if let Some(signer) = &signer {
+            if prevote {
+                self.cast_vote(signer, height, round, None, VotePhase::Prevote)
+                    .await?;
+            }
+            if precommit {
+                self.cast_vote(signer, height, round, None, VotePhase::Precommit)
+                    .await?;
+            }
+        }
+        self.enter_round(height, round + 1).await?;
+        self.advance_round().await
+    }
+
+    async fn cast_vote(
+        &self,
+        signer: &BlockSigner,
+        height: usize,
+        round: u32,
+        block_hash: Option<&str>,
+        phase: VotePhase,
+    ) -> Result<(), ChainError> {
+        let vote = Vote::new(signer, height, round, block_hash, phase);
+        self.round.write().await.add_vote(&vote);
+        self.broadcast_message(P2PMessage::Vote(vote)).await
+    }
+
+    /// Hashes of the validators' keys, sorted to take turns proposing
+    async fn validator_hashes(&self) -> Result<Vec<String>, ChainError> {
+        let mut validators: Vec<String> = self
+            .db
+            .validators()
+            .await?
+            .into_iter()
+            .map(|key| key.hash)
+            .collect();
+        validators.sort();
+        Ok(validators)
+    }
+
+    /// Our signing key, if it belongs to the validator set and we may sign
+    async fn local_validator(&self) -> Option<BlockSigner> {
+        if self.read_only {
+            return None;
+        }
+        let signer = self.db.get_private_key().await.ok()?;
+        let validators = self.db.validators().await.ok()?;
+        validators
+            .iter()
+            .any(|key| key.hash == signer.2.hash)
+            .then_some(signer)
+    }
+
+    /// Locks the round state, starting a fresh height if `height` is ahead of it
+    async fn current_round(&self, height: usize) -> tokio::sync::RwLockWriteGuard<'_, RoundState> {
+        let mut round = self.round.write().await;
+        if round.height < height {
+            *round = RoundState::new(height);
+        }
+        round
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use types::{
+        BlockType,
+        keys::{KeyOperation, KeyTransaction},
+        validation::BlockRejection,
+    };
+
+    use crate::testing::*;
+
+    fn block(signer: &BlockSigner, height: usize) -> Block {
+        Block::new(signer, &"00".repeat(32), vec![], height, [0u8; 32])
+    }
+
+    fn signer() -> BlockSigner {
+        let key = types::crypto::get_private_key();
+        let verifying_key = types::crypto::get_public_key(&key);
+        let pub_key = types::PubKey::from_verifying_key("validator", &verifying_key);
+        (key, verifying_key, pub_key)
+    }
+
+    fn vote(signer: &BlockSigner, round: u32, hash: Option<&str>, phase: VotePhase) -> Vote {
+        Vote::new(signer, 1, round, hash, phase)
+    }
+
+    #[test]
+    fn test_nil_votes_never_commit() {
+        let signers: Vec<_> = (0..4).map(|_| signer()).collect();
+        let proposal = block(&signers[0], 1);
+        let mut state = RoundState::new(1);
+        state.add_proposal(proposal.clone());
+
+        for signer in &signers {
+            state.add_vote(&vote(signer, 0, None, VotePhase::Prevote));
+            state.add_vote(&vote(signer, 0, None, VotePhase::Precommit));
+        }
+        assert_eq!(state.polka(0, 3), None);
+        assert!(state.commit(3).is_none());
+        // Everyone precommitted without agreeing, so the next round starts
+        assert_eq!(state.next_round(4), Some(1));
+
+        for signer in &signers[..3] {
+            state.add_vote(&vote(signer, 1, Some(&proposal.hash), VotePhase::Prevote));
+            state.add_vote(&vote(signer, 1, Some(&proposal.hash), VotePhase::Precommit));
+        }
+        assert_eq!(state.polka(1, 3), Some(proposal.hash.clone()));
+        let (committed, certificate) = state.commit(3).unwrap();
+        assert_eq!(committed.hash, proposal.hash);
+        assert_eq!((certificate.round, certificate.signer_count()), (1, 3));
+        let validators: Vec<_> = signers.iter().map(|signer| signer.2.clone()).collect();
+        assert!(certificate.verify(&validators));
+    }
+
+    #[test]
+    fn test_validators_catch_up_with_later_rounds() {
+        let signers: Vec<_> = (0..4).map(|_| signer()).collect();
+        let mut state = RoundState::new(1);
+
+        // A single validator ahead could be faulty, two include an honest one
+        state.add_vote(&vote(&signers[0], 3, None, VotePhase::Prevote));
+        assert_eq!(state.next_round(4), None);
+        state.add_vote(&vote(&signers[1], 2, None, VotePhase::Precommit));
+        state.add_vote(&vote(&signers[1], 3, None, VotePhase::Precommit));
+        assert_eq!(state.next_round(4), Some(3));
+    }
+
+    #[test]
+    fn test_prevotes_follow_the_proposer_turn_and_lock() {
+        let signers: Vec<_> = (0..2).map(|_| signer()).collect();
+        let mut validators: Vec<_> = signers.iter().map(|signer| signer.2.hash.clone()).collect();
+        validators.sort();
+        let by = |hash: &str| signers.iter().find(|signer| signer.2.hash == hash).unwrap();
+        let first = block(by(&validators[1]), 1);
+        let second = block(by(&validators[0]), 1);
+
+        let mut state = RoundState::new(1);
+        state.add_proposal(first.clone());
+        state.add_proposal(second.clone());
+        // Height 1, round 0 is the turn of the second validator
+        assert_eq!(proposer(&validators, 1, 0), Some(&validators[1]));
+        assert_eq!(state.prevote_target(&validators), Some(first.hash.clone()));
+        state.round = 1;
+        assert_eq!(state.prevote_target(&validators), Some(second.hash.clone()));
+
+        // Once precommitted, a block is prevoted whoever proposes
+        state.locked = Some(first.hash.clone());
+        assert_eq!(state.prevote_target(&validators), Some(first.hash));
+    }
+
+    #[tokio::test]
+    async fn test_single_validator_commits_proposal() {
+        let mut blockchain = single_validator_chain().await;
+        let signer = blockchain.db.get_private_key().await.unwrap();
+        let genesis = blockchain.last_block().await.unwrap();
+
+        let block = Block::new(&signer, &genesis.hash, vec![], 1, [0u8; 32]);
+        blockchain.propose_block(block.clone()).await.unwrap();
+
+        let committed = blockchain.last_block().await.unwrap();
+        assert_eq!(committed.hash, block.hash);
+        let certificate = committed.certificate.unwrap();
+        assert_eq!(certificate.signer_count(), 1);
+        assert!(certificate.verify(&blockchain.validators().await.unwrap()));
+    }
+
+    /// A chain whose validators are its own key and `other`, at height 1
+    async fn two_validator_chain(other: &BlockSigner) -> BlockChain {
+        let mut chain = single_validator_chain().await;
+        let signer = chain.db.get_private_key().await.unwrap();
+        let register = KeyTransaction::new(
+            &signer,
+            KeyOperation::Register {
+                creator: "observer-1".to_string(),
+                pub_key: bincode::serialize(&other.1).unwrap(),
+            },
+        );
+        propose(&mut chain, BlockType::KeyManagement(vec![register]))
+            .await
+            .unwrap();
+        assert_eq!(chain.validators().await.unwrap().len(), 2);
+        chain
+    }
+
+    #[tokio::test]
+    async fn test_split_round_moves_to_the_next_proposer() {
+        let other = signer();
+        let mut chain = two_validator_chain(&other).await;
+        let signer = chain.db.get_private_key().await.unwrap();
+        let tip = chain.last_block().await.unwrap();
+        let block = Block::new(&signer, &tip.hash, vec![], 2, [0u8; 32]);
+        chain.propose_block(block.clone()).await.unwrap();
+        assert_eq!(chain.get_chain_height().await.unwrap(), 1);
+
+        // The other validator saw no proposal in time and votes nil
+        for phase in [VotePhase::Prevote, VotePhase::Precommit] {
+            let vote = Vote::new(&other, 2, 0, None, phase);
+            chain.handle_vote(vote, None).await.unwrap();
+        }
+        assert_eq!(chain.round.read().await.round, 1);
+        assert_eq!(chain.get_chain_height().await.unwrap(), 1);
+
+        for phase in [VotePhase::Prevote, VotePhase::Precommit] {
+            let vote = Vote::new(&other, 2, 1, Some(&block.hash), phase);
+            chain.handle_vote(vote, None).await.unwrap();
+        }
+        let committed = chain.last_block().await.unwrap();
+        assert_eq!(committed.hash, block.hash);
+        let certificate = committed.certificate.unwrap();
+        assert_eq!((certificate.round, certificate.signer_count()), (1, 2));
+        assert!(certificate.verify(&chain.validators().await.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_silent_validator_times_the_round_out() {
+        let other = signer();
+        let mut chain = two_validator_chain(&other).await;
+        chain.config.round_timeout = Duration::from_millis(20);
+        let signer = chain.db.get_private_key().await.unwrap();
+        let tip = chain.last_block().await.unwrap();
+        let block = Block::new(&signer, &tip.hash, vec![], 2, [0u8; 32]);
+        chain.propose_block(block.clone()).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let state = chain.round.read().await;
+        assert!(state.round >= 1);
+        // Having prevoted the block, we precommitted nil when the round ran out
+        let precommits = &state.votes[&(0, VotePhase::Precommit)];
+        assert!(precommits[&None].contains_key(&signer.2.hash));
+        assert_eq!(chain.get_chain_height().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_uncertified_block_is_rejected() {
+        let mut blockchain = single_validator_chain().await;
+        let signer = blockchain.db.get_private_key().await.unwrap();
+        let genesis = blockchain.last_block().await.unwrap();
+
+        let block = Block::new(&signer, &genesis.hash, vec![], 1, [0u8; 32]);
+        assert!(matches!(
+            blockchain.add_block_to_chain(block).await,
+            Err(ChainError::BlockRejected(
+                BlockRejection::InvalidCertificate
+            ))
+        ));
+        assert_eq!(blockchain.get_chain_height().await.unwrap(), 0);
+    }
+}
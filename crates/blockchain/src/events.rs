@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+
+use types::{
+    Block, CandidateResult,
+    error::ChainError,
+    events::{ChainEvent, TallyDelta},
+};
+
+use crate::BlockChain;
+
+impl BlockChain {
+    /// Tells subscribers about a change to the chain
+    pub(crate) fn emit(&self, event: ChainEvent) {
+        // Nobody listening is not an error
+        let _ = self.events.send(event);
+    }
+
+    /// Adds a committed block on top of the tip and announces it, with the
+    /// votes it moved in every area when someone is listening
+    pub(crate) async fn append_block(&mut self, block: &Block) -> Result<i64, ChainError> {
+        let subscribed = self.events.receiver_count() > 0;
+        let before = if subscribed {
+            self.current_votes(block).await?
+        } else {
+            vec![]
+        };
+        let height = self.db.add_block(block).await?;
+        let deltas = if subscribed {
+            let after = self.current_votes(block).await?;
+            self.tally_deltas(before, after).await?
+        } else {
+            vec![]
+        };
+        self.emit(ChainEvent::BlockAdded {
+            height: block.height,
+            hash: block.hash.clone(),
+            deltas,
+        });
+        Ok(height)
+    }
+
+    /// Current votes at the stations `block` adds or replaces results for
+    async fn current_votes(&self, block: &Block) -> Result<Vec<CandidateResult>, ChainError> {
+        let mut votes = vec![];
+        for (election_id, station_id) in block.inner.stations() {
+            votes.extend(self.db.station_votes(election_id, station_id).await?);
+        }
+        Ok(votes)
+    }
+
+    /// Sums the change of each candidate's votes into every area containing
+    /// the station
+    async fn tally_deltas(
+        &self,
+        before: Vec<CandidateResult>,
+        after: Vec<CandidateResult>,
+    ) -> Result<Vec<TallyDelta>, ChainError> {
+        let mut changes = BTreeMap::<(i64, i64, i64), i64>::new();
+        for (result, sign) in after
+            .iter()
+            .map(|r| (r, 1))
+            .chain(before.iter().map(|r| (r, -1)))
+        {
+            *changes
+                .entry((result.election_id, result.station_id, result.candidate_id))
+                .or_default() += sign * result.votes;
+        }
+
+        let mut deltas = vec![];
+        for ((election_id, station_id, candidate_id), votes) in changes {
+            if votes == 0 {
+                continue;
+            }
+            let Some(position_type) = self.db.candidate_position(candidate_id).await? else {
+                continue;
+            };
+            for area in self.db.station_areas(station_id).await? {
+                TallyDelta::merge(
+                    &mut deltas,
+                    election_id,
+                    area,
+                    &position_type,
+                    candidate_id,
+                    votes,
+                );
+            }
+        }
+        Ok(deltas)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::{BlockType, election::Area};
+
+    use crate::testing::*;
+
+    #[tokio::test]
+    async fn test_added_blocks_announce_tally_deltas() {
+        let mut chain = election_chain().await;
+        let mut events = chain.subscribe_events();
+        propose(
+            &mut chain,
+            BlockType::Result(vec![result(1, 60), result(2, 20)]),
+        )
+        .await
+        .unwrap();
+        // A second submission moves the averages, by half its difference
+        propose(
+            &mut chain,
+            BlockType::Result(vec![result(1, 70), result(2, 20)]),
+        )
+        .await
+        .unwrap();
+
+        let mut added = vec![];
+        while let Ok(event) = events.try_recv() {
+            if let ChainEvent::BlockAdded { height, deltas, .. } = event {
+                added.push((height, deltas));
+            }
+        }
+        assert_eq!(added.len(), 2);
+        let (height, deltas) = &added[1];
+        assert_eq!(*height, 3);
+        let delta = |area| TallyDelta {
+            election_id: ELECTION,
+            area,
+            position_type: "Mp".into(),
+            candidate_id: 1,
+            votes: 5,
+        };
+        assert_eq!(
+            deltas,
+            &[
+                Area::Station(STATION),
+                Area::Ward(563),
+                Area::Constituency(113),
+                Area::County(22),
+                Area::National,
+            ]
+            .map(delta)
+        );
+    }
+}
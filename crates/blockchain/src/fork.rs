@@ -0,0 +1,412 @@
+use std::cmp::Ordering;
+
+use types::{Block, PubKey, error::ChainError, events::ChainEvent, keys::apply_key_operation};
+
+use crate::{BlockChain, BlockValidator, RoundState};
+
+/// Applies the key operations in `block` to `validators`, the set that
+/// certified it, giving the set that certifies the next block
+fn apply_key_transactions(validators: &mut Vec<PubKey>, block: &Block) {
+    for tx in block.inner.key_transactions() {
+        apply_key_operation(validators, &tx.operation, block.height, block.timestamp);
+    }
+}
+
+/// Distinct validators with a valid precommit on each block of a branch,
+/// starting from `validators`, the set right after the fork
+fn branch_weight(mut validators: Vec<PubKey>, blocks: &[Block]) -> usize {
+    let mut weight = 0;
+    for block in blocks {
+        weight += block
+            .certificate
+            .as_ref()
+            .filter(|certificate| {
+                certificate.height == block.height && certificate.block_hash == block.hash
+            })
+            .map_or(0, |certificate| {
+                certificate.valid_signers(&validators).len()
+            });
+        apply_key_transactions(&mut validators, block);
+    }
+    weight
+}
+
+/// Fork choice: whether `candidate` should replace `canonical`, both starting
+/// right after their common ancestor, where `validators` were the validator
+/// set. The branch with the most verified validator signatures wins, ties go
+/// to the lowest hash at the fork.
+pub fn prefers(candidate: &[Block], canonical: &[Block], validators: &[PubKey]) -> bool {
+    let weight = |blocks| branch_weight(validators.to_vec(), blocks);
+    match weight(candidate).cmp(&weight(canonical)) {
+        Ordering::Greater => true,
+        Ordering::Less => false,
+        Ordering::Equal => match (candidate.first(), canonical.first()) {
+            (Some(candidate), Some(canonical)) => candidate.hash < canonical.hash,
+            (candidate, _) => candidate.is_some(),
+        },
+    }
+}
+
+impl BlockChain {
+    /// Handles a committed block that does not extend our tip. It is kept as
+    /// a side block and the chain is reorganised if its branch wins.
+    pub(crate) async fn handle_fork_block(&mut self, block: Block) -> Result<i64, ChainError> {
+        let height = self.get_chain_height().await?;
+        if self.db.has_block(&block.hash).await? {
+            return Ok(height);
+        }
+
+        let parent = match self.db.get_block_by_hash(&block.prev_hash).await {
+            Ok(parent) => Some(parent),
+            Err(sqlx::Error::RowNotFound) => self.db.get_side_block(&block.prev_hash).await?,
+            Err(e) => return Err(e.into()),
+        };
+        let Some(parent) = parent else {
+            log::debug!(
+                "Skipping block {} at height {}: parent {} is unknown",
+                block.hash,
+                block.height,
+                block.prev_hash
+            );
+            return Ok(height);
+        };
+
+        // Walk back through the side blocks to where the branch leaves the chain
+        let mut branch = vec![block];
+        while let Some(ancestor) = self.db.get_side_block(&branch[0].prev_hash).await? {
+            branch.insert(0, ancestor);
+        }
+        let fork_height = branch[0].height as i64 - 1;
+
+        // The branch is certified by the validators at the fork, as changed
+        // by the key transactions on the branch itself
+        let fork_validators = self.db.validators_after(fork_height).await?;
+        let mut validators = fork_validators.clone();
+        let (block, ancestors) = branch.split_last().expect("branch holds the block");
+        for ancestor in ancestors {
+            apply_key_transactions(&mut validators, ancestor);
+        }
+        self.validator()
+            .validate_side(block, &parent, &validators)?;
+        self.db.add_side_block(block).await?;
+        log::info!(
+            "🔀 Stored side block {} (height: {})",
+            block.hash,
+            block.height
+        );
+
+        let canonical = self.db.get_blocks_in_range(fork_height + 1, height).await?;
+        if !prefers(&branch, &canonical, &fork_validators) {
+            return Ok(height);
+        }
+        self.reorganize(fork_height, branch).await
+    }
+
+    /// Replaces the chain above `fork_height` with `branch`. Each block of
+    /// the branch is first validated against the state the blocks before it
+    /// leave, on a scratch copy of the chain. Only a branch that is valid
+    /// throughout is then swapped in, in a single transaction, so the chain
+    /// never shows a half applied reorganisation.
+    async fn reorganize(
+        &mut self,
+        fork_height: i64,
+        branch: Vec<Block>,
+    ) -> Result<i64, ChainError> {
+        let mut scratch = self.db.scratch_copy().await?;
+        scratch.reorganize(fork_height, &[]).await?;
+        for (index, block) in branch.iter().enumerate() {
+            let parent = scratch.last_block().await?;
+            let replayed = match BlockValidator::new(&scratch, self.config.max_clock_drift)
+                .validate_committed(block, &parent)
+                .await
+            {
+                Ok(()) => scratch.add_block(block).await.map_err(ChainError::from),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = replayed {
+                log::warn!(
+                    "🔀 Branch block {} failed on replay, keeping the chain: {e}",
+                    block.hash
+                );
+                let invalid: Vec<_> = branch[index..].iter().map(|b| b.hash.clone()).collect();
+                self.db.remove_side_blocks(&invalid).await?;
+                return Err(e);
+            }
+        }
+        drop(scratch);
+        let detached = self.db.reorganize(fork_height, &branch).await?;
+        let attached: Vec<_> = branch.iter().map(|block| block.hash.clone()).collect();
+
+        let event = ChainEvent::Reorg {
+            fork_height: fork_height as usize,
+            old_tip: detached
+                .last()
+                .map(|block| block.hash.clone())
+                .unwrap_or_default(),
+            new_tip: attached.last().cloned().unwrap_or_default(),
+            detached: detached.iter().map(|block| block.hash.clone()).collect(),
+            attached,
+        };
+        log::warn!(
+            "🔀 Reorganised chain at height {fork_height}: {} blocks detached, {} attached",
+            detached.len(),
+            branch.len()
+        );
+        self.emit(event);
+
+        let height = self.get_chain_height().await?;
+        *self.round.write().await = RoundState::new(height as usize + 1);
+        Ok(height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::{
+        BlockSigner, BlockType,
+        consensus::ValidatorSignature,
+        keys::{KeyOperation, KeyTransaction},
+        validation::BlockRejection,
+    };
+
+    use crate::testing::*;
+
+    fn signer(creator: &str) -> BlockSigner {
+        let key = types::crypto::get_private_key();
+        let verifying_key = types::crypto::get_public_key(&key);
+        let pub_key = PubKey::from_verifying_key(creator, &verifying_key);
+        (key, verifying_key, pub_key)
+    }
+
+    fn block(signer: &BlockSigner, votes: &[&BlockSigner]) -> Block {
+        let results = vec![result(1, votes.len() * 10)];
+        let merkle_root = BlockType::Result(results.clone()).merkle_root();
+        certify(
+            votes,
+            Block::new(signer, &"00".repeat(32), results, 1, merkle_root),
+        )
+    }
+
+    #[test]
+    fn test_fork_choice() {
+        let signers: Vec<_> = (0..4).map(|i| signer(&format!("validator-{i}"))).collect();
+        let validators: Vec<_> = signers.iter().map(|signer| signer.2.clone()).collect();
+        let [a, b, c, d] = [&signers[0], &signers[1], &signers[2], &signers[3]];
+
+        // More signatures win regardless of hash
+        let (three, two) = ([block(a, &[a, b, c])], [block(a, &[a, b])]);
+        assert!(prefers(&three, &two, &validators));
+        assert!(!prefers(&two, &three, &validators));
+        // A longer branch accumulates more signatures
+        assert!(prefers(
+            &[two[0].clone(), block(b, &[c, d])],
+            &three,
+            &validators
+        ));
+        // Ties go to the lowest hash
+        let other = [block(b, &[c, d])];
+        assert_eq!(
+            prefers(&two, &other, &validators),
+            two[0].hash < other[0].hash
+        );
+
+        // Repeated and forged entries do not add weight
+        let mut padded = block(b, &[a]);
+        let certificate = padded.certificate.as_mut().unwrap();
+        let signature = certificate.signatures[0].clone();
+        certificate.signatures.extend([
+            signature.clone(),
+            ValidatorSignature {
+                validator: b.2.hash.clone(),
+                signature: signature.signature,
+            },
+        ]);
+        assert_eq!(certificate.signer_count(), 2);
+        assert_eq!(branch_weight(validators.clone(), &[padded]), 1);
+        // Neither do keys outside the validator set
+        let outsider = signer("outsider");
+        assert_eq!(branch_weight(validators, &[block(a, &[&outsider])]), 0);
+    }
+
+    #[test]
+    fn test_branch_weight_follows_key_transactions() {
+        let (a, b) = (signer("validator-a"), signer("validator-b"));
+        let register = KeyTransaction::new(
+            &a,
+            KeyOperation::Register {
+                creator: "validator-b".to_string(),
+                pub_key: bincode::serialize(&b.1).unwrap(),
+            },
+        );
+        let first = certify(
+            &[&a],
+            Block::with_inner(
+                &a,
+                &"00".repeat(32),
+                BlockType::KeyManagement(vec![register]),
+                1,
+            ),
+        );
+        let second = certify(&[&a, &b], Block::new(&b, &first.hash, vec![], 2, [0u8; 32]));
+        assert_eq!(branch_weight(vec![a.2.clone()], &[first, second]), 3);
+    }
+
+    #[tokio::test]
+    async fn test_heavier_branch_triggers_reorg() {
+        let mut blockchain = single_validator_chain().await;
+        let mut events = blockchain.subscribe_events();
+        let signer = blockchain.db.get_private_key().await.unwrap();
+        let genesis = blockchain.last_block().await.unwrap();
+
+        let a = Block::new(&signer, &genesis.hash, vec![], 1, [0u8; 32]);
+        blockchain.propose_block(a.clone()).await.unwrap();
+
+        // A competing block at the same height, registering a second key
+        let new_private_key = types::crypto::get_private_key();
+        let new_key = types::crypto::get_public_key(&new_private_key);
+        let new_signer = (
+            new_private_key,
+            new_key,
+            types::PubKey::from_verifying_key("observer-1", &new_key),
+        );
+        let register = KeyTransaction::new(
+            &signer,
+            KeyOperation::Register {
+                creator: "observer-1".to_string(),
+                pub_key: bincode::serialize(&new_key).unwrap(),
+            },
+        );
+        let b = certify(
+            &[&signer],
+            Block::with_inner(
+                &signer,
+                &genesis.hash,
+                BlockType::KeyManagement(vec![register]),
+                1,
+            ),
+        );
+        blockchain.add_block_to_chain(b.clone()).await.unwrap();
+        let tip = blockchain.last_block().await.unwrap();
+        assert_eq!(tip.hash, a.hash.clone().min(b.hash.clone()));
+
+        // On `b`'s branch the registered key is a validator too, and may propose
+        let c = certify(
+            &[&signer, &new_signer],
+            Block::with_inner(&new_signer, &b.hash, BlockType::Agents(vec![]), 2),
+        );
+        blockchain.add_block_to_chain(c.clone()).await.unwrap();
+
+        let tip = blockchain.last_block().await.unwrap();
+        assert_eq!(tip.hash, c.hash);
+        assert_eq!(
+            blockchain.get_block_by_height(1).await.unwrap().hash,
+            b.hash
+        );
+        assert_eq!(blockchain.validators().await.unwrap().len(), 2);
+        assert!(blockchain.get_side_block(&a.hash).await.unwrap().is_some());
+        assert!(blockchain.is_valid().await.unwrap());
+
+        let mut reorgs = vec![];
+        while let Ok(event) = events.try_recv() {
+            if let ChainEvent::Reorg {
+                fork_height,
+                detached,
+                attached,
+                ..
+            } = event
+            {
+                reorgs.push((fork_height, detached, attached));
+            }
+        }
+        let [(fork_height, detached, attached)] = &reorgs[..] else {
+            panic!("Expected a single reorg, got {reorgs:?}");
+        };
+        assert_eq!(*fork_height, 0);
+        assert_eq!(*detached, vec![a.hash]);
+        assert!(attached.contains(&b.hash));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_branch_is_rolled_back() {
+        let mut blockchain = election_chain().await;
+        let signer = blockchain.db.get_private_key().await.unwrap();
+        let tip = blockchain.last_block().await.unwrap();
+
+        // Two competing blocks, the one with the lower hash becomes canonical
+        // so the other has to win on weight
+        let tally = |votes| BlockType::Result(vec![result(1, votes), result(2, 21)]);
+        let x = certify(
+            &[&signer],
+            Block::with_inner(&signer, &tip.hash, tally(66), 2),
+        );
+        let y = certify(
+            &[&signer],
+            Block::with_inner(&signer, &tip.hash, tally(67), 2),
+        );
+        let (canonical, side) = if x.hash < y.hash { (x, y) } else { (y, x) };
+        blockchain
+            .add_block_to_chain(canonical.clone())
+            .await
+            .unwrap();
+        blockchain.add_block_to_chain(side.clone()).await.unwrap();
+        assert_eq!(blockchain.last_block().await.unwrap().hash, canonical.hash);
+        let tip_row = sqlx::query_scalar::<_, i64>("SELECT rowid FROM blockchain WHERE hash = ?1");
+        let rowid = tip_row
+            .bind(canonical.hash.clone())
+            .fetch_one(&blockchain.chain_db)
+            .await
+            .unwrap();
+
+        // The heavier branch carries results for an election that does not
+        // exist, which only shows once the branch is replayed
+        let invalid = certify(
+            &[&signer],
+            Block::with_inner(
+                &signer,
+                &side.hash,
+                BlockType::Result(vec![types::CandidateResult::new(
+                    2,
+                    STATION as usize,
+                    1,
+                    10,
+                )]),
+                3,
+            ),
+        );
+        assert!(matches!(
+            blockchain.add_block_to_chain(invalid.clone()).await,
+            Err(ChainError::BlockRejected(
+                BlockRejection::OutsideElection { .. }
+            ))
+        ));
+
+        assert_eq!(blockchain.get_chain_height().await.unwrap(), 2);
+        assert_eq!(blockchain.last_block().await.unwrap().hash, canonical.hash);
+        // The old tip was never detached and written back
+        let tip_row = sqlx::query_scalar::<_, i64>("SELECT rowid FROM blockchain WHERE hash = ?1");
+        let now = tip_row
+            .bind(canonical.hash.clone())
+            .fetch_one(&blockchain.chain_db)
+            .await
+            .unwrap();
+        assert_eq!(now, rowid);
+        let votes = blockchain.station_votes(ELECTION, STATION).await.unwrap();
+        assert_eq!(votes, canonical.get_results());
+        assert!(
+            blockchain
+                .get_side_block(&side.hash)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            blockchain
+                .get_side_block(&invalid.hash)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(blockchain.is_valid().await.unwrap());
+    }
+}
@@ -0,0 +1,284 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::SocketAddr,
+    time::Instant,
+};
+
+use types::{Block, error::ChainError, p2p::P2PMessage};
+
+use crate::{BlockChain, SecureStream};
+
+/// How many hashes each cache remembers before forgetting the oldest
+pub const SEEN_CACHE_SIZE: usize = 10_000;
+
+/// A bounded set that forgets the oldest entries first
+#[derive(Debug)]
+pub struct SeenCache {
+    capacity: usize,
+    entries: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl Default for SeenCache {
+    fn default() -> Self {
+        Self::new(SEEN_CACHE_SIZE)
+    }
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Returns `false` if `hash` was already in the cache
+    pub fn insert(&mut self, hash: &str) -> bool {
+        if self.entries.contains(hash) {
+            return false;
+        }
+        if self.order.len() >= self.capacity
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.entries.remove(&oldest);
+        }
+        self.entries.insert(hash.to_string());
+        self.order.push_back(hash.to_string());
+        true
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.entries.contains(hash)
+    }
+}
+
+/// A broadcast together with the peer it came from, which must not get it back
+#[derive(Debug, Clone)]
+pub struct Gossip {
+    pub origin: Option<SocketAddr>,
+    pub message: P2PMessage,
+}
+
+/// What we and each peer are known to have
+#[derive(Debug, Default)]
+pub struct GossipState {
+    /// Blocks we have accepted
+    seen: SeenCache,
+    /// Blocks being validated, a failed one can be fetched again
+    validating: HashSet<String>,
+    /// Blocks each peer announced or was sent
+    peer_known: HashMap<SocketAddr, SeenCache>,
+    /// Blocks requested with `GetData` and not received yet
+    requested: HashMap<String, Instant>,
+}
+
+impl GossipState {
+    fn mark_known(&mut self, peer: SocketAddr, hashes: &[String]) {
+        let known = self.peer_known.entry(peer).or_default();
+        for hash in hashes {
+            known.insert(hash);
+        }
+    }
+}
+
+impl BlockChain {
+    /// Relays an inventory of newly accepted blocks to every peer but `origin`
+    pub(crate) async fn relay_blocks(
+        &self,
+        hashes: Vec<String>,
+        origin: Option<SocketAddr>,
+    ) -> Result<(), ChainError> {
+        {
+            let mut gossip = self.gossip.write().await;
+            for hash in &hashes {
+                gossip.seen.insert(hash);
+            }
+        }
+        self.relay_message(P2PMessage::Inventory { hashes }, origin)
+            .await
+    }
+
+    /// Trims an outgoing message to what `peer` does not know yet,
+    /// `None` if nothing is left to send
+    pub(crate) async fn filter_for_peer(
+        &self,
+        peer: SocketAddr,
+        message: P2PMessage,
+    ) -> Option<P2PMessage> {
+        let P2PMessage::Inventory { hashes } = message else {
+            return Some(message);
+        };
+        let mut gossip = self.gossip.write().await;
+        let known = gossip.peer_known.entry(peer).or_default();
+        let hashes: Vec<String> = hashes.into_iter().filter(|h| known.insert(h)).collect();
+        (!hashes.is_empty()).then_some(P2PMessage::Inventory { hashes })
+    }
+
+    pub(crate) async fn forget_peer(&self, peer: SocketAddr) {
+        self.gossip.write().await.peer_known.remove(&peer);
+    }
+
+    /// Requests the announced blocks we have not seen or asked for yet
+    pub(crate) async fn handle_inventory(
+        &self,
+        hashes: Vec<String>,
+        peer: SocketAddr,
+        stream: &mut SecureStream,
+    ) -> Result<(), ChainError> {
+        let candidates: Vec<String> = {
+            let mut gossip = self.gossip.write().await;
+            gossip.mark_known(peer, &hashes);
+            let timeout = self.config.connection_timeout;
+            gossip
+                .requested
+                .retain(|_, requested| requested.elapsed() <= timeout);
+            hashes
+                .into_iter()
+                .filter(|hash| {
+                    !gossip.seen.contains(hash)
+                        && !gossip.validating.contains(hash)
+                        && !gossip.requested.contains_key(hash)
+                })
+                .collect()
+        };
+
+        let mut wanted = Vec::new();
+        for hash in candidates {
+            if !self.db.has_block(&hash).await? {
+                wanted.push(hash);
+            }
+        }
+        if wanted.is_empty() {
+            return Ok(());
+        }
+
+        {
+            let mut gossip = self.gossip.write().await;
+            for hash in &wanted {
+                gossip.requested.insert(hash.clone(), Instant::now());
+            }
+        }
+        log::debug!("Requesting {} blocks from {peer}", wanted.len());
+        self.send_message(stream, &P2PMessage::GetData { hashes: wanted })
+            .await
+    }
+
+    pub(crate) async fn handle_get_data(
+        &self,
+        hashes: Vec<String>,
+        peer: SocketAddr,
+        stream: &mut SecureStream,
+    ) -> Result<(), ChainError> {
+        for hash in hashes.iter().take(self.config.sync_batch_size as usize) {
+            if let Some(block) = self.get_block_by_hash(hash).await? {
+                let response = P2PMessage::BlockResponse { block: Some(block) };
+                self.send_message(stream, &response).await?;
+            }
+        }
+        self.gossip.write().await.mark_known(peer, &hashes);
+        Ok(())
+    }
+
+    /// Handles a full block pushed or sent by a peer, relaying it once accepted
+    pub(crate) async fn handle_gossiped_block(
+        &mut self,
+        block: Block,
+        peer: SocketAddr,
+    ) -> Result<(), ChainError> {
+        let hash = block.hash.clone();
+        {
+            let mut gossip = self.gossip.write().await;
+            gossip.mark_known(peer, std::slice::from_ref(&hash));
+            gossip.requested.remove(&hash);
+            if gossip.seen.contains(&hash) || !gossip.validating.insert(hash.clone()) {
+                return Ok(());
+            }
+        }
+
+        // Only accepted blocks are marked as seen, so a block that failed here
+        // is still fetched when another peer announces it
+        let res = self.handle_new_block(block).await;
+        self.gossip.write().await.validating.remove(&hash);
+        res?;
+        // Blocks with an unknown parent are dropped and left to the sync
+        if self.db.has_block(&hash).await? {
+            self.relay_blocks(vec![hash], Some(peer)).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use types::p2p::P2PConfig;
+
+    #[test]
+    fn test_seen_cache_forgets_oldest() {
+        let mut cache = SeenCache::new(2);
+        assert!(cache.insert("a"));
+        assert!(!cache.insert("a"));
+        assert!(cache.insert("b"));
+        assert!(cache.insert("c"));
+        assert!(!cache.contains("a"));
+        assert!(cache.contains("b") && cache.contains("c"));
+    }
+
+    #[tokio::test]
+    async fn test_gossiped_block_is_relayed_once() {
+        let mut source = single_validator_chain().await;
+        let signer = source.db.get_private_key().await.unwrap();
+        let genesis = source.last_block().await.unwrap();
+        source
+            .propose_block(Block::new(&signer, &genesis.hash, vec![], 1, [0u8; 32]))
+            .await
+            .unwrap();
+        let block = source.last_block().await.unwrap();
+
+        let mut node = follower_of(&source, P2PConfig::default()).await;
+        let mut relayed = node.message_tx.subscribe();
+        let (a, b): (SocketAddr, SocketAddr) = (
+            "127.0.0.1:9001".parse().unwrap(),
+            "127.0.0.1:9002".parse().unwrap(),
+        );
+
+        // A copy without its certificate fails validation and is not remembered,
+        // so the genuine block under the same hash is still accepted
+        let mut uncertified = block.clone();
+        uncertified.certificate = None;
+        let forger: SocketAddr = "127.0.0.1:9003".parse().unwrap();
+        assert!(
+            node.handle_gossiped_block(uncertified, forger)
+                .await
+                .is_err()
+        );
+        assert!(relayed.try_recv().is_err());
+        {
+            let gossip = node.gossip.read().await;
+            assert!(!gossip.seen.contains(&block.hash));
+            assert!(gossip.validating.is_empty());
+        }
+
+        node.handle_gossiped_block(block.clone(), a).await.unwrap();
+        assert_eq!(node.get_chain_height().await.unwrap(), 1);
+        let gossip = relayed.try_recv().unwrap();
+        assert_eq!(gossip.origin, Some(a));
+        // The sender already has the block, the other peer only gets the hash
+        assert!(
+            node.filter_for_peer(a, gossip.message.clone())
+                .await
+                .is_none()
+        );
+        assert!(matches!(
+            node.filter_for_peer(b, gossip.message).await,
+            Some(P2PMessage::Inventory { hashes }) if hashes == vec![block.hash.clone()]
+        ));
+
+        // The same block arriving from another peer is not relayed again
+        node.handle_gossiped_block(block, b).await.unwrap();
+        assert!(relayed.try_recv().is_err());
+    }
+}
@@ -1,22 +1,45 @@
-use bincode::deserialize;
-use database::{Database, SqlitePool};
+mod attachments;
+mod bootstrap;
+mod consensus;
+mod events;
+mod fork;
+mod gossip;
+mod peers;
+mod sync;
+#[cfg(test)]
+mod testing;
+mod transport;
+mod validator;
+
+pub use consensus::RoundState;
+pub use gossip::SeenCache;
+pub use peers::reconnect_backoff;
+pub use sync::SyncStatus;
+pub use transport::{NodeKey, SecureStream};
+pub use validator::BlockValidator;
+
+use database::{AttachmentStore, Database, SqlitePool};
 use log::info;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
-    sync::{RwLock, broadcast},
+    sync::{RwLock, broadcast, mpsc},
 };
 use types::{
     Block,
-    config::Config,
+    config::{Config, Mode},
     error::ChainError,
-    p2p::{P2PConfig, P2PMessage, PeerConnection},
+    events::ChainEvent,
+    p2p::{
+        Capabilities, MIN_PROTOCOL_VERSION, Misbehaviour, P2PConfig, P2PMessage, PROTOCOL_VERSION,
+        PeerConnection, negotiate_version,
+    },
 };
 
 use std::{
     collections::HashMap,
     net::SocketAddr,
     ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
     sync::Arc,
     time::Instant,
 };
@@ -25,10 +48,19 @@ use std::{
 pub struct BlockChain {
     pub db: Database,
     peers: Arc<RwLock<HashMap<SocketAddr, PeerConnection>>>,
-    message_tx: broadcast::Sender<P2PMessage>,
+    message_tx: broadcast::Sender<gossip::Gossip>,
     node_id: String,
+    node_key: NodeKey,
     config: P2PConfig,
     is_running: Arc<RwLock<bool>>,
+    round: Arc<RwLock<RoundState>>,
+    events: broadcast::Sender<ChainEvent>,
+    sync: Arc<RwLock<sync::SyncState>>,
+    gossip: Arc<RwLock<gossip::GossipState>>,
+    /// Messages addressed to a single connected peer
+    peer_tx: Arc<RwLock<HashMap<SocketAddr, mpsc::UnboundedSender<P2PMessage>>>>,
+    /// Observers follow the chain but never sign, propose or vote
+    read_only: bool,
 }
 
 impl Deref for BlockChain {
@@ -46,24 +78,87 @@ impl DerefMut for BlockChain {
 
 impl BlockChain {
     pub fn new(db: Database, config: Option<P2PConfig>) -> Self {
+        Self::with_node_key(db, config, NodeKey::generate())
+    }
+
+    /// Creates a chain whose P2P identity is `node_key`
+    pub fn with_node_key(db: Database, config: Option<P2PConfig>, node_key: NodeKey) -> Self {
         let (tx, _) = broadcast::channel(1000);
+        let (events, _) = broadcast::channel(100);
         Self {
             db,
             peers: Arc::new(RwLock::new(HashMap::new())),
             message_tx: tx,
-            node_id: uuid::Uuid::new_v4().to_string(),
+            node_id: node_key.node_id(),
+            node_key,
             config: config.unwrap_or_default(),
             is_running: Arc::new(RwLock::new(false)),
+            round: Arc::new(RwLock::new(RoundState::default())),
+            events,
+            sync: Arc::new(RwLock::new(sync::SyncState::default())),
+            gossip: Arc::new(RwLock::new(gossip::GossipState::default())),
+            peer_tx: Arc::new(RwLock::new(HashMap::new())),
+            read_only: false,
         }
     }
 
+    /// Turns this node into an observer, which refuses to sign even if it has a key
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
     pub async fn from_config(config: Config) -> Self {
+        let attachments = config
+            .attachments
+            .clone()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                let main_db = config.main_db.trim_start_matches("sqlite:");
+                let main_db = main_db.split('?').next().unwrap_or_default();
+                Path::new(main_db.trim_start_matches("//"))
+                    .parent()
+                    .unwrap_or(Path::new("."))
+                    .join("attachments")
+            });
         let db = Database::new(
             SqlitePool::connect(&config.main_db).await.unwrap(),
             SqlitePool::connect(&config.private_db).await.unwrap(),
-        );
-        let config = config.peer_config.unwrap_or_default();
-        Self::new(db, Some(config))
+        )
+        .with_attachments(AttachmentStore::new(attachments));
+        let p2p_config = config.peer_config.clone().unwrap_or_default();
+        let node_key = match db.get_node_key().await.unwrap() {
+            Some(key) => NodeKey::from_private(&key).unwrap(),
+            None => {
+                let key = NodeKey::generate();
+                db.set_node_key(key.private()).await.unwrap();
+                key
+            }
+        };
+        let mut blockchain = Self::with_node_key(db, Some(p2p_config), node_key);
+
+        // Configured peers are kept reconnected
+        let mut seeds: Vec<SocketAddr> = config
+            .peers
+            .unwrap_or_default()
+            .values()
+            .map(|addr| addr.parse().expect("Invalid peer address"))
+            .collect();
+        if let Some(Mode::Observer { peer_addr, .. }) = &config.mode {
+            blockchain = blockchain.read_only();
+            seeds.push(peer_addr.parse().expect("Invalid peer address"));
+        }
+        blockchain.add_known_peers(&seeds, true).await.unwrap();
+        blockchain
+    }
+
+    /// Notifies about changes to the canonical chain, such as reorganisations
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ChainEvent> {
+        self.events.subscribe()
     }
 
     // Start P2P server
@@ -82,6 +177,10 @@ impl BlockChain {
         tokio::spawn(async move {
             blockchain.peer_maintenance_loop().await;
         });
+        let blockchain = self.clone();
+        tokio::spawn(async move {
+            blockchain.reconnect_loop().await;
+        });
 
         // Accept incoming connections
         loop {
@@ -94,6 +193,21 @@ impl BlockChain {
 
                     let mut blockchain = self.clone();
                     tokio::spawn(async move {
+                        let handshake = tokio::time::timeout(
+                            blockchain.config.connection_timeout,
+                            SecureStream::accept(stream, &blockchain.node_key),
+                        );
+                        let stream = match handshake.await {
+                            Ok(Ok(stream)) => stream,
+                            Ok(Err(e)) => {
+                                log::warn!("Handshake with {peer_addr} failed: {e}");
+                                return;
+                            }
+                            Err(_) => {
+                                log::warn!("Handshake with {peer_addr} timed out");
+                                return;
+                            }
+                        };
                         if let Err(e) = blockchain.handle_peer_connection(stream, peer_addr).await {
                             log::error!("Error handling peer {peer_addr}: {e}");
                         }
@@ -113,8 +227,21 @@ impl BlockChain {
         }
         log::info!("🔗 Connecting to peer: {addr}");
 
-        let stream = tokio::time::timeout(self.config.connection_timeout, TcpStream::connect(addr))
-            .await??;
+        let stream = tokio::time::timeout(self.config.connection_timeout, async {
+            let stream = TcpStream::connect(addr).await?;
+            SecureStream::connect(stream, &self.node_key).await
+        })
+        .await
+        .map_err(ChainError::from)
+        .and_then(|res| res);
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                self.record_peer_failure(addr).await?;
+                return Err(e);
+            }
+        };
+        self.db.peer_seen(&addr.to_string()).await?;
 
         let mut blockchain = self.clone();
 
@@ -128,66 +255,107 @@ impl BlockChain {
     // Handle individual peer connection
     async fn handle_peer_connection(
         &mut self,
-        mut stream: TcpStream,
+        mut stream: SecureStream,
         peer_addr: SocketAddr,
     ) -> Result<(), ChainError> {
         log::debug!("New peer connection: {peer_addr}");
-
-        // Add peer to peer list
-        {
-            let mut peers = self.peers.write().await;
-            peers.insert(peer_addr, PeerConnection::new(peer_addr));
+        let node_id = stream.remote_node_id();
+        if let Err(e) = self.check_ban(&node_id, peer_addr).await {
+            log::debug!("Refusing banned peer {peer_addr}: {e}");
+            return self.disconnect_banned(&mut stream, Err(e)).await;
         }
 
         // Perform handshake
-        let chain_height = self
-            .get_chain_height()
-            .await
-            .unwrap_or(0)
-            .try_into()
-            .unwrap_or_default();
+        let chain_height = self.get_chain_height().await.unwrap_or(0);
         let hello_msg = P2PMessage::Hello {
             node_id: self.node_id.clone(),
-            version: 1,
+            version: PROTOCOL_VERSION,
             chain_height,
+            capabilities: self.capabilities(),
         };
 
         self.send_message(&mut stream, &hello_msg).await?;
 
+        // Add peer to peer list
+        let peers = {
+            let mut peers = self.peers.write().await;
+            peers.insert(peer_addr, PeerConnection::new(peer_addr));
+            peers.len()
+        };
+        let (peer_tx, mut peer_rx) = mpsc::unbounded_channel();
+        self.peer_tx.write().await.insert(peer_addr, peer_tx);
+        self.emit(ChainEvent::PeerConnected { peers });
+
         // Handle messages
         let mut message_rx = self.message_tx.subscribe();
 
-        loop {
+        let res = loop {
             tokio::select! {
-                // Handle incoming messages from peer
+                // Handle incoming messages from peer. Losing the race to the
+                // branches below keeps a partly read message, see `SecureStream::recv`
                 result = self.read_message(&mut stream) => {
                     match result {
                         Ok(message) => {
                             // Extract stream handling into separate async block to avoid cycle
-                            self.process_p2p_message(message, peer_addr, &mut stream).await?;
+                            if let Err(e) = self.process_p2p_message(message, peer_addr, &mut stream).await {
+                                break Err(e);
+                            }
+                        }
+                        // The frame was read whole, so the stream is still usable
+                        Err(ChainError::SerializationError(_)) => {
+                            if let Err(e) = self.penalize(peer_addr, &node_id, Misbehaviour::MalformedMessage).await {
+                                break Err(e);
+                            }
+                        }
+                        // The rest of the message was not read, the stream cannot recover
+                        Err(e @ ChainError::MessageTooLarge(_)) => {
+                            break self
+                                .penalize(peer_addr, &node_id, Misbehaviour::OversizedMessage)
+                                .await
+                                .and(Err(e));
                         }
                         Err(_) => {
                             log::warn!("Connection closed by peer: {peer_addr}");
-                            break;
+                            break Ok(());
                         }
                     }
                 }
 
+                // Send messages addressed to this peer
+                Some(message) = peer_rx.recv() => {
+                    if let Err(e) = self.send_message(&mut stream, &message).await {
+                        break Err(e);
+                    }
+                }
+
                 // Forward broadcast messages to peer
                 broadcast_result = message_rx.recv() => {
-                    if let Ok(message) = broadcast_result {
-                        // Don't send message back to sender
-                        self.send_message(&mut stream, &message).await?;
+                    // Don't send message back to sender, nor blocks it already has
+                    if let Ok(gossip) = broadcast_result
+                        && gossip.origin != Some(peer_addr)
+                        && let Some(message) = self.filter_for_peer(peer_addr, gossip.message).await
+                        && let Err(e) = self.send_message(&mut stream, &message).await
+                    {
+                        break Err(e);
                     }
                 }
             }
-        }
+        };
+        let res = self.disconnect_banned(&mut stream, res).await;
 
-        // Clean up peer connection
-        self.peers.write().await.remove(&peer_addr);
+        // Clean up peer connection, also on errors so the peer can be reconnected
+        let peers = {
+            let mut peers = self.peers.write().await;
+            peers.remove(&peer_addr);
+            peers.len()
+        };
+        self.peer_tx.write().await.remove(&peer_addr);
+        self.forget_peer(peer_addr).await;
         log::warn!("Disconnected from peer: {peer_addr}");
+        self.emit(ChainEvent::PeerDisconnected { peers });
+        self.release_sync_peer(peer_addr).await?;
 
-        Ok(())
+        res
     }
 
     // Process P2P messages (separated to avoid async cycles)
@@ -195,30 +363,42 @@ impl BlockChain {
         &mut self,
         message: P2PMessage,
         peer_addr: SocketAddr,
-        stream: &mut TcpStream,
+        stream: &mut SecureStream,
     ) -> Result<(), ChainError> {
         // Update last seen
-        if let Some(peer) = self.peers.write().await.get_mut(&peer_addr) {
-            peer.last_seen = Instant::now();
+        let observer = match self.peers.write().await.get_mut(&peer_addr) {
+            Some(peer) => {
+                peer.last_seen = Instant::now();
+                peer.capabilities.contains(Capabilities::OBSERVER)
+            }
+            None => false,
+        };
+        if observer && message.is_consensus() {
+            log::debug!("Ignoring a consensus message from observer {peer_addr}");
+            return Ok(());
         }
 
         match message {
             P2PMessage::Hello {
                 node_id,
-                version: _,
+                version,
                 chain_height,
+                capabilities,
             } => {
-                self.handle_hello_message(node_id, chain_height, peer_addr, stream)
+                let peer = (version, capabilities);
+                self.handle_hello_message(node_id, chain_height, peer, peer_addr, stream)
                     .await
             }
 
             P2PMessage::HelloResponse {
                 node_id,
+                version,
                 chain_height,
                 accepted,
-                ..
+                capabilities,
             } => {
-                self.handle_hello_response(node_id, chain_height, accepted, peer_addr, stream)
+                let peer = (version, capabilities);
+                self.handle_hello_response(node_id, chain_height, accepted, peer, peer_addr, stream)
                     .await
             }
 
@@ -228,19 +408,41 @@ impl BlockChain {
                     block.hash,
                     block.height
                 );
-                self.handle_new_block(block).await?;
-                Ok(())
+                let hash = block.hash.clone();
+                let res = self.handle_gossiped_block(block, peer_addr).await;
+                self.report_rejection(res, &hash, peer_addr, stream).await
             }
 
+            P2PMessage::Inventory { hashes } => {
+                self.handle_inventory(hashes, peer_addr, stream).await
+            }
+
+            P2PMessage::GetData { hashes } => self.handle_get_data(hashes, peer_addr, stream).await,
+
+            P2PMessage::BlockResponse { block: Some(block) } => {
+                let hash = block.hash.clone();
+                let res = self.handle_gossiped_block(block, peer_addr).await;
+                self.report_rejection(res, &hash, peer_addr, stream).await
+            }
+
+            P2PMessage::Proposal { block, round } => {
+                log::info!(
+                    "📝 Received proposal: {} (height: {}, round: {})",
+                    block.hash,
+                    block.height,
+                    round
+                );
+                let hash = block.hash.clone();
+                let res = self.handle_proposal(block, round, Some(peer_addr)).await;
+                self.report_rejection(res, &hash, peer_addr, stream).await
+            }
+
+            P2PMessage::Vote(vote) => self.handle_vote(vote, Some(peer_addr)).await,
+
             P2PMessage::BlockRequest { hash } => self.handle_block_request(hash, stream).await,
 
             P2PMessage::ChainHeightRequest => {
-                let height = self
-                    .get_chain_height()
-                    .await
-                    .unwrap_or(0)
-                    .try_into()
-                    .unwrap();
+                let height = self.get_chain_height().await.unwrap_or(0);
                 let response = P2PMessage::ChainHeightResponse { height };
                 self.send_message(stream, &response).await
             }
@@ -253,7 +455,27 @@ impl BlockChain {
                     .await
             }
 
-            P2PMessage::BlocksResponse { blocks } => self.handle_blocks_response(blocks).await,
+            P2PMessage::GetHeaders {
+                start_height,
+                count,
+            } => {
+                self.handle_get_headers_request(start_height, count, stream)
+                    .await
+            }
+
+            P2PMessage::HeadersResponse { headers } => {
+                self.handle_headers_response(headers, peer_addr, &stream.remote_node_id())
+                    .await
+            }
+
+            P2PMessage::BlocksResponse { blocks } => {
+                self.handle_blocks_response(blocks, peer_addr, stream).await
+            }
+
+            P2PMessage::BlockRejected { hash, reason } => {
+                log::warn!("⛔ Peer {peer_addr} rejected block {hash}: {reason}");
+                Ok(())
+            }
 
             P2PMessage::GetPeers => self.handle_get_peers_request(stream).await,
 
@@ -263,6 +485,18 @@ impl BlockChain {
 
             P2PMessage::Ping => {
                 info!("🏓 Received ping from {peer_addr}");
+                let pings = match self.peers.write().await.get_mut(&peer_addr) {
+                    Some(peer) => {
+                        peer.pings += 1;
+                        peer.pings
+                    }
+                    None => 0,
+                };
+                if pings > self.config.max_pings_per_interval {
+                    return self
+                        .penalize(peer_addr, &stream.remote_node_id(), Misbehaviour::PingFlood)
+                        .await;
+                }
                 let response = P2PMessage::Pong;
                 self.send_message(stream, &response).await
             }
@@ -275,6 +509,15 @@ impl BlockChain {
                 Ok(())
             }
 
+            P2PMessage::AttachmentRequest { hash } => {
+                self.handle_attachment_request(hash, stream).await
+            }
+
+            P2PMessage::AttachmentResponse { hash, data } => {
+                self.handle_attachment_response(hash, data, peer_addr, stream)
+                    .await
+            }
+
             P2PMessage::Disconnect { reason } => {
                 log::debug!("👋 Peer {peer_addr} disconnecting: {reason}");
                 Err(ChainError::PeerError("Peer disconnected".into()))
@@ -292,58 +535,98 @@ impl BlockChain {
         &self,
         node_id: String,
         chain_height: i64,
+        (version, capabilities): (u32, Capabilities),
         peer_addr: SocketAddr,
-        stream: &mut TcpStream,
+        stream: &mut SecureStream,
     ) -> Result<(), ChainError> {
-        log::debug!("Received hello from {node_id} (height: {chain_height})");
+        log::debug!("Received hello from {node_id} (height: {chain_height}, version: {version})");
+        Self::check_node_id(&node_id, stream)?;
+
+        let our_height = self.get_chain_height().await?;
+        let Some(version) = negotiate_version(version) else {
+            let reason = format!(
+                "Incompatible protocol version {version}, need at least {MIN_PROTOCOL_VERSION}"
+            );
+            let response = P2PMessage::HelloResponse {
+                node_id: self.node_id.clone(),
+                version: PROTOCOL_VERSION,
+                chain_height: our_height,
+                accepted: false,
+                capabilities: self.capabilities(),
+            };
+            self.send_message(stream, &response).await?;
+            let disconnect = P2PMessage::Disconnect {
+                reason: reason.clone(),
+            };
+            self.send_message(stream, &disconnect).await?;
+            return Err(ChainError::PeerError(reason));
+        };
 
         // Update peer info
         if let Some(peer) = self.peers.write().await.get_mut(&peer_addr) {
             peer.node_id = Some(node_id.clone());
             peer.chain_height = chain_height;
+            peer.version = version;
+            peer.capabilities = capabilities;
         }
 
         // Send response
-        let our_height = self.get_chain_height().await?.try_into().unwrap();
         let response = P2PMessage::HelloResponse {
             node_id: self.node_id.clone(),
-            version: 1,
+            version: PROTOCOL_VERSION,
             chain_height: our_height,
             accepted: true,
+            capabilities: self.capabilities(),
         };
         self.send_message(stream, &response).await?;
 
-        dbg!(our_height, chain_height);
-
         // Start sync if peer has higher chain
-        if chain_height > (our_height as i64) {
-            self.request_chain_sync(peer_addr, stream).await?;
+        if chain_height > our_height {
+            self.start_sync(peer_addr, chain_height).await?;
         }
 
         Ok(())
     }
 
+    /// The node id a peer claims must be the key it authenticated with
+    fn check_node_id(node_id: &str, stream: &SecureStream) -> Result<(), ChainError> {
+        if node_id != stream.remote_node_id() {
+            return Err(ChainError::PeerError(format!(
+                "Node id {node_id} does not match the transport key {}",
+                stream.remote_node_id()
+            )));
+        }
+        Ok(())
+    }
+
     async fn handle_hello_response(
         &self,
         node_id: String,
         chain_height: i64,
         accepted: bool,
+        (version, capabilities): (u32, Capabilities),
         peer_addr: SocketAddr,
-        stream: &mut TcpStream,
+        stream: &mut SecureStream,
     ) -> Result<(), ChainError> {
         if accepted {
             log::debug!("Handshake accepted by {node_id} (height: {chain_height})");
+            Self::check_node_id(&node_id, stream)?;
+            let version = negotiate_version(version).ok_or_else(|| {
+                ChainError::PeerError(format!("Incompatible protocol version {version}"))
+            })?;
 
             // Update peer info
             if let Some(peer) = self.peers.write().await.get_mut(&peer_addr) {
                 peer.node_id = Some(node_id);
                 peer.chain_height = chain_height;
+                peer.version = version;
+                peer.capabilities = capabilities;
             }
 
             // Start sync if peer has higher chain
             let our_height = self.get_chain_height().await.unwrap_or(0);
             if chain_height > our_height {
-                self.request_chain_sync(peer_addr, stream).await?;
+                self.start_sync(peer_addr, chain_height).await?;
             }
         } else {
             log::debug!("Handshake rejected by peer {peer_addr}");
@@ -356,7 +639,7 @@ impl BlockChain {
     async fn handle_block_request(
         &self,
         hash: String,
-        stream: &mut TcpStream,
+        stream: &mut SecureStream,
     ) -> Result<(), ChainError> {
         if let Some(block) = self.get_block_by_hash(&hash).await? {
             let response = P2PMessage::BlockResponse { block: Some(block) };
@@ -371,22 +654,70 @@ impl BlockChain {
         &self,
         start_height: i64,
         count: u32,
-        stream: &mut TcpStream,
+        stream: &mut SecureStream,
     ) -> Result<(), ChainError> {
         let blocks = self.get_blocks_range(start_height, count).await?;
         let response = P2PMessage::BlocksResponse { blocks };
         self.send_message(stream, &response).await
     }
 
-    async fn handle_blocks_response(&mut self, blocks: Vec<Block>) -> Result<(), ChainError> {
+    async fn handle_blocks_response(
+        &mut self,
+        blocks: Vec<Block>,
+        peer_addr: SocketAddr,
+        stream: &mut SecureStream,
+    ) -> Result<(), ChainError> {
         log::debug!("Received {} blocks for sync", blocks.len());
+        if self.is_syncing().await {
+            return match self.handle_sync_blocks(blocks, peer_addr).await? {
+                Some((hash, e)) => {
+                    self.report_rejection(Err(e), &hash, peer_addr, stream)
+                        .await
+                }
+                None => Ok(()),
+            };
+        }
         for block in blocks {
-            let _ = self.add_block_to_chain(block).await; // TODO: handle multiple similar blocks
+            let hash = block.hash.clone();
+            if let Err(e) = self.add_block_to_chain(block).await {
+                // Every later block builds on this one, so stop here
+                return self
+                    .report_rejection(Err(e), &hash, peer_addr, stream)
+                    .await;
+            }
         }
         Ok(())
     }
 
-    async fn handle_get_peers_request(&self, stream: &mut TcpStream) -> Result<(), ChainError> {
+    /// Tells the peer why its block was rejected instead of dropping the
+    /// connection, the peer is penalised for it
+    async fn report_rejection(
+        &self,
+        res: Result<(), ChainError>,
+        hash: &str,
+        peer_addr: SocketAddr,
+        stream: &mut SecureStream,
+    ) -> Result<(), ChainError> {
+        match res {
+            Err(ChainError::BlockRejected(reason)) => {
+                log::warn!("⛔ Rejected block {hash}: {reason}");
+                let response = P2PMessage::BlockRejected {
+                    hash: hash.to_string(),
+                    reason: reason.clone(),
+                };
+                self.send_message(stream, &response).await?;
+                self.penalize(
+                    peer_addr,
+                    &stream.remote_node_id(),
+                    Misbehaviour::InvalidBlock(reason),
+                )
+                .await
+            }
+            res => res,
+        }
+    }
+
+    async fn handle_get_peers_request(&self, stream: &mut SecureStream) -> Result<(), ChainError> {
         let peers: Vec<SocketAddr> = self.peers.read().await.keys().cloned().collect();
         let response = P2PMessage::PeersResponse { peers };
         self.send_message(stream, &response).await
@@ -410,6 +741,8 @@ impl BlockChain {
             }
         }
 
+        self.add_known_peers(&peers_to_connect, false).await?;
+
         // Connect to new peers outside of the async context
         for peer in peers_to_connect {
             self.spawn_peer_connection(peer);
@@ -428,41 +761,27 @@ impl BlockChain {
 
     // Broadcast message to all peers
     pub async fn broadcast_message(&self, message: P2PMessage) -> Result<(), ChainError> {
-        let _ = self.message_tx.send(message);
+        self.relay_message(message, None).await
+    }
+
+    /// Broadcasts to every peer except `origin`, the peer the message came from
+    pub(crate) async fn relay_message(
+        &self,
+        message: P2PMessage,
+        origin: Option<SocketAddr>,
+    ) -> Result<(), ChainError> {
+        let _ = self.message_tx.send(gossip::Gossip { origin, message });
         Ok(())
     }
 
-    // Announce new block to network
+    // Announce new block to network, peers fetch it with `GetData` if they need it
     pub async fn announce_block(&self, block: Block) -> Result<(), ChainError> {
         log::info!(
             "📢 Announcing new block: {} (height: {})",
             block.hash,
             block.height
         );
-        self.broadcast_message(P2PMessage::BlockAnnouncement(block))
-            .await
-    }
-
-    // Request chain synchronization
-    async fn request_chain_sync(
-        &self,
-        peer_addr: SocketAddr,
-        stream: &mut TcpStream,
-    ) -> Result<(), ChainError> {
-        let our_height = self.get_chain_height().await.unwrap_or(0);
-
-        if let Some(peer) = self.peers.write().await.get_mut(&peer_addr) {
-            peer.is_syncing = true;
-        }
-
-        log::info!("🔄 Starting chain sync from height {our_height} with {peer_addr}");
-
-        let sync_msg = P2PMessage::GetBlocks {
-            start_height: our_height.try_into().unwrap(),
-            count: self.config.sync_batch_size,
-        };
-
-        self.send_message(stream, &sync_msg).await
+        self.relay_blocks(vec![block.hash], None).await
     }
 
     // Peer maintenance (ping, cleanup)
@@ -476,6 +795,12 @@ impl BlockChain {
 
             log::info!("🔍 Peer maintenance: {} peers connected", peers.len());
 
+            // Ping counts are per interval
+            for peer in self.peers.write().await.values_mut() {
+                peer.pings = 0;
+            }
+            self.decay_scores().await;
+
             for peer_addr in peers {
                 // Check if peer is still alive
                 let should_disconnect = {
@@ -494,44 +819,114 @@ impl BlockChain {
 
             // Broadcast ping to check peer health
             let _ = self.broadcast_message(P2PMessage::Ping).await;
+
+            // Hand timed out sync requests to other peers, or try again after a stall
+            if let Err(e) = self.drive_sync().await {
+                log::error!("Chain sync failed: {e}");
+            }
+            if let Err(e) = self.retry_sync().await {
+                log::error!("Retrying chain sync failed: {e}");
+            }
+
+            if let Err(e) = self.request_missing_attachments().await {
+                log::error!("Requesting attachments failed: {e}");
+            }
+            if let Err(e) = self
+                .collect_attachments(attachments::UNREFERENCED_ATTACHMENT_TTL)
+                .await
+            {
+                log::error!("Deleting unreferenced attachments failed: {e}");
+            }
+        }
+    }
+
+    /// Features this node advertises in the handshake
+    pub fn capabilities(&self) -> Capabilities {
+        let capabilities = Capabilities::COMPRESSION | Capabilities::ATTACHMENTS;
+        if self.read_only {
+            capabilities | Capabilities::OBSERVER
+        } else {
+            capabilities
         }
     }
 
-    // Message serialization
+    // Message serialization, downgraded to what the peer supports
     async fn send_message(
         &self,
-        stream: &mut TcpStream,
+        stream: &mut SecureStream,
         message: &P2PMessage,
     ) -> Result<(), ChainError> {
-        let data = bincode::serialize(message)?;
-        let len = data.len() as u32;
-
-        // Send length prefix
-        stream.write_all(&len.to_be_bytes()).await?;
-        // Send message data
-        stream.write_all(&data).await?;
-        stream.flush().await?;
+        let peer_addr = stream.peer_addr()?;
+        let (version, capabilities) = self
+            .peers
+            .read()
+            .await
+            .get(&peer_addr)
+            .map(|peer| (peer.version, peer.capabilities))
+            .unwrap_or((MIN_PROTOCOL_VERSION, Capabilities::empty()));
+        // Older peers could not decode it
+        let since = message.since_version();
+        if since > version {
+            log::debug!("Not sending a version {since} message to {peer_addr} (version {version})");
+            return Ok(());
+        }
+        if message.is_consensus() && capabilities.contains(Capabilities::OBSERVER) {
+            log::debug!("Not sending a consensus message to observer {peer_addr}");
+            return Ok(());
+        }
 
-        Ok(())
+        let mut data = bincode::serialize(message)?;
+        if data.len() > self.config.compression_threshold
+            && capabilities.contains(Capabilities::COMPRESSION)
+        {
+            let compressed = lz4_flex::compress_prepend_size(&data);
+            data = bincode::serialize(&P2PMessage::Compressed(compressed))?;
+        }
+        stream.send(&data).await
     }
 
-    async fn read_message(&self, stream: &mut TcpStream) -> Result<P2PMessage, ChainError> {
-        // Read length prefix
-        let mut len_bytes = [0u8; 4];
-        stream.read_exact(&mut len_bytes).await?;
-        let len = u32::from_be_bytes(len_bytes) as usize;
+    async fn read_message(&self, stream: &mut SecureStream) -> Result<P2PMessage, ChainError> {
+        // Looked up first, so that `recv` is the only await a cancelled read can stop at
+        let peer_addr = stream.peer_addr()?;
+        let version = self
+            .peers
+            .read()
+            .await
+            .get(&peer_addr)
+            .map_or(MIN_PROTOCOL_VERSION, |peer| peer.version);
+        loop {
+            let data = stream.recv(self.config.max_message_size).await?;
+            let message = match P2PMessage::decode(&data, version)? {
+                Some(P2PMessage::Compressed(compressed)) => {
+                    self.decompress_message(&compressed, version)?
+                }
+                message => message,
+            };
+            match message {
+                Some(message) => return Ok(message),
+                None => log::debug!(
+                    "Dropping a message from {peer_addr} in its version {version} encoding"
+                ),
+            }
+        }
+    }
 
-        // Check message size limit
+    fn decompress_message(
+        &self,
+        compressed: &[u8],
+        version: u32,
+    ) -> Result<Option<P2PMessage>, ChainError> {
+        let malformed = |e: String| ChainError::from(Box::new(bincode::ErrorKind::Custom(e)));
+        let (len, body) =
+            lz4_flex::block::uncompressed_size(compressed).map_err(|e| malformed(e.to_string()))?;
         if len > self.config.max_message_size {
-            return Err(ChainError::Other("Message too large".into()));
+            return Err(ChainError::MessageTooLarge(len));
+        }
+        let data = lz4_flex::decompress(body, len).map_err(|e| malformed(e.to_string()))?;
+        match P2PMessage::decode(&data, version)? {
+            Some(P2PMessage::Compressed(_)) => Err(malformed("nested compressed message".into())),
+            message => Ok(message),
         }
-
-        // Read message data
-        let mut data = vec![0u8; len];
-        stream.read_exact(&mut data).await?;
-
-        let message: P2PMessage = deserialize(&data)?;
-        Ok(message)
     }
 
     // Blockchain operations (these would interact with your Database)
@@ -541,19 +936,23 @@ impl BlockChain {
     }
 
     async fn get_block_by_hash(&self, hash: &str) -> Result<Option<Block>, ChainError> {
-        let res = self.db.get_block_by_hash(hash).await?;
-        Ok(Some(res))
+        match self.db.get_block_by_hash(hash).await {
+            Ok(block) => Ok(Some(block)),
+            Err(sqlx::Error::RowNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
     }
 
-    #[allow(unused)]
     async fn get_blocks_range(
         &self,
         start_height: i64,
         count: u32,
     ) -> Result<Vec<Block>, ChainError> {
+        // Peers ask again for whatever did not fit in one batch
+        let count = count.min(self.config.sync_batch_size) as i64;
         Ok(self
             .db
-            .get_blocks_in_range(start_height, count as i64)
+            .get_blocks_in_range(start_height, start_height + count - 1)
             .await?)
     }
 
@@ -563,6 +962,14 @@ impl BlockChain {
     }
 
     async fn add_block_to_chain(&mut self, block: Block) -> Result<i64, ChainError> {
+        let parent = self.db.last_block().await?;
+        if block.prev_hash != parent.hash {
+            return self.handle_fork_block(block).await;
+        }
+
+        // Only blocks finalised by a quorum of validators are accepted
+        self.validator().validate_committed(&block, &parent).await?;
+
         // self.db
         //     .add_public_key(
         //         block.creator_pub_key.as_bytes(),
@@ -571,8 +978,11 @@ impl BlockChain {
         //         block.height as i32,
         //     )
         //     .await;
-        let res = self.db.add_block(&block).await?;
-        Ok(res)
+        self.append_block(&block).await
+    }
+
+    pub fn validator(&self) -> BlockValidator<'_> {
+        BlockValidator::new(&self.db, self.config.max_clock_drift)
     }
 
     // Get connected peers info
@@ -604,6 +1014,11 @@ pub async fn start_node(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::*;
+    use types::{
+        BlockType,
+        keys::{KeyOperation, KeyTransaction},
+    };
 
     #[tokio::test]
     async fn test_p2p_blockchain_creation() {
@@ -613,4 +1028,289 @@ mod tests {
         assert_eq!(blockchain.peer_count().await, 0);
         assert!(!blockchain.node_id.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_key_registry_is_replayed_from_chain() {
+        let mut blockchain = single_validator_chain().await;
+        let signer = blockchain.db.get_private_key().await.unwrap();
+        let genesis = blockchain.last_block().await.unwrap();
+        assert_eq!(blockchain.validators().await.unwrap().len(), 1);
+
+        let new_key = types::crypto::get_public_key(&types::crypto::get_private_key());
+        let register = KeyTransaction::new(
+            &signer,
+            KeyOperation::Register {
+                creator: "observer-1".to_string(),
+                pub_key: bincode::serialize(&new_key).unwrap(),
+            },
+        );
+        let block = Block::with_inner(
+            &signer,
+            &genesis.hash,
+            BlockType::KeyManagement(vec![register]),
+            1,
+        );
+        blockchain.propose_block(block).await.unwrap();
+        assert_eq!(blockchain.validators().await.unwrap().len(), 2);
+
+        let revoke = KeyTransaction::new(
+            &signer,
+            KeyOperation::Revoke {
+                pubkey_hash: types::crypto::sha256_digest(&new_key),
+            },
+        );
+        let tip = blockchain.last_block().await.unwrap();
+        let block = Block::with_inner(
+            &signer,
+            &tip.hash,
+            BlockType::KeyManagement(vec![revoke]),
+            2,
+        );
+        // With two validators the unknown key must precommit too, so bypass consensus
+        blockchain.add_block(&block).await.unwrap();
+
+        let validators = blockchain.validators().await.unwrap();
+        assert_eq!(validators.len(), 1);
+        assert_eq!(validators[0].hash, signer.2.hash);
+        assert!(blockchain.is_valid().await.unwrap());
+    }
+
+    async fn read(stream: &mut SecureStream) -> P2PMessage {
+        bincode::deserialize(&stream.recv(1 << 20).await.unwrap()).unwrap()
+    }
+
+    async fn write(stream: &mut SecureStream, message: &P2PMessage) {
+        stream
+            .send(&bincode::serialize(message).unwrap())
+            .await
+            .unwrap();
+    }
+
+    /// Connects to `node` and reads its `Hello`, returning the stream, our
+    /// address as the node sees it and the key we connected with
+    async fn connect(mut node: BlockChain) -> (SecureStream, SocketAddr, NodeKey) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, peer_addr) = listener.accept().await.unwrap();
+            let stream = SecureStream::accept(stream, &node.node_key).await.unwrap();
+            node.handle_peer_connection(stream, peer_addr).await
+        });
+
+        let key = NodeKey::generate();
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let local_addr = stream.local_addr().unwrap();
+        let mut stream = SecureStream::connect(stream, &key).await.unwrap();
+        assert!(matches!(
+            read(&mut stream).await,
+            P2PMessage::Hello { version: PROTOCOL_VERSION, capabilities, .. }
+                if capabilities.contains(Capabilities::COMPRESSION)
+        ));
+        (stream, local_addr, key)
+    }
+
+    /// Completes the handshake as a peer on `version`
+    async fn handshake(
+        node: BlockChain,
+        version: u32,
+        capabilities: Capabilities,
+    ) -> (SecureStream, SocketAddr) {
+        let (mut stream, addr, key) = connect(node).await;
+        let hello = P2PMessage::Hello {
+            node_id: key.node_id(),
+            version,
+            chain_height: 0,
+            capabilities,
+        };
+        write(&mut stream, &hello).await;
+        assert!(matches!(
+            read(&mut stream).await,
+            P2PMessage::HelloResponse { accepted: true, .. }
+        ));
+        (stream, addr)
+    }
+
+    fn nil_vote() -> types::consensus::Vote {
+        types::consensus::Vote {
+            height: 1,
+            round: 0,
+            block_hash: None,
+            phase: types::consensus::VotePhase::Prevote,
+            validator: String::new(),
+            signature: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_incompatible_peer_is_turned_away() {
+        let node = single_validator_chain().await;
+        let (mut stream, _, key) = connect(node).await;
+
+        // A version 1 hello, which ends before the capabilities
+        let hello = (0u32, key.node_id(), 1u32, 0i64);
+        stream
+            .send(&bincode::serialize(&hello).unwrap())
+            .await
+            .unwrap();
+        assert!(matches!(
+            read(&mut stream).await,
+            P2PMessage::HelloResponse {
+                accepted: false,
+                ..
+            }
+        ));
+        assert!(matches!(
+            read(&mut stream).await,
+            P2PMessage::Disconnect { reason } if reason.contains("version 1")
+        ));
+        assert!(stream.recv(1 << 20).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_previous_version_peer_syncs_without_consensus() {
+        let node = single_validator_chain().await;
+        let version = PROTOCOL_VERSION - 1;
+        let (mut stream, addr) = handshake(node.clone(), version, Capabilities::COMPRESSION).await;
+        assert_eq!(node.peers.read().await[&addr].version, version);
+
+        // A vote from before consensus rounds is dropped, the peer is not to blame
+        let vote = (
+            18u32,
+            1u64,
+            "ab".repeat(32),
+            0u32,
+            "cd".repeat(32),
+            "ef".repeat(32),
+        );
+        stream
+            .send(&bincode::serialize(&vote).unwrap())
+            .await
+            .unwrap();
+        let request = P2PMessage::GetHeaders {
+            start_height: 0,
+            count: 10,
+        };
+        write(&mut stream, &request).await;
+        assert!(matches!(
+            read(&mut stream).await,
+            P2PMessage::HeadersResponse { headers } if headers.len() == 1
+        ));
+        assert_eq!(node.peers.read().await[&addr].score, 0);
+
+        // Nor is it sent votes it could not decode
+        node.broadcast_message(P2PMessage::Vote(nil_vote()))
+            .await
+            .unwrap();
+        node.broadcast_message(P2PMessage::Ping).await.unwrap();
+        assert!(matches!(read(&mut stream).await, P2PMessage::Ping));
+    }
+
+    #[tokio::test]
+    async fn test_observers_take_no_part_in_consensus() {
+        let node = single_validator_chain().await;
+        let signer = node.db.get_private_key().await.unwrap();
+        let genesis = node.last_block().await.unwrap();
+        let capabilities = Capabilities::COMPRESSION | Capabilities::OBSERVER;
+        let (mut stream, _) = handshake(node.clone(), PROTOCOL_VERSION, capabilities).await;
+
+        node.broadcast_message(P2PMessage::Vote(nil_vote()))
+            .await
+            .unwrap();
+        node.broadcast_message(P2PMessage::Ping).await.unwrap();
+        assert!(matches!(read(&mut stream).await, P2PMessage::Ping));
+
+        // A forged proposal would be answered with `BlockRejected`, were it not ignored
+        let mut block = Block::with_inner(&signer, &genesis.hash, BlockType::Result(vec![]), 1);
+        block.hash = "00".repeat(32);
+        write(&mut stream, &P2PMessage::Proposal { block, round: 0 }).await;
+        write(&mut stream, &P2PMessage::Ping).await;
+        assert!(matches!(read(&mut stream).await, P2PMessage::Pong));
+    }
+
+    #[tokio::test]
+    async fn test_compressed_messages_are_bounded() {
+        let chain = single_validator_chain().await;
+        let genesis = chain.last_block().await.unwrap();
+        let message = P2PMessage::BlocksResponse {
+            blocks: vec![genesis; 20],
+        };
+        let data = bincode::serialize(&message).unwrap();
+        let compressed = lz4_flex::compress_prepend_size(&data);
+        assert!(compressed.len() < data.len());
+        assert!(matches!(
+            chain.decompress_message(&compressed, PROTOCOL_VERSION),
+            Ok(Some(P2PMessage::BlocksResponse { blocks })) if blocks.len() == 20
+        ));
+
+        let config = P2PConfig {
+            max_message_size: data.len() - 1,
+            ..Default::default()
+        };
+        let node = follower_of(&chain, config).await;
+        assert!(matches!(
+            node.decompress_message(&compressed, PROTOCOL_VERSION),
+            Err(ChainError::MessageTooLarge(_))
+        ));
+        assert!(matches!(
+            node.decompress_message(&[1, 2, 3], PROTOCOL_VERSION),
+            Err(ChainError::SerializationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_explorer_pages_through_blocks() {
+        let mut chain = election_chain().await;
+        let signer = chain.db.get_private_key().await.unwrap();
+        propose(
+            &mut chain,
+            BlockType::Result(vec![result(1, 66), result(2, 21)]),
+        )
+        .await
+        .unwrap();
+        let chain_votes = chain.station_votes(ELECTION, STATION).await.unwrap();
+        let attestations = types::attestation::compare(&chain_votes, &chain_votes);
+        propose(&mut chain, BlockType::Attestation(attestations))
+            .await
+            .unwrap();
+
+        let page = chain.block_summaries(1, 2).await.unwrap();
+        let listed: Vec<_> = page
+            .items
+            .iter()
+            .map(|b| (b.height, b.kind.as_str(), b.transactions))
+            .collect();
+        assert_eq!(listed, vec![(2, "result", 2), (1, "election", 1)]);
+        assert_eq!(page.total, 4);
+        assert!(page.has_more());
+
+        // The election block names the station's constituency, not the station
+        let history = chain.station_history(STATION, 0, 20).await.unwrap();
+        let heights: Vec<_> = history.items.iter().map(|b| b.height).collect();
+        assert_eq!(heights, vec![3, 2]);
+
+        let signed = chain.blocks_by_signer(&signer.2.hash, 0, 20).await.unwrap();
+        assert_eq!(signed.total, 4);
+        assert!(
+            chain
+                .blocks_by_signer("f00d", 0, 20)
+                .await
+                .unwrap()
+                .items
+                .is_empty()
+        );
+        assert_eq!(chain.results_at_height(2).await.unwrap().len(), 2);
+
+        let summary = chain.chain_summary().await.unwrap();
+        assert_eq!(summary.height, 3);
+        assert_eq!(summary.tip.kind, "attestation");
+        assert_eq!(
+            summary.genesis_hash,
+            chain.get_block_by_height(0).await.unwrap().hash
+        );
+        assert_eq!(
+            (summary.signers, summary.elections, summary.results),
+            (1, 1, 2)
+        );
+        assert_eq!(summary.stations.pending, 1);
+    }
 }
@@ -0,0 +1,299 @@
+use std::{net::SocketAddr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use types::{
+    error::ChainError,
+    p2p::{Misbehaviour, P2PMessage},
+};
+
+use crate::{BlockChain, SecureStream};
+
+/// Delay before the next attempt after `failures` consecutive failures
+pub fn reconnect_backoff(failures: i64, base: Duration, max: Duration) -> Duration {
+    let factor = 1u32 << failures.clamp(0, 16);
+    base.saturating_mul(factor).min(max)
+}
+
+impl BlockChain {
+    /// Remembers the peers in `addresses` so they survive a restart
+    pub async fn add_known_peers(
+        &self,
+        addresses: &[SocketAddr],
+        permanent: bool,
+    ) -> Result<(), ChainError> {
+        for address in addresses {
+            self.db.add_peer(&address.to_string(), permanent).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn record_peer_failure(&self, addr: SocketAddr) -> Result<(), ChainError> {
+        let address = addr.to_string();
+        let Some(peer) = self.db.get_peer(&address).await? else {
+            return Ok(());
+        };
+        let backoff = reconnect_backoff(
+            peer.failures,
+            self.config.reconnect_backoff,
+            self.config.max_reconnect_backoff,
+        );
+        log::debug!(
+            "Retrying {address} in {}s after {} failures",
+            backoff.as_secs(),
+            peer.failures + 1
+        );
+        self.db
+            .peer_failed(&address, Utc::now().timestamp() + backoff.as_secs() as i64)
+            .await?;
+        Ok(())
+    }
+
+    /// Adds the penalty for `misbehaviour` to the peer's score, banning it
+    /// with [`ChainError::PeerBanned`] once the score reaches the threshold
+    pub(crate) async fn penalize(
+        &self,
+        peer_addr: SocketAddr,
+        node_id: &str,
+        misbehaviour: Misbehaviour,
+    ) -> Result<(), ChainError> {
+        let score = match self.peers.write().await.get_mut(&peer_addr) {
+            Some(peer) => {
+                peer.score += misbehaviour.penalty();
+                peer.score
+            }
+            None => return Ok(()),
+        };
+        log::warn!("⚠️ Peer {peer_addr} {misbehaviour} (score {score})");
+        if score < self.config.ban_threshold {
+            return Ok(());
+        }
+
+        let bans = self
+            .db
+            .get_ban(node_id)
+            .await?
+            .map(|ban| ban.bans)
+            .unwrap_or_default();
+        let banned_until = (bans + 1 < self.config.permanent_ban_after as i64)
+            .then(|| Utc::now().timestamp() + self.config.ban_duration.as_secs() as i64);
+        let reason = misbehaviour.to_string();
+        self.db
+            .ban_peer(node_id, peer_addr, &reason, banned_until)
+            .await?;
+        log::warn!("🚫 Banned peer {peer_addr} ({node_id}): {reason}");
+        Err(ChainError::PeerBanned(reason))
+    }
+
+    /// Fails with [`ChainError::PeerBanned`] if `node_id` or the IP it connects from is banned
+    pub(crate) async fn check_ban(
+        &self,
+        node_id: &str,
+        peer_addr: SocketAddr,
+    ) -> Result<(), ChainError> {
+        let now = Utc::now().timestamp();
+        match self.db.active_ban(node_id, peer_addr.ip(), now).await? {
+            Some(ban) => {
+                let until = ban
+                    .banned_until
+                    .and_then(|t| DateTime::from_timestamp(t, 0))
+                    .map(|t| format!("until {}", t.to_rfc3339()))
+                    .unwrap_or_else(|| "permanently".to_string());
+                Err(ChainError::PeerBanned(format!("{} ({until})", ban.reason)))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Forgives part of every peer's misbehaviour score, called once per `ping_interval`
+    /// so only peers that keep misbehaving reach the ban threshold
+    pub(crate) async fn decay_scores(&self) {
+        for peer in self.peers.write().await.values_mut() {
+            peer.score = (peer.score - self.config.score_decay).max(0);
+        }
+    }
+
+    /// Tells a banned peer why before the connection is dropped
+    pub(crate) async fn disconnect_banned(
+        &self,
+        stream: &mut SecureStream,
+        res: Result<(), ChainError>,
+    ) -> Result<(), ChainError> {
+        if let Err(ChainError::PeerBanned(reason)) = &res {
+            let message = P2PMessage::Disconnect {
+                reason: format!("banned: {reason}"),
+            };
+            let _ = self.send_message(stream, &message).await;
+        }
+        res
+    }
+
+    /// Reconnects to permanent peers from the peer book whose backoff has expired
+    pub(crate) async fn reconnect_loop(&self) {
+        let mut interval = tokio::time::interval(self.config.reconnect_backoff);
+
+        while *self.is_running.read().await {
+            interval.tick().await;
+
+            let peers = match self.db.peers().await {
+                Ok(peers) => peers,
+                Err(e) => {
+                    log::error!("Failed to load the peer book: {e}");
+                    continue;
+                }
+            };
+            let now = Utc::now().timestamp();
+            let banned: Vec<String> = match self.db.bans().await {
+                Ok(bans) => bans
+                    .into_iter()
+                    .filter(|ban| ban.is_active(now))
+                    .map(|ban| ban.ip)
+                    .collect(),
+                Err(e) => {
+                    log::error!("Failed to load bans: {e}");
+                    continue;
+                }
+            };
+            for peer in peers {
+                if !peer.permanent || peer.next_attempt.is_some_and(|t| t > now) {
+                    continue;
+                }
+                let Ok(addr) = peer.address.parse::<SocketAddr>() else {
+                    log::warn!("Ignoring malformed peer address {}", peer.address);
+                    continue;
+                };
+                if banned.contains(&addr.ip().to_string()) {
+                    continue;
+                }
+                if !self.peers.read().await.contains_key(&addr) {
+                    self.spawn_peer_connection(addr);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use types::p2p::{P2PConfig, PeerConnection};
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let base = Duration::from_secs(5);
+        let max = Duration::from_secs(600);
+        assert_eq!(reconnect_backoff(0, base, max), Duration::from_secs(5));
+        assert_eq!(reconnect_backoff(3, base, max), Duration::from_secs(40));
+        assert_eq!(reconnect_backoff(10, base, max), max);
+        assert_eq!(reconnect_backoff(1000, base, max), max);
+    }
+
+    #[tokio::test]
+    async fn test_peer_book_tracks_failures() {
+        let blockchain = single_validator_chain().await;
+        let addr: SocketAddr = "127.0.0.1:9090".parse().unwrap();
+        blockchain.add_known_peers(&[addr], true).await.unwrap();
+
+        blockchain.record_peer_failure(addr).await.unwrap();
+        blockchain.record_peer_failure(addr).await.unwrap();
+        let peer = blockchain
+            .get_peer("127.0.0.1:9090")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(peer.permanent);
+        assert_eq!(peer.failures, 2);
+        assert!(peer.next_attempt.unwrap() > chrono::Utc::now().timestamp());
+
+        blockchain.peer_seen("127.0.0.1:9090").await.unwrap();
+        let peer = blockchain
+            .get_peer("127.0.0.1:9090")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(peer.failures, 0);
+        assert!(peer.next_attempt.is_none() && peer.last_seen.is_some());
+
+        // Rediscovering a permanent peer keeps it permanent
+        blockchain.add_known_peers(&[addr], false).await.unwrap();
+        assert!(blockchain.peers().await.unwrap()[0].permanent);
+    }
+
+    #[tokio::test]
+    async fn test_misbehaving_peer_is_banned() {
+        let chain = single_validator_chain().await;
+        let config = P2PConfig {
+            permanent_ban_after: 2,
+            ..Default::default()
+        };
+        let node = follower_of(&chain, config).await;
+        let addr: SocketAddr = "127.0.0.1:9090".parse().unwrap();
+        let node_id = "ab".repeat(32);
+
+        for ban in 0..2 {
+            node.peers
+                .write()
+                .await
+                .insert(addr, PeerConnection::new(addr));
+            for _ in 0..4 {
+                node.penalize(addr, &node_id, Misbehaviour::MalformedMessage)
+                    .await
+                    .unwrap();
+            }
+            assert!(node.check_ban(&node_id, addr).await.is_ok());
+            let res = node
+                .penalize(addr, &node_id, Misbehaviour::MalformedMessage)
+                .await;
+            assert!(matches!(res, Err(ChainError::PeerBanned(_))));
+            assert!(matches!(
+                node.check_ban(&node_id, addr).await,
+                Err(ChainError::PeerBanned(_))
+            ));
+            // A fresh key from the same IP is refused as well
+            assert!(matches!(
+                node.check_ban(&"cd".repeat(32), "127.0.0.1:9191".parse().unwrap())
+                    .await,
+                Err(ChainError::PeerBanned(_))
+            ));
+
+            let ban_row = node.get_ban(&node_id).await.unwrap().unwrap();
+            assert_eq!(ban_row.bans, ban + 1);
+            assert_eq!(ban_row.address, "127.0.0.1:9090");
+            assert_eq!(ban_row.ip, "127.0.0.1");
+            // The second ban is permanent
+            assert_eq!(ban_row.banned_until.is_none(), ban == 1);
+
+            // Let the temporary ban run out
+            sqlx::query("UPDATE bans SET banned_until = 0 WHERE banned_until IS NOT NULL")
+                .execute(&node.chain_db)
+                .await
+                .unwrap();
+        }
+
+        assert!(node.unban_peer(&node_id).await.unwrap());
+        assert!(node.check_ban(&node_id, addr).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_misbehaviour_scores_decay() {
+        let chain = single_validator_chain().await;
+        let node = follower_of(&chain, P2PConfig::default()).await;
+        let addr: SocketAddr = "127.0.0.1:9090".parse().unwrap();
+        let node_id = "ab".repeat(32);
+        node.peers
+            .write()
+            .await
+            .insert(addr, PeerConnection::new(addr));
+
+        // Occasional slips are forgiven before they add up to a ban
+        for _ in 0..20 {
+            node.penalize(addr, &node_id, Misbehaviour::MalformedMessage)
+                .await
+                .unwrap();
+            node.decay_scores().await;
+            node.decay_scores().await;
+        }
+        assert_eq!(node.peers.read().await[&addr].score, 0);
+        assert!(node.check_ban(&node_id, addr).await.is_ok());
+    }
+}
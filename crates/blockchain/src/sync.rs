@@ -0,0 +1,615 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use types::{
+    Block, SignedHeader,
+    crypto::hash_block,
+    error::ChainError,
+    events::ChainEvent,
+    p2p::{Misbehaviour, P2PMessage},
+};
+
+use crate::{BlockChain, SecureStream};
+
+/// Most headers requested or served at once
+pub const MAX_HEADERS: u32 = 2000;
+
+/// Wait before syncing again after every peer failed to serve the sync,
+/// doubled for each consecutive failure up to [`MAX_SYNC_RETRY`]
+const SYNC_RETRY: Duration = Duration::from_secs(10);
+const MAX_SYNC_RETRY: Duration = Duration::from_secs(600);
+
+/// Progress of the chain sync, as reported by [`BlockChain::sync_status`]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncStatus {
+    pub syncing: bool,
+    pub current_height: i64,
+    pub target_height: i64,
+    /// Highest header linked to our chain, bodies are downloaded up to here
+    pub header_height: i64,
+    pub blocks_per_sec: f64,
+    /// Peers we are downloading from
+    pub peers: usize,
+}
+
+/// Headers-first sync. Headers are fetched from the best peer and linked to
+/// our tip, bodies are then downloaded in batches from every peer that has
+/// them and applied in order. Requests to peers that disconnect or time out
+/// are handed to the remaining peers. If no peer is left the sync is
+/// abandoned and retried later with the peers ahead of us.
+#[derive(Debug, Default)]
+pub struct SyncState {
+    target_height: i64,
+    /// Peers ahead of us and the height they advertised
+    sources: HashMap<SocketAddr, i64>,
+    headers: BTreeMap<i64, SignedHeader>,
+    headers_request: Option<(SocketAddr, Instant)>,
+    /// In-flight body batches: start height -> (end height, peer, sent at)
+    body_requests: BTreeMap<i64, (i64, SocketAddr, Instant)>,
+    bodies: BTreeMap<i64, Block>,
+    /// When the sync started and our height at the time
+    started: Option<(Instant, i64)>,
+    /// Syncs abandoned in a row and when to try again
+    stalls: u32,
+    retry_at: Option<Instant>,
+}
+
+impl SyncState {
+    fn is_active(&self) -> bool {
+        self.started.is_some()
+    }
+
+    fn is_backing_off(&self) -> bool {
+        self.retry_at.is_some_and(|at| Instant::now() < at)
+    }
+
+    /// Abandons the sync and backs off before the next attempt
+    fn stall(&mut self) -> Duration {
+        let stalls = self.stalls + 1;
+        let delay = SYNC_RETRY
+            .saturating_mul(1 << (stalls - 1).min(16))
+            .min(MAX_SYNC_RETRY);
+        *self = SyncState {
+            stalls,
+            retry_at: Some(Instant::now() + delay),
+            ..Default::default()
+        };
+        delay
+    }
+
+    fn expire_requests(&mut self, timeout: Duration) {
+        if self
+            .headers_request
+            .is_some_and(|(_, sent)| sent.elapsed() > timeout)
+        {
+            self.headers_request = None;
+        }
+        self.body_requests
+            .retain(|_, (_, _, sent)| sent.elapsed() <= timeout);
+    }
+
+    fn release_peer(&mut self, peer: SocketAddr) {
+        self.sources.remove(&peer);
+        if self.headers_request.is_some_and(|(addr, _)| addr == peer) {
+            self.headers_request = None;
+        }
+        self.body_requests.retain(|_, (_, addr, _)| *addr != peer);
+    }
+
+    /// Drops everything above `height`, e.g. after a body failed validation
+    fn reset_above(&mut self, height: i64) {
+        self.headers.retain(|h, _| *h <= height);
+        self.bodies.retain(|h, _| *h <= height);
+        self.body_requests.retain(|start, _| *start <= height);
+        self.headers_request = None;
+    }
+
+    /// Requests to send so headers and bodies up to the target are in flight
+    fn next_requests(&mut self, height: i64, batch: i64) -> Vec<(SocketAddr, P2PMessage)> {
+        let now = Instant::now();
+        let mut requests = Vec::new();
+        self.headers.retain(|h, _| *h > height);
+        self.bodies.retain(|h, _| *h > height);
+        self.body_requests.retain(|_, (end, _, _)| *end > height);
+
+        let header_height = self.header_height(height);
+        if self.headers_request.is_none() && header_height < self.target_height {
+            let best = self
+                .sources
+                .iter()
+                .max_by_key(|(_, h)| **h)
+                .map(|(addr, _)| *addr);
+            if let Some(peer) = best {
+                self.headers_request = Some((peer, now));
+                requests.push((
+                    peer,
+                    P2PMessage::GetHeaders {
+                        start_height: header_height + 1,
+                        count: MAX_HEADERS,
+                    },
+                ));
+            }
+        }
+
+        // One body batch per peer at a time spreads the download across peers
+        let busy: HashSet<SocketAddr> = self.body_requests.values().map(|(_, a, _)| *a).collect();
+        let mut idle: Vec<(SocketAddr, i64)> = self
+            .sources
+            .iter()
+            .filter(|(addr, _)| !busy.contains(addr))
+            .map(|(addr, h)| (*addr, *h))
+            .collect();
+        idle.sort();
+
+        let mut start = height + 1;
+        while start <= header_height && !idle.is_empty() {
+            if self.bodies.contains_key(&start) {
+                start += 1;
+                continue;
+            }
+            if let Some((_, (end, _, _))) = self.body_requests.range(..=start).next_back()
+                && *end >= start
+            {
+                start = end + 1;
+                continue;
+            }
+            let next_request = self
+                .body_requests
+                .range(start..)
+                .next()
+                .map(|(s, _)| s - 1)
+                .unwrap_or(i64::MAX);
+            let end = (start + batch - 1).min(header_height).min(next_request);
+
+            let Some(pos) = idle.iter().position(|(_, h)| *h >= end) else {
+                break;
+            };
+            let (peer, _) = idle.remove(pos);
+            self.body_requests.insert(start, (end, peer, now));
+            requests.push((
+                peer,
+                P2PMessage::GetBlocks {
+                    start_height: start,
+                    count: (end - start + 1) as u32,
+                },
+            ));
+            start = end + 1;
+        }
+        requests
+    }
+
+    fn header_height(&self, height: i64) -> i64 {
+        self.headers.keys().next_back().copied().unwrap_or(height)
+    }
+}
+
+impl BlockChain {
+    pub async fn sync_status(&self) -> SyncStatus {
+        let current_height = self.get_chain_height().await.unwrap_or(-1);
+        let sync = self.sync.read().await;
+        let blocks_per_sec = sync
+            .started
+            .map(|(started, start_height)| {
+                let elapsed = started.elapsed().as_secs_f64();
+                if elapsed > 0.0 {
+                    (current_height - start_height) as f64 / elapsed
+                } else {
+                    0.0
+                }
+            })
+            .unwrap_or_default();
+        SyncStatus {
+            syncing: sync.is_active(),
+            current_height,
+            target_height: sync.target_height.max(current_height),
+            header_height: sync.header_height(current_height),
+            blocks_per_sec,
+            peers: sync.sources.len(),
+        }
+    }
+
+    /// Starts or extends the sync after `peer` advertised `peer_height`
+    pub(crate) async fn start_sync(
+        &self,
+        peer: SocketAddr,
+        peer_height: i64,
+    ) -> Result<(), ChainError> {
+        let height = self.get_chain_height().await?;
+        if peer_height <= height {
+            return Ok(());
+        }
+        let started = {
+            let mut sync = self.sync.write().await;
+            if sync.is_backing_off() {
+                log::debug!("Not syncing from {peer} yet, backing off after a failed sync");
+                return Ok(());
+            }
+            let started = !sync.is_active();
+            if started {
+                log::info!("🔄 Starting chain sync from height {height} to {peer_height}");
+                sync.started = Some((Instant::now(), height));
+            }
+            sync.sources.insert(peer, peer_height);
+            sync.target_height = sync.target_height.max(peer_height);
+            started
+        };
+        if started {
+            self.emit(ChainEvent::SyncStarted {
+                height,
+                target_height: peer_height,
+            });
+        }
+        if let Some(peer) = self.peers.write().await.get_mut(&peer) {
+            peer.is_syncing = true;
+        }
+        self.drive_sync().await
+    }
+
+    /// Sends whatever requests the sync needs next, or finishes it
+    pub(crate) async fn drive_sync(&self) -> Result<(), ChainError> {
+        let height = self.get_chain_height().await?;
+        let (requests, finished) = {
+            let mut sync = self.sync.write().await;
+            if !sync.is_active() {
+                return Ok(());
+            }
+            if height >= sync.target_height {
+                let sources: Vec<SocketAddr> = sync.sources.keys().copied().collect();
+                *sync = SyncState::default();
+                log::info!("✅ Chain sync complete at height {height}");
+                (Vec::new(), Some(sources))
+            } else if sync.sources.is_empty() {
+                // Every peer failed us, the released ones are no longer syncing
+                let delay = sync.stall();
+                log::warn!(
+                    "Chain sync stalled at height {height} with no peers left, retrying in {delay:?}"
+                );
+                (Vec::new(), Some(Vec::new()))
+            } else {
+                sync.expire_requests(self.config.connection_timeout);
+                let batch = self.config.sync_batch_size.max(1) as i64;
+                (sync.next_requests(height, batch), None)
+            }
+        };
+
+        if let Some(sources) = finished {
+            self.emit(ChainEvent::SyncFinished { height });
+            let mut peers = self.peers.write().await;
+            for addr in sources {
+                if let Some(peer) = peers.get_mut(&addr) {
+                    peer.is_syncing = false;
+                }
+            }
+        }
+        for (peer, request) in requests {
+            self.send_to_peer(peer, request).await;
+        }
+        Ok(())
+    }
+
+    /// Stops downloading from a peer, its requests go to the others
+    pub(crate) async fn release_sync_peer(&self, peer: SocketAddr) -> Result<(), ChainError> {
+        self.sync.write().await.release_peer(peer);
+        self.drive_sync().await
+    }
+
+    /// Syncs again from the peers ahead of us once the back-off of a stalled sync is over
+    pub(crate) async fn retry_sync(&self) -> Result<(), ChainError> {
+        {
+            let mut sync = self.sync.write().await;
+            if sync.is_active() || sync.retry_at.is_none() || sync.is_backing_off() {
+                return Ok(());
+            }
+            sync.retry_at = None;
+        }
+        let height = self.get_chain_height().await?;
+        let ahead: Vec<(SocketAddr, i64)> = self
+            .peers
+            .read()
+            .await
+            .values()
+            .filter(|peer| peer.chain_height > height)
+            .map(|peer| (peer.addr, peer.chain_height))
+            .collect();
+        for (peer, peer_height) in ahead {
+            self.start_sync(peer, peer_height).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn handle_get_headers_request(
+        &self,
+        start_height: i64,
+        count: u32,
+        stream: &mut SecureStream,
+    ) -> Result<(), ChainError> {
+        let end = start_height + count.min(MAX_HEADERS) as i64 - 1;
+        let headers = self
+            .db
+            .get_blocks_in_range(start_height, end)
+            .await?
+            .iter()
+            .map(Block::signed_header)
+            .collect();
+        self.send_message(stream, &P2PMessage::HeadersResponse { headers })
+            .await
+    }
+
+    /// Links the headers to the ones we have, stopping at the first that does
+    /// not fit. Headers are only taken in answer to our `GetHeaders`, the peer
+    /// is penalised for any others.
+    pub(crate) async fn handle_headers_response(
+        &self,
+        headers: Vec<SignedHeader>,
+        peer: SocketAddr,
+        node_id: &str,
+    ) -> Result<(), ChainError> {
+        let tip = self.db.last_block().await?;
+        {
+            let mut sync = self.sync.write().await;
+            if sync.headers_request.is_none_or(|(addr, _)| addr != peer) {
+                drop(sync);
+                return self
+                    .penalize(peer, node_id, Misbehaviour::UnsolicitedHeaders)
+                    .await;
+            }
+            sync.headers_request = None;
+            let (mut prev_height, mut prev_hash) = match sync.headers.last_key_value() {
+                Some((height, header)) => (*height, header.hash.clone()),
+                None => (tip.height as i64, tip.hash.clone()),
+            };
+
+            let mut linked = 0;
+            for header in headers {
+                let height = header.height as i64;
+                if height <= prev_height {
+                    continue;
+                }
+                let hash = header.header().map(|h| hash_block(&h));
+                if height != prev_height + 1
+                    || header.prev_hash != prev_hash
+                    || hash.as_ref() != Some(&header.hash)
+                {
+                    log::warn!("Peer {peer} sent a header at height {height} that does not link");
+                    sync.release_peer(peer);
+                    break;
+                }
+                prev_height = height;
+                prev_hash = header.hash.clone();
+                sync.headers.insert(height, header);
+                linked += 1;
+            }
+            // Without new headers the peer cannot help us reach the target
+            if linked == 0 {
+                sync.release_peer(peer);
+            }
+            log::debug!("Linked {linked} headers from {peer}, up to height {prev_height}");
+        }
+        self.drive_sync().await
+    }
+
+    /// Stores bodies that match their headers and applies them in order.
+    /// Returns the hash and error of a body that failed validation.
+    pub(crate) async fn handle_sync_blocks(
+        &mut self,
+        blocks: Vec<Block>,
+        peer: SocketAddr,
+    ) -> Result<Option<(String, ChainError)>, ChainError> {
+        {
+            let mut sync = self.sync.write().await;
+            if let Some(first) = blocks.first() {
+                let start = first.height as i64;
+                if sync
+                    .body_requests
+                    .get(&start)
+                    .is_some_and(|(_, addr, _)| *addr == peer)
+                {
+                    sync.body_requests.remove(&start);
+                }
+            }
+            for block in blocks {
+                let height = block.height as i64;
+                if sync
+                    .headers
+                    .get(&height)
+                    .is_some_and(|header| header.hash == block.hash)
+                {
+                    sync.bodies.insert(height, block);
+                }
+            }
+        }
+
+        let mut rejected = None;
+        loop {
+            let next = self.get_chain_height().await? + 1;
+            let Some(block) = self.sync.write().await.bodies.remove(&next) else {
+                break;
+            };
+            let hash = block.hash.clone();
+            if let Err(e) = self.add_block_to_chain(block).await {
+                // The header chain from here on is not valid, start over from our tip
+                let mut sync = self.sync.write().await;
+                sync.reset_above(next - 1);
+                sync.release_peer(peer);
+                rejected = Some((hash, e));
+                break;
+            }
+        }
+
+        self.drive_sync().await?;
+        Ok(rejected)
+    }
+
+    pub(crate) async fn is_syncing(&self) -> bool {
+        self.sync.read().await.is_active()
+    }
+
+    pub(crate) async fn send_to_peer(&self, peer: SocketAddr, message: P2PMessage) {
+        match self.peer_tx.read().await.get(&peer) {
+            Some(tx) => {
+                let _ = tx.send(message);
+            }
+            None => log::debug!("Cannot send to {peer}, not connected"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use types::p2p::{P2PConfig, PeerConnection};
+
+    #[tokio::test]
+    async fn test_headers_first_sync_across_peers() {
+        let mut source = single_validator_chain().await;
+        let signer = source.db.get_private_key().await.unwrap();
+        for height in 1..=25 {
+            let tip = source.last_block().await.unwrap();
+            let block = Block::new(&signer, &tip.hash, vec![], height, [0u8; 32]);
+            source.propose_block(block).await.unwrap();
+        }
+
+        let config = P2PConfig {
+            sync_batch_size: 10,
+            ..Default::default()
+        };
+        let mut node = follower_of(&source, config).await;
+        let (a, b): (SocketAddr, SocketAddr) = (
+            "127.0.0.1:9001".parse().unwrap(),
+            "127.0.0.1:9002".parse().unwrap(),
+        );
+        node.start_sync(a, 25).await.unwrap();
+        node.start_sync(b, 25).await.unwrap();
+
+        let status = node.sync_status().await;
+        assert!(status.syncing);
+        assert_eq!((status.current_height, status.target_height), (0, 25));
+        assert_eq!(status.peers, 2);
+
+        let headers = source
+            .get_blocks_in_range(1, 25)
+            .await
+            .unwrap()
+            .iter()
+            .map(Block::signed_header)
+            .collect();
+        // Only the peer the headers were asked from is listened to
+        let (asked, _) = node.sync.read().await.headers_request.unwrap();
+        node.handle_headers_response(headers, asked, &"ab".repeat(32))
+            .await
+            .unwrap();
+        assert_eq!(node.sync_status().await.header_height, 25);
+
+        // Bodies may arrive out of order, from either peer
+        let batch = |start, end| source.get_blocks_in_range(start, end);
+        node.handle_sync_blocks(batch(11, 20).await.unwrap(), b)
+            .await
+            .unwrap();
+        assert_eq!(node.get_chain_height().await.unwrap(), 0);
+        node.handle_sync_blocks(batch(1, 10).await.unwrap(), a)
+            .await
+            .unwrap();
+        assert_eq!(node.get_chain_height().await.unwrap(), 20);
+        assert!(node.sync_status().await.syncing);
+
+        node.handle_sync_blocks(batch(21, 25).await.unwrap(), a)
+            .await
+            .unwrap();
+        let status = node.sync_status().await;
+        assert!(!status.syncing);
+        assert_eq!(status.current_height, 25);
+        assert_eq!(
+            node.last_block().await.unwrap().hash,
+            source.last_block().await.unwrap().hash
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_rejects_unlinked_headers() {
+        let mut source = single_validator_chain().await;
+        let signer = source.db.get_private_key().await.unwrap();
+        let genesis = source.last_block().await.unwrap();
+        source
+            .propose_block(Block::new(&signer, &genesis.hash, vec![], 1, [0u8; 32]))
+            .await
+            .unwrap();
+
+        let node = follower_of(&source, P2PConfig::default()).await;
+        let peer: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        node.start_sync(peer, 1).await.unwrap();
+
+        let mut header = source.last_block().await.unwrap().signed_header();
+        header.merkle_root = [1u8; 32];
+        node.handle_headers_response(vec![header], peer, &"ab".repeat(32))
+            .await
+            .unwrap();
+
+        // The only peer is released, so the sync stops instead of waiting forever
+        let status = node.sync_status().await;
+        assert_eq!(status.header_height, 0);
+        assert_eq!(status.peers, 0);
+        assert!(!status.syncing);
+        assert!(!node.is_syncing().await);
+
+        // Peers are not synced from again until the back-off is over
+        node.start_sync(peer, 1).await.unwrap();
+        assert!(!node.is_syncing().await);
+        let mut connection = PeerConnection::new(peer);
+        connection.chain_height = 1;
+        node.peers.write().await.insert(peer, connection);
+        node.retry_sync().await.unwrap();
+        assert!(!node.is_syncing().await);
+
+        node.sync.write().await.retry_at = Some(Instant::now());
+        node.retry_sync().await.unwrap();
+        assert!(node.is_syncing().await);
+    }
+
+    #[tokio::test]
+    async fn test_unsolicited_headers_are_dropped() {
+        let mut source = single_validator_chain().await;
+        let signer = source.db.get_private_key().await.unwrap();
+        let genesis = source.last_block().await.unwrap();
+        source
+            .propose_block(Block::new(&signer, &genesis.hash, vec![], 1, [0u8; 32]))
+            .await
+            .unwrap();
+        let header = source.last_block().await.unwrap().signed_header();
+
+        let node = follower_of(&source, P2PConfig::default()).await;
+        let (peer, stranger): (SocketAddr, SocketAddr) = (
+            "127.0.0.1:9001".parse().unwrap(),
+            "127.0.0.1:9002".parse().unwrap(),
+        );
+        node.peers
+            .write()
+            .await
+            .insert(stranger, PeerConnection::new(stranger));
+
+        // Nobody was asked yet
+        node.handle_headers_response(vec![header.clone()], stranger, &"cd".repeat(32))
+            .await
+            .unwrap();
+        assert_eq!(node.sync_status().await.header_height, 0);
+        let penalty = Misbehaviour::UnsolicitedHeaders.penalty();
+        assert_eq!(node.peers.read().await[&stranger].score, penalty);
+
+        // Nor does asking one peer let another answer
+        node.start_sync(peer, 1).await.unwrap();
+        node.handle_headers_response(vec![header.clone()], stranger, &"cd".repeat(32))
+            .await
+            .unwrap();
+        assert_eq!(node.sync_status().await.header_height, 0);
+        assert_eq!(node.peers.read().await[&stranger].score, 2 * penalty);
+
+        node.handle_headers_response(vec![header], peer, &"ab".repeat(32))
+            .await
+            .unwrap();
+        assert_eq!(node.sync_status().await.header_height, 1);
+    }
+}
@@ -0,0 +1,151 @@
+//! Chains and blocks shared by the tests of the modules in this crate
+
+use database::Database;
+use p256::ecdsa::SigningKey;
+use types::{
+    Block, BlockSigner, BlockType, CandidateResult, StationSummary,
+    agents::{Agent, Submission},
+    consensus::{CommitCertificate, ValidatorSignature, Vote, VotePhase},
+    election::Area,
+    error::ChainError,
+    p2p::P2PConfig,
+};
+
+use crate::BlockChain;
+
+/// A single station in Juja with three candidates
+pub(crate) const REFERENCE_DATA: &str = "
+    INSERT INTO positions VALUES ('Mp');
+    INSERT INTO parties VALUES (1, 'ODM', ''), (2, 'PNU', '');
+    INSERT INTO counties VALUES (22, 'Kiambu');
+    INSERT INTO constituencies VALUES (113, 22, 'Juja');
+    INSERT INTO wards VALUES (563, 113, 'Kalimoni');
+    INSERT INTO stations VALUES (22113056303301, 563, 33, 'Athi Primary School', 533);
+    INSERT INTO candidates VALUES
+        (1, 'Mwas', 'M', '', 'Mp', 1, 22113056303301),
+        (2, 'Omosh', 'M', '', 'Mp', 2, 22113056303301),
+        (3, 'Wanjiku', 'F', '', 'Mp', 1, 22113056303301);
+";
+pub(crate) const STATION: i64 = 22113056303301;
+pub(crate) const ELECTION: i64 = 1;
+
+/// A chain with a genesis block whose key is the only validator
+pub(crate) async fn single_validator_chain() -> BlockChain {
+    let mut db = Database::new_in_memory();
+    sqlx::query(database::MAIN_SETUP)
+        .execute(&db.chain_db)
+        .await
+        .unwrap();
+    sqlx::query(database::PRIV_SETUP)
+        .execute(&db.private_db)
+        .await
+        .unwrap();
+
+    let key = types::crypto::get_private_key();
+    let verifying_key = types::crypto::get_public_key(&key);
+    let pub_key = types::PubKey::from_verifying_key("genesis", &verifying_key);
+    db.add_private_key(&key.to_bytes().to_vec(), &pub_key.hash)
+        .await
+        .unwrap();
+
+    let reference_data = Database::reference_data_from_sql(REFERENCE_DATA)
+        .await
+        .unwrap();
+    let genesis = Block::genesis(&(key, verifying_key, pub_key), reference_data);
+    db.bootstrap(&genesis).await.unwrap();
+    BlockChain::new(db, None)
+}
+
+/// A single validator chain with election 1 registered at height 1
+pub(crate) async fn election_chain() -> BlockChain {
+    let mut chain = single_validator_chain().await;
+    let election = types::election::Election {
+        id: ELECTION,
+        name: "Juja by-election".into(),
+        date: chrono::NaiveDate::from_ymd_opt(2025, 11, 27).unwrap(),
+        election_type: types::election::ElectionType::ByElection,
+        areas: vec![types::election::Area::Constituency(113)],
+        candidates: vec![1, 2],
+    };
+    propose(&mut chain, BlockType::Election(election))
+        .await
+        .unwrap();
+    chain
+}
+
+/// A node without a signing key that shares `chain`'s genesis block
+pub(crate) async fn follower_of(chain: &BlockChain, config: P2PConfig) -> BlockChain {
+    let mut db = Database::new_in_memory();
+    sqlx::query(database::MAIN_SETUP)
+        .execute(&db.chain_db)
+        .await
+        .unwrap();
+    db.add_block(&chain.get_block_by_height(0).await.unwrap())
+        .await
+        .unwrap();
+    BlockChain::new(db, Some(config))
+}
+
+/// Proposes a block carrying `inner` on top of the current tip
+pub(crate) async fn propose(chain: &mut BlockChain, inner: BlockType) -> Result<(), ChainError> {
+    let signer = chain.db.get_private_key().await.unwrap();
+    let tip = chain.last_block().await.unwrap();
+    let block = Block::with_inner(&signer, &tip.hash, inner, tip.height + 1);
+    chain.propose_block(block).await
+}
+
+/// Commits `block` with the precommits of `signers` in round 0
+pub(crate) fn certify(signers: &[&BlockSigner], mut block: Block) -> Block {
+    let signatures = signers
+        .iter()
+        .map(|signer| {
+            let vote = Vote::new(
+                signer,
+                block.height,
+                0,
+                Some(&block.hash),
+                VotePhase::Precommit,
+            );
+            ValidatorSignature {
+                validator: vote.validator,
+                signature: vote.signature,
+            }
+        })
+        .collect();
+    block.certificate = Some(CommitCertificate {
+        height: block.height,
+        round: 0,
+        block_hash: block.hash.clone(),
+        signatures,
+    });
+    block
+}
+
+/// Votes for `candidate_id` at the test station in the test election
+pub(crate) fn result(candidate_id: usize, votes: usize) -> CandidateResult {
+    CandidateResult::new(ELECTION as usize, STATION as usize, candidate_id, votes)
+}
+
+/// Registers a new agent of `organisation` for the ward of the test station
+pub(crate) async fn register_agent(chain: &mut BlockChain, organisation: &str) -> SigningKey {
+    let key = types::crypto::get_private_key();
+    let agent = Agent {
+        pub_key: bincode::serialize(&types::crypto::get_public_key(&key)).unwrap(),
+        organisation: organisation.into(),
+        scope: vec![Area::Ward(563)],
+    };
+    propose(chain, BlockType::Agents(vec![agent]))
+        .await
+        .unwrap();
+    key
+}
+
+/// A tally signed by the agent `key`
+pub(crate) fn tally(
+    key: &SigningKey,
+    results: Vec<CandidateResult>,
+    summaries: Vec<StationSummary>,
+    attachments: Vec<String>,
+) -> BlockType {
+    BlockType::Submission(Submission::new(key, results, summaries, attachments))
+}
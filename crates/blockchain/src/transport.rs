@@ -0,0 +1,326 @@
+use std::net::SocketAddr;
+
+use snow::{
+    Builder, HandshakeState, TransportState,
+    params::DHChoice,
+    resolvers::{CryptoResolver, DefaultResolver},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use types::error::ChainError;
+
+/// Mutual authentication with static X25519 keys, neither side needs to know
+/// the other's key in advance.
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+/// Largest Noise message on the wire
+const MAX_NOISE_MESSAGE: usize = 65535;
+/// Room for the ChaChaPoly tag
+const MAX_CHUNK: usize = MAX_NOISE_MESSAGE - 16;
+
+fn noise_error(e: snow::Error) -> ChainError {
+    ChainError::PeerError(format!("Noise error: {e}"))
+}
+
+/// Static key identifying this node on the P2P network
+#[derive(Clone)]
+pub struct NodeKey {
+    private: Vec<u8>,
+    public: Vec<u8>,
+}
+
+impl NodeKey {
+    pub fn generate() -> Self {
+        let keypair = Builder::new(NOISE_PARAMS.parse().unwrap())
+            .generate_keypair()
+            .unwrap();
+        Self {
+            private: keypair.private,
+            public: keypair.public,
+        }
+    }
+
+    pub fn from_private(private: &[u8]) -> Result<Self, ChainError> {
+        let mut dh = DefaultResolver
+            .resolve_dh(&DHChoice::Curve25519)
+            .ok_or_else(|| ChainError::PeerError("Curve25519 is unavailable".into()))?;
+        if private.len() != dh.priv_len() {
+            return Err(ChainError::PeerError("Invalid node key".into()));
+        }
+        dh.set(private);
+        Ok(Self {
+            private: private.to_vec(),
+            public: dh.pubkey().to_vec(),
+        })
+    }
+
+    pub fn private(&self) -> &[u8] {
+        &self.private
+    }
+
+    /// Hex encoded public key, used as the node id
+    pub fn node_id(&self) -> String {
+        hex::encode(&self.public)
+    }
+}
+
+impl std::fmt::Debug for NodeKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NodeKey")
+            .field("public", &self.node_id())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A TCP connection encrypted and authenticated with Noise
+pub struct SecureStream {
+    stream: TcpStream,
+    transport: TransportState,
+    buf: Vec<u8>,
+    /// Bytes read from the socket that do not make up a whole frame yet
+    rx: Vec<u8>,
+    /// Length and decrypted chunks of a message received in part
+    pending: Option<(usize, Vec<u8>)>,
+}
+
+impl SecureStream {
+    /// Runs the handshake as the side that opened the connection
+    pub async fn connect(stream: TcpStream, key: &NodeKey) -> Result<Self, ChainError> {
+        let handshake = Builder::new(NOISE_PARAMS.parse().unwrap())
+            .local_private_key(&key.private)
+            .build_initiator()
+            .map_err(noise_error)?;
+        Self::handshake(stream, handshake, true).await
+    }
+
+    /// Runs the handshake as the side that accepted the connection
+    pub async fn accept(stream: TcpStream, key: &NodeKey) -> Result<Self, ChainError> {
+        let handshake = Builder::new(NOISE_PARAMS.parse().unwrap())
+            .local_private_key(&key.private)
+            .build_responder()
+            .map_err(noise_error)?;
+        Self::handshake(stream, handshake, false).await
+    }
+
+    async fn handshake(
+        mut stream: TcpStream,
+        mut handshake: HandshakeState,
+        mut writing: bool,
+    ) -> Result<Self, ChainError> {
+        let mut buf = vec![0u8; MAX_NOISE_MESSAGE];
+        let mut payload = vec![0u8; MAX_NOISE_MESSAGE];
+        while !handshake.is_handshake_finished() {
+            if writing {
+                let len = handshake
+                    .write_message(&[], &mut buf)
+                    .map_err(noise_error)?;
+                write_frame(&mut stream, &buf[..len]).await?;
+            } else {
+                let frame = read_frame(&mut stream).await?;
+                handshake
+                    .read_message(&frame, &mut payload)
+                    .map_err(noise_error)?;
+            }
+            writing = !writing;
+        }
+        let transport = handshake.into_transport_mode().map_err(noise_error)?;
+        Ok(Self {
+            stream,
+            transport,
+            buf,
+            rx: Vec::new(),
+            pending: None,
+        })
+    }
+
+    /// Hex encoded static key the peer proved ownership of during the handshake
+    pub fn remote_node_id(&self) -> String {
+        self.transport
+            .get_remote_static()
+            .map(hex::encode)
+            .unwrap_or_default()
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr, ChainError> {
+        Ok(self.stream.peer_addr()?)
+    }
+
+    /// Sends `data` as an encrypted length header followed by encrypted chunks
+    pub async fn send(&mut self, data: &[u8]) -> Result<(), ChainError> {
+        self.send_chunk(&(data.len() as u32).to_be_bytes()).await?;
+        for chunk in data.chunks(MAX_CHUNK) {
+            self.send_chunk(chunk).await?;
+        }
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Receives a message sent with [`Self::send`]. Cancel safe, so it can
+    /// race other futures in a `select!`: frames are only decrypted once they
+    /// are complete, and a message received in part is finished by the next
+    /// call.
+    pub async fn recv(&mut self, max_len: usize) -> Result<Vec<u8>, ChainError> {
+        loop {
+            while let Some(chunk) = self.next_chunk()? {
+                let Some((len, data)) = self.pending.as_mut() else {
+                    let len_bytes: [u8; 4] = chunk
+                        .try_into()
+                        .map_err(|_| ChainError::PeerError("Malformed message header".into()))?;
+                    let len = u32::from_be_bytes(len_bytes) as usize;
+                    if len > max_len {
+                        return Err(ChainError::MessageTooLarge(len));
+                    }
+                    if len == 0 {
+                        return Ok(vec![]);
+                    }
+                    self.pending = Some((len, Vec::with_capacity(len)));
+                    continue;
+                };
+                if chunk.is_empty() || data.len() + chunk.len() > *len {
+                    return Err(ChainError::PeerError("Malformed message body".into()));
+                }
+                data.extend_from_slice(&chunk);
+                if data.len() == *len {
+                    return Ok(self
+                        .pending
+                        .take()
+                        .map(|(_, data)| data)
+                        .unwrap_or_default());
+                }
+            }
+            self.rx.reserve(MAX_NOISE_MESSAGE);
+            if self.stream.read_buf(&mut self.rx).await? == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+    }
+
+    /// Decrypts the next frame, if it has arrived in full
+    fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, ChainError> {
+        let Some(len_bytes) = self.rx.get(..2) else {
+            return Ok(None);
+        };
+        let end = 2 + u16::from_be_bytes([len_bytes[0], len_bytes[1]]) as usize;
+        if self.rx.len() < end {
+            return Ok(None);
+        }
+        let len = self
+            .transport
+            .read_message(&self.rx[2..end], &mut self.buf)
+            .map_err(noise_error)?;
+        self.rx.drain(..end);
+        Ok(Some(self.buf[..len].to_vec()))
+    }
+
+    async fn send_chunk(&mut self, chunk: &[u8]) -> Result<(), ChainError> {
+        let len = self
+            .transport
+            .write_message(chunk, &mut self.buf)
+            .map_err(noise_error)?;
+        write_frame(&mut self.stream, &self.buf[..len]).await
+    }
+}
+
+async fn write_frame(stream: &mut TcpStream, frame: &[u8]) -> Result<(), ChainError> {
+    stream
+        .write_all(&(frame.len() as u16).to_be_bytes())
+        .await?;
+    stream.write_all(frame).await?;
+    Ok(())
+}
+
+async fn read_frame(stream: &mut TcpStream) -> Result<Vec<u8>, ChainError> {
+    let mut len_bytes = [0u8; 2];
+    stream.read_exact(&mut len_bytes).await?;
+    let mut frame = vec![0u8; u16::from_be_bytes(len_bytes) as usize];
+    stream.read_exact(&mut frame).await?;
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_handshake_authenticates_both_sides() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_key = NodeKey::generate();
+        let client_key = NodeKey::generate();
+
+        let server = {
+            let server_key = server_key.clone();
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut secure = SecureStream::accept(stream, &server_key).await.unwrap();
+                let message = secure.recv(1 << 20).await.unwrap();
+                secure.send(&message).await.unwrap();
+                secure.remote_node_id()
+            })
+        };
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut secure = SecureStream::connect(stream, &client_key).await.unwrap();
+        assert_eq!(secure.remote_node_id(), server_key.node_id());
+
+        // Larger than a single Noise message
+        let message: Vec<u8> = (0..200_000).map(|i| i as u8).collect();
+        secure.send(&message).await.unwrap();
+        assert_eq!(secure.recv(1 << 20).await.unwrap(), message);
+        assert_eq!(server.await.unwrap(), client_key.node_id());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_recv_keeps_the_partial_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (resume_tx, resume_rx) = tokio::sync::oneshot::channel();
+        let first: Vec<u8> = (0..200_000).map(|i| i as u8).collect();
+
+        let sender = {
+            let first = first.clone();
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut secure = SecureStream::accept(stream, &NodeKey::generate())
+                    .await
+                    .unwrap();
+                // The header and the first chunk, then a pause mid-message
+                let mut chunks = first.chunks(MAX_CHUNK);
+                secure
+                    .send_chunk(&(first.len() as u32).to_be_bytes())
+                    .await
+                    .unwrap();
+                secure.send_chunk(chunks.next().unwrap()).await.unwrap();
+                resume_rx.await.unwrap();
+                for chunk in chunks {
+                    secure.send_chunk(chunk).await.unwrap();
+                }
+                secure.send(b"second").await.unwrap();
+            })
+        };
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut secure = SecureStream::connect(stream, &NodeKey::generate())
+            .await
+            .unwrap();
+        let cancelled =
+            tokio::time::timeout(Duration::from_millis(200), secure.recv(1 << 20)).await;
+        assert!(cancelled.is_err());
+        assert!(secure.pending.is_some());
+
+        resume_tx.send(()).unwrap();
+        assert_eq!(secure.recv(1 << 20).await.unwrap(), first);
+        assert_eq!(secure.recv(1 << 20).await.unwrap(), b"second");
+        sender.await.unwrap();
+    }
+
+    #[test]
+    fn test_node_key_roundtrip() {
+        let key = NodeKey::generate();
+        let restored = NodeKey::from_private(key.private()).unwrap();
+        assert_eq!(restored.node_id(), key.node_id());
+    }
+}
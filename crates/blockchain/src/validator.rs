@@ -0,0 +1,827 @@
+use std::{
+    collections::{HashMap, HashSet, hash_map::Entry},
+    time::Duration,
+};
+
+use chrono::Utc;
+use database::Database;
+use types::{
+    Block, BlockType, CandidateResult, PubKey, StationSummary,
+    agents::{Agent, AgentSignature, Submission},
+    crypto::{hash_block, is_attachment_digest, public_key_from_bytes, verify_hash},
+    error::ChainError,
+    validation::BlockRejection,
+};
+
+/// Checks a block against its parent and the on-chain key registry before
+/// it is added to the chain.
+pub struct BlockValidator<'a> {
+    db: &'a Database,
+    max_clock_drift: Duration,
+}
+
+impl<'a> BlockValidator<'a> {
+    pub fn new(db: &'a Database, max_clock_drift: Duration) -> Self {
+        Self {
+            db,
+            max_clock_drift,
+        }
+    }
+
+    /// Validates everything but the commit certificate, as needed for proposals
+    pub async fn validate(&self, block: &Block, parent: &Block) -> Result<(), ChainError> {
+        Self::check_linkage(block, parent)?;
+        self.check_timestamp(block, parent)?;
+        Self::check_merkle_root(block)?;
+        let hash = Self::check_hash(block)?;
+        self.check_signer(block, &hash).await?;
+        self.check_key_transactions(block).await?;
+        self.check_reference_data(block).await?;
+        self.check_election(block).await?;
+        Self::check_agents(block)?;
+        self.check_agent_results(block).await?;
+        self.check_results(block).await?;
+        self.check_summaries(block).await?;
+        Self::check_attachments(block)?;
+        self.check_attestations(block).await?;
+        self.check_corrections(block).await
+    }
+
+    /// Validates a block that claims to be final
+    pub async fn validate_committed(
+        &self,
+        block: &Block,
+        parent: &Block,
+    ) -> Result<(), ChainError> {
+        self.validate(block, parent).await?;
+        Ok(Self::check_certificate(
+            block,
+            &self.db.validators().await?,
+        )?)
+    }
+
+    /// Checks a committed block of a side branch against `validators`, the
+    /// validator set of the branch. What depends on the rest of the branch's
+    /// state is checked once the branch is replayed onto the chain.
+    pub fn validate_side(
+        &self,
+        block: &Block,
+        parent: &Block,
+        validators: &[PubKey],
+    ) -> Result<(), BlockRejection> {
+        Self::check_linkage(block, parent)?;
+        self.check_timestamp(block, parent)?;
+        Self::check_merkle_root(block)?;
+        let hash = Self::check_hash(block)?;
+        let pub_key = validators
+            .iter()
+            .find(|key| key.hash == block.signature_pub_key_hash)
+            .ok_or_else(|| BlockRejection::UnknownSigner(block.signature_pub_key_hash.clone()))?;
+        Self::check_signature(block, pub_key, &hash)?;
+        Self::check_certificate(block, validators)
+    }
+
+    fn check_certificate(block: &Block, validators: &[PubKey]) -> Result<(), BlockRejection> {
+        let certified = block.certificate.as_ref().is_some_and(|certificate| {
+            certificate.height == block.height
+                && certificate.block_hash == block.hash
+                && certificate.verify(validators)
+        });
+        if !certified {
+            return Err(BlockRejection::InvalidCertificate);
+        }
+        Ok(())
+    }
+
+    /// Checks that a genesis block is consistent with itself. It has no parent
+    /// or registered signer to check against, so its hash has to be trusted.
+    pub fn validate_genesis(block: &Block) -> Result<(), ChainError> {
+        if block.height != 0 || !matches!(block.inner, BlockType::Genesis { .. }) {
+            return Err(BlockRejection::NotGenesis.into());
+        }
+        Self::check_merkle_root(block)?;
+        Self::check_hash(block)?;
+        Ok(())
+    }
+
+    fn check_linkage(block: &Block, parent: &Block) -> Result<(), BlockRejection> {
+        if block.height != parent.height + 1 {
+            return Err(BlockRejection::HeightMismatch {
+                expected: parent.height + 1,
+                found: block.height,
+            });
+        }
+        if block.prev_hash != parent.hash {
+            return Err(BlockRejection::PrevHashMismatch {
+                expected: parent.hash.clone(),
+                found: block.prev_hash.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    fn check_timestamp(&self, block: &Block, parent: &Block) -> Result<(), BlockRejection> {
+        let timestamp = block.timestamp.timestamp();
+        if timestamp < parent.timestamp.timestamp() {
+            return Err(BlockRejection::TimestampBeforeParent(timestamp));
+        }
+        if timestamp > Utc::now().timestamp() + self.max_clock_drift.as_secs() as i64 {
+            return Err(BlockRejection::TimestampInFuture(timestamp));
+        }
+        Ok(())
+    }
+
+    fn check_merkle_root(block: &Block) -> Result<(), BlockRejection> {
+        if block.inner.merkle_root() != block.merkle_root {
+            return Err(BlockRejection::MerkleRootMismatch);
+        }
+        Ok(())
+    }
+
+    /// Re-hashes the header, returning the hash on success
+    fn check_hash(block: &Block) -> Result<String, BlockRejection> {
+        let expected = block
+            .header()
+            .map(|header| hash_block(&header))
+            .unwrap_or_default();
+        if expected != block.hash {
+            return Err(BlockRejection::HashMismatch {
+                expected,
+                found: block.hash.clone(),
+            });
+        }
+        Ok(expected)
+    }
+
+    async fn check_signer(&self, block: &Block, hash: &str) -> Result<(), ChainError> {
+        let pub_key = match self.db.get_public_key(&block.signature_pub_key_hash).await {
+            Ok(pub_key) => pub_key,
+            Err(sqlx::Error::RowNotFound) => {
+                return Err(
+                    BlockRejection::UnknownSigner(block.signature_pub_key_hash.clone()).into(),
+                );
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if !pub_key.is_active_at(block.height) {
+            return Err(BlockRejection::RevokedSigner(pub_key.hash).into());
+        }
+        Ok(Self::check_signature(block, &pub_key, hash)?)
+    }
+
+    fn check_signature(block: &Block, pub_key: &PubKey, hash: &str) -> Result<(), BlockRejection> {
+        let valid = public_key_from_bytes(&pub_key.bytes).is_some_and(|key| {
+            verify_hash(&key, hash, &block.hash_signature)
+                && verify_hash(&key, &block.prev_hash, &block.prev_hash_signature)
+        });
+        if !valid {
+            return Err(BlockRejection::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    /// Key operations must be signed by a current validator
+    async fn check_key_transactions(&self, block: &Block) -> Result<(), ChainError> {
+        let validators = self.db.validators().await?;
+        for tx in block.inner.key_transactions() {
+            if !validators.iter().any(|key| tx.verify(key)) {
+                return Err(BlockRejection::InvalidKeyTransaction.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Gazette records have to refer to records on chain or earlier in the block
+    async fn check_reference_data(&self, block: &Block) -> Result<(), ChainError> {
+        match self
+            .db
+            .check_reference_data(block.inner.reference_data())
+            .await
+        {
+            Err(sqlx::Error::Database(e)) => {
+                Err(BlockRejection::InvalidReferenceData(e.message().to_string()).into())
+            }
+            res => Ok(res?),
+        }
+    }
+
+    /// Election ids are never reused and an election needs somewhere to vote
+    /// and someone to vote for
+    async fn check_election(&self, block: &Block) -> Result<(), ChainError> {
+        let BlockType::Election(election) = &block.inner else {
+            return Ok(());
+        };
+        if election.name.trim().is_empty()
+            || election.areas.is_empty()
+            || election.candidates.is_empty()
+            || self.db.election(election.id).await?.is_some()
+        {
+            return Err(BlockRejection::InvalidElection(election.id).into());
+        }
+        Ok(())
+    }
+
+    /// Agents are identified by their key, which has to be a valid one
+    fn check_agents(block: &Block) -> Result<(), BlockRejection> {
+        match block
+            .inner
+            .agents()
+            .iter()
+            .find(|agent| agent.hash().is_none())
+        {
+            Some(agent) => Err(BlockRejection::InvalidAgent(hex::encode(&agent.pub_key))),
+            None => Ok(()),
+        }
+    }
+
+    /// Results collected by agents can only come from them, the node relaying
+    /// them can not alter them. Results no agent signed are only taken from
+    /// the key that signed the genesis block.
+    async fn check_agent_results(&self, block: &Block) -> Result<(), ChainError> {
+        match &block.inner {
+            BlockType::Result(_) => {
+                let genesis = self.db.get_block_by_height(0).await?;
+                if block.signature_pub_key_hash != genesis.signature_pub_key_hash {
+                    return Err(BlockRejection::UnsignedResults.into());
+                }
+                Ok(())
+            }
+            BlockType::Submission(submission) => self.authorise_submission(submission).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// A submission has to be signed by a registered agent allowed to submit
+    /// for every station in it
+    pub async fn authorise_submission(&self, submission: &Submission) -> Result<(), ChainError> {
+        let signed_by = &submission.signed_by;
+        let agent = self
+            .authorised_agent(&signed_by.agent, &submission.results)
+            .await?;
+        if !submission.verify(&agent.pub_key) {
+            return Err(BlockRejection::InvalidSubmissionSignature(signed_by.agent.clone()).into());
+        }
+        Ok(())
+    }
+
+    /// Attachments are uploaded by agents that may still submit, signing the
+    /// attachment's digest
+    pub async fn authorise_upload(
+        &self,
+        signed_by: &AgentSignature,
+        digest: &str,
+    ) -> Result<(), ChainError> {
+        let authorised = self.db.agent(&signed_by.agent).await?.is_some_and(|agent| {
+            !agent.scope.is_empty() && signed_by.verify(&agent.pub_key, &digest)
+        });
+        if !authorised {
+            return Err(BlockRejection::InvalidSubmissionSignature(signed_by.agent.clone()).into());
+        }
+        Ok(())
+    }
+
+    /// The registered agent `agent_hash` if it may submit for every station in `results`
+    async fn authorised_agent(
+        &self,
+        agent_hash: &str,
+        results: &[CandidateResult],
+    ) -> Result<Agent, ChainError> {
+        let agent = self.db.agent(agent_hash).await?;
+        for result in results {
+            let covered = match &agent {
+                Some(_) => self.db.agent_covers(agent_hash, result.station_id).await?,
+                None => false,
+            };
+            if !covered {
+                return Err(BlockRejection::UnauthorisedSubmission {
+                    agent: agent_hash.to_string(),
+                    station_id: result.station_id,
+                }
+                .into());
+            }
+        }
+        agent.ok_or_else(|| {
+            BlockRejection::InvalidSubmissionSignature(agent_hash.to_string()).into()
+        })
+    }
+
+    /// Results, corrected or not, have to be for a registered election that
+    /// covers the station and the candidate
+    async fn check_results(&self, block: &Block) -> Result<(), ChainError> {
+        let results: Vec<&CandidateResult> = match &block.inner {
+            BlockType::Result(_) | BlockType::Submission(_) => {
+                block.inner.results().iter().collect()
+            }
+            BlockType::Correction(corrections) => {
+                corrections.iter().flat_map(|c| &c.results).collect()
+            }
+            _ => return Ok(()),
+        };
+        for result in results {
+            let covered = self
+                .db
+                .election_covers(result.election_id, result.station_id, result.candidate_id)
+                .await?;
+            if !covered {
+                return Err(BlockRejection::OutsideElection {
+                    election_id: result.election_id,
+                    station_id: result.station_id,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// A tally form summary has to match the votes submitted with it and the
+    /// ballots cast can not exceed the station's registered voters
+    async fn check_summaries(&self, block: &Block) -> Result<(), ChainError> {
+        let submissions: Vec<(&[CandidateResult], &[StationSummary])> = match &block.inner {
+            BlockType::Submission(Submission {
+                results, summaries, ..
+            }) => vec![(results, summaries)],
+            BlockType::Correction(corrections) => corrections
+                .iter()
+                .map(|c| (&c.results[..], &c.summaries[..]))
+                .collect(),
+            _ => return Ok(()),
+        };
+        let mut positions: HashMap<i64, Option<String>> = HashMap::new();
+        let mut stations = HashSet::new();
+        for (results, summaries) in submissions {
+            for summary in summaries {
+                let station_id = summary.station_id;
+                let mut votes = None;
+                for result in results
+                    .iter()
+                    .filter(|r| r.election_id == summary.election_id && r.station_id == station_id)
+                {
+                    if let Entry::Vacant(entry) = positions.entry(result.candidate_id) {
+                        entry.insert(self.db.candidate_position(result.candidate_id).await?);
+                    }
+                    if positions[&result.candidate_id].as_ref() == Some(&summary.position_type) {
+                        *votes.get_or_insert(0) += result.votes;
+                    }
+                }
+                let registered_voters = self.db.registered_voters(station_id).await?;
+                let valid = stations.insert((
+                    summary.election_id,
+                    station_id,
+                    summary.position_type.as_str(),
+                )) && votes.is_some_and(|votes| summary.is_consistent(votes))
+                    && registered_voters.is_none_or(|voters| summary.total_cast <= voters);
+                if !valid {
+                    return Err(BlockRejection::InvalidSummary { station_id }.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Attachments are referenced by digest, their blobs follow separately
+    fn check_attachments(block: &Block) -> Result<(), BlockRejection> {
+        match block
+            .inner
+            .attachments()
+            .into_iter()
+            .find(|hash| !is_attachment_digest(hash))
+        {
+            Some(hash) => Err(BlockRejection::InvalidAttachment(hash.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Attested chain votes must be what the chain holds when the block is added
+    async fn check_attestations(&self, block: &Block) -> Result<(), ChainError> {
+        let BlockType::Attestation(attestations) = &block.inner else {
+            return Ok(());
+        };
+        let mut stations: HashMap<(i64, i64), Vec<CandidateResult>> = HashMap::new();
+        for attestation in attestations {
+            let key = (attestation.election_id, attestation.station_id);
+            let station_id = attestation.station_id;
+            if let Entry::Vacant(entry) = stations.entry(key) {
+                entry.insert(self.db.station_votes(key.0, station_id).await?);
+            }
+            let chain_votes = stations[&key]
+                .iter()
+                .find(|r| r.candidate_id == attestation.candidate_id)
+                .map(|r| r.votes);
+            if !attestation.is_consistent() || attestation.chain_votes != chain_votes {
+                return Err(BlockRejection::InvalidAttestation { station_id }.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// A correction has to give a reason, stay within its station and replace
+    /// results that are still current
+    async fn check_corrections(&self, block: &Block) -> Result<(), ChainError> {
+        let BlockType::Correction(corrections) = &block.inner else {
+            return Ok(());
+        };
+        let mut stations = HashSet::new();
+        for correction in corrections {
+            let (election_id, station_id) = (correction.election_id, correction.station_id);
+            let valid = stations.insert((election_id, station_id))
+                && !correction.reason.trim().is_empty()
+                && !correction.results.is_empty()
+                && correction
+                    .results
+                    .iter()
+                    .all(|r| r.election_id == election_id && r.station_id == station_id)
+                && self
+                    .db
+                    .current_result_heights(election_id, station_id)
+                    .await?
+                    .contains(&(correction.original_height as i64));
+            if !valid {
+                return Err(BlockRejection::InvalidCorrection { station_id }.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[tokio::test]
+    async fn test_corrections_supersede_results() {
+        let mut chain = election_chain().await;
+        let signer = chain.db.get_private_key().await.unwrap();
+        propose(
+            &mut chain,
+            BlockType::Result(vec![result(1, 66), result(2, 12)]),
+        )
+        .await
+        .unwrap();
+
+        let correction = |original_height, reason: &str| {
+            BlockType::Correction(vec![types::Correction {
+                election_id: ELECTION,
+                station_id: STATION,
+                original_height,
+                results: vec![result(1, 66), result(2, 21)],
+                summaries: vec![],
+                reason: reason.to_string(),
+                attachments: vec![],
+            }])
+        };
+
+        for invalid in [correction(2, " "), correction(1, "Digits swapped")] {
+            assert!(matches!(
+                propose(&mut chain, invalid).await,
+                Err(ChainError::BlockRejected(
+                    BlockRejection::InvalidCorrection { .. }
+                ))
+            ));
+        }
+        propose(&mut chain, correction(2, "Digits swapped"))
+            .await
+            .unwrap();
+
+        let votes: Vec<_> = chain
+            .station_votes(ELECTION, STATION)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.votes)
+            .collect();
+        assert_eq!(votes, vec![66, 21]);
+
+        let corrections = chain.corrections_by_station(STATION).await.unwrap();
+        assert_eq!(corrections[0].original_height, 2);
+        assert_eq!(corrections[0].corrected_by, signer.2.hash);
+
+        // Only the latest correction can be corrected
+        assert!(
+            propose(&mut chain, correction(2, "Digits swapped"))
+                .await
+                .is_err()
+        );
+        propose(&mut chain, correction(3, "Recounted"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_results_are_scoped_to_an_election() {
+        let mut chain = election_chain().await;
+        let stored = chain.election(ELECTION).await.unwrap().unwrap();
+        assert_eq!(stored.candidates, vec![1, 2]);
+
+        // Election ids are not reused
+        let mut duplicate = stored.clone();
+        duplicate.name = "Juja re-run".into();
+        assert!(matches!(
+            propose(&mut chain, BlockType::Election(duplicate)).await,
+            Err(ChainError::BlockRejected(BlockRejection::InvalidElection(
+                ELECTION
+            )))
+        ));
+
+        // Candidate 3 is not on the ballot, election 2 does not exist
+        for outside in [
+            result(3, 10),
+            types::CandidateResult::new(2, STATION as usize, 1, 10),
+        ] {
+            assert!(matches!(
+                propose(&mut chain, BlockType::Result(vec![outside])).await,
+                Err(ChainError::BlockRejected(
+                    BlockRejection::OutsideElection { .. }
+                ))
+            ));
+        }
+
+        propose(
+            &mut chain,
+            BlockType::Result(vec![result(1, 66), result(2, 21)]),
+        )
+        .await
+        .unwrap();
+        let votes: Vec<_> = chain
+            .results_by_station(ELECTION, STATION)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.candidate_id, r.votes))
+            .collect();
+        assert_eq!(votes, vec![(1, 66), (2, 21)]);
+        assert!(
+            chain
+                .results_by_station(2, STATION)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_results_only_come_from_the_genesis_key() {
+        let chain = election_chain().await;
+        let tip = chain.last_block().await.unwrap();
+        let height = (tip.height + 1) as usize;
+        let key = types::crypto::get_private_key();
+        let verifying_key = types::crypto::get_public_key(&key);
+        let pub_key = PubKey::from_verifying_key("validator-2", &verifying_key);
+        chain
+            .add_public_key(&pub_key.bytes, &pub_key.creator, &pub_key.hash, 1)
+            .await
+            .unwrap();
+        let other = (key, verifying_key, pub_key);
+
+        let validator = BlockValidator::new(&chain.db, Duration::from_secs(60));
+        let results = BlockType::Result(vec![result(1, 66), result(2, 21)]);
+        let block = Block::with_inner(&other, &tip.hash, results.clone(), height);
+        assert!(matches!(
+            validator.validate(&block, &tip).await,
+            Err(ChainError::BlockRejected(BlockRejection::UnsignedResults))
+        ));
+
+        let signer = chain.db.get_private_key().await.unwrap();
+        let block = Block::with_inner(&signer, &tip.hash, results, height);
+        validator.validate(&block, &tip).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_attestations_are_checked_against_the_chain() {
+        let mut chain = election_chain().await;
+        propose(
+            &mut chain,
+            BlockType::Result(vec![result(1, 66), result(2, 21)]),
+        )
+        .await
+        .unwrap();
+
+        let official = vec![result(1, 66), result(2, 20)];
+        let chain_votes = chain.station_votes(ELECTION, STATION).await.unwrap();
+        let attestations = types::attestation::compare(&official, &chain_votes);
+
+        // Vouching for numbers the chain does not hold is rejected
+        let mut forged = attestations.clone();
+        forged[1] = types::attestation::Attestation::new(ELECTION, STATION, 2, Some(20), Some(20));
+        assert!(matches!(
+            propose(&mut chain, BlockType::Attestation(forged)).await,
+            Err(ChainError::BlockRejected(
+                BlockRejection::InvalidAttestation { .. }
+            ))
+        ));
+
+        propose(&mut chain, BlockType::Attestation(attestations))
+            .await
+            .unwrap();
+        let statuses: Vec<_> = chain
+            .attestations_by_station(STATION)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|a| (a.candidate_id, a.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![(1, "match".to_string()), (2, "mismatch".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reference_data_must_be_consistent() {
+        let mut chain = single_validator_chain().await;
+
+        // A ward has to be in a known constituency
+        let orphan = types::reference::ReferenceData::Ward {
+            code: 564,
+            constituency_code: 999,
+            name: "Theta".into(),
+        };
+        assert!(matches!(
+            propose(&mut chain, BlockType::ReferenceData(vec![orphan])).await,
+            Err(ChainError::BlockRejected(
+                BlockRejection::InvalidReferenceData(_)
+            ))
+        ));
+        assert_eq!(chain.get_chain_height().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_tally_summaries_must_add_up() {
+        let mut chain = election_chain().await;
+        let key = register_agent(&mut chain, "Juja Observers").await;
+        let summary = |rejected, total_valid, total_cast| types::StationSummary {
+            election_id: ELECTION,
+            station_id: STATION,
+            position_type: "Mp".to_string(),
+            rejected,
+            disputed: 1,
+            objected: 0,
+            total_valid,
+            total_cast,
+        };
+        let tally = |summary| {
+            tally(
+                &key,
+                vec![result(1, 66), result(2, 21)],
+                vec![summary],
+                vec![],
+            )
+        };
+
+        // Votes do not add up to the valid total, rejected ballots not to the
+        // total cast, and more ballots cast than registered voters
+        for invalid in [
+            summary(3, 80, 83),
+            summary(3, 87, 100),
+            summary(513, 87, 600),
+        ] {
+            assert!(matches!(
+                propose(&mut chain, tally(invalid)).await,
+                Err(ChainError::BlockRejected(
+                    BlockRejection::InvalidSummary { .. }
+                ))
+            ));
+        }
+        propose(&mut chain, tally(summary(3, 87, 90)))
+            .await
+            .unwrap();
+        let summaries = chain.summaries_by_station(ELECTION, STATION).await.unwrap();
+        assert_eq!(summaries, vec![summary(3, 87, 90)]);
+    }
+
+    #[tokio::test]
+    async fn test_submissions_need_an_authorised_agent() {
+        use crate::BlockChain;
+        use types::election::Area;
+
+        let mut chain = election_chain().await;
+        let key = types::crypto::get_private_key();
+        let agent = |scope| Agent {
+            pub_key: bincode::serialize(&types::crypto::get_public_key(&key)).unwrap(),
+            organisation: "Juja Observers".into(),
+            scope,
+        };
+        let submission = Submission::new(&key, vec![result(1, 66), result(2, 21)], vec![], vec![]);
+        let unauthorised = Err(BlockRejection::UnauthorisedSubmission {
+            agent: submission.signed_by.agent.clone(),
+            station_id: STATION,
+        });
+        let submit = async |chain: &mut BlockChain, submission: &Submission| {
+            propose(chain, BlockType::Submission(submission.clone()))
+                .await
+                .map_err(|e| match e {
+                    ChainError::BlockRejected(rejection) => rejection,
+                    e => panic!("{e}"),
+                })
+        };
+
+        assert_eq!(submit(&mut chain, &submission).await, unauthorised);
+        propose(
+            &mut chain,
+            BlockType::Agents(vec![agent(vec![Area::Station(STATION + 1)])]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(submit(&mut chain, &submission).await, unauthorised);
+
+        // Re-registering the key replaces the agent's scope
+        propose(
+            &mut chain,
+            BlockType::Agents(vec![agent(vec![Area::Ward(563)])]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            chain.agents().await.unwrap(),
+            vec![agent(vec![Area::Ward(563)])]
+        );
+        let mut inflated = submission.clone();
+        inflated.results[0].votes += 100;
+        assert_eq!(
+            submit(&mut chain, &inflated).await,
+            Err(BlockRejection::InvalidSubmissionSignature(
+                submission.signed_by.agent.clone()
+            ))
+        );
+        submit(&mut chain, &submission).await.unwrap();
+        let block = chain.last_block().await.unwrap();
+        assert!(matches!(&block.inner, BlockType::Submission(s) if *s == submission));
+        assert_eq!(block.get_results(), submission.results);
+
+        // Revoked agents have no stations left
+        propose(&mut chain, BlockType::Agents(vec![agent(vec![])]))
+            .await
+            .unwrap();
+        assert_eq!(submit(&mut chain, &submission).await, unauthorised);
+    }
+
+    #[tokio::test]
+    async fn test_tallies_need_an_authorised_agent() {
+        let mut chain = election_chain().await;
+        let results = vec![result(1, 66), result(2, 21)];
+        let summary = types::StationSummary {
+            election_id: ELECTION,
+            station_id: STATION,
+            position_type: "Mp".to_string(),
+            rejected: 3,
+            disputed: 1,
+            objected: 0,
+            total_valid: 87,
+            total_cast: 90,
+        };
+        let rejection = |res: Result<(), ChainError>| match res {
+            Err(ChainError::BlockRejected(rejection)) => rejection,
+            res => panic!("{res:?}"),
+        };
+
+        // Signed by a key that is not a registered agent
+        let stranger = types::crypto::get_private_key();
+        let unregistered = tally(&stranger, results.clone(), vec![summary.clone()], vec![]);
+        assert!(matches!(
+            rejection(propose(&mut chain, unregistered).await),
+            BlockRejection::UnauthorisedSubmission {
+                station_id: STATION,
+                ..
+            }
+        ));
+
+        // The node relaying the tally can not change what the agent signed
+        let key = register_agent(&mut chain, "Juja Observers").await;
+        let mut altered = tally(&key, results.clone(), vec![summary.clone()], vec![]);
+        if let BlockType::Submission(submission) = &mut altered {
+            submission.summaries[0].disputed = 0;
+        }
+        assert!(matches!(
+            rejection(propose(&mut chain, altered).await),
+            BlockRejection::InvalidSubmissionSignature(_)
+        ));
+
+        propose(&mut chain, tally(&key, results, vec![summary], vec![]))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tampered_proposal_is_rejected() {
+        let mut blockchain = single_validator_chain().await;
+        let signer = blockchain.db.get_private_key().await.unwrap();
+        let genesis = blockchain.last_block().await.unwrap();
+
+        let mut block = Block::new(&signer, &genesis.hash, vec![], 1, [0u8; 32]);
+        block.set_results(vec![types::CandidateResult::new(1, 1, 1, 100)]);
+        assert!(matches!(
+            blockchain.propose_block(block).await,
+            Err(ChainError::BlockRejected(
+                BlockRejection::MerkleRootMismatch
+            ))
+        ));
+
+        let mut block = Block::new(&signer, &genesis.hash, vec![], 1, [0u8; 32]);
+        block.hash_signature = block.prev_hash_signature.clone();
+        assert!(matches!(
+            blockchain.propose_block(block).await,
+            Err(ChainError::BlockRejected(BlockRejection::InvalidSignature))
+        ));
+        assert_eq!(blockchain.get_chain_height().await.unwrap(), 0);
+    }
+}
@@ -75,17 +75,22 @@ CREATE INDEX candidates_party_id ON candidates(party_id);
 
 CREATE INDEX candidates_voting_station ON candidates(voting_station);
 
+-- Derived by replaying the key transactions in the chain
 CREATE TABLE pubkeys (
     pubkey_hash VARCHAR NOT NULL PRIMARY KEY,
-    creator VARCHAR NOT NULL UNIQUE,
+    creator VARCHAR NOT NULL,
     pubkey VARCHAR NOT NULL,
     state CHAR NOT NULL,
+    -- A: active, R: revoked
     time_added INTEGER NOT NULL,
     time_revoked INTEGER,
     block_height INTEGER NOT NULL,
+    revoked_height INTEGER,
     metadata VARCHAR -- JSON
 );
 
+CREATE INDEX pubkeys_creator ON pubkeys(creator);
+
 CREATE TABLE blockchain (
     height INTEGER NOT NULL UNIQUE,
     sigkey_hash VARCHAR NOT NULL,
@@ -96,30 +101,213 @@ CREATE TABLE blockchain (
     prev_hash_signature VARCHAR NOT NULL,
     version INTEGER NOT NULL,
     merkle_root BLOB NOT NULL,
+    certificate BLOB,
+    -- bincode encoded CommitCertificate
+    payload BLOB,
+    -- bincode encoded BlockType, NULL for result blocks
     FOREIGN KEY("sigkey_hash") REFERENCES "pubkeys" ("pubkey_hash")
 );
 
 CREATE INDEX blockchain_sigkey_hash ON blockchain(sigkey_hash);
 
+-- Committed blocks on competing branches, kept so the node can reorganise
+CREATE TABLE side_blocks (
+    hash VARCHAR NOT NULL PRIMARY KEY,
+    height INTEGER NOT NULL,
+    prev_hash VARCHAR NOT NULL,
+    block BLOB NOT NULL -- bincode encoded Block, including its results
+);
+
+CREATE INDEX side_blocks_height ON side_blocks(height);
+
 CREATE TABLE peers (
     address VARCHAR NOT NULL PRIMARY KEY,
     -- in the format "address:port", lowercase
     time_added INTEGER NOT NULL,
-    -- time last seen
-    permanent BOOLEAN NOT NULL DEFAULT 0
+    permanent BOOLEAN NOT NULL DEFAULT 0,
+    -- time of the last successful handshake
+    last_seen INTEGER,
+    -- consecutive failed connection attempts
+    failures INTEGER NOT NULL DEFAULT 0,
+    -- no reconnect before this time
+    next_attempt INTEGER
+);
+
+CREATE TABLE bans (
+    -- hex encoded transport key of the peer
+    node_id VARCHAR NOT NULL PRIMARY KEY,
+    -- address the peer was connected from when banned
+    address VARCHAR NOT NULL,
+    -- IP of that address, other keys connecting from it are refused as well
+    ip VARCHAR NOT NULL,
+    reason VARCHAR NOT NULL,
+    -- how many times the peer was banned
+    bans INTEGER NOT NULL DEFAULT 1,
+    -- NULL for a permanent ban
+    banned_until INTEGER,
+    time_added INTEGER NOT NULL
+);
+CREATE INDEX bans_ip ON bans(ip);
+
+-- Elections registered by election blocks
+CREATE TABLE elections (
+    id int PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    date DATE NOT NULL,
+    -- general or by_election
+    election_type VARCHAR NOT NULL,
+    block_height int NOT NULL,
+    FOREIGN KEY ("block_height") REFERENCES "blockchain" ("height")
+);
+
+CREATE TABLE election_areas (
+    election_id int NOT NULL,
+    -- national, county, constituency, ward or station
+    area_type VARCHAR NOT NULL,
+    -- NULL for national
+    area_code int,
+    FOREIGN KEY ("election_id") REFERENCES "elections" ("id")
+);
+
+CREATE INDEX election_areas_election ON election_areas(election_id);
+
+CREATE TABLE election_candidates (
+    election_id int NOT NULL,
+    candidate_id int NOT NULL,
+    PRIMARY KEY (election_id, candidate_id),
+    FOREIGN KEY ("election_id") REFERENCES "elections" ("id"),
+    FOREIGN KEY ("candidate_id") REFERENCES "candidates" ("id")
 );
 
 CREATE TABLE "results" (
+    "election_id" int NOT NULL,
     "station_id" int NOT NULL,
     "candidate_id" int NOT NULL,
     "votes" int NOT NULL,
     "block_height" int NOT NULL,
+    -- height of the correction that replaced this result, NULL while current
+    "superseded_by" int,
+    -- organisation of the agent that signed it, NULL for results from a validator
+    "organisation" VARCHAR,
+    FOREIGN KEY ("election_id") REFERENCES "elections" ("id"),
     FOREIGN KEY ("candidate_id") REFERENCES "candidates" ("id"),
     FOREIGN KEY ("station_id") REFERENCES "stations" ("id"),
     FOREIGN KEY ("block_height") REFERENCES "blockchain" ("height")
 );
 
--- Prevent exceeding registered voters in a station
+-- Results that have not been corrected, what every aggregate is built from
+CREATE VIEW current_results AS
+SELECT * FROM results WHERE superseded_by IS NULL;
+
+-- Whether independent submitters agree on a station's current results,
+-- recomputed whenever a block changes them
+CREATE TABLE station_confirmations (
+    election_id int NOT NULL,
+    station_id int NOT NULL,
+    -- pending, confirmed or disputed
+    state VARCHAR NOT NULL,
+    -- distinct organisations behind the current results
+    organisations int NOT NULL,
+    -- height of the block that last changed the state
+    block_height int NOT NULL,
+    PRIMARY KEY (election_id, station_id),
+    FOREIGN KEY ("station_id") REFERENCES "stations" ("id")
+);
+
+CREATE INDEX station_confirmations_state ON station_confirmations(state);
+
+-- Provenance of result corrections, replayed from correction blocks
+CREATE TABLE corrections (
+    election_id int NOT NULL,
+    station_id int NOT NULL,
+    -- height of the block holding the corrected results
+    original_height int NOT NULL,
+    reason VARCHAR NOT NULL,
+    -- sigkey hash of the signer of the correction block
+    corrected_by VARCHAR NOT NULL,
+    block_height int NOT NULL,
+    FOREIGN KEY ("station_id") REFERENCES "stations" ("id"),
+    FOREIGN KEY ("block_height") REFERENCES "blockchain" ("height")
+);
+
+CREATE INDEX corrections_station ON corrections(station_id);
+
+-- Findings of verification nodes, replayed from attestation blocks
+CREATE TABLE attestations (
+    election_id int NOT NULL,
+    station_id int NOT NULL,
+    candidate_id int NOT NULL,
+    -- match, mismatch or missing
+    status VARCHAR NOT NULL,
+    official_votes int,
+    chain_votes int,
+    -- sigkey hash of the verification node
+    attester VARCHAR NOT NULL,
+    block_height int NOT NULL,
+    FOREIGN KEY ("block_height") REFERENCES "blockchain" ("height")
+);
+
+CREATE INDEX attestations_station ON attestations(station_id);
+
+-- Ballot accounting of a station's tally form (Form 34A), one row per position
+CREATE TABLE station_summaries (
+    election_id int NOT NULL,
+    station_id int NOT NULL,
+    position_type TEXT NOT NULL,
+    rejected int NOT NULL,
+    disputed int NOT NULL,
+    objected int NOT NULL,
+    total_valid int NOT NULL,
+    total_cast int NOT NULL,
+    block_height int NOT NULL,
+    -- height of the correction that replaced this summary, NULL while current
+    superseded_by int,
+    FOREIGN KEY ("election_id") REFERENCES "elections" ("id"),
+    FOREIGN KEY ("station_id") REFERENCES "stations" ("id"),
+    FOREIGN KEY ("position_type") REFERENCES "positions" ("title"),
+    FOREIGN KEY ("block_height") REFERENCES "blockchain" ("height")
+);
+
+CREATE INDEX station_summaries_station ON station_summaries(station_id);
+
+CREATE VIEW current_station_summaries AS
+SELECT * FROM station_summaries WHERE superseded_by IS NULL;
+
+-- Evidence attached to submissions, the blobs live in the attachment store
+CREATE TABLE attachments (
+    -- SHA3-256 of the blob
+    hash VARCHAR NOT NULL,
+    block_height int NOT NULL,
+    FOREIGN KEY ("block_height") REFERENCES "blockchain" ("height")
+);
+
+CREATE INDEX attachments_hash ON attachments(hash);
+
+-- Party agents and observers allowed to submit results, replayed from agent blocks
+CREATE TABLE agents (
+    -- hash of the agent's key
+    agent_hash VARCHAR PRIMARY KEY,
+    -- hex of the bincode encoded verifying key
+    pubkey VARCHAR NOT NULL,
+    organisation VARCHAR NOT NULL,
+    -- height of the block that last registered or changed the agent
+    block_height int NOT NULL
+);
+
+-- Areas an agent may submit results for, none once the agent is revoked
+CREATE TABLE agent_areas (
+    agent_hash VARCHAR NOT NULL,
+    -- national, county, constituency, ward or station
+    area_type VARCHAR NOT NULL,
+    -- NULL for national
+    area_code int,
+    FOREIGN KEY ("agent_hash") REFERENCES "agents" ("agent_hash")
+);
+
+CREATE INDEX agent_areas_agent ON agent_areas(agent_hash);
+
+-- Prevent exceeding registered voters in a station. Every agent reports the
+-- whole station, so only the results of the same block add up.
 CREATE TRIGGER limit_votes_per_station
 BEFORE INSERT ON results
 FOR EACH ROW
@@ -128,7 +316,10 @@ BEGIN
         CASE
             WHEN (NEW.votes + IFNULL((SELECT SUM(votes) 
                                       FROM results 
-                                      WHERE station_id = NEW.station_id), 0))
+                                      WHERE station_id = NEW.station_id
+                                        AND election_id = NEW.election_id
+                                        AND block_height = NEW.block_height
+                                        AND superseded_by IS NULL), 0))
                  > (SELECT registered_voters 
                     FROM stations 
                     WHERE id = NEW.station_id)
@@ -153,6 +344,8 @@ BEGIN
         END;
 END;
 
+CREATE INDEX results_election_id ON results(election_id);
+
 CREATE INDEX results_station_id ON results(station_id);
 
 CREATE INDEX results_candidate_id ON results(candidate_id);
@@ -160,3 +353,5 @@ CREATE INDEX results_candidate_id ON results(candidate_id);
 CREATE INDEX results_votes ON results(votes);
 
 CREATE INDEX results_block_height ON results(block_height);
+
+CREATE INDEX results_superseded_by ON results(superseded_by);
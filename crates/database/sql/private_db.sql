@@ -2,4 +2,9 @@ CREATE TABLE privkeys (
     pubkey_hash VARCHAR NOT NULL PRIMARY KEY,
     privkey VARCHAR NOT NULL,
     time_added INTEGER NOT NULL
-);
\ No newline at end of file
+);
+-- X25519 static key authenticating this node on the encrypted P2P transport
+CREATE TABLE node_key (
+    privkey BLOB NOT NULL,
+    time_added INTEGER NOT NULL
+);
@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use types::crypto::{attachment_digest, is_attachment_digest};
+
+/// Blobs of an in-memory store by digest, with when they were stored
+type MemoryBlobs = HashMap<String, (SystemTime, Vec<u8>)>;
+
+/// Content addressed store for the evidence attached to submissions. Blobs are
+/// named by their [`attachment_digest`], so a blob is never overwritten by
+/// different content.
+#[derive(Debug, Clone)]
+pub struct AttachmentStore {
+    dir: Option<PathBuf>,
+    memory: Arc<RwLock<MemoryBlobs>>,
+}
+
+impl AttachmentStore {
+    /// A store keeping its blobs as files under `dir`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: Some(dir.into()),
+            memory: Default::default(),
+        }
+    }
+
+    /// A store that forgets its blobs once dropped
+    pub fn in_memory() -> Self {
+        Self {
+            dir: None,
+            memory: Default::default(),
+        }
+    }
+
+    /// `dir/ab/abcd...`, so no directory grows too large
+    fn path(&self, hash: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        Some(dir.join(&hash[..2]).join(hash))
+    }
+
+    /// Stores `data`, returning its digest
+    pub async fn put(&self, data: &[u8]) -> io::Result<String> {
+        let hash = attachment_digest(data);
+        match self.path(&hash) {
+            Some(path) => {
+                if tokio::fs::try_exists(&path).await? {
+                    return Ok(hash);
+                }
+                let parent = path.parent().expect("attachment paths have a parent");
+                tokio::fs::create_dir_all(parent).await?;
+                // Readers never see a partially written blob
+                let partial = path.with_extension("partial");
+                tokio::fs::write(&partial, data).await?;
+                tokio::fs::rename(&partial, &path).await?;
+            }
+            None => {
+                self.memory
+                    .write()
+                    .unwrap()
+                    .entry(hash.clone())
+                    .or_insert_with(|| (SystemTime::now(), data.to_vec()));
+            }
+        }
+        Ok(hash)
+    }
+
+    pub async fn get(&self, hash: &str) -> io::Result<Option<Vec<u8>>> {
+        if !is_attachment_digest(hash) {
+            return Ok(None);
+        }
+        match self.path(hash) {
+            Some(path) => match tokio::fs::read(path).await {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            },
+            None => Ok(self
+                .memory
+                .read()
+                .unwrap()
+                .get(hash)
+                .map(|(_, data)| data.clone())),
+        }
+    }
+
+    pub async fn contains(&self, hash: &str) -> io::Result<bool> {
+        if !is_attachment_digest(hash) {
+            return Ok(false);
+        }
+        match self.path(hash) {
+            Some(path) => tokio::fs::try_exists(path).await,
+            None => Ok(self.memory.read().unwrap().contains_key(hash)),
+        }
+    }
+
+    /// Every stored blob with the time it was stored
+    pub async fn list(&self) -> io::Result<Vec<(String, SystemTime)>> {
+        let Some(dir) = &self.dir else {
+            return Ok(self
+                .memory
+                .read()
+                .unwrap()
+                .iter()
+                .map(|(hash, (stored, _))| (hash.clone(), *stored))
+                .collect());
+        };
+        let mut blobs = Vec::new();
+        let mut shards = match tokio::fs::read_dir(dir).await {
+            Ok(shards) => shards,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(blobs),
+            Err(e) => return Err(e),
+        };
+        while let Some(shard) = shards.next_entry().await? {
+            if !shard.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut entries = tokio::fs::read_dir(shard.path()).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if is_attachment_digest(&name) {
+                    blobs.push((name, entry.metadata().await?.modified()?));
+                }
+            }
+        }
+        Ok(blobs)
+    }
+
+    pub async fn remove(&self, hash: &str) -> io::Result<()> {
+        if !is_attachment_digest(hash) {
+            return Ok(());
+        }
+        match self.path(hash) {
+            Some(path) => match tokio::fs::remove_file(path).await {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+            None => {
+                self.memory.write().unwrap().remove(hash);
+                Ok(())
+            }
+        }
+    }
+}
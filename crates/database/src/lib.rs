@@ -1,12 +1,39 @@
-use sqlx::{Row, sqlite::SqliteRow};
+mod attachments;
+mod scratch;
+
+pub use attachments::AttachmentStore;
+pub use scratch::ScratchDatabase;
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+};
+
+use sqlx::{
+    FromRow, Row,
+    sqlite::{SqlitePoolOptions, SqliteRow},
+};
 
 use bincode::deserialize;
-use chrono::{DateTime, Utc};
-use p256::ecdsa::{Signature, SigningKey, VerifyingKey, signature::Verifier};
+use chrono::{DateTime, NaiveDate, Utc};
+use p256::ecdsa::{SigningKey, VerifyingKey};
+use sqlx::{Sqlite, Transaction};
 use types::{
-    Block, ElectionBlockHeader, PubKey,
-    models::{Constituency, County, Party, Station, Ward},
-    results::{Candidate, GeneralResult, LastResultSummary},
+    Block, CandidateResult, ElectionBlockHeader, PubKey, StationSummary,
+    agents::Agent,
+    crypto::verify_hash,
+    election::{Area, Election, ElectionType},
+    explorer::{BlockSummary, ChainSummary, Page},
+    keys::{KeyOperation, key_hash},
+    models::{
+        Ban, Constituency, County, Party, Peer, ResultRevision, Station, StationAttestation,
+        StationCorrection, Ward,
+    },
+    proof::{InclusionProof, KeyProof},
+    reference::ReferenceData,
+    results::{
+        Candidate, Confirmation, ConfirmationCounts, GeneralResult, LastResultSummary, TallyTotals,
+    },
 };
 
 pub const PRIV_SETUP: &str = include_str!("../sql/private_db.sql");
@@ -18,6 +45,8 @@ pub use sqlx::SqlitePool;
 pub struct Database {
     pub chain_db: SqlitePool,
     pub private_db: SqlitePool,
+    /// Blobs of the attachments referenced on chain, as far as we have them
+    pub attachments: AttachmentStore,
 }
 
 impl Database {
@@ -25,34 +54,100 @@ impl Database {
         Self {
             chain_db,
             private_db,
+            attachments: AttachmentStore::in_memory(),
         }
     }
 
+    /// Keeps attachments in `store` instead of memory
+    pub fn with_attachments(mut self, store: AttachmentStore) -> Self {
+        self.attachments = store;
+        self
+    }
+
     pub fn new_in_memory() -> Self {
-        let chain_db = SqlitePool::connect_lazy("sqlite::memory:").unwrap();
-        let private_db = SqlitePool::connect_lazy("sqlite::memory:").unwrap();
+        // Every connection to `sqlite::memory:` opens a separate database,
+        // so keep exactly one connection alive for the lifetime of the pool
+        let options = || {
+            SqlitePoolOptions::new()
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+        };
+        let chain_db = options().connect_lazy("sqlite::memory:").unwrap();
+        let private_db = options().connect_lazy("sqlite::memory:").unwrap();
         Self {
             chain_db,
             private_db,
+            attachments: AttachmentStore::in_memory(),
+        }
+    }
+    /// Adds `genesis` as the first block, loading the gazette it carries
+    pub async fn bootstrap(&mut self, genesis: &Block) -> Result<i64, sqlx::Error> {
+        if !matches!(genesis.inner, types::BlockType::Genesis { .. }) {
+            return Err(sqlx::Error::Protocol(format!(
+                "Block {} is not a genesis block",
+                genesis.hash
+            )));
         }
+        self.add_block(genesis).await
     }
+
     pub async fn add_block(&mut self, block: &Block) -> Result<i64, sqlx::Error> {
-        let mut tx = self.chain_db.begin().await.unwrap();
-        let mut height = block.height as i64;
+        let height = block.height as i64;
 
-        // ignore genesis
-        if height != 0 {
-            height = self.get_height().await? as i64 + 1;
-        } else {
+        if height == 0 {
             assert!(
-                matches!(block.inner, types::BlockType::Genesis),
+                matches!(block.inner, types::BlockType::Genesis { .. }),
                 "First block must be genesis"
             );
+        } else {
+            let tip = self.get_height().await?;
+            if height != tip + 1 {
+                return Err(sqlx::Error::Protocol(format!(
+                    "Block {} at height {height} does not extend the chain at height {tip}",
+                    block.hash
+                )));
+            }
         }
 
-        dbg!(&block);
+        let mut tx = self.chain_db.begin().await?;
+        Self::insert_block(&mut tx, block).await?;
+        // self.is_valid().await?; // TODO: Handle invalid chains more gracefully
+        tx.commit().await?;
 
-        sqlx::query("INSERT INTO blockchain (hash, height, prev_hash, sigkey_hash, hash_signature, prev_hash_signature, timestamp, version, merkle_root) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9);")
+        Ok(height)
+    }
+
+    async fn insert_block(
+        tx: &mut Transaction<'_, Sqlite>,
+        block: &Block,
+    ) -> Result<(), sqlx::Error> {
+        let height = block.height as i64;
+        let certificate = block
+            .certificate
+            .as_ref()
+            .map(bincode::serialize)
+            .transpose()
+            .map_err(|e| sqlx::Error::Decode(e))?;
+        let payload = match &block.inner {
+            types::BlockType::Result(_) => None,
+            // Submitted results are stored as rows too, but not the agent's
+            // signature and the rest needed to rebuild the block
+            inner => Some(bincode::serialize(inner).map_err(|e| sqlx::Error::Decode(e))?),
+        };
+
+        // Keys first, a block may be signed by a key it registers (genesis)
+        for key_tx in block.inner.key_transactions() {
+            Self::apply_key_operation(tx, &key_tx.operation, height, block.timestamp).await?;
+        }
+        for record in block.inner.reference_data() {
+            Self::apply_reference_data(&mut **tx, record).await?;
+        }
+        for agent in block.inner.agents() {
+            Self::apply_agent(tx, agent, height).await?;
+        }
+
+        sqlx::query("INSERT INTO blockchain (hash, height, prev_hash, sigkey_hash, hash_signature, prev_hash_signature, timestamp, version, merkle_root, certificate, payload) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11);")
         .bind(&block.hash)
         .bind(height)
         .bind(&block.prev_hash)
@@ -62,30 +157,725 @@ impl Database {
         .bind(block.timestamp)
         .bind(block.version as i64)
         .bind(&block.merkle_root[..])
-        .execute(&mut *tx).await?.last_insert_rowid();
+        .bind(certificate)
+        .bind(payload)
+        .execute(&mut **tx).await?;
 
-        let results = match &block.inner {
-            types::BlockType::Result(results) => results,
-            _ => &vec![],
-        };
+        let results = block.inner.results();
+        let organisation = Self::submitting_organisation(tx, block).await?;
+        for result in results {
+            Self::insert_result(tx, result, height, organisation.as_deref()).await?;
+        }
+        for summary in block.inner.summaries() {
+            Self::insert_summary(tx, summary, height).await?;
+        }
+        for hash in block.inner.attachments() {
+            sqlx::query("INSERT INTO attachments VALUES (?1, ?2)")
+                .bind(hash)
+                .bind(height)
+                .execute(&mut **tx)
+                .await?;
+        }
+
+        if let types::BlockType::Correction(corrections) = &block.inner {
+            for correction in corrections {
+                sqlx::query(
+                    "UPDATE results SET superseded_by = ?1
+                    WHERE election_id = ?2 AND station_id = ?3 AND superseded_by IS NULL",
+                )
+                .bind(height)
+                .bind(correction.election_id)
+                .bind(correction.station_id)
+                .execute(&mut **tx)
+                .await?;
+                sqlx::query(
+                    "UPDATE station_summaries SET superseded_by = ?1
+                    WHERE election_id = ?2 AND station_id = ?3 AND superseded_by IS NULL",
+                )
+                .bind(height)
+                .bind(correction.election_id)
+                .bind(correction.station_id)
+                .execute(&mut **tx)
+                .await?;
+                sqlx::query("INSERT INTO corrections VALUES (?1, ?2, ?3, ?4, ?5, ?6)")
+                    .bind(correction.election_id)
+                    .bind(correction.station_id)
+                    .bind(correction.original_height as i64)
+                    .bind(&correction.reason)
+                    .bind(&block.signature_pub_key_hash)
+                    .bind(height)
+                    .execute(&mut **tx)
+                    .await?;
+                for result in &correction.results {
+                    Self::insert_result(tx, result, height, organisation.as_deref()).await?;
+                }
+                for summary in &correction.summaries {
+                    Self::insert_summary(tx, summary, height).await?;
+                }
+            }
+        }
 
-        println!("Adding results: {:?}", results);
+        if let types::BlockType::Attestation(attestations) = &block.inner {
+            for attestation in attestations {
+                sqlx::query("INSERT INTO attestations VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")
+                    .bind(attestation.election_id)
+                    .bind(attestation.station_id)
+                    .bind(attestation.candidate_id)
+                    .bind(attestation.status.as_str())
+                    .bind(attestation.official_votes)
+                    .bind(attestation.chain_votes)
+                    .bind(&block.signature_pub_key_hash)
+                    .bind(height)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+        }
 
-        for result in results {
-            let query = "INSERT INTO results VALUES(?1, ?2, ?3, ?4);";
-            let _res = sqlx::query(query)
-                .bind(result.station_id)
-                .bind(result.candidate_id)
-                .bind(result.votes)
+        if let types::BlockType::Election(election) = &block.inner {
+            sqlx::query("INSERT INTO elections VALUES (?1, ?2, ?3, ?4, ?5)")
+                .bind(election.id)
+                .bind(&election.name)
+                .bind(election.date)
+                .bind(election.election_type.as_str())
                 .bind(height)
-                .execute(&mut *tx)
+                .execute(&mut **tx)
                 .await?;
+            for area in &election.areas {
+                let (area_type, area_code) = area.parts();
+                sqlx::query("INSERT INTO election_areas VALUES (?1, ?2, ?3)")
+                    .bind(election.id)
+                    .bind(area_type)
+                    .bind(area_code)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+            for candidate_id in &election.candidates {
+                sqlx::query("INSERT INTO election_candidates VALUES (?1, ?2)")
+                    .bind(election.id)
+                    .bind(candidate_id)
+                    .execute(&mut **tx)
+                    .await?;
+            }
         }
 
-        // self.is_valid().await?; // TODO: Handle invalid chains more gracefully
+        for (election_id, station_id) in block.inner.stations() {
+            Self::update_confirmation(tx, election_id, station_id, height).await?;
+        }
+        Ok(())
+    }
+
+    /// The organisation of the agent who signed the results in `block`. Results
+    /// from a validator have none and never count towards a confirmation, the
+    /// validator only vouches for having relayed them.
+    async fn submitting_organisation(
+        tx: &mut Transaction<'_, Sqlite>,
+        block: &Block,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let Some(agent) = block.inner.agent() else {
+            return Ok(None);
+        };
+        let row: Option<(String,)> =
+            sqlx::query_as("SELECT organisation FROM agents WHERE agent_hash = ?1")
+                .bind(agent)
+                .fetch_optional(&mut **tx)
+                .await?;
+        Ok(row.map(|(organisation,)| organisation))
+    }
+
+    async fn insert_result(
+        tx: &mut Transaction<'_, Sqlite>,
+        result: &CandidateResult,
+        height: i64,
+        organisation: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO results (election_id, station_id, candidate_id, votes, block_height, organisation) VALUES (?1, ?2, ?3, ?4, ?5, ?6);",
+        )
+        .bind(result.election_id)
+        .bind(result.station_id)
+        .bind(result.candidate_id)
+        .bind(result.votes)
+        .bind(height)
+        .bind(organisation)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    /// Recomputes whether the submitters of a station's current results
+    /// agree, see [`Confirmation::of`]
+    async fn update_confirmation(
+        tx: &mut Transaction<'_, Sqlite>,
+        election_id: i64,
+        station_id: i64,
+        height: i64,
+    ) -> Result<(), sqlx::Error> {
+        let results: Vec<(Option<String>, i64, i64)> = sqlx::query_as(
+            "SELECT organisation, candidate_id, votes FROM current_results
+            WHERE election_id = ?1 AND station_id = ?2",
+        )
+        .bind(election_id)
+        .bind(station_id)
+        .fetch_all(&mut **tx)
+        .await?;
+        if results.is_empty() {
+            sqlx::query(
+                "DELETE FROM station_confirmations WHERE election_id = ?1 AND station_id = ?2",
+            )
+            .bind(election_id)
+            .bind(station_id)
+            .execute(&mut **tx)
+            .await?;
+            return Ok(());
+        }
+        let organisations = results
+            .iter()
+            .filter_map(|(organisation, ..)| organisation.as_ref())
+            .collect::<std::collections::BTreeSet<_>>()
+            .len();
+        sqlx::query(
+            "INSERT INTO station_confirmations VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT (election_id, station_id) DO UPDATE SET
+            state = excluded.state, organisations = excluded.organisations,
+            block_height = excluded.block_height",
+        )
+        .bind(election_id)
+        .bind(station_id)
+        .bind(Confirmation::of(&results).as_str())
+        .bind(organisations as i64)
+        .bind(height)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn insert_summary(
+        tx: &mut Transaction<'_, Sqlite>,
+        summary: &StationSummary,
+        height: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO station_summaries (election_id, station_id, position_type, rejected, disputed, objected, total_valid, total_cast, block_height) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9);",
+        )
+        .bind(summary.election_id)
+        .bind(summary.station_id)
+        .bind(&summary.position_type)
+        .bind(summary.rejected)
+        .bind(summary.disputed)
+        .bind(summary.objected)
+        .bind(summary.total_valid)
+        .bind(summary.total_cast)
+        .bind(height)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    /// Stores a committed block that is not (yet) on the canonical chain
+    pub async fn add_side_block(&self, block: &Block) -> Result<(), sqlx::Error> {
+        Self::insert_side_block(&self.chain_db, block).await
+    }
+
+    async fn insert_side_block<'e, E>(executor: E, block: &Block) -> Result<(), sqlx::Error>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let bytes = bincode::serialize(block).map_err(|e| sqlx::Error::Decode(e))?;
+        sqlx::query(
+            "INSERT OR REPLACE INTO side_blocks (hash, height, prev_hash, block) VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(&block.hash)
+        .bind(block.height as i64)
+        .bind(&block.prev_hash)
+        .bind(bytes)
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_side_block(&self, hash: &str) -> Result<Option<Block>, sqlx::Error> {
+        let row: Option<(Vec<u8>,)> =
+            sqlx::query_as("SELECT block FROM side_blocks WHERE hash = ?1")
+                .bind(hash)
+                .fetch_optional(&self.chain_db)
+                .await?;
+        row.map(|(bytes,)| deserialize(&bytes).map_err(|e| sqlx::Error::Decode(e)))
+            .transpose()
+    }
+
+    /// Forgets side blocks, such as a branch that failed validation when replayed
+    pub async fn remove_side_blocks(&self, hashes: &[String]) -> Result<(), sqlx::Error> {
+        for hash in hashes {
+            sqlx::query("DELETE FROM side_blocks WHERE hash = ?1")
+                .bind(hash)
+                .execute(&self.chain_db)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Whether the block is known, on the canonical chain or a side branch
+    pub async fn has_block(&self, hash: &str) -> Result<bool, sqlx::Error> {
+        let (count,): (i64,) = sqlx::query_as(
+            "SELECT (SELECT COUNT(*) FROM blockchain WHERE hash = ?1) + (SELECT COUNT(*) FROM side_blocks WHERE hash = ?1)",
+        )
+        .bind(hash)
+        .fetch_one(&self.chain_db)
+        .await?;
+        Ok(count > 0)
+    }
+
+    /// Replaces every canonical block above `fork_height` with `branch`.
+    ///
+    /// The detached blocks are moved to `side_blocks` and returned; their
+    /// results and key operations are rolled back before `branch` is replayed.
+    pub async fn reorganize(
+        &mut self,
+        fork_height: i64,
+        branch: &[Block],
+    ) -> Result<Vec<Block>, sqlx::Error> {
+        let tip = self.get_height().await?;
+        let detached = self.get_blocks_in_range(fork_height + 1, tip).await?;
+
+        let mut tx = self.chain_db.begin().await?;
+        for block in &detached {
+            Self::insert_side_block(&mut *tx, block).await?;
+        }
+        if detached
+            .iter()
+            .any(|block| !block.inner.reference_data().is_empty())
+        {
+            Self::replay_reference_data(&mut tx, fork_height).await?;
+        }
+        if detached
+            .iter()
+            .any(|block| !block.inner.agents().is_empty())
+        {
+            Self::replay_agents(&mut tx, fork_height).await?;
+        }
+
+        for sql in [
+            "DELETE FROM results WHERE block_height > ?1",
+            "UPDATE results SET superseded_by = NULL WHERE superseded_by > ?1",
+            "DELETE FROM station_summaries WHERE block_height > ?1",
+            "UPDATE station_summaries SET superseded_by = NULL WHERE superseded_by > ?1",
+            "DELETE FROM corrections WHERE block_height > ?1",
+            "DELETE FROM attestations WHERE block_height > ?1",
+            "DELETE FROM attachments WHERE block_height > ?1",
+            "DELETE FROM election_areas WHERE election_id IN (SELECT id FROM elections WHERE block_height > ?1)",
+            "DELETE FROM election_candidates WHERE election_id IN (SELECT id FROM elections WHERE block_height > ?1)",
+            "DELETE FROM elections WHERE block_height > ?1",
+            "DELETE FROM blockchain WHERE height > ?1",
+            "DELETE FROM pubkeys WHERE block_height > ?1",
+            "UPDATE pubkeys SET state = 'A', time_revoked = NULL, revoked_height = NULL WHERE revoked_height > ?1",
+        ] {
+            sqlx::query(sql).bind(fork_height).execute(&mut *tx).await?;
+        }
+        // The stations the detached blocks touched fall back to the results below the fork
+        let mut stations: Vec<_> = detached
+            .iter()
+            .flat_map(|block| block.inner.stations())
+            .collect();
+        stations.sort_unstable();
+        stations.dedup();
+        for (election_id, station_id) in stations {
+            let (height,): (Option<i64>,) = sqlx::query_as(
+                "SELECT MAX(block_height) FROM current_results WHERE election_id = ?1 AND station_id = ?2",
+            )
+            .bind(election_id)
+            .bind(station_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            Self::update_confirmation(&mut tx, election_id, station_id, height.unwrap_or(0))
+                .await?;
+        }
+
+        for block in branch {
+            Self::insert_block(&mut tx, block).await?;
+            sqlx::query("DELETE FROM side_blocks WHERE hash = ?1")
+                .bind(&block.hash)
+                .execute(&mut *tx)
+                .await?;
+        }
         tx.commit().await?;
 
-        Ok(height)
+        Ok(detached)
+    }
+
+    /// Registers or updates a gazetted record
+    async fn apply_reference_data<'e, E>(
+        executor: E,
+        record: &ReferenceData,
+    ) -> Result<(), sqlx::Error>
+    where
+        E: sqlx::Executor<'e, Database = Sqlite>,
+    {
+        let query = match record {
+            ReferenceData::Position(title) => {
+                sqlx::query("INSERT OR IGNORE INTO positions VALUES (?1)").bind(title)
+            }
+            ReferenceData::Party { id, title, logo } => sqlx::query(
+                "INSERT INTO parties VALUES (?1, ?2, ?3)
+                ON CONFLICT (id) DO UPDATE SET title = excluded.title, logo = excluded.logo",
+            )
+            .bind(id)
+            .bind(title)
+            .bind(logo),
+            ReferenceData::County { code, name } => sqlx::query(
+                "INSERT INTO counties VALUES (?1, ?2)
+                ON CONFLICT (county_code) DO UPDATE SET county_name = excluded.county_name",
+            )
+            .bind(code)
+            .bind(name),
+            ReferenceData::Constituency {
+                code,
+                county_code,
+                name,
+            } => sqlx::query(
+                "INSERT INTO constituencies VALUES (?1, ?2, ?3)
+                ON CONFLICT (constituency_code) DO UPDATE SET
+                county_code = excluded.county_code, constituency_name = excluded.constituency_name",
+            )
+            .bind(code)
+            .bind(county_code)
+            .bind(name),
+            ReferenceData::Ward {
+                code,
+                constituency_code,
+                name,
+            } => sqlx::query(
+                "INSERT INTO wards VALUES (?1, ?2, ?3)
+                ON CONFLICT (ward_code) DO UPDATE SET
+                constituency_code = excluded.constituency_code, ward_name = excluded.ward_name",
+            )
+            .bind(code)
+            .bind(constituency_code)
+            .bind(name),
+            ReferenceData::Station {
+                id,
+                ward_code,
+                reg_center_code,
+                name,
+                registered_voters,
+            } => sqlx::query(
+                "INSERT INTO stations VALUES (?1, ?2, ?3, ?4, ?5)
+                ON CONFLICT (id) DO UPDATE SET ward_code = excluded.ward_code,
+                reg_center_code = excluded.reg_center_code, station_name = excluded.station_name,
+                registered_voters = excluded.registered_voters",
+            )
+            .bind(id)
+            .bind(ward_code)
+            .bind(reg_center_code)
+            .bind(name)
+            .bind(registered_voters),
+            ReferenceData::Candidate {
+                id,
+                name,
+                gender,
+                photo,
+                position_type,
+                party_id,
+                voting_station,
+            } => sqlx::query(
+                "INSERT INTO candidates VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                ON CONFLICT (id) DO UPDATE SET name = excluded.name, gender = excluded.gender,
+                photo = excluded.photo, position_type = excluded.position_type,
+                party_id = excluded.party_id, voting_station = excluded.voting_station",
+            )
+            .bind(id)
+            .bind(name)
+            .bind(gender)
+            .bind(photo)
+            .bind(position_type)
+            .bind(party_id)
+            .bind(voting_station),
+        };
+        query.execute(executor).await?;
+        Ok(())
+    }
+
+    /// Rebuilds the gazette from the blocks up to `height`. Updates cannot be
+    /// undone one by one, so every record is cleared and replayed.
+    async fn replay_reference_data(
+        tx: &mut Transaction<'_, Sqlite>,
+        height: i64,
+    ) -> Result<(), sqlx::Error> {
+        // Results and elections keep pointing at records that are about to
+        // be inserted again
+        sqlx::query("PRAGMA defer_foreign_keys = ON")
+            .execute(&mut **tx)
+            .await?;
+        for table in [
+            "candidates",
+            "stations",
+            "wards",
+            "constituencies",
+            "counties",
+            "parties",
+            "positions",
+        ] {
+            sqlx::query(&format!("DELETE FROM {table}"))
+                .execute(&mut **tx)
+                .await?;
+        }
+
+        let payloads: Vec<(Vec<u8>,)> = sqlx::query_as(
+            "SELECT payload FROM blockchain WHERE height <= ?1 AND payload IS NOT NULL ORDER BY height",
+        )
+        .bind(height)
+        .fetch_all(&mut **tx)
+        .await?;
+        for (payload,) in payloads {
+            let inner: types::BlockType =
+                deserialize(&payload).map_err(|e| sqlx::Error::Decode(e))?;
+            for record in inner.reference_data() {
+                Self::apply_reference_data(&mut **tx, record).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Registers an agent, or replaces its organisation and scope
+    async fn apply_agent(
+        tx: &mut Transaction<'_, Sqlite>,
+        agent: &Agent,
+        height: i64,
+    ) -> Result<(), sqlx::Error> {
+        let agent_hash = agent
+            .hash()
+            .ok_or_else(|| sqlx::Error::Protocol("Invalid public key in agent record".into()))?;
+        sqlx::query(
+            "INSERT INTO agents VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (agent_hash) DO UPDATE SET
+            organisation = excluded.organisation, block_height = excluded.block_height",
+        )
+        .bind(&agent_hash)
+        .bind(hex::encode(&agent.pub_key))
+        .bind(&agent.organisation)
+        .bind(height)
+        .execute(&mut **tx)
+        .await?;
+        sqlx::query("DELETE FROM agent_areas WHERE agent_hash = ?1")
+            .bind(&agent_hash)
+            .execute(&mut **tx)
+            .await?;
+        for area in &agent.scope {
+            let (area_type, area_code) = area.parts();
+            sqlx::query("INSERT INTO agent_areas VALUES (?1, ?2, ?3)")
+                .bind(&agent_hash)
+                .bind(area_type)
+                .bind(area_code)
+                .execute(&mut **tx)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the agent registry from the blocks up to `height`, like
+    /// [`Self::replay_reference_data`]
+    async fn replay_agents(
+        tx: &mut Transaction<'_, Sqlite>,
+        height: i64,
+    ) -> Result<(), sqlx::Error> {
+        for sql in ["DELETE FROM agent_areas", "DELETE FROM agents"] {
+            sqlx::query(sql).execute(&mut **tx).await?;
+        }
+        let payloads: Vec<(i64, Vec<u8>)> = sqlx::query_as(
+            "SELECT height, payload FROM blockchain WHERE height <= ?1 AND payload IS NOT NULL ORDER BY height",
+        )
+        .bind(height)
+        .fetch_all(&mut **tx)
+        .await?;
+        for (height, payload) in payloads {
+            let inner: types::BlockType =
+                deserialize(&payload).map_err(|e| sqlx::Error::Decode(e))?;
+            for agent in inner.agents() {
+                Self::apply_agent(tx, agent, height).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies `records` without keeping them, failing like adding a block
+    /// carrying them would
+    pub async fn check_reference_data(&self, records: &[ReferenceData]) -> Result<(), sqlx::Error> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut tx = self.chain_db.begin().await?;
+        for record in records {
+            Self::apply_reference_data(&mut *tx, record).await?;
+        }
+        tx.rollback().await
+    }
+
+    /// Loads a SQL file of `INSERT`s into the reference tables of a scratch
+    /// database and reads it back as gazette records
+    pub async fn reference_data_from_sql(sql: &str) -> Result<Vec<ReferenceData>, sqlx::Error> {
+        let scratch = Self::new_in_memory();
+        sqlx::raw_sql(MAIN_SETUP).execute(&scratch.chain_db).await?;
+        sqlx::raw_sql(sql).execute(&scratch.chain_db).await?;
+        scratch.export_reference_data().await
+    }
+
+    /// The whole gazette, every record after the ones it refers to
+    pub async fn export_reference_data(&self) -> Result<Vec<ReferenceData>, sqlx::Error> {
+        let mut records = Vec::new();
+        let positions: Vec<(String,)> =
+            sqlx::query_as("SELECT title FROM positions ORDER BY title")
+                .fetch_all(&self.chain_db)
+                .await?;
+        records.extend(
+            positions
+                .into_iter()
+                .map(|(title,)| ReferenceData::Position(title)),
+        );
+
+        let parties: Vec<(i64, Option<String>, Option<String>)> =
+            sqlx::query_as("SELECT id, title, logo FROM parties ORDER BY id")
+                .fetch_all(&self.chain_db)
+                .await?;
+        records.extend(
+            parties
+                .into_iter()
+                .map(|(id, title, logo)| ReferenceData::Party {
+                    id,
+                    title: title.unwrap_or_default(),
+                    logo,
+                }),
+        );
+
+        let counties: Vec<(i64, Option<String>)> =
+            sqlx::query_as("SELECT county_code, county_name FROM counties ORDER BY county_code")
+                .fetch_all(&self.chain_db)
+                .await?;
+        records.extend(
+            counties
+                .into_iter()
+                .map(|(code, name)| ReferenceData::County {
+                    code,
+                    name: name.unwrap_or_default(),
+                }),
+        );
+
+        let constituencies: Vec<(i64, i64, Option<String>)> = sqlx::query_as(
+            "SELECT constituency_code, county_code, constituency_name FROM constituencies
+            ORDER BY constituency_code",
+        )
+        .fetch_all(&self.chain_db)
+        .await?;
+        records.extend(constituencies.into_iter().map(|(code, county_code, name)| {
+            ReferenceData::Constituency {
+                code,
+                county_code,
+                name: name.unwrap_or_default(),
+            }
+        }));
+
+        let wards: Vec<(i64, i64, Option<String>)> = sqlx::query_as(
+            "SELECT ward_code, constituency_code, ward_name FROM wards ORDER BY ward_code",
+        )
+        .fetch_all(&self.chain_db)
+        .await?;
+        records.extend(wards.into_iter().map(|(code, constituency_code, name)| {
+            ReferenceData::Ward {
+                code,
+                constituency_code,
+                name: name.unwrap_or_default(),
+            }
+        }));
+
+        let stations = sqlx::query(
+            "SELECT id, ward_code, reg_center_code, station_name, registered_voters FROM stations
+            ORDER BY id",
+        )
+        .fetch_all(&self.chain_db)
+        .await?;
+        records.extend(stations.iter().map(|row| {
+            ReferenceData::Station {
+                id: row.get("id"),
+                ward_code: row.get("ward_code"),
+                reg_center_code: row.get("reg_center_code"),
+                name: row
+                    .get::<Option<String>, _>("station_name")
+                    .unwrap_or_default(),
+                registered_voters: row
+                    .get::<Option<i64>, _>("registered_voters")
+                    .unwrap_or_default(),
+            }
+        }));
+
+        let candidates = sqlx::query("SELECT * FROM candidates ORDER BY id")
+            .fetch_all(&self.chain_db)
+            .await?;
+        records.extend(candidates.iter().map(|row| ReferenceData::Candidate {
+            id: row.get("id"),
+            name: row.get("name"),
+            gender: row.get("gender"),
+            photo: row.get("photo"),
+            position_type: row.get("position_type"),
+            party_id: row.get("party_id"),
+            voting_station: row.get("voting_station"),
+        }));
+        Ok(records)
+    }
+
+    /// Replays a key transaction into the `pubkeys` table
+    async fn apply_key_operation(
+        tx: &mut Transaction<'_, Sqlite>,
+        operation: &KeyOperation,
+        height: i64,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        let invalid_key = || sqlx::Error::Protocol("Invalid public key in key transaction".into());
+        let register = "INSERT INTO pubkeys(pubkey_hash, creator, pubkey, state, time_added, block_height) VALUES (?, ?, ?, 'A', ?, ?)";
+        let revoke = "UPDATE pubkeys SET state = 'R', time_revoked = ?, revoked_height = ? WHERE pubkey_hash = ? AND revoked_height IS NULL";
+
+        match operation {
+            KeyOperation::Register { creator, pub_key } => {
+                sqlx::query(register)
+                    .bind(key_hash(pub_key).ok_or_else(invalid_key)?)
+                    .bind(creator)
+                    .bind(hex::encode(pub_key))
+                    .bind(timestamp.timestamp())
+                    .bind(height)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+            KeyOperation::Revoke { pubkey_hash } => {
+                sqlx::query(revoke)
+                    .bind(timestamp.timestamp())
+                    .bind(height)
+                    .bind(pubkey_hash)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+            KeyOperation::Rotate {
+                pubkey_hash,
+                new_pub_key,
+            } => {
+                let (creator,): (String,) =
+                    sqlx::query_as("SELECT creator FROM pubkeys WHERE pubkey_hash = ?")
+                        .bind(pubkey_hash)
+                        .fetch_one(&mut **tx)
+                        .await?;
+                sqlx::query(revoke)
+                    .bind(timestamp.timestamp())
+                    .bind(height)
+                    .bind(pubkey_hash)
+                    .execute(&mut **tx)
+                    .await?;
+                sqlx::query(register)
+                    .bind(key_hash(new_pub_key).ok_or_else(invalid_key)?)
+                    .bind(creator)
+                    .bind(hex::encode(new_pub_key))
+                    .bind(timestamp.timestamp())
+                    .bind(height)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+        }
+        Ok(())
     }
 
     pub async fn add_public_key(
@@ -127,6 +917,26 @@ impl Database {
         Ok(res)
     }
 
+    pub async fn get_node_key(&self) -> Result<Option<Vec<u8>>, sqlx::Error> {
+        let row: Option<(Vec<u8>,)> = sqlx::query_as("SELECT privkey FROM node_key LIMIT 1")
+            .fetch_optional(&self.private_db)
+            .await?;
+        Ok(row.map(|(key,)| key))
+    }
+
+    pub async fn set_node_key(&self, priv_key: &[u8]) -> Result<(), sqlx::Error> {
+        let mut tx = self.private_db.begin().await?;
+        sqlx::query("DELETE FROM node_key")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO node_key(privkey, time_added) VALUES (?, ?)")
+            .bind(priv_key)
+            .bind(Utc::now().timestamp())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+
     pub async fn get_my_public_key_hashes(&self) -> Result<Vec<String>, sqlx::Error> {
         let mut pool = self.private_db.acquire().await?;
         let sql = "SELECT pubkey_hash FROM privkeys";
@@ -145,40 +955,76 @@ impl Database {
     pub async fn get_private_key(&self) -> Result<(SigningKey, VerifyingKey, PubKey), sqlx::Error> {
         let (private_key, public_key_hash) = self.get_private_key_from_db().await?;
         let pub_key = self.get_public_key(&public_key_hash).await?;
-        let private_key = SigningKey::from_slice(&hex::decode(&private_key).unwrap()).unwrap();
+        let private_key = hex::decode(&private_key)
+            .ok()
+            .and_then(|bytes| SigningKey::from_slice(&bytes).ok())
+            .ok_or_else(|| sqlx::Error::Protocol("Malformed private key".into()))?;
 
-        let public_key = deserialize(&pub_key.bytes).unwrap();
+        let public_key = deserialize(&pub_key.bytes).map_err(|e| sqlx::Error::Decode(e))?;
         Ok((private_key, public_key, pub_key))
     }
 
     pub async fn get_public_key(&self, hash: &str) -> Result<PubKey, sqlx::Error> {
-        let sql = "SELECT pubkey_hash, pubkey, state, time_added, COALESCE(time_revoked, -1), block_height, creator FROM pubkeys WHERE pubkey_hash = ?1";
+        let sql = "SELECT pubkey_hash, pubkey, state, time_added, time_revoked, block_height, revoked_height, creator FROM pubkeys WHERE pubkey_hash = ?1";
         let mut pool = self.chain_db.acquire().await?;
-        let res: (String, String, String, DateTime<Utc>, i64, i64, String) =
-            sqlx::query_as(sql).bind(hash).fetch_one(&mut *pool).await?;
-        // Create a NaiveDateTime from the timestamp
-        let mut is_revoked = false;
-        let mut time_revoked = None;
-
-        if res.4 == -1 {
-            let naive = DateTime::from_timestamp(res.4, 0)
-                .expect("Failed to create NaiveDateTime")
-                .naive_utc();
-            time_revoked = Some(DateTime::from_naive_utc_and_offset(naive, Utc));
-            is_revoked = true;
-        };
+        #[allow(clippy::type_complexity)]
+        let res: (
+            String,
+            String,
+            String,
+            DateTime<Utc>,
+            Option<DateTime<Utc>>,
+            i64,
+            Option<i64>,
+            String,
+        ) = sqlx::query_as(sql).bind(hash).fetch_one(&mut *pool).await?;
 
         Ok(PubKey {
             hash: res.0,
-            bytes: hex::decode(res.1).unwrap(),
+            bytes: hex::decode(res.1).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
             state: res.2,
             time_added: res.3,
-            is_revoked,
-            time_revoked,
-            add_block_height: res.5 as usize,
-            creator: res.6,
+            is_revoked: res.4.is_some(),
+            time_revoked: res.4,
+            // Keys registered outside the chain (before genesis) use -1
+            add_block_height: res.5.max(0) as usize,
+            revoked_block_height: res.6.map(|h| h as usize),
+            creator: res.7,
         })
     }
+
+    /// Active (non-revoked) keys that form the validator set
+    /// The validators once the block at `height` was added, which certify
+    /// the block after it
+    pub async fn validators_after(&self, height: i64) -> Result<Vec<PubKey>, sqlx::Error> {
+        let hashes: Vec<(String,)> = sqlx::query_as(
+            "SELECT pubkey_hash FROM pubkeys WHERE block_height <= ?1
+            AND (revoked_height > ?1 OR (revoked_height IS NULL AND time_revoked IS NULL))
+            ORDER BY pubkey_hash",
+        )
+        .bind(height)
+        .fetch_all(&self.chain_db)
+        .await?;
+        let mut validators = Vec::with_capacity(hashes.len());
+        for (hash,) in hashes {
+            validators.push(self.get_public_key(&hash).await?);
+        }
+        Ok(validators)
+    }
+
+    pub async fn validators(&self) -> Result<Vec<PubKey>, sqlx::Error> {
+        let hashes: Vec<(String,)> = sqlx::query_as(
+            "SELECT pubkey_hash FROM pubkeys WHERE state = 'A' AND time_revoked IS NULL ORDER BY pubkey_hash",
+        )
+        .fetch_all(&self.chain_db)
+        .await?;
+        let mut validators = Vec::with_capacity(hashes.len());
+        for (hash,) in hashes {
+            validators.push(self.get_public_key(&hash).await?);
+        }
+        Ok(validators)
+    }
+
     pub async fn get_height(&self) -> Result<i64, sqlx::Error> {
         let mut pool = self.chain_db.acquire().await?;
         let len: (i64,) =
@@ -200,14 +1046,13 @@ impl Database {
             .bind(height)
             .fetch_one(&mut *pool)
             .await?;
-        let results = sqlx::query_as("Select * from results where block_height = ?1")
-            .bind(height)
-            .fetch_all(&mut *pool)
-            .await?;
-        let pub_key = self
-            .get_public_key(&block.signature_pub_key_hash)
-            .await
-            .unwrap();
+        let results =
+            sqlx::query_as("SELECT * FROM results WHERE block_height = ?1 ORDER BY rowid")
+                .bind(height)
+                .fetch_all(&mut *pool)
+                .await?;
+        drop(pool);
+        let pub_key = self.get_public_key(&block.signature_pub_key_hash).await?;
         block.set_results(results);
         block.set_pub_key(pub_key);
         Ok(block)
@@ -215,104 +1060,751 @@ impl Database {
 
     pub async fn get_block_by_hash(&self, hash: &str) -> Result<Block, sqlx::Error> {
         let mut pool = self.chain_db.acquire().await?;
-        let mut block: Block = sqlx::query_as("SELECT * FROM blockchain WHERE hash_signature = ?1")
+        let mut block: Block = sqlx::query_as("SELECT * FROM blockchain WHERE hash = ?1")
+            .bind(hash)
+            .fetch_one(&mut *pool)
+            .await?;
+        let results =
+            sqlx::query_as("SELECT * FROM results WHERE block_height = ?1 ORDER BY rowid")
+                .bind(block.height as i64)
+                .fetch_all(&mut *pool)
+                .await?;
+        drop(pool);
+        let pub_key = self.get_public_key(&block.signature_pub_key_hash).await?;
+        block.set_results(results);
+        block.set_pub_key(pub_key);
+        Ok(block)
+    }
+
+    pub async fn is_valid(&self) -> Result<bool, sqlx::Error> {
+        let height = self.get_height().await?;
+        let mut prev_hash = [0u8; 32];
+
+        for index in 0..=height {
+            let block = self.get_block_by_height(index).await?;
+
+            // 1. Reconstruct the header using the stored merkle_root
+            let header = ElectionBlockHeader {
+                block_number: block.height as i64,
+                merkle_root: block.merkle_root, // already stored, no recomputation
+                previous_hash: prev_hash,
+                validator_signature: block.signature_pub_key_hash.clone(),
+                timestamp: block.timestamp.timestamp(),
+            };
+
+            // 2. Re-hash header and compare
+            let calculated_hash = types::crypto::hash_block(&header);
+            if calculated_hash != block.hash {
+                return Err(sqlx::Error::Protocol(format!(
+                    "Block hash mismatch at index {}",
+                    index
+                )));
+            }
+
+            // 3. Verify signature with a key that was active at this height
+            let pub_key = self.get_public_key(&block.signature_pub_key_hash).await?;
+            if !pub_key.is_active_at(block.height) {
+                return Err(sqlx::Error::Protocol(format!(
+                    "Block at index {} signed by a revoked key {}",
+                    index, pub_key.hash
+                )));
+            }
+            let verifier: VerifyingKey = deserialize(&pub_key.bytes).map_err(|_| {
+                sqlx::Error::Protocol(format!(
+                    "Malformed key {} for the block at index {}",
+                    pub_key.hash, index
+                ))
+            })?;
+            if !verify_hash(&verifier, &calculated_hash, &block.hash_signature) {
+                return Err(sqlx::Error::Protocol(format!(
+                    "Invalid block signature at index {}",
+                    index
+                )));
+            }
+
+            // 4. Verify chain linkage
+            if block.prev_hash != hex::encode(prev_hash) {
+                return Err(sqlx::Error::Protocol(format!(
+                    "Previous hash mismatch at index {}",
+                    index
+                )));
+            }
+            prev_hash = hex::decode(&block.hash)
+                .ok()
+                .and_then(|hash| hash.try_into().ok())
+                .ok_or_else(|| {
+                    sqlx::Error::Protocol(format!("Malformed block hash at index {}", index))
+                })?;
+        }
+
+        Ok(true)
+    }
+
+    // TODO: Optimize this to a single query
+    pub async fn get_blocks_in_range(
+        &self,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<Block>, sqlx::Error> {
+        let mut blocks = Vec::new();
+        let raw_blocks: Vec<Block> = sqlx::query_as(
+            "SELECT * FROM blockchain WHERE height >= ?1 AND height <= ?2 ORDER BY height",
+        )
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.chain_db)
+        .await?;
+        for mut block in raw_blocks {
+            let results =
+                sqlx::query_as("SELECT * FROM results WHERE block_height = ?1 ORDER BY rowid")
+                    .bind(block.height as i64)
+                    .fetch_all(&self.chain_db)
+                    .await?;
+            let pub_key = self.get_public_key(&block.signature_pub_key_hash).await?;
+            block.set_results(results);
+            block.set_pub_key(pub_key);
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
+
+    /// The most recent blocks, the tip first
+    pub async fn block_summaries(
+        &self,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Page<BlockSummary>, sqlx::Error> {
+        self.block_page("height >= ?1", 0_i64, offset, limit).await
+    }
+
+    /// Blocks signed by the key hashing to `sigkey_hash`, newest first
+    pub async fn blocks_by_signer(
+        &self,
+        sigkey_hash: &str,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Page<BlockSummary>, sqlx::Error> {
+        self.block_page("sigkey_hash = ?1", sigkey_hash.to_string(), offset, limit)
+            .await
+    }
+
+    /// Every block that touched a station: its results, tally forms,
+    /// corrections and attestations, newest first
+    pub async fn station_history(
+        &self,
+        station_id: i64,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Page<BlockSummary>, sqlx::Error> {
+        self.block_page(
+            "height IN (
+                SELECT block_height FROM results WHERE station_id = ?1
+                UNION SELECT block_height FROM station_summaries WHERE station_id = ?1
+                UNION SELECT block_height FROM corrections WHERE station_id = ?1
+                UNION SELECT block_height FROM attestations WHERE station_id = ?1
+            )",
+            station_id,
+            offset,
+            limit,
+        )
+        .await
+    }
+
+    /// Summaries of the blocks matching `filter`, a condition on the
+    /// `blockchain` table binding `arg` as `?1`
+    async fn block_page<A>(
+        &self,
+        filter: &str,
+        arg: A,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Page<BlockSummary>, sqlx::Error>
+    where
+        A: for<'q> sqlx::Encode<'q, Sqlite> + sqlx::Type<Sqlite> + Clone + Send,
+    {
+        let (total,): (i64,) =
+            sqlx::query_as(&format!("SELECT COUNT(*) FROM blockchain WHERE {filter}"))
+                .bind(arg.clone())
+                .fetch_one(&self.chain_db)
+                .await?;
+        let blocks: Vec<Block> = sqlx::query_as(&format!(
+            "SELECT * FROM blockchain WHERE {filter} ORDER BY height DESC LIMIT ?2 OFFSET ?3"
+        ))
+        .bind(arg)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.chain_db)
+        .await?;
+        let mut items = Vec::with_capacity(blocks.len());
+        for mut block in blocks {
+            // Result blocks keep their transactions in the results table
+            if matches!(block.inner, types::BlockType::Result(_)) {
+                let results =
+                    sqlx::query_as("SELECT * FROM results WHERE block_height = ?1 ORDER BY rowid")
+                        .bind(block.height as i64)
+                        .fetch_all(&self.chain_db)
+                        .await?;
+                block.set_results(results);
+            }
+            items.push(BlockSummary::from(&block));
+        }
+        Ok(Page {
+            items,
+            total,
+            offset,
+            limit,
+        })
+    }
+
+    /// The results recorded at `height`, including those corrected since
+    pub async fn results_at_height(&self, height: i64) -> Result<Vec<ResultRevision>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT * FROM results WHERE block_height = ?1 ORDER BY station_id, candidate_id",
+        )
+        .bind(height)
+        .fetch_all(&self.chain_db)
+        .await
+    }
+
+    /// Overview of the chain, `RowNotFound` before genesis
+    pub async fn chain_summary(&self) -> Result<ChainSummary, sqlx::Error> {
+        let tip = self.block_summaries(0, 1).await?;
+        let tip = tip
+            .items
+            .into_iter()
+            .next()
+            .ok_or(sqlx::Error::RowNotFound)?;
+        let (genesis_hash,): (String,) =
+            sqlx::query_as("SELECT hash FROM blockchain WHERE height = 0")
+                .fetch_one(&self.chain_db)
+                .await?;
+        let (signers, elections, results): (i64, i64, i64) = sqlx::query_as(
+            "SELECT
+                (SELECT COUNT(DISTINCT sigkey_hash) FROM blockchain),
+                (SELECT COUNT(*) FROM elections),
+                (SELECT COUNT(*) FROM current_results)",
+        )
+        .fetch_one(&self.chain_db)
+        .await?;
+        let stations = sqlx::query_as::<_, ConfirmationCounts>(
+            "SELECT
+                COALESCE(SUM(state = 'pending'), 0) AS pending,
+                COALESCE(SUM(state = 'confirmed'), 0) AS confirmed,
+                COALESCE(SUM(state = 'disputed'), 0) AS disputed
+            FROM station_confirmations",
+        )
+        .fetch_one(&self.chain_db)
+        .await?;
+        Ok(ChainSummary {
+            height: tip.height as i64,
+            tip,
+            genesis_hash,
+            signers,
+            elections,
+            results,
+            stations,
+        })
+    }
+
+    /// Adds a peer to the peer book, `permanent` peers are always reconnected
+    pub async fn add_peer(&self, address: &str, permanent: bool) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO peers (address, time_added, permanent) VALUES (?1, ?2, ?3)
+            ON CONFLICT(address) DO UPDATE SET permanent = permanent OR excluded.permanent",
+        )
+        .bind(address.to_lowercase())
+        .bind(Utc::now().timestamp())
+        .bind(permanent)
+        .execute(&self.chain_db)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns `false` if the peer was not in the peer book
+    pub async fn remove_peer(&self, address: &str) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query("DELETE FROM peers WHERE address = ?1")
+            .bind(address.to_lowercase())
+            .execute(&self.chain_db)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    pub async fn peers(&self) -> Result<Vec<Peer>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM peers ORDER BY address")
+            .fetch_all(&self.chain_db)
+            .await
+    }
+
+    pub async fn get_peer(&self, address: &str) -> Result<Option<Peer>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM peers WHERE address = ?1")
+            .bind(address.to_lowercase())
+            .fetch_optional(&self.chain_db)
+            .await
+    }
+
+    /// Records a successful handshake, resetting the backoff
+    pub async fn peer_seen(&self, address: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE peers SET last_seen = ?1, failures = 0, next_attempt = NULL WHERE address = ?2",
+        )
+        .bind(Utc::now().timestamp())
+        .bind(address.to_lowercase())
+        .execute(&self.chain_db)
+        .await?;
+        Ok(())
+    }
+
+    /// Records a failed connection attempt, postponing the next one until `next_attempt`
+    pub async fn peer_failed(&self, address: &str, next_attempt: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE peers SET failures = failures + 1, next_attempt = ?1 WHERE address = ?2",
+        )
+        .bind(next_attempt)
+        .bind(address.to_lowercase())
+        .execute(&self.chain_db)
+        .await?;
+        Ok(())
+    }
+
+    /// Bans a peer and the IP it connected from until `banned_until`, or for good if `None`
+    pub async fn ban_peer(
+        &self,
+        node_id: &str,
+        address: SocketAddr,
+        reason: &str,
+        banned_until: Option<i64>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO bans (node_id, address, ip, reason, banned_until, time_added)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT(node_id) DO UPDATE SET address = excluded.address,
+                ip = excluded.ip, reason = excluded.reason, bans = bans + 1,
+                banned_until = excluded.banned_until, time_added = excluded.time_added",
+        )
+        .bind(node_id)
+        .bind(address.to_string().to_lowercase())
+        .bind(address.ip().to_string().to_lowercase())
+        .bind(reason)
+        .bind(banned_until)
+        .bind(Utc::now().timestamp())
+        .execute(&self.chain_db)
+        .await?;
+        Ok(())
+    }
+
+    /// Lifts a ban, returns `false` if the peer was not banned
+    pub async fn unban_peer(&self, node_id: &str) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query("DELETE FROM bans WHERE node_id = ?1")
+            .bind(node_id)
+            .execute(&self.chain_db)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    pub async fn get_ban(&self, node_id: &str) -> Result<Option<Ban>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM bans WHERE node_id = ?1")
+            .bind(node_id)
+            .fetch_optional(&self.chain_db)
+            .await
+    }
+
+    /// The ban still in force at `now` on `node_id` or on any key that connected from `ip`,
+    /// permanent bans first
+    pub async fn active_ban(
+        &self,
+        node_id: &str,
+        ip: IpAddr,
+        now: i64,
+    ) -> Result<Option<Ban>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT * FROM bans WHERE (node_id = ?1 OR ip = ?2)
+                AND (banned_until IS NULL OR banned_until > ?3)
+            ORDER BY banned_until IS NOT NULL, banned_until DESC LIMIT 1",
+        )
+        .bind(node_id)
+        .bind(ip.to_string().to_lowercase())
+        .bind(now)
+        .fetch_optional(&self.chain_db)
+        .await
+    }
+
+    /// Every ban, including expired ones
+    pub async fn bans(&self) -> Result<Vec<Ban>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM bans ORDER BY time_added DESC")
+            .fetch_all(&self.chain_db)
+            .await
+    }
+
+    pub async fn election(&self, id: i64) -> Result<Option<Election>, sqlx::Error> {
+        let row: Option<(i64, String, NaiveDate, String)> =
+            sqlx::query_as("SELECT id, name, date, election_type FROM elections WHERE id = ?1")
+                .bind(id)
+                .fetch_optional(&self.chain_db)
+                .await?;
+        let Some((id, name, date, election_type)) = row else {
+            return Ok(None);
+        };
+        let election_type = match election_type.as_str() {
+            "by_election" => ElectionType::ByElection,
+            _ => ElectionType::General,
+        };
+
+        let areas: Vec<(String, Option<i64>)> = sqlx::query_as(
+            "SELECT area_type, area_code FROM election_areas WHERE election_id = ?1",
+        )
+        .bind(id)
+        .fetch_all(&self.chain_db)
+        .await?;
+        let candidates: Vec<(i64,)> = sqlx::query_as(
+            "SELECT candidate_id FROM election_candidates WHERE election_id = ?1 ORDER BY candidate_id",
+        )
+        .bind(id)
+        .fetch_all(&self.chain_db)
+        .await?;
+
+        Ok(Some(Election {
+            id,
+            name,
+            date,
+            election_type,
+            areas: areas
+                .iter()
+                .filter_map(|(area_type, area_code)| Area::from_parts(area_type, *area_code))
+                .collect(),
+            candidates: candidates.into_iter().map(|(id,)| id).collect(),
+        }))
+    }
+
+    /// Every registered election, the most recent first
+    pub async fn elections(&self) -> Result<Vec<Election>, sqlx::Error> {
+        let ids: Vec<(i64,)> =
+            sqlx::query_as("SELECT id FROM elections ORDER BY date DESC, id DESC")
+                .fetch_all(&self.chain_db)
+                .await?;
+        let mut elections = Vec::with_capacity(ids.len());
+        for (id,) in ids {
+            elections.extend(self.election(id).await?);
+        }
+        Ok(elections)
+    }
+
+    /// Whether the station lies in one of the election's areas and the
+    /// candidate is on its ballot
+    pub async fn election_covers(
+        &self,
+        election_id: i64,
+        station_id: i64,
+        candidate_id: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let (count,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM election_areas ea, stations s
+            JOIN wards w ON s.ward_code = w.ward_code
+            JOIN constituencies con ON w.constituency_code = con.constituency_code
+            WHERE ea.election_id = ?1 AND s.id = ?2
+            AND EXISTS (SELECT 1 FROM election_candidates ec WHERE ec.election_id = ?1 AND ec.candidate_id = ?3)
+            AND (ea.area_type = 'national'
+                OR (ea.area_type = 'county' AND ea.area_code = con.county_code)
+                OR (ea.area_type = 'constituency' AND ea.area_code = con.constituency_code)
+                OR (ea.area_type = 'ward' AND ea.area_code = w.ward_code)
+                OR (ea.area_type = 'station' AND ea.area_code = s.id))",
+        )
+        .bind(election_id)
+        .bind(station_id)
+        .bind(candidate_id)
+        .fetch_one(&self.chain_db)
+        .await?;
+        Ok(count > 0)
+    }
+
+    pub async fn agent(&self, agent_hash: &str) -> Result<Option<Agent>, sqlx::Error> {
+        let row: Option<(String, String)> =
+            sqlx::query_as("SELECT pubkey, organisation FROM agents WHERE agent_hash = ?1")
+                .bind(agent_hash)
+                .fetch_optional(&self.chain_db)
+                .await?;
+        let Some((pub_key, organisation)) = row else {
+            return Ok(None);
+        };
+        let areas: Vec<(String, Option<i64>)> =
+            sqlx::query_as("SELECT area_type, area_code FROM agent_areas WHERE agent_hash = ?1")
+                .bind(agent_hash)
+                .fetch_all(&self.chain_db)
+                .await?;
+        Ok(Some(Agent {
+            pub_key: hex::decode(pub_key).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+            organisation,
+            scope: areas
+                .iter()
+                .filter_map(|(area_type, area_code)| Area::from_parts(area_type, *area_code))
+                .collect(),
+        }))
+    }
+
+    /// Every registered agent, revoked ones included
+    pub async fn agents(&self) -> Result<Vec<Agent>, sqlx::Error> {
+        let hashes: Vec<(String,)> =
+            sqlx::query_as("SELECT agent_hash FROM agents ORDER BY organisation, agent_hash")
+                .fetch_all(&self.chain_db)
+                .await?;
+        let mut agents = Vec::with_capacity(hashes.len());
+        for (agent_hash,) in hashes {
+            agents.extend(self.agent(&agent_hash).await?);
+        }
+        Ok(agents)
+    }
+
+    /// Whether the station lies in one of the areas the agent may submit for
+    pub async fn agent_covers(
+        &self,
+        agent_hash: &str,
+        station_id: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let (count,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM agent_areas aa, stations s
+            JOIN wards w ON s.ward_code = w.ward_code
+            JOIN constituencies con ON w.constituency_code = con.constituency_code
+            WHERE aa.agent_hash = ?1 AND s.id = ?2
+            AND (aa.area_type = 'national'
+                OR (aa.area_type = 'county' AND aa.area_code = con.county_code)
+                OR (aa.area_type = 'constituency' AND aa.area_code = con.constituency_code)
+                OR (aa.area_type = 'ward' AND aa.area_code = w.ward_code)
+                OR (aa.area_type = 'station' AND aa.area_code = s.id))",
+        )
+        .bind(agent_hash)
+        .bind(station_id)
+        .fetch_one(&self.chain_db)
+        .await?;
+        Ok(count > 0)
+    }
+
+    /// Proves the latest current result of a candidate at a station, in the
+    /// most recent election covering both unless `election_id` is given
+    pub async fn inclusion_proof(
+        &self,
+        election_id: Option<i64>,
+        station_id: i64,
+        candidate_id: i64,
+    ) -> Result<Option<InclusionProof>, sqlx::Error> {
+        let row = sqlx::query(
+            "SELECT election_id, station_id, candidate_id, votes, block_height FROM current_results
+            WHERE station_id = ?1 AND candidate_id = ?2 AND (?3 IS NULL OR election_id = ?3)
+            ORDER BY block_height DESC LIMIT 1",
+        )
+        .bind(station_id)
+        .bind(candidate_id)
+        .bind(election_id)
+        .fetch_optional(&self.chain_db)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let result = CandidateResult::from_row(&row)?;
+        let block = self.get_block_by_height(row.get("block_height")).await?;
+        let signer = self.get_public_key(&block.signature_pub_key_hash).await?;
+        let key_transactions = self.key_proofs(block.height as i64).await?;
+        Ok(InclusionProof::new(
+            &block,
+            &result,
+            &signer.bytes,
+            key_transactions,
+        ))
+    }
+
+    /// Proofs of the key transactions below `height`, from which clients
+    /// replay the validator set certifying the block at `height`
+    pub async fn key_proofs(&self, height: i64) -> Result<Vec<KeyProof>, sqlx::Error> {
+        let heights: Vec<(i64,)> = sqlx::query_as(
+            "SELECT block_height FROM pubkeys WHERE block_height < ?1
+            UNION SELECT revoked_height FROM pubkeys WHERE revoked_height < ?1
+            ORDER BY 1",
+        )
+        .bind(height)
+        .fetch_all(&self.chain_db)
+        .await?;
+        let mut proofs = Vec::new();
+        for (height,) in heights {
+            proofs.extend(KeyProof::all(&self.get_block_by_height(height).await?));
+        }
+        Ok(proofs)
+    }
+
+    /// Digests of every attachment referenced on chain, oldest first
+    pub async fn attachment_hashes(&self) -> Result<Vec<String>, sqlx::Error> {
+        let hashes: Vec<(String,)> =
+            sqlx::query_as("SELECT hash FROM attachments GROUP BY hash ORDER BY MIN(block_height)")
+                .fetch_all(&self.chain_db)
+                .await?;
+        Ok(hashes.into_iter().map(|(hash,)| hash).collect())
+    }
+
+    /// Whether a block on chain references the attachment
+    pub async fn has_attachment(&self, hash: &str) -> Result<bool, sqlx::Error> {
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM attachments WHERE hash = ?1")
             .bind(hash)
-            .fetch_one(&mut *pool)
-            .await?;
-        let results = sqlx::query_as("Select * from results where block_height = ?1")
-            .bind(block.height as i64)
-            .fetch_all(&mut *pool)
+            .fetch_one(&self.chain_db)
             .await?;
-        let pub_key = self
-            .get_public_key(&block.signature_pub_key_hash)
-            .await
-            .unwrap();
-        block.set_results(results);
-        block.set_pub_key(pub_key);
-        Ok(block)
+        Ok(count > 0)
     }
 
-    pub async fn is_valid(&self) -> Result<bool, sqlx::Error> {
-        let height = self.get_height().await?;
-
-        for index in 0..height {
-            dbg!(index);
-            let block = self.get_block_by_height(index).await?;
+    pub async fn candidate_position(
+        &self,
+        candidate_id: i64,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let row: Option<(String,)> =
+            sqlx::query_as("SELECT position_type FROM candidates WHERE id = ?1")
+                .bind(candidate_id)
+                .fetch_optional(&self.chain_db)
+                .await?;
+        Ok(row.map(|(position_type,)| position_type))
+    }
 
-            let prev_hash = if index == 0 {
-                [0u8; 32]
-            } else {
-                self.get_block_by_height(index - 1)
-                    .await?
-                    .hash
-                    .as_bytes()
-                    .try_into()
-                    .unwrap()
-            };
+    /// Every area containing the station, from the station itself out to the nation
+    pub async fn station_areas(&self, station_id: i64) -> Result<Vec<Area>, sqlx::Error> {
+        let row: Option<(i64, i64, i64)> = sqlx::query_as(
+            "SELECT s.ward_code, w.constituency_code, con.county_code FROM stations s
+            JOIN wards w ON s.ward_code = w.ward_code
+            JOIN constituencies con ON w.constituency_code = con.constituency_code
+            WHERE s.id = ?1",
+        )
+        .bind(station_id)
+        .fetch_optional(&self.chain_db)
+        .await?;
+        let mut areas = vec![Area::Station(station_id)];
+        if let Some((ward, constituency, county)) = row {
+            areas.extend([
+                Area::Ward(ward),
+                Area::Constituency(constituency),
+                Area::County(county),
+            ]);
+        }
+        areas.push(Area::National);
+        Ok(areas)
+    }
 
-            // 1. Reconstruct the header using the stored merkle_root
-            let header = ElectionBlockHeader {
-                block_number: block.height as i64,
-                merkle_root: block.merkle_root, // already stored, no recomputation
-                previous_hash: prev_hash,
-                validator_signature: block.signature_pub_key_hash.clone(),
-                timestamp: block.timestamp.timestamp() as i64,
-            };
+    pub async fn registered_voters(&self, station_id: i64) -> Result<Option<i64>, sqlx::Error> {
+        let row: Option<(Option<i64>,)> =
+            sqlx::query_as("SELECT registered_voters FROM stations WHERE id = ?1")
+                .bind(station_id)
+                .fetch_optional(&self.chain_db)
+                .await?;
+        Ok(row.and_then(|(registered_voters,)| registered_voters))
+    }
 
-            // 2. Re-hash header and compare
-            let calculated_hash = types::crypto::hash_block(&header);
-            if calculated_hash != block.hash {
-                return Err(sqlx::Error::Protocol(
-                    format!("Block hash mismatch at index {}", index).into(),
-                ));
-            }
+    /// Current tally form summaries of a station, one per position and submission
+    pub async fn summaries_by_station(
+        &self,
+        election_id: i64,
+        station_id: i64,
+    ) -> Result<Vec<StationSummary>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT * FROM current_station_summaries WHERE election_id = ?1 AND station_id = ?2
+            ORDER BY position_type, block_height",
+        )
+        .bind(election_id)
+        .bind(station_id)
+        .fetch_all(&self.chain_db)
+        .await
+    }
 
-            // 3. Verify signature
-            let pub_key = self.get_public_key(&block.signature_pub_key_hash).await?;
-            let verifier: VerifyingKey = deserialize(&pub_key.bytes).unwrap();
-            let signature_bytes = hex::decode(&block.hash_signature).unwrap();
-            let signature: Signature = deserialize(&signature_bytes).unwrap();
-            verifier
-                .verify(calculated_hash.as_bytes(), &signature)
-                .unwrap();
+    /// Fills in the tally totals of the area for each candidate's position,
+    /// and its confirmation counts. Like votes, each station's summaries are
+    /// averaged over submissions.
+    async fn with_tally(
+        &self,
+        election_id: i64,
+        area: Area,
+        mut results: Vec<GeneralResult>,
+    ) -> Result<Vec<GeneralResult>, sqlx::Error> {
+        let (column, area_code) = Self::area_filter(area);
+        let rows = sqlx::query(&format!(
+            "WITH station_tally AS (
+                SELECT
+                    ss.station_id,
+                    ss.position_type,
+                    CAST(AVG(ss.rejected) AS INTEGER) AS rejected,
+                    CAST(AVG(ss.disputed) AS INTEGER) AS disputed,
+                    CAST(AVG(ss.objected) AS INTEGER) AS objected,
+                    CAST(AVG(ss.total_valid) AS INTEGER) AS total_valid,
+                    CAST(AVG(ss.total_cast) AS INTEGER) AS total_cast
+                FROM current_station_summaries ss
+                JOIN stations s ON ss.station_id = s.id
+                JOIN wards w ON s.ward_code = w.ward_code
+                JOIN constituencies con ON w.constituency_code = con.constituency_code
+                WHERE ss.election_id = ?1 AND (?2 IS NULL OR {column} = ?2)
+                GROUP BY ss.station_id, ss.position_type
+            )
+            SELECT
+                c.id AS candidate_id,
+                SUM(t.rejected) AS rejected,
+                SUM(t.disputed) AS disputed,
+                SUM(t.objected) AS objected,
+                SUM(t.total_valid) AS total_valid,
+                SUM(t.total_cast) AS total_cast
+            FROM station_tally t
+            JOIN candidates c ON c.position_type = t.position_type
+            JOIN election_candidates ec ON ec.candidate_id = c.id AND ec.election_id = ?1
+            GROUP BY c.id"
+        ))
+        .bind(election_id)
+        .bind(area_code)
+        .fetch_all(&self.chain_db)
+        .await?;
 
-            // 4. Verify chain linkage
-            if block.prev_hash != hex::encode(prev_hash) {
-                return Err(sqlx::Error::Protocol(
-                    format!("Previous hash mismatch at index {}", index).into(),
-                ));
-            }
+        let mut totals = HashMap::with_capacity(rows.len());
+        for row in rows {
+            totals.insert(
+                row.get::<i64, _>("candidate_id"),
+                TallyTotals::from_row(&row)?,
+            );
         }
-
-        Ok(true)
+        for result in &mut results {
+            result.tally = totals.get(&(result.candidate_id as i64)).cloned();
+        }
+        self.with_confirmation(election_id, area, results).await
     }
 
-    // TODO: Optimize this to a single query
-    pub async fn get_blocks_in_range(
+    /// Fills in how many of the area's stations are pending, confirmed or
+    /// disputed
+    async fn with_confirmation(
         &self,
-        start: i64,
-        end: i64,
-    ) -> Result<Vec<Block>, sqlx::Error> {
-        dbg!(start, end);
-        let mut blocks = Vec::new();
-        let mut pool = self.chain_db.acquire().await?;
-        let raw_blocks: Vec<Block> =
-            sqlx::query_as("SELECT * FROM blockchain WHERE height >= ?1 AND height <= ?2")
-                .bind(start as i64)
-                .bind(end as i64)
-                .fetch_all(&mut *pool)
-                .await?;
-        for mut block in raw_blocks {
-            let results = sqlx::query_as("Select * from results where block_height = ?1")
-                .bind(block.height as i64)
-                .fetch_all(&mut *pool)
-                .await?;
-            let pub_key = self.get_public_key(&block.signature_pub_key_hash).await?;
-            block.set_results(results);
-            block.set_pub_key(pub_key);
-            blocks.push(block);
+        election_id: i64,
+        area: Area,
+        mut results: Vec<GeneralResult>,
+    ) -> Result<Vec<GeneralResult>, sqlx::Error> {
+        let (column, area_code) = Self::area_filter(area);
+        let counts = sqlx::query_as::<_, ConfirmationCounts>(&format!(
+            "SELECT
+                COALESCE(SUM(sc.state = 'pending'), 0) AS pending,
+                COALESCE(SUM(sc.state = 'confirmed'), 0) AS confirmed,
+                COALESCE(SUM(sc.state = 'disputed'), 0) AS disputed
+            FROM station_confirmations sc
+            JOIN stations s ON sc.station_id = s.id
+            JOIN wards w ON s.ward_code = w.ward_code
+            JOIN constituencies con ON w.constituency_code = con.constituency_code
+            WHERE sc.election_id = ?1 AND (?2 IS NULL OR {column} = ?2)"
+        ))
+        .bind(election_id)
+        .bind(area_code)
+        .fetch_one(&self.chain_db)
+        .await?;
+        for result in &mut results {
+            result.confirmation = counts.clone();
         }
-        Ok(blocks)
+        Ok(results)
+    }
+
+    /// The column selecting stations in `area` when `stations s`, `wards w`
+    /// and `constituencies con` are joined, and the code to match it with
+    fn area_filter(area: Area) -> (&'static str, Option<i64>) {
+        let (area_type, area_code) = area.parts();
+        let column = match area_type {
+            "county" => "con.county_code",
+            "constituency" => "w.constituency_code",
+            "ward" => "s.ward_code",
+            "station" => "s.id",
+            _ => "NULL",
+        };
+        (column, area_code)
     }
 
     pub async fn positions(&self) -> Result<Vec<String>, sqlx::Error> {
@@ -406,8 +1898,97 @@ impl Database {
         Ok(results)
     }
 
+    /// Current votes per candidate at a station, averaged over submissions
+    /// like [`Database::results_by_station`]
+    pub async fn station_votes(
+        &self,
+        election_id: i64,
+        station_id: i64,
+    ) -> Result<Vec<CandidateResult>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT election_id, station_id, candidate_id, CAST(AVG(votes) AS INTEGER) AS votes
+            FROM current_results WHERE election_id = ?1 AND station_id = ?2
+            GROUP BY candidate_id ORDER BY candidate_id",
+        )
+        .bind(election_id)
+        .bind(station_id)
+        .fetch_all(&self.chain_db)
+        .await
+    }
+
+    /// Heights of the blocks holding the current results of a station
+    pub async fn current_result_heights(
+        &self,
+        election_id: i64,
+        station_id: i64,
+    ) -> Result<Vec<i64>, sqlx::Error> {
+        let heights = sqlx::query_as::<_, (i64,)>(
+            "SELECT DISTINCT block_height FROM current_results WHERE election_id = ?1 AND station_id = ?2",
+        )
+        .bind(election_id)
+        .bind(station_id)
+        .fetch_all(&self.chain_db)
+        .await?;
+        Ok(heights.into_iter().map(|(height,)| height).collect())
+    }
+
+    /// Every correction made to a station's results, newest first
+    pub async fn corrections_by_station(
+        &self,
+        station_id: i64,
+    ) -> Result<Vec<StationCorrection>, sqlx::Error> {
+        sqlx::query_as("SELECT * FROM corrections WHERE station_id = ?1 ORDER BY block_height DESC")
+            .bind(station_id)
+            .fetch_all(&self.chain_db)
+            .await
+    }
+
+    /// Every result submitted for a station, including the superseded ones
+    pub async fn result_history(
+        &self,
+        station_id: i64,
+    ) -> Result<Vec<ResultRevision>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT * FROM results WHERE station_id = ?1 ORDER BY block_height DESC, candidate_id",
+        )
+        .bind(station_id)
+        .fetch_all(&self.chain_db)
+        .await
+    }
+
+    /// Every attestation published for a station, newest first
+    pub async fn attestations_by_station(
+        &self,
+        station_id: i64,
+    ) -> Result<Vec<StationAttestation>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT * FROM attestations WHERE station_id = ?1
+            ORDER BY block_height DESC, candidate_id",
+        )
+        .bind(station_id)
+        .fetch_all(&self.chain_db)
+        .await
+    }
+
+    /// The most recent attestation `attester` published for each candidate and station
+    pub async fn latest_attestations(
+        &self,
+        attester: &str,
+    ) -> Result<Vec<StationAttestation>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT * FROM attestations a WHERE attester = ?1 AND block_height = (
+                SELECT MAX(block_height) FROM attestations b WHERE b.attester = a.attester
+                AND b.election_id = a.election_id AND b.station_id = a.station_id AND b.candidate_id = a.candidate_id
+            )",
+        )
+        .bind(attester)
+        .fetch_all(&self.chain_db)
+        .await
+    }
+
     pub async fn results_by_station(
         &self,
+        election_id: i64,
         station_id: i64,
     ) -> Result<Vec<GeneralResult>, sqlx::Error> {
         let results = sqlx::query_as::<_, GeneralResult>(
@@ -419,11 +2000,11 @@ impl Database {
                     CAST(AVG(r.votes) AS INTEGER) AS votes,
                     AVG(r.votes * r.votes) AS sq_votes,
                     AVG(r.votes) AS avg_votes
-                FROM results r
+                FROM current_results r
                 JOIN stations s ON r.station_id = s.id
                 JOIN candidates c ON r.candidate_id = c.id
                 LEFT JOIN parties p ON c.party_id = p.id
-                WHERE s.id = ?
+                WHERE r.election_id = ? AND s.id = ?
                 GROUP BY c.id
             )
             SELECT
@@ -440,6 +2021,7 @@ impl Database {
             ORDER BY votes DESC;
             ",
         )
+        .bind(election_id)
         .bind(station_id)
         .fetch_all(&self.chain_db)
         .await?;
@@ -453,11 +2035,13 @@ impl Database {
             })
             .collect();
 
-        Ok(results)
+        self.with_tally(election_id, Area::Station(station_id), results)
+            .await
     }
 
     pub async fn results_by_ward(
         &self,
+        election_id: i64,
         ward_code: &i32,
     ) -> Result<Vec<GeneralResult>, sqlx::Error> {
         let results = sqlx::query_as::<_, GeneralResult>(
@@ -467,11 +2051,11 @@ impl Database {
                         c.id AS candidate_id,
                         AVG(r.votes) AS avg_votes,
                         AVG(r.votes * r.votes) AS avg_sq_votes
-                    FROM results r
+                    FROM current_results r
                     JOIN stations s ON r.station_id = s.id
                     JOIN candidates c ON r.candidate_id = c.id
                     JOIN wards w ON s.ward_code = w.ward_code
-                    WHERE w.ward_code = ?
+                    WHERE r.election_id = ? AND w.ward_code = ?
                     GROUP BY s.id, c.id
                 ),
                 candidate_summary AS (
@@ -500,6 +2084,7 @@ impl Database {
                 ORDER BY candidate_summary.votes DESC;
                 "
             )
+            .bind(election_id)
             .bind(ward_code)
             .fetch_all(&self.chain_db)
             .await?;
@@ -513,11 +2098,13 @@ impl Database {
             })
             .collect();
 
-        Ok(results)
+        self.with_tally(election_id, Area::Ward(*ward_code as i64), results)
+            .await
     }
 
     pub async fn results_by_constituency(
         &self,
+        election_id: i64,
         constituency_code: &i32,
         position_type: &str,
     ) -> Result<Vec<GeneralResult>, sqlx::Error> {
@@ -528,12 +2115,12 @@ impl Database {
                         c.id AS candidate_id,
                         AVG(r.votes) AS avg_votes,
                         AVG(r.votes * r.votes) AS avg_sq_votes
-                    FROM results r
+                    FROM current_results r
                     JOIN stations s ON r.station_id = s.id
                     JOIN wards w ON s.ward_code = w.ward_code
                     JOIN constituencies con ON w.constituency_code = con.constituency_code
                     JOIN candidates c ON r.candidate_id = c.id
-                    WHERE con.constituency_code = ? AND c.position_type = ?
+                    WHERE r.election_id = ? AND con.constituency_code = ? AND c.position_type = ?
                     GROUP BY s.id, c.id
                 ),
                 candidate_summary AS (
@@ -562,6 +2149,7 @@ impl Database {
                 ORDER BY cs.total_votes DESC;
                 ",
         )
+        .bind(election_id)
         .bind(constituency_code)
         .bind(position_type)
         .fetch_all(&self.chain_db)
@@ -576,11 +2164,17 @@ impl Database {
             })
             .collect();
 
-        Ok(results)
+        self.with_tally(
+            election_id,
+            Area::Constituency(*constituency_code as i64),
+            results,
+        )
+        .await
     }
 
     pub async fn results_by_county(
         &self,
+        election_id: i64,
         county_code: &i32,
         position_type: &str,
     ) -> Result<Vec<GeneralResult>, sqlx::Error> {
@@ -591,13 +2185,13 @@ impl Database {
                     c.id AS candidate_id,
                     AVG(r.votes) AS avg_votes,
                     AVG(r.votes * r.votes) AS avg_sq_votes
-                FROM results r
+                FROM current_results r
                 JOIN stations s ON r.station_id = s.id
                 JOIN wards w ON s.ward_code = w.ward_code
                 JOIN constituencies con ON w.constituency_code = con.constituency_code
                 JOIN counties co ON con.county_code = co.county_code
                 JOIN candidates c ON r.candidate_id = c.id
-                WHERE co.county_code = ? AND c.position_type = ?
+                WHERE r.election_id = ? AND co.county_code = ? AND c.position_type = ?
                 GROUP BY s.id, c.id
             ),
             candidate_summary AS (
@@ -626,6 +2220,7 @@ impl Database {
             ORDER BY cs.total_votes DESC;
             ",
         )
+        .bind(election_id)
         .bind(county_code)
         .bind(position_type)
         .fetch_all(&self.chain_db)
@@ -640,23 +2235,26 @@ impl Database {
             })
             .collect();
 
-        Ok(results)
+        self.with_tally(election_id, Area::County(*county_code as i64), results)
+            .await
     }
 
     pub async fn candidates_by_station(
         &self,
+        election_id: i64,
         station_id: i32,
     ) -> Result<Vec<Candidate>, sqlx::Error> {
         let results = sqlx::query_as::<_, Candidate>(
             r#"
             SELECT c.*, p.title AS party_name
             FROM candidates c
-            JOIN candidate_areas ca ON ca.candidate_id = c.id
+            JOIN election_candidates ec ON ec.candidate_id = c.id
             JOIN parties p ON c.party_id = p.id
-            WHERE ca.area_type = 'station'
-              AND ca.station_id = ?
+            WHERE ec.election_id = ?
+              AND c.voting_station = ?
             "#,
         )
+        .bind(election_id)
         .bind(station_id)
         .fetch_all(&self.chain_db)
         .await?;
@@ -664,17 +2262,24 @@ impl Database {
         Ok(results)
     }
 
-    pub async fn candidates_by_ward(&self, ward_code: &i32) -> Result<Vec<Candidate>, sqlx::Error> {
+    pub async fn candidates_by_ward(
+        &self,
+        election_id: i64,
+        ward_code: &i32,
+    ) -> Result<Vec<Candidate>, sqlx::Error> {
         let results = sqlx::query_as::<_, Candidate>(
             r#"
             SELECT c.*, p.title AS party_name
             FROM candidates c
+            JOIN election_candidates ec ON ec.candidate_id = c.id
             JOIN stations st ON c.voting_station = st.id
             JOIN parties p ON c.party_id = p.id
-            WHERE c.position_type = 'Mca'
+            WHERE ec.election_id = ?
+              AND c.position_type = 'Mca'
               AND st.ward_code = ?
             "#,
         )
+        .bind(election_id)
         .bind(ward_code)
         .fetch_all(&self.chain_db)
         .await?;
@@ -684,6 +2289,7 @@ impl Database {
 
     pub async fn candidates_by_constituency(
         &self,
+        election_id: i64,
         constituency_code: &i32,
         position_type: &str,
     ) -> Result<Vec<Candidate>, sqlx::Error> {
@@ -694,11 +2300,14 @@ impl Database {
             INNER JOIN stations st ON c.voting_station = st.id
             INNER JOIN wards w ON w.ward_code = st.ward_code
             INNER JOIN constituencies cts ON cts.constituency_code = w.constituency_code
+            JOIN election_candidates ec ON ec.candidate_id = c.id
             JOIN parties p ON c.party_id = p.id
-            WHERE c.position_type = ?
+            WHERE ec.election_id = ?
+              AND c.position_type = ?
               AND cts.constituency_code = ?
             "#,
         )
+        .bind(election_id)
         .bind(position_type)
         .bind(constituency_code)
         .fetch_all(&self.chain_db)
@@ -709,6 +2318,7 @@ impl Database {
 
     pub async fn candidates_by_county(
         &self,
+        election_id: i64,
         county_code: &i32,
         position_type: &str,
     ) -> Result<Vec<Candidate>, sqlx::Error> {
@@ -720,11 +2330,14 @@ impl Database {
             INNER JOIN wards w ON w.ward_code = st.ward_code
             INNER JOIN constituencies cts ON cts.constituency_code = w.constituency_code
             INNER JOIN counties ct ON ct.county_code = cts.county_code
+            JOIN election_candidates ec ON ec.candidate_id = c.id
             JOIN parties p ON c.party_id = p.id
-            WHERE c.position_type = ?
+            WHERE ec.election_id = ?
+              AND c.position_type = ?
               AND ct.county_code = ?
             "#,
         )
+        .bind(election_id)
         .bind(position_type)
         .bind(county_code)
         .fetch_all(&self.chain_db)
@@ -733,27 +2346,39 @@ impl Database {
         Ok(results)
     }
 
-    pub async fn candidates_national(&self) -> Result<Vec<Candidate>, sqlx::Error> {
+    pub async fn candidates_national(
+        &self,
+        election_id: i64,
+    ) -> Result<Vec<Candidate>, sqlx::Error> {
         let results = sqlx::query_as::<_, Candidate>(
             r#"
             SELECT c.*, p.title AS party_name
-            JOIN parties p ON c.party_id = p.id
             FROM candidates c
-            WHERE c.position_type = 'President'
+            JOIN election_candidates ec ON ec.candidate_id = c.id
+            JOIN parties p ON c.party_id = p.id
+            WHERE ec.election_id = ?
+              AND c.position_type = 'President'
             "#,
         )
+        .bind(election_id)
         .fetch_all(&self.chain_db)
         .await?;
 
         Ok(results)
     }
+    /// The five most recent stations, and every disputed one so it does not
+    /// drop out of the feed
     pub async fn last_five_results(&self) -> Result<Vec<LastResultSummary>, sqlx::Error> {
         let mut results = sqlx::query_as::<_, LastResultSummary>(
             "WITH latest_stations AS (
-                SELECT DISTINCT station_id
-                FROM results
-                ORDER BY station_id DESC
-                LIMIT 5
+                SELECT station_id FROM (
+                    SELECT DISTINCT station_id
+                    FROM current_results
+                    ORDER BY station_id DESC
+                    LIMIT 5
+                )
+                UNION
+                SELECT station_id FROM station_confirmations WHERE state = 'disputed'
             ),
             station_candidate_votes AS (
                 SELECT
@@ -764,7 +2389,7 @@ impl Database {
                     COALESCE(p.title, 'Independent') AS party_title,
                     c.position_type,
                     r.votes
-                FROM results r
+                FROM current_results r
                 JOIN stations s ON r.station_id = s.id
                 JOIN candidates c ON r.candidate_id = c.id
                 LEFT JOIN parties p ON c.party_id = p.id
@@ -817,6 +2442,18 @@ impl Database {
                 FROM station_candidate_agg sca
                 JOIN station_totals st ON sca.station_id = st.station_id AND sca.position_type = st.position_type
             ),
+            station_tally AS (
+                SELECT
+                    station_id,
+                    position_type,
+                    CAST(AVG(rejected) AS INTEGER) AS rejected,
+                    CAST(AVG(disputed) AS INTEGER) AS disputed,
+                    CAST(AVG(objected) AS INTEGER) AS objected,
+                    CAST(AVG(total_cast) AS INTEGER) AS total_cast
+                FROM current_station_summaries
+                WHERE station_id IN (SELECT station_id FROM latest_stations)
+                GROUP BY station_id, position_type
+            ),
             final_results AS (
                 SELECT
                     rc1.position_type,
@@ -832,7 +2469,17 @@ impl Database {
                     rc2.party_title AS candidate2_party,
                     rc2.votes AS candidate2_votes,
                     rc2.percentage AS candidate2_percentage,
-                    sd.sd_squared AS sd
+                    sd.sd_squared AS sd,
+                    t.rejected,
+                    t.disputed,
+                    t.objected,
+                    t.total_cast,
+                    COALESCE((
+                        SELECT sc.state FROM station_confirmations sc
+                        WHERE sc.station_id = rc1.station_id
+                        ORDER BY sc.state = 'disputed' DESC, sc.block_height DESC
+                        LIMIT 1
+                    ), 'pending') AS confirmation
                 FROM ranked_candidates rc1
                 LEFT JOIN ranked_candidates rc2
                     ON rc1.station_id = rc2.station_id
@@ -841,6 +2488,9 @@ impl Database {
                 JOIN station_sd sd
                     ON rc1.station_id = sd.station_id
                     AND rc1.position_type = sd.position_type
+                LEFT JOIN station_tally t
+                    ON rc1.station_id = t.station_id
+                    AND rc1.position_type = t.position_type
                 WHERE rc1.rank = 1
             )
             SELECT * FROM final_results
@@ -858,3 +2508,573 @@ impl Database {
         Ok(results)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::{
+        BlockSigner, BlockType, Correction,
+        agents::{AgentSignature, Submission},
+    };
+
+    /// A single station in Juja, with 533 registered voters and three candidates
+    const REFERENCE_DATA: &str = "
+        INSERT INTO positions VALUES ('Mp');
+        INSERT INTO parties VALUES (1, 'ODM', ''), (2, 'PNU', '');
+        INSERT INTO counties VALUES (22, 'Kiambu');
+        INSERT INTO constituencies VALUES (113, 22, 'Juja');
+        INSERT INTO wards VALUES (563, 113, 'Kalimoni');
+        INSERT INTO stations VALUES (22113056303301, 563, 33, 'Athi Primary School', 533);
+        INSERT INTO candidates VALUES
+            (1, 'Mwas', 'M', '', 'Mp', 1, 22113056303301),
+            (2, 'Omosh', 'M', '', 'Mp', 2, 22113056303301),
+            (3, 'Wanjiku', 'F', '', 'Mp', 1, 22113056303301);
+    ";
+    const STATION: i64 = 22113056303301;
+    const ELECTION: i64 = 1;
+
+    /// A database holding the genesis block and election 1 at height 1,
+    /// with the key that signed both
+    async fn election_db() -> (Database, BlockSigner) {
+        let mut db = Database::new_in_memory();
+        sqlx::query(MAIN_SETUP).execute(&db.chain_db).await.unwrap();
+
+        let key = types::crypto::get_private_key();
+        let verifying_key = types::crypto::get_public_key(&key);
+        let pub_key = PubKey::from_verifying_key("genesis", &verifying_key);
+        let signer = (key, verifying_key, pub_key);
+        let reference_data = Database::reference_data_from_sql(REFERENCE_DATA)
+            .await
+            .unwrap();
+        db.bootstrap(&Block::genesis(&signer, reference_data))
+            .await
+            .unwrap();
+
+        let election = Election {
+            id: ELECTION,
+            name: "Juja by-election".into(),
+            date: NaiveDate::from_ymd_opt(2025, 11, 27).unwrap(),
+            election_type: ElectionType::ByElection,
+            areas: vec![Area::Constituency(113)],
+            candidates: vec![1, 2],
+        };
+        append(&mut db, &signer, BlockType::Election(election))
+            .await
+            .unwrap();
+        (db, signer)
+    }
+
+    /// Adds a block carrying `inner` on top of the tip, as consensus would
+    async fn append(
+        db: &mut Database,
+        signer: &BlockSigner,
+        inner: BlockType,
+    ) -> Result<i64, sqlx::Error> {
+        let tip = db.last_block().await?;
+        db.add_block(&Block::with_inner(signer, &tip.hash, inner, tip.height + 1))
+            .await
+    }
+
+    fn result(candidate_id: usize, votes: usize) -> CandidateResult {
+        CandidateResult::new(ELECTION as usize, STATION as usize, candidate_id, votes)
+    }
+
+    /// A tally from `agent`, the database leaves checking its signature to the validator
+    fn tally(
+        agent: &str,
+        results: Vec<CandidateResult>,
+        summaries: Vec<StationSummary>,
+    ) -> BlockType {
+        BlockType::Submission(Submission {
+            results,
+            summaries,
+            attachments: vec![],
+            signed_by: AgentSignature {
+                agent: agent.to_string(),
+                signature: String::new(),
+            },
+        })
+    }
+
+    fn correction(original_height: usize, results: Vec<CandidateResult>) -> Correction {
+        Correction {
+            election_id: ELECTION,
+            station_id: STATION,
+            original_height,
+            results,
+            summaries: vec![],
+            reason: "Digits swapped".into(),
+            attachments: vec![],
+        }
+    }
+
+    async fn votes(db: &Database) -> Vec<i64> {
+        db.station_votes(ELECTION, STATION)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.votes)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_corrections_supersede_results() {
+        let (mut db, signer) = election_db().await;
+        append(
+            &mut db,
+            &signer,
+            BlockType::Result(vec![result(1, 66), result(2, 12)]),
+        )
+        .await
+        .unwrap();
+        append(
+            &mut db,
+            &signer,
+            BlockType::Correction(vec![correction(2, vec![result(1, 66), result(2, 21)])]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(votes(&db).await, vec![66, 21]);
+
+        // The original results stay, marked with the height that replaced them
+        let history: Vec<_> = db
+            .result_history(STATION)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.block_height, r.votes, r.superseded_by))
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                (3, 66, None),
+                (3, 21, None),
+                (2, 66, Some(3)),
+                (2, 12, Some(3))
+            ]
+        );
+        let corrections = db.corrections_by_station(STATION).await.unwrap();
+        assert_eq!(corrections[0].original_height, 2);
+        assert_eq!(corrections[0].corrected_by, signer.2.hash);
+
+        // Detaching the correction makes the original results current again
+        db.reorganize(2, &[]).await.unwrap();
+        assert_eq!(votes(&db).await, vec![66, 12]);
+        assert!(
+            db.result_history(STATION)
+                .await
+                .unwrap()
+                .iter()
+                .all(|r| r.block_height == 2 && r.superseded_by.is_none())
+        );
+        assert!(db.corrections_by_station(STATION).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_vote_limit_counts_current_results_of_a_block() {
+        let (mut db, signer) = election_db().await;
+
+        // More votes than the 533 registered voters, nothing of the block is kept
+        let overflow = BlockType::Result(vec![result(1, 300), result(2, 234)]);
+        assert!(append(&mut db, &signer, overflow).await.is_err());
+        assert_eq!(db.get_height().await.unwrap(), 1);
+        assert!(db.result_history(STATION).await.unwrap().is_empty());
+
+        append(
+            &mut db,
+            &signer,
+            BlockType::Result(vec![result(1, 300), result(2, 200)]),
+        )
+        .await
+        .unwrap();
+        // The second correction in a block supersedes the first, whose votes
+        // then no longer count towards the limit
+        append(
+            &mut db,
+            &signer,
+            BlockType::Correction(vec![
+                correction(2, vec![result(1, 300), result(2, 220)]),
+                correction(2, vec![result(1, 300), result(2, 230)]),
+            ]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(votes(&db).await, vec![300, 230]);
+        let (current,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM current_results")
+            .fetch_one(&db.chain_db)
+            .await
+            .unwrap();
+        assert_eq!(current, 2);
+    }
+
+    #[tokio::test]
+    async fn test_elections_are_rolled_back_with_their_results() {
+        let (mut db, signer) = election_db().await;
+        let election = db.election(ELECTION).await.unwrap().unwrap();
+        assert_eq!(election.areas, vec![Area::Constituency(113)]);
+        assert_eq!(election.candidates, vec![1, 2]);
+        append(
+            &mut db,
+            &signer,
+            BlockType::Result(vec![result(1, 66), result(2, 21)]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(votes(&db).await, vec![66, 21]);
+        assert!(db.station_votes(2, STATION).await.unwrap().is_empty());
+
+        db.reorganize(0, &[]).await.unwrap();
+        assert!(db.election(ELECTION).await.unwrap().is_none());
+        assert!(db.elections().await.unwrap().is_empty());
+        assert!(db.result_history(STATION).await.unwrap().is_empty());
+        for table in ["election_areas", "election_candidates"] {
+            let (rows,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM {table}"))
+                .fetch_one(&db.chain_db)
+                .await
+                .unwrap();
+            assert_eq!(rows, 0, "{table}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reference_data_is_replayed_on_reorganisation() {
+        let (mut db, signer) = election_db().await;
+        let gazette = db.export_reference_data().await.unwrap();
+        let rename = ReferenceData::Station {
+            id: STATION,
+            ward_code: 563,
+            reg_center_code: Some(33),
+            name: "Athi Primary".into(),
+            registered_voters: 540,
+        };
+        let ward = ReferenceData::Ward {
+            code: 564,
+            constituency_code: 113,
+            name: "Theta".into(),
+        };
+        append(&mut db, &signer, BlockType::ReferenceData(vec![rename]))
+            .await
+            .unwrap();
+        append(&mut db, &signer, BlockType::ReferenceData(vec![ward]))
+            .await
+            .unwrap();
+        assert_eq!(db.registered_voters(STATION).await.unwrap(), Some(540));
+        assert_eq!(db.wards().await.unwrap().len(), 2);
+
+        // Updates cannot be undone one by one, the gazette is rebuilt from
+        // the blocks left on chain
+        db.reorganize(2, &[]).await.unwrap();
+        assert_eq!(db.registered_voters(STATION).await.unwrap(), Some(540));
+        assert_eq!(db.wards().await.unwrap().len(), 1);
+        db.reorganize(1, &[]).await.unwrap();
+        assert_eq!(db.export_reference_data().await.unwrap(), gazette);
+        // The election still points at the replayed constituency
+        assert!(db.election(ELECTION).await.unwrap().is_some());
+
+        // A record that does not fit the gazette is refused without being kept
+        let orphan = ReferenceData::Ward {
+            code: 565,
+            constituency_code: 999,
+            name: "Kalimoni West".into(),
+        };
+        assert!(db.check_reference_data(&[orphan]).await.is_err());
+        assert_eq!(db.export_reference_data().await.unwrap(), gazette);
+    }
+
+    #[tokio::test]
+    async fn test_summaries_follow_the_current_results() {
+        let (mut db, signer) = election_db().await;
+        let summary = |rejected, total_cast| StationSummary {
+            election_id: ELECTION,
+            station_id: STATION,
+            position_type: "Mp".to_string(),
+            rejected,
+            disputed: 1,
+            objected: 0,
+            total_valid: 87,
+            total_cast,
+        };
+        append(
+            &mut db,
+            &signer,
+            tally(
+                "agent",
+                vec![result(1, 66), result(2, 21)],
+                vec![summary(3, 90)],
+            ),
+        )
+        .await
+        .unwrap();
+        let results = db.results_by_station(ELECTION, STATION).await.unwrap();
+        assert_eq!(
+            results[0].tally,
+            Some(TallyTotals {
+                rejected: 3,
+                disputed: 1,
+                objected: 0,
+                total_valid: 87,
+                total_cast: 90,
+            })
+        );
+        let live = db.last_five_results().await.unwrap();
+        assert_eq!((live[0].rejected, live[0].total_cast), (Some(3), Some(90)));
+
+        // A correction replaces the summaries along with the results
+        let mut recount = correction(2, vec![result(1, 66), result(2, 21)]);
+        recount.summaries = vec![summary(2, 89)];
+        append(&mut db, &signer, BlockType::Correction(vec![recount]))
+            .await
+            .unwrap();
+        let summaries = db.summaries_by_station(ELECTION, STATION).await.unwrap();
+        assert_eq!(summaries, vec![summary(2, 89)]);
+
+        db.reorganize(2, &[]).await.unwrap();
+        let summaries = db.summaries_by_station(ELECTION, STATION).await.unwrap();
+        assert_eq!(summaries, vec![summary(3, 90)]);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_keys_are_errors() {
+        let (db, signer) = election_db().await;
+        assert!(db.is_valid().await.unwrap());
+        let hash = db.get_block_by_height(1).await.unwrap().hash;
+
+        // A key that is hex but not a key fails validation...
+        let corrupt = |pubkey: &'static str| {
+            sqlx::query("UPDATE pubkeys SET pubkey = ?1 WHERE pubkey_hash = ?2")
+                .bind(pubkey)
+                .bind(&signer.2.hash)
+                .execute(&db.chain_db)
+        };
+        corrupt("00").await.unwrap();
+        db.get_block_by_height(1).await.unwrap();
+        assert!(matches!(db.is_valid().await, Err(sqlx::Error::Protocol(_))));
+
+        // ...and one that is not even hex fails reading the blocks it signed
+        corrupt("not hex").await.unwrap();
+        assert!(matches!(
+            db.get_block_by_height(1).await,
+            Err(sqlx::Error::Decode(_))
+        ));
+        assert!(matches!(
+            db.get_block_by_hash(&hash).await,
+            Err(sqlx::Error::Decode(_))
+        ));
+        assert!(db.is_valid().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_inclusion_proofs_cover_current_results() {
+        let (mut db, signer) = election_db().await;
+        // Three results, so the last leaf is paired with itself
+        append(
+            &mut db,
+            &signer,
+            BlockType::Result(vec![result(1, 66), result(2, 12), result(3, 40)]),
+        )
+        .await
+        .unwrap();
+        for (candidate_id, votes) in [(1, 66), (2, 12), (3, 40)] {
+            let proof = db
+                .inclusion_proof(Some(ELECTION), STATION, candidate_id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!((proof.header.height, proof.result.votes), (2, votes));
+            assert_eq!(proof.verify(), Ok(()));
+        }
+        assert!(
+            db.inclusion_proof(Some(2), STATION, 1)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // A corrected result is proven through the correction carrying it
+        append(
+            &mut db,
+            &signer,
+            BlockType::Correction(vec![correction(2, vec![result(1, 66), result(2, 21)])]),
+        )
+        .await
+        .unwrap();
+        let proof = db.inclusion_proof(None, STATION, 2).await.unwrap().unwrap();
+        assert_eq!((proof.header.height, proof.result.votes), (3, 21));
+        assert!(matches!(proof.leaf, types::proof::ProofLeaf::Correction(_)));
+        assert_eq!(proof.verify(), Ok(()));
+        assert_eq!(proof.signer_hash(), Some(signer.2.hash.clone()));
+
+        // The genesis key is the validator set, which never certified these blocks
+        let headers: Vec<_> = db
+            .get_blocks_in_range(0, 3)
+            .await
+            .unwrap()
+            .iter()
+            .map(|block| block.signed_header())
+            .collect();
+        let validators = proof.validators(&headers).unwrap();
+        assert_eq!(validators.len(), 1);
+        assert_eq!(validators[0].hash, signer.2.hash);
+        assert_eq!(
+            proof.verify_certificate(&validators),
+            Err(types::proof::ProofError::Uncertified)
+        );
+        assert!(
+            db.inclusion_proof(None, STATION, 3)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stored_blocks_keep_their_canonical_encoding() {
+        let (mut db, signer) = election_db().await;
+        let blocks = [
+            // Result blocks are rebuilt from their rows, in submission order
+            BlockType::Result(vec![result(2, 21), result(1, 66)]),
+            tally("agent", vec![result(2, 20), result(1, 66)], vec![]),
+            BlockType::Correction(vec![correction(2, vec![result(2, 12), result(1, 60)])]),
+        ];
+        for inner in blocks {
+            let root = inner.merkle_root();
+            let height = append(&mut db, &signer, inner).await.unwrap();
+            let stored = db.get_block_by_height(height).await.unwrap();
+            assert_eq!(stored.merkle_root, root);
+            assert_eq!(stored.inner.merkle_root(), root, "block {height}");
+        }
+        let rebuilt = db.get_blocks_in_range(0, 4).await.unwrap();
+        assert!(
+            rebuilt
+                .iter()
+                .all(|block| block.inner.merkle_root() == block.merkle_root)
+        );
+        assert!(db.is_valid().await.unwrap());
+    }
+
+    /// The confirmation row of the test station, as `(state, organisations)`
+    async fn confirmation(db: &Database) -> Option<(String, i64)> {
+        sqlx::query_as(
+            "SELECT state, organisations FROM station_confirmations
+            WHERE election_id = ?1 AND station_id = ?2",
+        )
+        .bind(ELECTION)
+        .bind(STATION)
+        .fetch_optional(&db.chain_db)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_confirmations_are_recomputed_with_the_results() {
+        let (mut db, signer) = election_db().await;
+        let keys: Vec<_> = (0..3).map(|_| types::crypto::get_private_key()).collect();
+        let agents = ["ODM", "ODM", "PNU"]
+            .iter()
+            .zip(&keys)
+            .map(|(organisation, key)| Agent {
+                pub_key: bincode::serialize(&types::crypto::get_public_key(key)).unwrap(),
+                organisation: organisation.to_string(),
+                scope: vec![Area::Ward(563)],
+            })
+            .collect();
+        append(&mut db, &signer, BlockType::Agents(agents))
+            .await
+            .unwrap();
+        let submit = |key, votes| {
+            let results = vec![result(1, votes), result(2, 21)];
+            BlockType::Submission(Submission::new(key, results, vec![], vec![]))
+        };
+
+        // Both ODM agents agreeing is not independent confirmation
+        for key in &keys[..2] {
+            append(&mut db, &signer, submit(key, 66)).await.unwrap();
+        }
+        assert_eq!(confirmation(&db).await, Some(("pending".into(), 1)));
+        append(&mut db, &signer, submit(&keys[2], 66))
+            .await
+            .unwrap();
+        assert_eq!(confirmation(&db).await, Some(("confirmed".into(), 2)));
+
+        // A diverging count disputes the station, which then stays in the live feed
+        append(&mut db, &signer, submit(&keys[2], 60))
+            .await
+            .unwrap();
+        assert_eq!(confirmation(&db).await, Some(("disputed".into(), 2)));
+        let ward = db.results_by_ward(ELECTION, &563).await.unwrap();
+        assert_eq!(
+            ward[0].confirmation,
+            ConfirmationCounts {
+                disputed: 1,
+                ..Default::default()
+            }
+        );
+        let live = db.last_five_results().await.unwrap();
+        assert_eq!(live[0].confirmation, Confirmation::Disputed.as_str());
+
+        // Detached submissions no longer count
+        db.reorganize(5, &[]).await.unwrap();
+        assert_eq!(confirmation(&db).await, Some(("confirmed".into(), 2)));
+        db.reorganize(2, &[]).await.unwrap();
+        assert_eq!(confirmation(&db).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_results_from_nodes_do_not_confirm() {
+        let (mut db, signer) = election_db().await;
+        let key = types::crypto::get_private_key();
+        let verifying_key = types::crypto::get_public_key(&key);
+        let pub_key = PubKey::from_verifying_key("Observer node", &verifying_key);
+        db.add_public_key(&pub_key.bytes, &pub_key.creator, &pub_key.hash, 1)
+            .await
+            .unwrap();
+        let other_node = (key, verifying_key, pub_key);
+
+        // Two nodes with different creators relaying the same votes
+        for node in [&signer, &other_node] {
+            append(
+                &mut db,
+                node,
+                BlockType::Result(vec![result(1, 66), result(2, 21)]),
+            )
+            .await
+            .unwrap();
+        }
+        assert_eq!(confirmation(&db).await, Some(("pending".into(), 0)));
+
+        // Nor does a tally naming an agent that was never registered
+        append(
+            &mut db,
+            &other_node,
+            tally("unregistered", vec![result(1, 66), result(2, 21)], vec![]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(confirmation(&db).await, Some(("pending".into(), 0)));
+    }
+
+    #[tokio::test]
+    async fn test_scratch_copy_is_private() {
+        let (db, signer) = election_db().await;
+        let mut scratch = db.scratch_copy().await.unwrap();
+        assert_eq!(
+            scratch.last_block().await.unwrap().hash,
+            db.last_block().await.unwrap().hash
+        );
+
+        append(
+            &mut scratch,
+            &signer,
+            BlockType::Result(vec![result(1, 66)]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(scratch.last_block().await.unwrap().height, 2);
+        assert_eq!(db.last_block().await.unwrap().height, 1);
+
+        let path = scratch.path.clone();
+        assert!(path.exists());
+        drop(scratch);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,70 @@
+use std::{
+    ops::{Deref, DerefMut},
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+use crate::Database;
+
+/// Tells apart the scratch copies of a process
+static SCRATCH_COPIES: AtomicUsize = AtomicUsize::new(0);
+
+/// A private copy of the chain database, to try changes on that nobody else
+/// sees. The copy lives in a temporary file removed once it is dropped.
+#[derive(Debug)]
+pub struct ScratchDatabase {
+    db: Database,
+    pub(crate) path: PathBuf,
+}
+
+impl Database {
+    /// Copies the chain database as it is now. The private database is not
+    /// copied, the copy starts without keys.
+    pub async fn scratch_copy(&self) -> Result<ScratchDatabase, sqlx::Error> {
+        let path = std::env::temp_dir().join(format!(
+            "ubu-scratch-{}-{}.db",
+            std::process::id(),
+            SCRATCH_COPIES.fetch_add(1, Ordering::Relaxed)
+        ));
+        // As a URI, or the copy of an in-memory database stays in memory too
+        sqlx::query("VACUUM INTO ?1")
+            .bind(format!("file:{}?mode=rwc", path.display()))
+            .execute(&self.chain_db)
+            .await?;
+        let connected = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(SqliteConnectOptions::new().filename(&path))
+            .await;
+        let chain_db = match connected {
+            Ok(chain_db) => chain_db,
+            Err(e) => {
+                let _ = std::fs::remove_file(&path);
+                return Err(e);
+            }
+        };
+        let db = Database::new(chain_db, Database::new_in_memory().private_db);
+        Ok(ScratchDatabase { db, path })
+    }
+}
+
+impl Deref for ScratchDatabase {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        &self.db
+    }
+}
+
+impl DerefMut for ScratchDatabase {
+    fn deref_mut(&mut self) -> &mut Database {
+        &mut self.db
+    }
+}
+
+impl Drop for ScratchDatabase {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
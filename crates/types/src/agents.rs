@@ -0,0 +1,170 @@
+use p256::ecdsa::SigningKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    CandidateResult, StationSummary,
+    crypto::{get_public_key, public_key_from_bytes, sha256_digest, sign_hash, verify_hash},
+    election::Area,
+    keys::key_hash,
+};
+
+/// A party agent or observer allowed to submit results for the stations in
+/// `scope`. Registers the agent, or replaces its record when the key is
+/// already on chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Agent {
+    /// Bincode encoded verifying key, like in `KeyOperation::Register`
+    pub pub_key: Vec<u8>,
+    /// Party or observer organisation the agent reports for
+    pub organisation: String,
+    /// Areas the agent may submit for, empty once the agent is revoked
+    pub scope: Vec<Area>,
+}
+
+impl Agent {
+    /// Identity of the agent, the hash of its key
+    pub fn hash(&self) -> Option<String> {
+        key_hash(&self.pub_key)
+    }
+}
+
+/// Station results signed by the agent who collected them, with the totals
+/// and scans of the stations' tally forms if the agent has them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Submission {
+    pub results: Vec<CandidateResult>,
+    #[serde(default)]
+    pub summaries: Vec<StationSummary>,
+    /// Digests of the scanned forms, see [`crate::crypto::attachment_digest`]
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    pub signed_by: AgentSignature,
+}
+
+/// An agent's signature over what it reports along with results, such as
+/// the totals and scans of a tally form
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentSignature {
+    /// Hash of the agent's key, see [`Agent::hash`]
+    pub agent: String,
+    pub signature: String,
+}
+
+impl AgentSignature {
+    pub fn sign<T: Serialize>(key: &SigningKey, payload: &T) -> Self {
+        Self {
+            agent: sha256_digest(&get_public_key(key)),
+            signature: sign_hash(key, &sha256_digest(payload)),
+        }
+    }
+
+    /// Whether `payload` was signed with `pub_key`, the agent's key
+    pub fn verify<T: Serialize>(&self, pub_key: &[u8], payload: &T) -> bool {
+        let Some(key) = public_key_from_bytes(pub_key) else {
+            return false;
+        };
+        key_hash(pub_key).as_ref() == Some(&self.agent)
+            && verify_hash(&key, &sha256_digest(payload), &self.signature)
+    }
+}
+
+impl Submission {
+    pub fn new(
+        key: &SigningKey,
+        results: Vec<CandidateResult>,
+        summaries: Vec<StationSummary>,
+        attachments: Vec<String>,
+    ) -> Self {
+        let signed_by = AgentSignature::sign(key, &(&results, &summaries, &attachments));
+        Self {
+            results,
+            summaries,
+            attachments,
+            signed_by,
+        }
+    }
+
+    /// What the agent signs: everything else in the submission
+    pub fn payload(&self) -> impl Serialize + '_ {
+        (&self.results, &self.summaries, &self.attachments)
+    }
+
+    /// Whether the submission was signed with `pub_key`, the agent's key
+    pub fn verify(&self, pub_key: &[u8]) -> bool {
+        self.signed_by.verify(pub_key, &self.payload())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::get_private_key;
+
+    #[test]
+    fn test_submission_signed_by_agent() {
+        let key = get_private_key();
+        let pub_key = bincode::serialize(&get_public_key(&key)).unwrap();
+        let submission = Submission::new(
+            &key,
+            vec![CandidateResult::new(1, 2, 3, 400)],
+            vec![],
+            vec!["ab".repeat(32)],
+        );
+        assert!(submission.verify(&pub_key));
+
+        let mut inflated = submission.clone();
+        inflated.results[0].votes += 1;
+        assert!(!inflated.verify(&pub_key));
+        let mut unattached = submission.clone();
+        unattached.attachments.clear();
+        assert!(!unattached.verify(&pub_key));
+
+        let other = bincode::serialize(&get_public_key(&get_private_key())).unwrap();
+        assert!(!submission.verify(&other));
+    }
+
+    #[test]
+    fn test_signature_covers_the_payload() {
+        let key = get_private_key();
+        let pub_key = bincode::serialize(&get_public_key(&key)).unwrap();
+        let payload = (vec![CandidateResult::new(1, 2, 3, 400)], 90);
+        let signature = AgentSignature::sign(&key, &payload);
+        assert!(signature.verify(&pub_key, &payload));
+        assert!(!signature.verify(&pub_key, &(payload.0, 91)));
+    }
+
+    /// `BlockType` as far as the `Tally` variant submissions replaced
+    #[derive(Serialize)]
+    enum TallyBlock {
+        _Pending,
+        _Genesis,
+        _Result,
+        Tally {
+            results: Vec<CandidateResult>,
+            summaries: Vec<StationSummary>,
+            attachments: Vec<String>,
+            signed_by: AgentSignature,
+        },
+    }
+
+    #[test]
+    fn test_stored_tallies_decode_as_submissions() {
+        let submission = Submission::new(
+            &get_private_key(),
+            vec![CandidateResult::new(1, 2, 3, 400)],
+            vec![],
+            vec!["ab".repeat(32)],
+        );
+        let tally = bincode::serialize(&TallyBlock::Tally {
+            results: submission.results.clone(),
+            summaries: submission.summaries.clone(),
+            attachments: submission.attachments.clone(),
+            signed_by: submission.signed_by.clone(),
+        })
+        .unwrap();
+        assert!(matches!(
+            bincode::deserialize(&tally).unwrap(),
+            crate::BlockType::Submission(s) if s == submission
+        ));
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::CandidateResult;
+
+/// How a result on chain compares with the official tally form
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttestationStatus {
+    Match,
+    Mismatch,
+    /// Only one of the chain and the tally form has the result
+    Missing,
+}
+
+impl AttestationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttestationStatus::Match => "match",
+            AttestationStatus::Mismatch => "mismatch",
+            AttestationStatus::Missing => "missing",
+        }
+    }
+}
+
+/// A verification node's finding for one candidate at one station
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    pub election_id: i64,
+    pub station_id: i64,
+    pub candidate_id: i64,
+    pub status: AttestationStatus,
+    /// Votes on the official tally form
+    pub official_votes: Option<i64>,
+    /// Votes on chain, as reported per station by the API
+    pub chain_votes: Option<i64>,
+}
+
+impl Attestation {
+    pub fn new(
+        election_id: i64,
+        station_id: i64,
+        candidate_id: i64,
+        official_votes: Option<i64>,
+        chain_votes: Option<i64>,
+    ) -> Self {
+        let status = match (official_votes, chain_votes) {
+            (Some(official), Some(chain)) if official == chain => AttestationStatus::Match,
+            (Some(_), Some(_)) => AttestationStatus::Mismatch,
+            _ => AttestationStatus::Missing,
+        };
+        Self {
+            election_id,
+            station_id,
+            candidate_id,
+            status,
+            official_votes,
+            chain_votes,
+        }
+    }
+
+    /// Whether `status` agrees with the vote counts
+    pub fn is_consistent(&self) -> bool {
+        (self.official_votes.is_some() || self.chain_votes.is_some())
+            && Self::new(
+                self.election_id,
+                self.station_id,
+                self.candidate_id,
+                self.official_votes,
+                self.chain_votes,
+            )
+            .status
+                == self.status
+    }
+}
+
+/// Compares the official tallies with the results on chain, for every
+/// station that has a tally
+pub fn compare(official: &[CandidateResult], chain: &[CandidateResult]) -> Vec<Attestation> {
+    let stations: BTreeSet<(i64, i64)> = official
+        .iter()
+        .map(|r| (r.election_id, r.station_id))
+        .collect();
+    let index = |results: &[CandidateResult]| -> BTreeMap<(i64, i64, i64), i64> {
+        results
+            .iter()
+            .filter(|r| stations.contains(&(r.election_id, r.station_id)))
+            .map(|r| ((r.election_id, r.station_id, r.candidate_id), r.votes))
+            .collect()
+    };
+    let official = index(official);
+    let chain = index(chain);
+
+    let keys: BTreeSet<&(i64, i64, i64)> = official.keys().chain(chain.keys()).collect();
+    keys.into_iter()
+        .map(|key| {
+            let (election_id, station_id, candidate_id) = *key;
+            Attestation::new(
+                election_id,
+                station_id,
+                candidate_id,
+                official.get(key).copied(),
+                chain.get(key).copied(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_with_official_tally() {
+        let official = vec![
+            CandidateResult::new(1, 1, 1, 66),
+            CandidateResult::new(1, 1, 2, 21),
+            CandidateResult::new(1, 1, 3, 5),
+        ];
+        let chain = vec![
+            CandidateResult::new(1, 1, 1, 66),
+            CandidateResult::new(1, 1, 2, 25),
+            CandidateResult::new(1, 1, 4, 9),
+            // No tally for this station, so nothing to say about it
+            CandidateResult::new(1, 2, 1, 40),
+            // Nor for the station in another election
+            CandidateResult::new(2, 1, 1, 40),
+        ];
+        let statuses: Vec<_> = compare(&official, &chain)
+            .into_iter()
+            .map(|a| (a.candidate_id, a.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (1, AttestationStatus::Match),
+                (2, AttestationStatus::Mismatch),
+                (3, AttestationStatus::Missing),
+                (4, AttestationStatus::Missing),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_attestation_consistency() {
+        let mut attestation = Attestation::new(1, 1, 1, Some(10), Some(12));
+        assert!(attestation.is_consistent());
+        attestation.status = AttestationStatus::Match;
+        assert!(!attestation.is_consistent());
+        assert!(!Attestation::new(1, 1, 1, None, None).is_consistent());
+    }
+}
@@ -0,0 +1,454 @@
+//! The canonical byte encoding of everything that is hashed or signed.
+//!
+//! Block hashes, Merkle leaves and the digests validators sign are SHA3-256
+//! over this encoding, so anyone can recompute them without this code. It is
+//! spelled out here rather than left to a serialization library, and matches
+//! the encoding existing chains were hashed with.
+//!
+//! A value is encoded field by field in declaration order, without field
+//! names, padding or a trailing terminator:
+//!
+//! | Type                          | Encoding                                                  |
+//! |-------------------------------|-----------------------------------------------------------|
+//! | `bool`                        | one byte, `0x00` or `0x01`                                |
+//! | `u8`..`u64`, `i8`..`i64`      | fixed width, little endian, two's complement              |
+//! | `usize`, `isize`              | as `u64` and `i64`                                        |
+//! | `char`                        | its UTF-8 bytes                                           |
+//! | strings, byte strings         | `u64` length in bytes, then the bytes (UTF-8 for strings) |
+//! | sequences (`Vec<T>`, `&[T]`)  | `u64` number of elements, then the elements               |
+//! | arrays (`[u8; 32]`), tuples   | the elements, the length is implied by the type           |
+//! | `Option<T>`                   | `0x00` for `None`, `0x01` followed by the value for `Some` |
+//! | `()`, unit structs            | nothing                                                   |
+//! | structs, newtype structs      | their fields                                              |
+//! | enums                         | `u32` index of the variant in declaration order, then its fields |
+//!
+//! Values with a textual form are encoded as that text: hashes and signatures
+//! as lowercase hex strings, dates as `YYYY-MM-DD`. Verifying keys are their
+//! DER encoded `SubjectPublicKeyInfo` as a byte string. Maps and floating
+//! point numbers have no canonical encoding and are rejected.
+//!
+//! For example the result `{ election_id: 1, station_id: 2, candidate_id: 3,
+//! votes: 400 }` encodes to the four `i64`s
+//! `0100000000000000 0200000000000000 0300000000000000 9001000000000000`.
+//! The tests below carry vectors for headers and transactions.
+
+use serde::{Serialize, ser};
+use sha3::{Digest, Sha3_256};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CanonicalError {
+    #[error("{0} have no canonical encoding")]
+    Unsupported(&'static str),
+    #[error("sequences must know their length up front")]
+    UnknownLength,
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl ser::Error for CanonicalError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        CanonicalError::Custom(msg.to_string())
+    }
+}
+
+/// Encodes `value` as described in the [module docs](self)
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, CanonicalError> {
+    let mut encoder = Encoder { out: Vec::new() };
+    value.serialize(&mut encoder)?;
+    Ok(encoder.out)
+}
+
+/// SHA3-256 of the canonical encoding of `value`
+pub fn digest<T: Serialize + ?Sized>(value: &T) -> [u8; 32] {
+    let bytes = to_bytes(value).expect("hashed values have a canonical encoding");
+    Sha3_256::digest(bytes).into()
+}
+
+struct Encoder {
+    out: Vec<u8>,
+}
+
+impl Encoder {
+    fn length(&mut self, len: usize) {
+        self.out.extend_from_slice(&(len as u64).to_le_bytes());
+    }
+}
+
+impl ser::Serializer for &mut Encoder {
+    type Ok = ();
+    type Error = CanonicalError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = ser::Impossible<(), CanonicalError>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<(), CanonicalError> {
+        self.out.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), CanonicalError> {
+        self.out.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), CanonicalError> {
+        self.out.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), CanonicalError> {
+        self.out.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), CanonicalError> {
+        self.out.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), CanonicalError> {
+        self.out.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), CanonicalError> {
+        self.out.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), CanonicalError> {
+        self.out.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), CanonicalError> {
+        self.out.extend_from_slice(&v.to_le_bytes());
+        Ok(())
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<(), CanonicalError> {
+        Err(CanonicalError::Unsupported("floating point numbers"))
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<(), CanonicalError> {
+        Err(CanonicalError::Unsupported("floating point numbers"))
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), CanonicalError> {
+        self.out
+            .extend_from_slice(v.encode_utf8(&mut [0; 4]).as_bytes());
+        Ok(())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), CanonicalError> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), CanonicalError> {
+        self.length(v.len());
+        self.out.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), CanonicalError> {
+        self.out.push(0);
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), CanonicalError> {
+        self.out.push(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), CanonicalError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), CanonicalError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+    ) -> Result<(), CanonicalError> {
+        self.serialize_u32(variant_index)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), CanonicalError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), CanonicalError> {
+        self.serialize_u32(variant_index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self, CanonicalError> {
+        self.length(len.ok_or(CanonicalError::UnknownLength)?);
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self, CanonicalError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self, CanonicalError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self, CanonicalError> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, CanonicalError> {
+        Err(CanonicalError::Unsupported("maps"))
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self, CanonicalError> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self, CanonicalError> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl ser::SerializeSeq for &mut Encoder {
+    type Ok = ();
+    type Error = CanonicalError;
+
+    fn serialize_element<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), CanonicalError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), CanonicalError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut Encoder {
+    type Ok = ();
+    type Error = CanonicalError;
+
+    fn serialize_element<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), CanonicalError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), CanonicalError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut Encoder {
+    type Ok = ();
+    type Error = CanonicalError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CanonicalError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), CanonicalError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for &mut Encoder {
+    type Ok = ();
+    type Error = CanonicalError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), CanonicalError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), CanonicalError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut Encoder {
+    type Ok = ();
+    type Error = CanonicalError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), CanonicalError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), CanonicalError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut Encoder {
+    type Ok = ();
+    type Error = CanonicalError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), CanonicalError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), CanonicalError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{BlockType, CandidateResult, Correction, ElectionBlockHeader, keys::KeyOperation};
+
+    fn vector(hex_bytes: &str) -> Vec<u8> {
+        hex::decode(hex_bytes.replace([' ', '\n'], "")).unwrap()
+    }
+
+    #[test]
+    fn test_canonical_vectors() {
+        let result = CandidateResult::new(1, 2, 3, 400);
+        assert_eq!(
+            to_bytes(&result).unwrap(),
+            vector("0100000000000000 0200000000000000 0300000000000000 9001000000000000")
+        );
+        assert_eq!(
+            hex::encode(digest(&result)),
+            "514b8c4e0c25307814517acf29112248401035da4cad30dbdde7739827082ef1"
+        );
+
+        let header = ElectionBlockHeader {
+            previous_hash: [0xaa; 32],
+            merkle_root: [0xbb; 32],
+            timestamp: 1_700_000_000,
+            block_number: 7,
+            validator_signature: "ab01".into(),
+        };
+        assert_eq!(
+            to_bytes(&header).unwrap(),
+            vector(&format!(
+                "{} {} 00f1536500000000 0700000000000000 0400000000000000 61623031",
+                "aa".repeat(32),
+                "bb".repeat(32),
+            ))
+        );
+        assert_eq!(
+            crate::crypto::hash_block(&header),
+            "f872859332fd785048e0422a391688e62cf0a7751ee58c27157134f7736a08fc"
+        );
+
+        // Enum index, then the struct variant's fields
+        let operation = KeyOperation::Revoke {
+            pubkey_hash: "c0".into(),
+        };
+        assert_eq!(
+            to_bytes(&operation).unwrap(),
+            vector("01000000 0200000000000000 6330")
+        );
+
+        let correction = Correction {
+            election_id: 1,
+            station_id: 2,
+            original_height: 5,
+            results: vec![result],
+            summaries: vec![],
+            reason: "typo".into(),
+            attachments: vec![],
+        };
+        assert_eq!(
+            hex::encode(digest(&correction)),
+            "411e6280c6ba955180bf9db6505d293c8e479585adc9c38f327a2c6ccc4e2341"
+        );
+
+        // An odd level pairs its last node with itself
+        let results = vec![
+            CandidateResult::new(1, 2, 3, 400),
+            CandidateResult::new(1, 2, 4, 10),
+            CandidateResult::new(1, 2, 5, 0),
+        ];
+        assert_eq!(
+            hex::encode(BlockType::Result(results).merkle_root()),
+            "9af1d108f73f21943070894db8e611ac6729717e84201ef3ca5f35c9822db669"
+        );
+    }
+
+    #[test]
+    fn test_canonical_rejects_ambiguous_values() {
+        assert_eq!(
+            to_bytes(&HashMap::from([(1, 2)])),
+            Err(CanonicalError::Unsupported("maps"))
+        );
+        assert_eq!(
+            to_bytes(&1.5f64),
+            Err(CanonicalError::Unsupported("floating point numbers"))
+        );
+    }
+
+    #[test]
+    fn test_canonical_matches_existing_chains() {
+        let header = ElectionBlockHeader {
+            previous_hash: [1; 32],
+            merkle_root: [2; 32],
+            timestamp: -5,
+            block_number: 1 << 40,
+            validator_signature: "f".repeat(64),
+        };
+        assert_eq!(
+            to_bytes(&header).unwrap(),
+            bincode::serialize(&header).unwrap()
+        );
+        let operation = KeyOperation::Revoke {
+            pubkey_hash: "c0".into(),
+        };
+        assert_eq!(
+            to_bytes(&operation).unwrap(),
+            bincode::serialize(&operation).unwrap()
+        );
+    }
+}
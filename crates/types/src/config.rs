@@ -8,14 +8,33 @@ pub struct Config {
     pub mode: Option<Mode>,
     pub main_db: String,
     pub private_db: String,
+    /// Directory of the attachment store, `attachments` next to `main_db` if unset
+    pub attachments: Option<String>,
     pub peer_config: Option<P2PConfig>,
     pub peers: Option<HashMap<String, String>>,
     pub http_addr: Option<String>,
+    /// Address of the admin API, which submits corrections, elections and
+    /// reference data signed with the node key. Keep it on a private interface,
+    /// the admin API is not served if unset.
+    pub admin_addr: Option<String>,
     pub node_addr: Option<String>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "mode", rename_all = "lowercase")]
 pub enum Mode {
-    Observer { peer_addr: String },
+    /// Follows the chain from `peer_addr` without ever signing. The genesis
+    /// block, whose reference data is loaded into the database, must hash to
+    /// `genesis_hash`.
+    Observer {
+        peer_addr: String,
+        genesis_hash: String,
+    },
+    /// Compares the results on chain with the official tallies found in
+    /// `tallies`, a JSON or CSV file or a directory of them, every `interval`
+    /// seconds and publishes attestation blocks
+    Verification {
+        tallies: String,
+        interval: Option<u64>,
+    },
 }
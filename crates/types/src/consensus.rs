@@ -0,0 +1,213 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{BlockSigner, crypto::sign_hash};
+use crate::{
+    PubKey,
+    crypto::{public_key_from_bytes, sha256_digest, verify_hash},
+};
+
+/// The voting steps of a consensus round. The propose step is the
+/// `P2PMessage::Proposal` itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum VotePhase {
+    Prevote,
+    Precommit,
+}
+
+/// A validator's signed vote for a block at a given height and round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Vote {
+    pub height: usize,
+    /// Rounds start at 0 and move on when a round times out without a commit
+    pub round: u32,
+    /// `None` is a nil vote, cast when the round timed out
+    pub block_hash: Option<String>,
+    pub phase: VotePhase,
+    /// Hash of the validator's public key
+    pub validator: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidatorSignature {
+    pub validator: String,
+    pub signature: String,
+}
+
+/// Precommit signatures from more than 2/3 of the validator set.
+/// A block is only final once it carries a valid certificate.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CommitCertificate {
+    pub height: usize,
+    /// Round the precommits were cast in
+    pub round: u32,
+    pub block_hash: String,
+    pub signatures: Vec<ValidatorSignature>,
+}
+
+/// Number of votes needed for a quorum, i.e. strictly more than 2/3 of the validators.
+pub fn quorum(validator_count: usize) -> usize {
+    validator_count * 2 / 3 + 1
+}
+
+impl Vote {
+    pub fn digest(height: usize, round: u32, block_hash: Option<&str>, phase: VotePhase) -> String {
+        sha256_digest(&(height as u64, round, block_hash, phase))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new(
+        signer: &BlockSigner,
+        height: usize,
+        round: u32,
+        block_hash: Option<&str>,
+        phase: VotePhase,
+    ) -> Self {
+        let signature = sign_hash(&signer.0, &Self::digest(height, round, block_hash, phase));
+        Self {
+            height,
+            round,
+            block_hash: block_hash.map(str::to_string),
+            phase,
+            validator: sha256_digest(&signer.1),
+            signature,
+        }
+    }
+
+    pub fn verify(&self, pub_key: &PubKey) -> bool {
+        let Some(key) = public_key_from_bytes(&pub_key.bytes) else {
+            return false;
+        };
+        pub_key.hash == self.validator
+            && verify_hash(
+                &key,
+                &Self::digest(
+                    self.height,
+                    self.round,
+                    self.block_hash.as_deref(),
+                    self.phase,
+                ),
+                &self.signature,
+            )
+    }
+}
+
+impl CommitCertificate {
+    /// Number of distinct validators that signed the certificate
+    pub fn signer_count(&self) -> usize {
+        self.signatures
+            .iter()
+            .map(|s| &s.validator)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Checks that more than 2/3 of `validators` produced a valid precommit for this block.
+    pub fn verify(&self, validators: &[PubKey]) -> bool {
+        self.valid_signers(validators).len() >= quorum(validators.len())
+    }
+
+    /// Distinct members of `validators` with a valid precommit in the
+    /// certificate, unlike [`Self::signer_count`] which trusts every entry
+    pub fn valid_signers(&self, validators: &[PubKey]) -> HashSet<&str> {
+        self.signatures
+            .iter()
+            .filter(|sig| {
+                validators.iter().any(|key| {
+                    Vote {
+                        height: self.height,
+                        round: self.round,
+                        block_hash: Some(self.block_hash.clone()),
+                        phase: VotePhase::Precommit,
+                        validator: sig.validator.clone(),
+                        signature: sig.signature.clone(),
+                    }
+                    .verify(key)
+                })
+            })
+            .map(|sig| sig.validator.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{get_private_key, get_public_key};
+
+    fn validator() -> (BlockSigner, PubKey) {
+        let key = get_private_key();
+        let verifying_key = get_public_key(&key);
+        let mut pub_key = PubKey::new_dummy();
+        pub_key.hash = sha256_digest(&verifying_key);
+        pub_key.bytes = bincode::serialize(&verifying_key).unwrap();
+        ((key, verifying_key, pub_key.clone()), pub_key)
+    }
+
+    #[test]
+    fn test_quorum_is_more_than_two_thirds() {
+        assert_eq!(quorum(1), 1);
+        assert_eq!(quorum(3), 3);
+        assert_eq!(quorum(4), 3);
+        assert_eq!(quorum(7), 5);
+    }
+
+    #[test]
+    fn test_vote_signature() {
+        let (signer, pub_key) = validator();
+        let vote = Vote::new(&signer, 1, 0, Some("abcd"), VotePhase::Prevote);
+        assert!(vote.verify(&pub_key));
+
+        let mut forged = vote.clone();
+        forged.phase = VotePhase::Precommit;
+        assert!(!forged.verify(&pub_key));
+
+        // A nil vote cannot be passed off as a vote for a block, or for another round
+        let nil = Vote::new(&signer, 1, 0, None, VotePhase::Prevote);
+        assert!(nil.verify(&pub_key));
+        for forged in [
+            Vote {
+                block_hash: Some("abcd".into()),
+                ..nil.clone()
+            },
+            Vote { round: 1, ..nil },
+        ] {
+            assert!(!forged.verify(&pub_key));
+        }
+    }
+
+    #[test]
+    fn test_certificate_requires_quorum() {
+        let validators: Vec<_> = (0..4).map(|_| validator()).collect();
+        let keys: Vec<PubKey> = validators.iter().map(|v| v.1.clone()).collect();
+
+        let mut certificate = CommitCertificate {
+            height: 3,
+            round: 1,
+            block_hash: "abcd".to_string(),
+            signatures: vec![],
+        };
+        for (signer, _) in validators.iter().take(2) {
+            let vote = Vote::new(signer, 3, 1, Some("abcd"), VotePhase::Precommit);
+            certificate.signatures.push(ValidatorSignature {
+                validator: vote.validator,
+                signature: vote.signature,
+            });
+        }
+        assert!(!certificate.verify(&keys));
+
+        let vote = Vote::new(&validators[2].0, 3, 1, Some("abcd"), VotePhase::Precommit);
+        certificate.signatures.push(ValidatorSignature {
+            validator: vote.validator,
+            signature: vote.signature,
+        });
+        assert!(certificate.verify(&keys));
+
+        // Precommits from another round do not certify this one
+        certificate.round = 0;
+        assert!(!certificate.verify(&keys));
+    }
+}
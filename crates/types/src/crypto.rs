@@ -1,14 +1,17 @@
-use bincode::serialize;
-use p256::{
-    ecdsa::{Signature, SigningKey, VerifyingKey, signature::Signer},
-    elliptic_curve::rand_core::OsRng,
+use bincode::{deserialize, serialize};
+use p256::ecdsa::{
+    Signature, SigningKey, VerifyingKey,
+    signature::{Signer, Verifier},
 };
+#[cfg(not(target_arch = "wasm32"))]
+use p256::elliptic_curve::rand_core::OsRng;
 use serde::Serialize;
 
 use sha3::{Digest, Sha3_256 as Sha256};
 
-use crate::ElectionBlockHeader;
+use crate::{ElectionBlockHeader, canonical};
 
+#[cfg(not(target_arch = "wasm32"))]
 pub fn get_private_key() -> SigningKey {
     SigningKey::random(&mut OsRng)
 }
@@ -19,19 +22,49 @@ pub fn sign_hash(key: &SigningKey, hash: &str) -> String {
     hex::encode(serialize(&signature).unwrap())
 }
 
+/// Verifies a signature produced by [`sign_hash`]
+pub fn verify_hash(key: &VerifyingKey, hash: &str, signature: &str) -> bool {
+    let (Ok(hash), Ok(signature)) = (hex::decode(hash), hex::decode(signature)) else {
+        return false;
+    };
+    let Ok(signature) = deserialize::<Signature>(&signature) else {
+        return false;
+    };
+    key.verify(&hash, &signature).is_ok()
+}
+
 pub fn hash_block(block: &ElectionBlockHeader) -> String {
     sha256_digest(block)
 }
 
+/// Hex encoded SHA3-256 of the [canonical](crate::canonical) encoding of `data`
 pub fn sha256_digest<T: Serialize>(data: &T) -> String {
-    let block_bytes = serialize(data).unwrap();
+    hex::encode(canonical::digest(data))
+}
+
+/// SHA3-256 of an attachment's raw bytes, the name it is stored and served under
+pub fn attachment_digest(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
 
-    let mut hasher = Sha256::new();
-    hasher.update(block_bytes);
-    let hashed_block = format!("{:x}", hasher.finalize());
-    hashed_block
+/// Whether `hash` looks like an [`attachment_digest`], which makes it safe to
+/// use as a file name
+pub fn is_attachment_digest(hash: &str) -> bool {
+    hash.len() == 64
+        && hash
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
 }
 
 pub fn get_public_key(key: &SigningKey) -> VerifyingKey {
     *key.verifying_key()
 }
+
+pub fn public_key_from_bytes(bytes: &[u8]) -> Option<VerifyingKey> {
+    deserialize(bytes).ok()
+}
+
+/// Parses a hex encoded secret scalar, as agents keep their keys
+pub fn signing_key_from_hex(secret: &str) -> Option<SigningKey> {
+    SigningKey::from_slice(&hex::decode(secret.trim()).ok()?).ok()
+}
@@ -0,0 +1,94 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ElectionType {
+    General,
+    ByElection,
+}
+
+impl ElectionType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ElectionType::General => "general",
+            ElectionType::ByElection => "by_election",
+        }
+    }
+}
+
+/// An area taking part in an election, every station within it included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Area {
+    National,
+    County(i64),
+    Constituency(i64),
+    Ward(i64),
+    Station(i64),
+}
+
+impl Area {
+    /// The `area_type` and `area_code` columns of `election_areas`
+    pub fn parts(&self) -> (&'static str, Option<i64>) {
+        match *self {
+            Area::National => ("national", None),
+            Area::County(code) => ("county", Some(code)),
+            Area::Constituency(code) => ("constituency", Some(code)),
+            Area::Ward(code) => ("ward", Some(code)),
+            Area::Station(code) => ("station", Some(code)),
+        }
+    }
+
+    pub fn from_parts(area_type: &str, area_code: Option<i64>) -> Option<Self> {
+        Some(match (area_type, area_code) {
+            ("national", _) => Area::National,
+            ("county", Some(code)) => Area::County(code),
+            ("constituency", Some(code)) => Area::Constituency(code),
+            ("ward", Some(code)) => Area::Ward(code),
+            ("station", Some(code)) => Area::Station(code),
+            _ => return None,
+        })
+    }
+
+    /// Parses an area given as `national` or `<type>:<code>`, e.g. `ward:563`
+    pub fn parse(area: &str) -> Option<Self> {
+        match area.split_once(':') {
+            Some((area_type, code)) => Self::from_parts(area_type, Some(code.parse().ok()?)),
+            None => Self::from_parts(area, None),
+        }
+    }
+}
+
+/// An election results are submitted for, registered on chain by an
+/// election block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Election {
+    pub id: i64,
+    pub name: String,
+    pub date: NaiveDate,
+    pub election_type: ElectionType,
+    pub areas: Vec<Area>,
+    /// Ids of the candidates on the ballot
+    pub candidates: Vec<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_area_round_trips_through_columns() {
+        for area in [
+            Area::National,
+            Area::County(22),
+            Area::Station(22113056303301),
+        ] {
+            let (area_type, area_code) = area.parts();
+            assert_eq!(Area::from_parts(area_type, area_code), Some(area));
+        }
+        assert_eq!(Area::from_parts("ward", None), None);
+        assert_eq!(Area::parse("ward:563"), Some(Area::Ward(563)));
+        assert_eq!(Area::parse("ward:Kalimoni"), None);
+    }
+}
@@ -1,5 +1,7 @@
 use tokio::time::error::Elapsed;
 
+use crate::validation::BlockRejection;
+
 #[derive(Debug, thiserror::Error)]
 pub enum ChainError {
     #[error("Database error: {0}")]
@@ -14,8 +16,18 @@ pub enum ChainError {
     AddrParseError(#[from] std::net::AddrParseError),
     #[error("Timeout error: {0}")]
     TimeoutError(#[from] Elapsed),
+    #[error("Block rejected: {0}")]
+    BlockRejected(#[from] BlockRejection),
+    #[error("Consensus error: {0}")]
+    ConsensusError(String),
     #[error("Peer error: {0}")]
     PeerError(String),
+    #[error("Message of {0} bytes is too large")]
+    MessageTooLarge(usize),
+    #[error("Peer banned: {0}")]
+    PeerBanned(String),
+    #[error("Read-only node cannot sign or originate blocks")]
+    ReadOnly,
     #[error("Other error: {0}")]
     Other(String),
 }
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+
+use crate::election::Area;
+
+/// Changes to the canonical chain that other components can react to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainEvent {
+    /// The canonical tip moved to a competing branch
+    Reorg {
+        /// Height of the last block both branches share
+        fork_height: usize,
+        old_tip: String,
+        new_tip: String,
+        /// Hashes of the blocks that left the canonical chain, lowest first
+        detached: Vec<String>,
+        /// Hashes of the blocks that replaced them, lowest first
+        attached: Vec<String>,
+    },
+    /// A committed block extended the chain
+    BlockAdded {
+        height: usize,
+        hash: String,
+        /// How the block moved each area's current votes, empty unless
+        /// someone was subscribed when it was added
+        deltas: Vec<TallyDelta>,
+    },
+    /// A peer completed the handshake. Peers are not named, the stream is
+    /// public and their addresses are not.
+    PeerConnected {
+        /// Peers connected now
+        peers: usize,
+    },
+    PeerDisconnected {
+        peers: usize,
+    },
+    /// A sync towards `target_height` started
+    SyncStarted {
+        height: i64,
+        target_height: i64,
+    },
+    SyncFinished {
+        height: i64,
+    },
+}
+
+/// Change of a candidate's current votes within an area
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TallyDelta {
+    pub election_id: i64,
+    pub area: Area,
+    pub position_type: String,
+    pub candidate_id: i64,
+    pub votes: i64,
+}
+
+impl TallyDelta {
+    /// Adds `votes` for the candidate in `area` to `deltas`
+    pub fn merge(
+        deltas: &mut Vec<TallyDelta>,
+        election_id: i64,
+        area: Area,
+        position_type: &str,
+        candidate_id: i64,
+        votes: i64,
+    ) {
+        match deltas.iter_mut().find(|d| {
+            d.election_id == election_id && d.area == area && d.candidate_id == candidate_id
+        }) {
+            Some(delta) => delta.votes += votes,
+            None => deltas.push(TallyDelta {
+                election_id,
+                area,
+                position_type: position_type.to_string(),
+                candidate_id,
+                votes,
+            }),
+        }
+    }
+}
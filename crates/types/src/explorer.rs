@@ -0,0 +1,67 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{Block, results::ConfirmationCounts};
+
+/// A slice of a longer listing, newest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Items in the whole listing
+    pub total: i64,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+impl<T> Page<T> {
+    /// Whether items remain after this page
+    pub fn has_more(&self) -> bool {
+        self.offset + (self.items.len() as i64) < self.total
+    }
+}
+
+/// What the explorer lists about a block, without its transactions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockSummary {
+    pub height: usize,
+    pub hash: String,
+    pub prev_hash: String,
+    /// Hash of the key that signed the block
+    pub signer: String,
+    pub timestamp: DateTime<Utc>,
+    /// Transaction type, see [`crate::BlockType::kind`]
+    pub kind: String,
+    pub transactions: usize,
+    /// Whether a quorum of validators committed the block
+    pub committed: bool,
+}
+
+impl From<&Block> for BlockSummary {
+    fn from(block: &Block) -> Self {
+        BlockSummary {
+            height: block.height,
+            hash: block.hash.clone(),
+            prev_hash: block.prev_hash.clone(),
+            signer: block.signature_pub_key_hash.clone(),
+            timestamp: block.timestamp,
+            kind: block.inner.kind().to_string(),
+            transactions: block.inner.transaction_count(),
+            committed: block.certificate.is_some(),
+        }
+    }
+}
+
+/// Overview of the chain a node holds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainSummary {
+    pub height: i64,
+    pub tip: BlockSummary,
+    pub genesis_hash: String,
+    /// Keys that signed at least one block
+    pub signers: i64,
+    pub elections: i64,
+    /// Current results, one per candidate and submission
+    pub results: i64,
+    /// Stations with current results, by confirmation state
+    pub stations: ConfirmationCounts,
+}
@@ -0,0 +1,129 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{BlockSigner, crypto::sign_hash};
+use crate::{
+    PubKey,
+    crypto::{public_key_from_bytes, sha256_digest, verify_hash},
+};
+
+/// Changes to the set of keys allowed to sign blocks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyOperation {
+    /// Add a new signer. `pub_key` is the bincode encoded verifying key.
+    Register {
+        creator: String,
+        pub_key: Vec<u8>,
+    },
+    Revoke {
+        pubkey_hash: String,
+    },
+    /// Revoke `pubkey_hash` and register `new_pub_key` for the same creator
+    Rotate {
+        pubkey_hash: String,
+        new_pub_key: Vec<u8>,
+    },
+}
+
+/// A key operation authorised by an existing validator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyTransaction {
+    pub operation: KeyOperation,
+    /// Hash of the validator key that signed the operation
+    pub signer: String,
+    pub signature: String,
+}
+
+/// Hash identifying a bincode encoded verifying key in the `pubkeys` table
+pub fn key_hash(pub_key: &[u8]) -> Option<String> {
+    public_key_from_bytes(pub_key).map(|key| sha256_digest(&key))
+}
+
+/// Applies `operation`, carried by the block at `height`, to `validators`,
+/// the set that certified that block
+pub fn apply_key_operation(
+    validators: &mut Vec<PubKey>,
+    operation: &KeyOperation,
+    height: usize,
+    timestamp: DateTime<Utc>,
+) {
+    let register = |validators: &mut Vec<PubKey>, creator: &str, pub_key: &[u8]| {
+        if let Some(hash) = key_hash(pub_key) {
+            validators.push(PubKey {
+                hash,
+                creator: creator.to_string(),
+                bytes: pub_key.to_vec(),
+                state: "A".to_string(),
+                time_added: timestamp,
+                is_revoked: false,
+                time_revoked: None,
+                add_block_height: height,
+                revoked_block_height: None,
+            });
+        }
+    };
+    match operation {
+        KeyOperation::Register { creator, pub_key } => register(validators, creator, pub_key),
+        KeyOperation::Revoke { pubkey_hash } => validators.retain(|key| key.hash != *pubkey_hash),
+        KeyOperation::Rotate {
+            pubkey_hash,
+            new_pub_key,
+        } => {
+            if let Some(index) = validators.iter().position(|key| key.hash == *pubkey_hash) {
+                let revoked = validators.remove(index);
+                register(validators, &revoked.creator, new_pub_key);
+            }
+        }
+    }
+}
+
+impl KeyTransaction {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new(signer: &BlockSigner, operation: KeyOperation) -> Self {
+        let signature = sign_hash(&signer.0, &sha256_digest(&operation));
+        Self {
+            operation,
+            signer: sha256_digest(&signer.1),
+            signature,
+        }
+    }
+
+    pub fn verify(&self, pub_key: &PubKey) -> bool {
+        let Some(key) = public_key_from_bytes(&pub_key.bytes) else {
+            return false;
+        };
+        pub_key.hash == self.signer
+            && verify_hash(&key, &sha256_digest(&self.operation), &self.signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{get_private_key, get_public_key};
+
+    #[test]
+    fn test_key_transaction_signature() {
+        let key = get_private_key();
+        let verifying_key = get_public_key(&key);
+        let pub_key = PubKey::from_verifying_key("validator-1", &verifying_key);
+        let signer = (key, verifying_key, pub_key.clone());
+
+        let new_key = get_public_key(&get_private_key());
+        let tx = KeyTransaction::new(
+            &signer,
+            KeyOperation::Register {
+                creator: "validator-2".to_string(),
+                pub_key: bincode::serialize(&new_key).unwrap(),
+            },
+        );
+        assert!(tx.verify(&pub_key));
+
+        let mut tampered = tx.clone();
+        tampered.operation = KeyOperation::Revoke {
+            pubkey_hash: pub_key.hash.clone(),
+        };
+        assert!(!tampered.verify(&pub_key));
+    }
+}
@@ -1,10 +1,20 @@
+pub mod agents;
+pub mod attestation;
+pub mod canonical;
 pub mod config;
-#[cfg(not(target_arch = "wasm32"))]
+pub mod consensus;
 pub mod crypto;
+pub mod election;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod error;
+pub mod events;
+pub mod explorer;
+pub mod keys;
 pub mod models;
 pub mod p2p;
+pub mod proof;
+pub mod reference;
+pub mod validation;
 
 #[cfg(not(target_arch = "wasm32"))]
 use bincode::deserialize;
@@ -12,16 +22,24 @@ use chrono::{DateTime, Utc};
 #[cfg(not(target_arch = "wasm32"))]
 use p256::ecdsa::{SigningKey, VerifyingKey};
 use serde::{Deserialize, Serialize};
-use sha3::{Digest, Sha3_256 as Sha256};
 
+use crate::{
+    agents::{Agent, Submission},
+    attestation::Attestation,
+    consensus::CommitCertificate,
+    election::Election,
+    keys::KeyTransaction,
+    reference::ReferenceData,
+};
+
+use crate::{crypto::hash_block, merkle::MerkleTree};
 #[cfg(not(target_arch = "wasm32"))]
 use crate::{
     crypto::{sha256_digest, sign_hash},
-    merkle::MerkleTree,
+    keys::KeyOperation,
 };
 pub const VERSION: usize = 1;
 
-#[cfg(not(target_arch = "wasm32"))]
 pub mod merkle;
 pub mod results;
 
@@ -29,16 +47,18 @@ pub mod results;
 pub type BlockSigner = (SigningKey, VerifyingKey, PubKey);
 
 #[cfg_attr(not(target_arch = "wasm32"), derive(sqlx::FromRow))]
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct CandidateResult {
+    pub election_id: i64,
     pub station_id: i64,
     pub candidate_id: i64,
     pub votes: i64,
 }
 
 impl CandidateResult {
-    pub fn new(station_id: usize, candidate_id: usize, votes: usize) -> Self {
+    pub fn new(election_id: usize, station_id: usize, candidate_id: usize, votes: usize) -> Self {
         Self {
+            election_id: election_id as i64,
             station_id: station_id as i64,
             candidate_id: candidate_id as i64,
             votes: votes as i64,
@@ -46,6 +66,50 @@ impl CandidateResult {
     }
 }
 
+/// The totals of a station's tally form (Form 34A) for one position
+#[cfg_attr(not(target_arch = "wasm32"), derive(sqlx::FromRow))]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct StationSummary {
+    pub election_id: i64,
+    pub station_id: i64,
+    pub position_type: String,
+    pub rejected: i64,
+    pub disputed: i64,
+    pub objected: i64,
+    /// Sum of the candidates' votes
+    pub total_valid: i64,
+    /// Valid votes and rejected ballots
+    pub total_cast: i64,
+}
+
+impl StationSummary {
+    /// Whether the totals add up to `votes`, the candidates' votes for the position
+    pub fn is_consistent(&self, votes: i64) -> bool {
+        self.total_valid == votes
+            && self.total_valid + self.rejected == self.total_cast
+            && [self.rejected, self.disputed, self.objected]
+                .iter()
+                .all(|count| *count >= 0)
+    }
+}
+
+/// Replaces the current results of a station. The superseded results stay on
+/// chain, marked with the height of the block carrying the correction.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Correction {
+    pub election_id: i64,
+    pub station_id: i64,
+    /// Height of the block holding the results being corrected
+    pub original_height: usize,
+    pub results: Vec<CandidateResult>,
+    /// Replaces the station's summaries as well, may be empty
+    pub summaries: Vec<StationSummary>,
+    pub reason: String,
+    /// Digests of the evidence backing the correction
+    #[serde(default)]
+    pub attachments: Vec<String>,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Block {
     pub hash: String,
@@ -60,13 +124,208 @@ pub struct Block {
     pub creator: String,
     pub creator_pub_key: String,
     pub version: usize,
+    /// Quorum of validator precommits, `None` until the block is committed
+    pub certificate: Option<CommitCertificate>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum BlockType {
     Pending,
-    Genesis,
+    /// Carries the registration of the initial signers and the gazette
+    /// (positions, parties, areas, stations, candidates)
+    Genesis {
+        keys: Vec<KeyTransaction>,
+        reference_data: Vec<ReferenceData>,
+    },
     Result(Vec<CandidateResult>),
+    /// Results submitted by a registered agent. Takes the place of the former
+    /// `Tally` variant, whose fields it shares, so stored tallies still decode.
+    Submission(Submission),
+    KeyManagement(Vec<KeyTransaction>),
+    /// Results checked against official tally forms by a verification node
+    Attestation(Vec<Attestation>),
+    /// Amendments to results already on chain
+    Correction(Vec<Correction>),
+    /// Registers an election results can be submitted for
+    Election(Election),
+    /// Additions and amendments to the gazette
+    ReferenceData(Vec<ReferenceData>),
+    /// Registers agents, or changes the stations they may submit for
+    Agents(Vec<Agent>),
+}
+
+impl BlockType {
+    /// Merkle root committing to every transaction in the block
+    pub fn merkle_root(&self) -> [u8; 32] {
+        self.merkle_tree().get_root_hash().unwrap_or_default()
+    }
+
+    /// The tree over the block's transactions, one leaf per transaction
+    pub fn merkle_tree(&self) -> MerkleTree {
+        match self {
+            BlockType::Pending => MerkleTree::new(),
+            BlockType::Result(results) => MerkleTree::from_election_results_proper(results),
+            BlockType::Genesis {
+                keys,
+                reference_data,
+            } => {
+                let leaves = keys
+                    .iter()
+                    .map(MerkleTree::hash_leaf)
+                    .chain(reference_data.iter().map(MerkleTree::hash_leaf))
+                    .collect();
+                MerkleTree::from_leaves(leaves)
+            }
+            BlockType::KeyManagement(txs) => {
+                MerkleTree::from_leaves(txs.iter().map(MerkleTree::hash_leaf).collect())
+            }
+            BlockType::Attestation(attestations) => {
+                MerkleTree::from_leaves(attestations.iter().map(MerkleTree::hash_leaf).collect())
+            }
+            BlockType::Correction(corrections) => {
+                MerkleTree::from_leaves(corrections.iter().map(MerkleTree::hash_leaf).collect())
+            }
+            BlockType::Election(election) => {
+                MerkleTree::from_leaves(vec![MerkleTree::hash_leaf(election)])
+            }
+            BlockType::ReferenceData(records) => {
+                MerkleTree::from_leaves(records.iter().map(MerkleTree::hash_leaf).collect())
+            }
+            // The results first, so their leaves are numbered like in a result block
+            BlockType::Submission(Submission {
+                results,
+                summaries,
+                attachments,
+                signed_by,
+            }) => MerkleTree::from_leaves(
+                results
+                    .iter()
+                    .map(MerkleTree::hash_leaf)
+                    .chain(summaries.iter().map(MerkleTree::hash_leaf))
+                    .chain(attachments.iter().map(MerkleTree::hash_leaf))
+                    .chain([MerkleTree::hash_leaf(signed_by)])
+                    .collect(),
+            ),
+            BlockType::Agents(agents) => {
+                MerkleTree::from_leaves(agents.iter().map(MerkleTree::hash_leaf).collect())
+            }
+        }
+    }
+
+    /// Name of the transaction type, as listed by the explorer
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BlockType::Pending => "pending",
+            BlockType::Genesis { .. } => "genesis",
+            BlockType::Result(_) => "result",
+            BlockType::KeyManagement(_) => "key_management",
+            BlockType::Attestation(_) => "attestation",
+            BlockType::Correction(_) => "correction",
+            BlockType::Election(_) => "election",
+            BlockType::ReferenceData(_) => "reference_data",
+            BlockType::Agents(_) => "agents",
+            BlockType::Submission(_) => "submission",
+        }
+    }
+
+    /// Number of transactions in the block, its leaves in the Merkle tree
+    pub fn transaction_count(&self) -> usize {
+        match self {
+            BlockType::Pending => 0,
+            BlockType::Genesis {
+                keys,
+                reference_data,
+            } => keys.len() + reference_data.len(),
+            BlockType::Result(results) => results.len(),
+            // The agent's signature is a leaf of its own
+            BlockType::Submission(Submission {
+                results,
+                summaries,
+                attachments,
+                ..
+            }) => results.len() + summaries.len() + attachments.len() + 1,
+            BlockType::KeyManagement(txs) => txs.len(),
+            BlockType::Attestation(attestations) => attestations.len(),
+            BlockType::Correction(corrections) => corrections.len(),
+            BlockType::Election(_) => 1,
+            BlockType::ReferenceData(records) => records.len(),
+            BlockType::Agents(agents) => agents.len(),
+        }
+    }
+
+    pub fn key_transactions(&self) -> &[KeyTransaction] {
+        match self {
+            BlockType::Genesis { keys: txs, .. } | BlockType::KeyManagement(txs) => txs,
+            _ => &[],
+        }
+    }
+
+    pub fn results(&self) -> &[CandidateResult] {
+        match self {
+            BlockType::Result(results) | BlockType::Submission(Submission { results, .. }) => {
+                results
+            }
+            _ => &[],
+        }
+    }
+
+    /// Election and station of every result the block adds or replaces,
+    /// without duplicates
+    pub fn stations(&self) -> Vec<(i64, i64)> {
+        let mut stations: Vec<_> = self
+            .results()
+            .iter()
+            .map(|r| (r.election_id, r.station_id))
+            .collect();
+        if let BlockType::Correction(corrections) = self {
+            stations.extend(corrections.iter().map(|c| (c.election_id, c.station_id)));
+        }
+        stations.sort_unstable();
+        stations.dedup();
+        stations
+    }
+
+    /// Hash of the agent who signed the block's results, `None` for results
+    /// signed by a validator
+    pub fn agent(&self) -> Option<&str> {
+        match self {
+            BlockType::Submission(submission) => Some(&submission.signed_by.agent),
+            _ => None,
+        }
+    }
+
+    pub fn summaries(&self) -> &[StationSummary] {
+        match self {
+            BlockType::Submission(submission) => &submission.summaries,
+            _ => &[],
+        }
+    }
+
+    /// Digests of the evidence attached to the block's submissions
+    pub fn attachments(&self) -> Vec<&String> {
+        match self {
+            BlockType::Submission(submission) => submission.attachments.iter().collect(),
+            BlockType::Correction(corrections) => {
+                corrections.iter().flat_map(|c| &c.attachments).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    pub fn agents(&self) -> &[Agent] {
+        match self {
+            BlockType::Agents(agents) => agents,
+            _ => &[],
+        }
+    }
+
+    pub fn reference_data(&self) -> &[ReferenceData] {
+        match self {
+            BlockType::Genesis { reference_data, .. }
+            | BlockType::ReferenceData(reference_data) => reference_data,
+            _ => &[],
+        }
+    }
 }
 
 impl Block {
@@ -81,15 +340,16 @@ impl Block {
         let prev_hash_signature = sign_hash(&signer.0, prev_hash);
         let sigkey_hash = sha256_digest(&signer.1);
         let creator = &signer.2.creator;
+        let timestamp = Utc::now();
 
-        let hash = crate::crypto::hash_block(&ElectionBlockHeader {
+        let hash = hash_block(&ElectionBlockHeader {
             previous_hash: hex::decode(prev_hash)
                 .unwrap()
                 .as_slice()
                 .try_into()
                 .unwrap(),
             merkle_root,
-            timestamp: Utc::now().timestamp() as i64,
+            timestamp: timestamp.timestamp(),
             block_number: height as i64,
             validator_signature: sigkey_hash.clone(),
         });
@@ -100,16 +360,30 @@ impl Block {
             inner: BlockType::Result(results),
             height,
             merkle_root,
-            timestamp: Utc::now(),
+            timestamp,
             prev_hash: prev_hash.to_string(),
             signature_pub_key_hash: sigkey_hash.to_string(),
             prev_hash_signature,
             version: VERSION,
             creator: creator.to_string(),
             creator_pub_key: sha256_digest(&signer.1),
+            certificate: None,
         }
     }
 
+    /// Creates a block for any transaction type, computing its Merkle root
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_inner(
+        signer: &BlockSigner,
+        prev_hash: &str,
+        inner: BlockType,
+        height: usize,
+    ) -> Self {
+        let mut block = Self::new(signer, prev_hash, vec![], height, inner.merkle_root());
+        block.inner = inner;
+        block
+    }
+
     pub fn set_results(&mut self, results: Vec<CandidateResult>) {
         if let BlockType::Result(ref mut rb) = self.inner {
             *rb = results;
@@ -118,34 +392,59 @@ impl Block {
         }
     }
 
-    pub fn get_results(&self) -> Vec<CandidateResult> {
-        if let BlockType::Result(ref rb) = self.inner {
-            rb.clone()
-        } else {
-            vec![]
+    /// The header this block's hash commits to, `None` if `prev_hash` is malformed
+    pub fn header(&self) -> Option<ElectionBlockHeader> {
+        self.signed_header().header()
+    }
+
+    pub fn signed_header(&self) -> SignedHeader {
+        SignedHeader {
+            hash: self.hash.clone(),
+            hash_signature: self.hash_signature.clone(),
+            merkle_root: self.merkle_root,
+            height: self.height,
+            signature_pub_key_hash: self.signature_pub_key_hash.clone(),
+            timestamp: self.timestamp,
+            prev_hash: self.prev_hash.clone(),
+            certificate: self.certificate.clone(),
         }
     }
 
-    #[cfg(not(target_arch = "wasm32"))]
+    pub fn get_results(&self) -> Vec<CandidateResult> {
+        self.inner.results().to_vec()
+    }
+
+    /// Names the creator of the block after `pub_key`, the key that signed it
     pub fn set_pub_key(&mut self, pub_key: PubKey) {
         self.creator = pub_key.creator;
-        let public_key: VerifyingKey = deserialize(&pub_key.bytes).unwrap();
-        self.creator_pub_key = sha256_digest(&public_key);
+        self.creator_pub_key = pub_key.hash;
     }
 
     #[cfg(not(target_arch = "wasm32"))]
-    pub fn genesis(signer: &BlockSigner, init_query_hash: String) -> Self {
+    pub fn genesis(signer: &BlockSigner, reference_data: Vec<ReferenceData>) -> Self {
         let prev_hash = "0000000000000000000000000000000000000000000000000000000000000000";
-        let tree = MerkleTree::from_election_results_proper(&[]);
-        let root = tree.get_root_hash();
-        let hash = crate::crypto::hash_block(&ElectionBlockHeader {
+        let init_query_hash = sha256_digest(&reference_data);
+        // The genesis signer registers itself so every node can replay the key set
+        let inner = BlockType::Genesis {
+            keys: vec![KeyTransaction::new(
+                signer,
+                KeyOperation::Register {
+                    creator: signer.2.creator.clone(),
+                    pub_key: bincode::serialize(&signer.1).unwrap(),
+                },
+            )],
+            reference_data,
+        };
+        let merkle_root = inner.merkle_root();
+        let timestamp = Utc::now();
+        let hash = hash_block(&ElectionBlockHeader {
             previous_hash: hex::decode(prev_hash)
                 .unwrap()
                 .as_slice()
                 .try_into()
                 .unwrap(),
-            merkle_root: root.unwrap_or_default(),
-            timestamp: Utc::now().timestamp() as i64,
+            merkle_root,
+            timestamp: timestamp.timestamp(),
             block_number: 0,
             validator_signature: sha256_digest(&signer.1),
         });
@@ -155,16 +454,17 @@ impl Block {
         Self {
             prev_hash: prev_hash.to_string(),
             hash_signature,
-            inner: BlockType::Genesis,
+            inner,
             height: 0,
             signature_pub_key_hash: sigkey_hash,
-            timestamp: Utc::now(),
+            timestamp,
             hash,
-            merkle_root: [0u8; 32],
+            merkle_root,
             prev_hash_signature,
             creator: "GENESIS".to_string(),
             creator_pub_key: "".to_string(), // No creator for genesis block?
             version: VERSION,
+            certificate: None,
         }
     }
 }
@@ -179,6 +479,7 @@ pub struct PubKey {
     pub is_revoked: bool,
     pub time_revoked: Option<DateTime<Utc>>,
     pub add_block_height: usize,
+    pub revoked_block_height: Option<usize>,
     // metadata:
 }
 
@@ -192,9 +493,30 @@ impl PubKey {
             is_revoked: false,
             time_revoked: None,
             add_block_height: 0,
+            revoked_block_height: None,
             creator: "test-node-1".to_string(),
         }
     }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_verifying_key(creator: &str, key: &VerifyingKey) -> Self {
+        Self {
+            hash: sha256_digest(key),
+            creator: creator.to_string(),
+            bytes: bincode::serialize(key).unwrap(),
+            state: "A".to_string(),
+            time_added: Utc::now(),
+            is_revoked: false,
+            time_revoked: None,
+            add_block_height: 0,
+            revoked_block_height: None,
+        }
+    }
+
+    /// Whether the key was allowed to sign a block at `height`
+    pub fn is_active_at(&self, height: usize) -> bool {
+        self.add_block_height <= height && self.revoked_block_height.is_none_or(|h| height < h)
+    }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -211,28 +533,22 @@ impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for Block {
         let timestamp = row.try_get("timestamp")?;
 
         let merkle_root: Vec<u8> = row.try_get("merkle_root")?;
-
-        if height == 0 {
-            return Ok(Block {
-                hash,
-                hash_signature,
-                inner: BlockType::Genesis,
-                height: height as usize,
-                signature_pub_key_hash: sigkey_hash,
-                timestamp,
-                prev_hash,
-                merkle_root: merkle_root.as_slice().try_into().unwrap(),
-                prev_hash_signature,
-                creator: Default::default(),
-                creator_pub_key: Default::default(),
-                version: version.try_into().unwrap(),
-            });
-        }
+        let certificate: Option<Vec<u8>> = row.try_get("certificate")?;
+        let certificate = certificate
+            .map(|bytes| deserialize(&bytes))
+            .transpose()
+            .map_err(|e| sqlx::Error::Decode(e))?;
+        // Results are stored in their own table, every other transaction type in `payload`
+        let payload: Option<Vec<u8>> = row.try_get("payload")?;
+        let inner = match payload {
+            Some(bytes) => deserialize(&bytes).map_err(|e| sqlx::Error::Decode(e))?,
+            None => BlockType::Result(vec![]),
+        };
 
         Ok(Block {
             hash,
             hash_signature,
-            inner: BlockType::Result(vec![]),
+            inner,
             height: height as usize,
             signature_pub_key_hash: sigkey_hash,
             timestamp,
@@ -242,10 +558,43 @@ impl<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> for Block {
             creator: Default::default(),
             creator_pub_key: Default::default(),
             version: version.try_into().unwrap(),
+            certificate,
         })
     }
 }
 
+/// A block without its transactions, exchanged during headers-first sync
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedHeader {
+    pub hash: String,
+    pub hash_signature: String,
+    pub merkle_root: [u8; 32],
+    pub height: usize,
+    pub signature_pub_key_hash: String,
+    pub timestamp: DateTime<Utc>,
+    pub prev_hash: String,
+    pub certificate: Option<CommitCertificate>,
+}
+
+impl SignedHeader {
+    /// The header `hash` commits to, `None` if `prev_hash` is malformed
+    pub fn header(&self) -> Option<ElectionBlockHeader> {
+        let previous_hash = hex::decode(&self.prev_hash).ok()?.try_into().ok()?;
+        Some(ElectionBlockHeader {
+            previous_hash,
+            merkle_root: self.merkle_root,
+            timestamp: self.timestamp.timestamp(),
+            block_number: self.height as i64,
+            validator_signature: self.signature_pub_key_hash.clone(),
+        })
+    }
+
+    /// Whether the header hashes to `hash`
+    pub fn hash_matches(&self) -> bool {
+        self.header().map(|header| hash_block(&header)).as_ref() == Some(&self.hash)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ElectionBlockHeader {
     pub previous_hash: [u8; 32],
@@ -257,9 +606,6 @@ pub struct ElectionBlockHeader {
 
 impl ElectionBlockHeader {
     pub fn hash(&self) -> [u8; 32] {
-        let serialized = bincode::serialize(self).unwrap();
-        let mut hasher = Sha256::new();
-        hasher.update(&serialized);
-        hasher.finalize().into()
+        canonical::digest(self)
     }
 }
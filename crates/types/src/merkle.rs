@@ -1,5 +1,6 @@
+use crate::{CandidateResult, canonical};
+use serde::Serialize;
 use sha3::{Digest, Sha3_256 as Sha256};
-use crate::CandidateResult;
 
 #[derive(Debug, Clone)]
 pub struct MerkleNode {
@@ -13,6 +14,12 @@ pub struct MerkleTree {
     pub leaves: Vec<[u8; 32]>,
 }
 
+impl Default for MerkleTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MerkleTree {
     pub fn new() -> Self {
         Self {
@@ -41,10 +48,7 @@ impl MerkleTree {
 
     // Hash a single election result
     fn hash_election_result(result: &CandidateResult) -> [u8; 32] {
-        let serialized = bincode::serialize(result).unwrap();
-        let mut hasher = Sha256::new();
-        hasher.update(&serialized);
-        hasher.finalize().into()
+        Self::hash_leaf(result)
     }
 
     // Recursively build the Merkle tree
@@ -169,6 +173,21 @@ impl MerkleTree {
         tree
     }
 
+    // Create a tree from already hashed leaves
+    pub fn from_leaves(leaves: Vec<[u8; 32]>) -> Self {
+        let mut tree = Self::new();
+        if !leaves.is_empty() {
+            tree.root = Some(Box::new(Self::build_tree_proper(&leaves)));
+        }
+        tree.leaves = leaves;
+        tree
+    }
+
+    // Hash any transaction into a leaf, over its canonical encoding
+    pub fn hash_leaf<T: Serialize>(data: &T) -> [u8; 32] {
+        canonical::digest(data)
+    }
+
     // Hash two values together
     fn hash_pair(left: [u8; 32], right: [u8; 32]) -> [u8; 32] {
         let mut hasher = Sha256::new();
@@ -188,53 +207,28 @@ impl MerkleTree {
             return None;
         }
 
+        // Walk the levels bottom-up, collecting the sibling at each level
         let mut proof = Vec::new();
-        Self::generate_proof_recursive(&self.root, target_index, 0, self.leaves.len(), &mut proof);
-        Some(proof)
-    }
+        let mut level = self.leaves.clone();
+        let mut index = target_index;
 
-    fn generate_proof_recursive(
-        node: &Option<Box<MerkleNode>>,
-        target_index: usize,
-        start_index: usize,
-        end_index: usize,
-        proof: &mut Vec<[u8; 32]>,
-    ) -> bool {
-        if let Some(node) = node {
-            // Leaf node
-            if node.left.is_none() && node.right.is_none() {
-                return start_index == target_index;
-            }
-
-            let mid = (start_index + end_index).div_ceil(2);
-
-            if target_index < mid {
-                // Target is in left subtree
-                if let Some(right) = &node.right {
-                    proof.push(right.hash);
-                }
-                return Self::generate_proof_recursive(
-                    &node.left,
-                    target_index,
-                    start_index,
-                    mid,
-                    proof,
-                );
+        while level.len() > 1 {
+            let sibling = if index.is_multiple_of(2) {
+                // Odd number of nodes: the last one is paired with itself
+                *level.get(index + 1).unwrap_or(&level[index])
             } else {
-                // Target is in right subtree
-                if let Some(left) = &node.left {
-                    proof.push(left.hash);
-                }
-                return Self::generate_proof_recursive(
-                    &node.right,
-                    target_index,
-                    mid,
-                    end_index,
-                    proof,
-                );
-            }
+                level[index - 1]
+            };
+            proof.push(sibling);
+
+            level = level
+                .chunks(2)
+                .map(|chunk| Self::hash_pair(chunk[0], *chunk.get(1).unwrap_or(&chunk[0])))
+                .collect();
+            index /= 2;
         }
-        false
+
+        Some(proof)
     }
 
     // Verify a Merkle proof
@@ -251,7 +245,7 @@ impl MerkleTree {
 
         for &sibling_hash in proof {
             range_size = range_size.div_ceil(2);
-            if index % 2 == 0 {
+            if index.is_multiple_of(2) {
                 // Current node is left child
                 current_hash = Self::hash_pair(current_hash, sibling_hash);
             } else {
@@ -289,13 +283,12 @@ impl MerkleTree {
 
 #[cfg(test)]
 mod tests {
-    use types::CandidateResult;
-
     use super::*;
 
     #[test]
     fn test_merkle_tree_single_item() {
         let results = vec![CandidateResult {
+            election_id: 1,
             station_id: 1,
             candidate_id: 1,
             votes: 100,
@@ -310,6 +303,7 @@ mod tests {
         let mut results = Vec::new();
         for i in 0..4 {
             results.push(CandidateResult {
+                election_id: 1,
                 station_id: i as i64,
                 candidate_id: (i + 1) as i64,
                 votes: (100 + i) as i64,
@@ -338,6 +332,7 @@ mod tests {
         let mut results = Vec::new();
         for i in 0..5 {
             results.push(CandidateResult {
+                election_id: 1,
                 station_id: i as i64,
                 candidate_id: (i + 1) as i64,
                 votes: (100 + i) as i64,
@@ -364,26 +359,31 @@ mod tests {
         // Create sample election results
         let results = vec![
             CandidateResult {
+                election_id: 1,
                 station_id: 1,
                 candidate_id: 1,
                 votes: 150,
             },
             CandidateResult {
+                election_id: 1,
                 station_id: 2,
                 candidate_id: 2,
                 votes: 200,
             },
             CandidateResult {
+                election_id: 1,
                 station_id: 3,
                 candidate_id: 1,
                 votes: 250,
             },
             CandidateResult {
+                election_id: 1,
                 station_id: 4,
                 candidate_id: 3,
                 votes: 300,
             },
             CandidateResult {
+                election_id: 1,
                 station_id: 5,
                 candidate_id: 2,
                 votes: 350,
@@ -64,6 +64,70 @@ pub struct Peer {
     pub address: String,
     pub time_added: i64,
     pub permanent: bool,
+    pub last_seen: Option<i64>,
+    pub failures: i64,
+    pub next_attempt: Option<i64>,
+}
+
+/// A row of the `attestations` table
+#[cfg_attr(not(target_arch = "wasm32"), derive(sqlx::FromRow))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StationAttestation {
+    pub election_id: i64,
+    pub station_id: i64,
+    pub candidate_id: i64,
+    pub status: String,
+    pub official_votes: Option<i64>,
+    pub chain_votes: Option<i64>,
+    pub attester: String,
+    pub block_height: i64,
+}
+
+/// A row of the `corrections` table
+#[cfg_attr(not(target_arch = "wasm32"), derive(sqlx::FromRow))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StationCorrection {
+    pub election_id: i64,
+    pub station_id: i64,
+    pub original_height: i64,
+    pub reason: String,
+    /// Sigkey hash of the signer of the correction block
+    pub corrected_by: String,
+    pub block_height: i64,
+}
+
+/// A result as submitted, whether or not it is still current
+#[cfg_attr(not(target_arch = "wasm32"), derive(sqlx::FromRow))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultRevision {
+    pub election_id: i64,
+    pub station_id: i64,
+    pub candidate_id: i64,
+    pub votes: i64,
+    pub block_height: i64,
+    /// Height of the correction that replaced this result
+    pub superseded_by: Option<i64>,
+    /// Organisation of whoever submitted the result
+    pub organisation: String,
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), derive(sqlx::FromRow))]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ban {
+    pub node_id: String,
+    pub address: String,
+    pub ip: String,
+    pub reason: String,
+    pub bans: i64,
+    /// `None` for a permanent ban
+    pub banned_until: Option<i64>,
+    pub time_added: i64,
+}
+
+impl Ban {
+    pub fn is_active(&self, now: i64) -> bool {
+        self.banned_until.is_none_or(|until| until > now)
+    }
 }
 
 #[cfg_attr(not(target_arch = "wasm32"), derive(sqlx::FromRow))]
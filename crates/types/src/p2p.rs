@@ -1,11 +1,107 @@
 use serde::{Deserialize, Serialize};
 use std::{
     net::SocketAddr,
+    ops::BitOr,
     time::{Duration, Instant},
 };
 
-use crate::Block;
+use crate::{Block, SignedHeader, consensus::Vote, validation::BlockRejection};
+
+/// Version of the P2P protocol spoken by this node.
+///
+/// 1. Initial protocol
+/// 2. Capability flags in `Hello` and `HelloResponse`, `Inventory` and
+///    `GetData` gossip, `BlockRejected`, headers-first sync and `Compressed`
+///    messages
+/// 3. Consensus rounds, with the round in `Proposal` and `Vote` and nil
+///    votes, and attachments
+pub const PROTOCOL_VERSION: u32 = 3;
+/// Oldest version we still talk to, older peers are turned away in the handshake.
+/// Version 1 peers cannot answer `GetHeaders`, so we could not sync from them.
+/// Version 2 peers sync and gossip blocks but take no part in consensus.
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+
+/// The version both sides speak, `None` if the peer is too old
+pub fn negotiate_version(peer_version: u32) -> Option<u32> {
+    (peer_version >= MIN_PROTOCOL_VERSION).then(|| peer_version.min(PROTOCOL_VERSION))
+}
+
+/// Optional features a node advertises in the handshake. Messages relying on
+/// a feature are only sent to peers that advertised it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// Understands `P2PMessage::Compressed`
+    pub const COMPRESSION: Self = Self(1);
+    /// Follows the chain but never proposes, votes or submits results
+    pub const OBSERVER: Self = Self(1 << 2);
+    /// Serves attachments with `AttachmentRequest`
+    pub const ATTACHMENTS: Self = Self(1 << 3);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Protocol violations that count towards banning a peer
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Misbehaviour {
+    #[error("sent a message larger than the limit")]
+    OversizedMessage,
+    #[error("sent a message that could not be decoded")]
+    MalformedMessage,
+    #[error("sent an invalid block: {0}")]
+    InvalidBlock(BlockRejection),
+    #[error("sent too many pings")]
+    PingFlood,
+    #[error("sent an attachment that does not match its digest")]
+    InvalidAttachment,
+    #[error("sent headers we did not ask it for")]
+    UnsolicitedHeaders,
+}
+
+impl Misbehaviour {
+    /// Score added to the peer, see [`P2PConfig::ban_threshold`]
+    pub fn penalty(&self) -> i32 {
+        match self {
+            Misbehaviour::OversizedMessage => 50,
+            Misbehaviour::MalformedMessage => 20,
+            // Forged blocks cannot come from an honest node, the rest may be a
+            // peer on another branch or with a skewed clock
+            Misbehaviour::InvalidBlock(
+                BlockRejection::HashMismatch { .. }
+                | BlockRejection::MerkleRootMismatch
+                | BlockRejection::InvalidSignature
+                | BlockRejection::InvalidKeyTransaction
+                | BlockRejection::InvalidCertificate,
+            ) => 50,
+            Misbehaviour::InvalidBlock(_) => 10,
+            Misbehaviour::InvalidAttachment => 50,
+            // Also a late answer to a request that timed out
+            Misbehaviour::PingFlood | Misbehaviour::UnsolicitedHeaders => 5,
+        }
+    }
+}
 // P2P Message Protocol
+/// Variants are encoded by their position, so new ones are appended at the
+/// end and report the version that introduced them in [`Self::since_version`]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum P2PMessage {
     // Handshake
@@ -13,12 +109,14 @@ pub enum P2PMessage {
         node_id: String,
         version: u32,
         chain_height: i64,
+        capabilities: Capabilities,
     },
     HelloResponse {
         node_id: String,
         version: u32,
         chain_height: i64,
         accepted: bool,
+        capabilities: Capabilities,
     },
 
     // Block synchronization
@@ -57,6 +155,144 @@ pub enum P2PMessage {
     Disconnect {
         reason: String,
     },
+
+    /// Hashes of blocks the sender has accepted
+    Inventory {
+        hashes: Vec<String>,
+    },
+    /// Requests the full blocks for hashes from an `Inventory`
+    GetData {
+        hashes: Vec<String>,
+    },
+    /// Sent back to a peer whose block failed validation
+    BlockRejected {
+        hash: String,
+        reason: BlockRejection,
+    },
+
+    // Consensus
+    /// A block proposed, or proposed again, for a round of its height
+    Proposal {
+        block: Block,
+        round: u32,
+    },
+    Vote(Vote),
+
+    // Headers-first sync
+    GetHeaders {
+        start_height: i64,
+        count: u32,
+    },
+    HeadersResponse {
+        headers: Vec<SignedHeader>,
+    },
+
+    /// An LZ4 compressed message, only sent to peers with
+    /// [`Capabilities::COMPRESSION`]
+    Compressed(Vec<u8>),
+
+    // Attachments, only sent to peers with `Capabilities::ATTACHMENTS`
+    AttachmentRequest {
+        hash: String,
+    },
+    AttachmentResponse {
+        hash: String,
+        /// `None` if the peer does not have it
+        data: Option<Vec<u8>>,
+    },
+}
+
+impl P2PMessage {
+    /// Protocol version that introduced the message, peers on an older
+    /// version are not sent it
+    pub fn since_version(&self) -> u32 {
+        match self {
+            P2PMessage::Proposal { .. }
+            | P2PMessage::Vote(_)
+            | P2PMessage::AttachmentRequest { .. }
+            | P2PMessage::AttachmentResponse { .. } => 3,
+            P2PMessage::Inventory { .. }
+            | P2PMessage::GetData { .. }
+            | P2PMessage::BlockRejected { .. }
+            | P2PMessage::GetHeaders { .. }
+            | P2PMessage::HeadersResponse { .. }
+            | P2PMessage::Compressed(_) => 2,
+            _ => 1,
+        }
+    }
+
+    /// Takes part in consensus, which observers neither send nor receive
+    pub fn is_consensus(&self) -> bool {
+        matches!(self, P2PMessage::Proposal { .. } | P2PMessage::Vote(_))
+    }
+
+    /// Decodes a message from a peer on protocol `version`.
+    ///
+    /// `Hello` and `HelloResponse` from version 1 peers end before the
+    /// capabilities and decode with none, so that older peers can be told
+    /// why they are turned away. `None` is a message the peer still encodes
+    /// the way its version did, which we cannot read but it is not to blame for.
+    pub fn decode(data: &[u8], version: u32) -> bincode::Result<Option<Self>> {
+        let err = match bincode::deserialize(data) {
+            Ok(message) => return Ok(Some(message)),
+            Err(e) => e,
+        };
+        let variant: u32 = bincode::deserialize(data)?;
+        if version < Self::encoding_version(variant) {
+            return Ok(None);
+        }
+        match bincode::deserialize(data) {
+            Ok(HandshakeV1::Hello {
+                node_id,
+                version,
+                chain_height,
+            }) => Ok(Some(P2PMessage::Hello {
+                node_id,
+                version,
+                chain_height,
+                capabilities: Capabilities::empty(),
+            })),
+            Ok(HandshakeV1::HelloResponse {
+                node_id,
+                version,
+                chain_height,
+                accepted,
+            }) => Ok(Some(P2PMessage::HelloResponse {
+                node_id,
+                version,
+                chain_height,
+                accepted,
+                capabilities: Capabilities::empty(),
+            })),
+            Err(_) => Err(err),
+        }
+    }
+
+    /// Version that last changed how the variant at position `variant` is
+    /// encoded, other than the handshake
+    fn encoding_version(variant: u32) -> u32 {
+        match variant {
+            // `Proposal` and `Vote`
+            17 | 18 => 3,
+            _ => 1,
+        }
+    }
+}
+
+/// `Hello` and `HelloResponse` as sent by version 1 peers
+#[derive(Deserialize)]
+enum HandshakeV1 {
+    Hello {
+        node_id: String,
+        version: u32,
+        chain_height: i64,
+    },
+    HelloResponse {
+        node_id: String,
+        version: u32,
+        chain_height: i64,
+        accepted: bool,
+    },
 }
 
 // Peer connection state
@@ -71,6 +307,13 @@ pub struct PeerConnection {
     pub bytes_sent: i64,
     pub bytes_received: i64,
     pub last_ping: Option<Instant>,
+    /// Misbehaviour score, the peer is banned once it reaches the threshold
+    pub score: i32,
+    /// Pings received since the last maintenance round
+    pub pings: u32,
+    /// Protocol version agreed on in the handshake
+    pub version: u32,
+    pub capabilities: Capabilities,
 }
 
 impl PeerConnection {
@@ -85,18 +328,42 @@ impl PeerConnection {
             bytes_sent: 0,
             bytes_received: 0,
             last_ping: None,
+            score: 0,
+            pings: 0,
+            version: MIN_PROTOCOL_VERSION,
+            capabilities: Capabilities::empty(),
         }
     }
 }
 
 // P2P Network configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct P2PConfig {
     pub max_peers: usize,
     pub ping_interval: Duration,
     pub connection_timeout: Duration,
     pub sync_batch_size: u32,
     pub max_message_size: usize,
+    /// How far in the future a block timestamp may be
+    pub max_clock_drift: Duration,
+    /// Delay before retrying a permanent peer, doubled after every failure
+    pub reconnect_backoff: Duration,
+    pub max_reconnect_backoff: Duration,
+    /// Misbehaviour score at which a peer is banned
+    pub ban_threshold: i32,
+    /// Score a peer is forgiven every `ping_interval`
+    pub score_decay: i32,
+    pub ban_duration: Duration,
+    /// Temporary bans after which the next one is permanent
+    pub permanent_ban_after: u32,
+    /// Pings a peer may send per `ping_interval` before it counts as flooding
+    pub max_pings_per_interval: u32,
+    /// Messages larger than this are compressed for peers that support it
+    pub compression_threshold: usize,
+    /// How long the first consensus round of a height may take before
+    /// validators vote nil and move on, each further round waits longer
+    pub round_timeout: Duration,
 }
 
 impl Default for P2PConfig {
@@ -107,6 +374,131 @@ impl Default for P2PConfig {
             connection_timeout: Duration::from_secs(10),
             sync_batch_size: 100,
             max_message_size: 10 * 1024 * 1024, // 10MB
+            max_clock_drift: Duration::from_secs(60),
+            reconnect_backoff: Duration::from_secs(5),
+            max_reconnect_backoff: Duration::from_secs(600),
+            ban_threshold: 100,
+            score_decay: 10,
+            ban_duration: Duration::from_secs(24 * 60 * 60),
+            permanent_ban_after: 3,
+            max_pings_per_interval: 10,
+            compression_threshold: 4 * 1024,
+            round_timeout: Duration::from_secs(3),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `Hello` as sent by version 1 nodes, without capabilities
+    #[derive(Serialize)]
+    enum HelloV1 {
+        Hello {
+            node_id: String,
+            version: u32,
+            chain_height: i64,
+        },
+    }
+
+    /// `Vote` as sent by version 2 nodes, before consensus rounds
+    #[derive(Serialize)]
+    struct VoteV2 {
+        variant: u32,
+        height: u64,
+        block_hash: String,
+        phase: u32,
+        validator: String,
+        signature: String,
+    }
+
+    #[test]
+    fn test_version_1_handshake_decodes() {
+        let old = bincode::serialize(&HelloV1::Hello {
+            node_id: "node".into(),
+            version: 1,
+            chain_height: 7,
+        })
+        .unwrap();
+        assert!(bincode::deserialize::<P2PMessage>(&old).is_err());
+        assert!(matches!(
+            P2PMessage::decode(&old, MIN_PROTOCOL_VERSION).unwrap(),
+            Some(P2PMessage::Hello { version: 1, chain_height: 7, capabilities, .. })
+                if capabilities == Capabilities::empty()
+        ));
+
+        let hello = P2PMessage::Hello {
+            node_id: "node".into(),
+            version: PROTOCOL_VERSION,
+            chain_height: 7,
+            capabilities: Capabilities::COMPRESSION | Capabilities::ATTACHMENTS,
+        };
+        let data = bincode::serialize(&hello).unwrap();
+        let Some(P2PMessage::Hello { capabilities, .. }) =
+            P2PMessage::decode(&data, MIN_PROTOCOL_VERSION).unwrap()
+        else {
+            panic!("expected a hello");
+        };
+        assert!(capabilities.contains(Capabilities::ATTACHMENTS));
+    }
+
+    #[test]
+    fn test_older_encodings_are_dropped() {
+        let old = bincode::serialize(&VoteV2 {
+            variant: 18,
+            height: 3,
+            block_hash: "ab".repeat(32),
+            phase: 0,
+            validator: "cd".repeat(32),
+            signature: "ef".repeat(32),
+        })
+        .unwrap();
+        assert!(matches!(P2PMessage::decode(&old, 2), Ok(None)));
+        // A peer that speaks the current encoding sent it malformed
+        assert!(P2PMessage::decode(&old, PROTOCOL_VERSION).is_err());
+        assert!(P2PMessage::decode(&[1, 2], 2).is_err());
+    }
+
+    #[test]
+    fn test_original_messages_keep_their_encoding() {
+        assert_eq!(
+            bincode::serialize(&P2PMessage::Ping).unwrap(),
+            11u32.to_le_bytes()
+        );
+        assert_eq!(
+            bincode::serialize(&P2PMessage::Disconnect {
+                reason: String::new()
+            })
+            .unwrap()[..4],
+            13u32.to_le_bytes()
+        );
+        // `P2PMessage::decode` tells older votes apart by their position
+        let vote = Vote {
+            height: 3,
+            round: 0,
+            block_hash: None,
+            phase: crate::consensus::VotePhase::Prevote,
+            validator: String::new(),
+            signature: String::new(),
+        };
+        assert_eq!(
+            bincode::serialize(&P2PMessage::Vote(vote)).unwrap()[..4],
+            18u32.to_le_bytes()
+        );
+    }
+
+    #[test]
+    fn test_negotiate_version() {
+        assert_eq!(negotiate_version(0), None);
+        assert_eq!(negotiate_version(MIN_PROTOCOL_VERSION - 1), None);
+        assert_eq!(
+            negotiate_version(MIN_PROTOCOL_VERSION),
+            Some(MIN_PROTOCOL_VERSION)
+        );
+        assert_eq!(
+            negotiate_version(PROTOCOL_VERSION + 5),
+            Some(PROTOCOL_VERSION)
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Block, BlockType, CandidateResult, Correction, PubKey, SignedHeader,
+    agents::Submission,
+    consensus::quorum,
+    crypto::{public_key_from_bytes, verify_hash},
+    keys::{KeyTransaction, apply_key_operation, key_hash},
+    merkle::MerkleTree,
+};
+
+/// The transaction a block's Merkle tree commits a result through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProofLeaf {
+    Result(CandidateResult),
+    /// A corrected result is committed as part of its correction
+    Correction(Correction),
+}
+
+impl ProofLeaf {
+    pub fn hash(&self) -> [u8; 32] {
+        match self {
+            ProofLeaf::Result(result) => MerkleTree::hash_leaf(result),
+            ProofLeaf::Correction(correction) => MerkleTree::hash_leaf(correction),
+        }
+    }
+
+    pub fn contains(&self, result: &CandidateResult) -> bool {
+        match self {
+            ProofLeaf::Result(leaf) => leaf == result,
+            ProofLeaf::Correction(correction) => correction.results.contains(result),
+        }
+    }
+}
+
+/// Shows that a key transaction is part of the block at `height`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyProof {
+    pub height: usize,
+    pub transaction: KeyTransaction,
+    pub leaf_index: usize,
+    pub total_leaves: usize,
+    /// Hex encoded sibling hashes, from the leaf up to the root
+    pub siblings: Vec<String>,
+}
+
+impl KeyProof {
+    /// Proves each key transaction in `block`, which are its first leaves
+    pub fn all(block: &Block) -> Vec<Self> {
+        let tree = block.inner.merkle_tree();
+        block
+            .inner
+            .key_transactions()
+            .iter()
+            .enumerate()
+            .filter_map(|(leaf_index, transaction)| {
+                Some(Self {
+                    height: block.height,
+                    transaction: transaction.clone(),
+                    leaf_index,
+                    total_leaves: tree.leaves.len(),
+                    siblings: tree
+                        .generate_proof(leaf_index)?
+                        .iter()
+                        .map(hex::encode)
+                        .collect(),
+                })
+            })
+            .collect()
+    }
+
+    fn leads_to(&self, merkle_root: [u8; 32]) -> bool {
+        leads_to(
+            MerkleTree::hash_leaf(&self.transaction),
+            &self.siblings,
+            merkle_root,
+            self.leaf_index,
+            self.total_leaves,
+        )
+    }
+}
+
+/// Whether the hex encoded `siblings` lead from `leaf` up to `merkle_root`
+fn leads_to(
+    leaf: [u8; 32],
+    siblings: &[String],
+    merkle_root: [u8; 32],
+    leaf_index: usize,
+    total_leaves: usize,
+) -> bool {
+    siblings
+        .iter()
+        .map(|sibling| hex::decode(sibling).ok()?.try_into().ok())
+        .collect::<Option<Vec<[u8; 32]>>>()
+        .is_some_and(|siblings| {
+            MerkleTree::verify_proof(leaf, &siblings, merkle_root, leaf_index, total_leaves)
+        })
+}
+
+/// Shows that a single result is part of a signed block, without the rest of
+/// the block or the chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub header: SignedHeader,
+    /// Hex of the bincode encoded verifying key that signed the block
+    pub signer_key: String,
+    pub result: CandidateResult,
+    pub leaf: ProofLeaf,
+    pub leaf_index: usize,
+    pub total_leaves: usize,
+    /// Hex encoded sibling hashes, from the leaf up to the root
+    pub siblings: Vec<String>,
+    /// The key transactions of the blocks below `header`, genesis first, to
+    /// replay the validator set that certified it from
+    pub key_transactions: Vec<KeyProof>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ProofError {
+    #[error("the leaf does not contain the result")]
+    ResultNotInLeaf,
+    #[error("the sibling path does not lead to the block's merkle root")]
+    MerkleRootMismatch,
+    #[error("the header does not hash to the block hash")]
+    HashMismatch,
+    #[error("the block signature does not verify against the signer key")]
+    InvalidSignature,
+    #[error("the signer key is not the one the header names")]
+    SignerMismatch,
+    #[error("a key transaction is not part of the chain below the block")]
+    UnknownKeyTransaction,
+    #[error("a key transaction is not signed by a validator")]
+    UnauthorisedKeyTransaction,
+    #[error("the block is not signed by a validator")]
+    UnknownSigner,
+    #[error("the block is not committed by more than 2/3 of the validators")]
+    Uncertified,
+}
+
+impl InclusionProof {
+    /// Proves `result`, which has to be in `block`, signed with `signer_key`.
+    /// `key_transactions` are those of the chain below `block`.
+    pub fn new(
+        block: &Block,
+        result: &CandidateResult,
+        signer_key: &[u8],
+        key_transactions: Vec<KeyProof>,
+    ) -> Option<Self> {
+        let (leaf, leaf_index) = match &block.inner {
+            BlockType::Result(results) | BlockType::Submission(Submission { results, .. }) => {
+                let index = results.iter().position(|r| r == result)?;
+                (ProofLeaf::Result(result.clone()), index)
+            }
+            BlockType::Correction(corrections) => {
+                let index = corrections
+                    .iter()
+                    .position(|c| c.results.contains(result))?;
+                (ProofLeaf::Correction(corrections[index].clone()), index)
+            }
+            _ => return None,
+        };
+        let tree = block.inner.merkle_tree();
+        let siblings = tree.generate_proof(leaf_index)?;
+        Some(Self {
+            header: block.signed_header(),
+            signer_key: hex::encode(signer_key),
+            result: result.clone(),
+            leaf,
+            leaf_index,
+            total_leaves: tree.leaves.len(),
+            siblings: siblings.iter().map(hex::encode).collect(),
+            key_transactions,
+        })
+    }
+
+    /// Hash the signer key is registered under, to compare with the validators
+    /// published for the chain
+    pub fn signer_hash(&self) -> Option<String> {
+        key_hash(&hex::decode(&self.signer_key).ok()?)
+    }
+
+    /// Checks the path from the result up to the block hash and its signature
+    pub fn verify(&self) -> Result<(), ProofError> {
+        if !self.leaf.contains(&self.result) {
+            return Err(ProofError::ResultNotInLeaf);
+        }
+        let included = leads_to(
+            self.leaf.hash(),
+            &self.siblings,
+            self.header.merkle_root,
+            self.leaf_index,
+            self.total_leaves,
+        );
+        if !included {
+            return Err(ProofError::MerkleRootMismatch);
+        }
+
+        if !self.header.hash_matches() {
+            return Err(ProofError::HashMismatch);
+        }
+        if self.signer_hash().as_ref() != Some(&self.header.signature_pub_key_hash) {
+            return Err(ProofError::SignerMismatch);
+        }
+        let signed = hex::decode(&self.signer_key)
+            .ok()
+            .and_then(|bytes| public_key_from_bytes(&bytes))
+            .is_some_and(|key| verify_hash(&key, &self.header.hash, &self.header.hash_signature));
+        if !signed {
+            return Err(ProofError::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    /// Replays the key transactions into the validator set that certified
+    /// the block. `headers` is the chain from a genesis the caller trusts,
+    /// whose keys are the first validators; every later key transaction has
+    /// to be signed by a validator of the set before its block.
+    pub fn validators(&self, headers: &[SignedHeader]) -> Result<Vec<PubKey>, ProofError> {
+        let mut validators = Vec::new();
+        let mut previous = None;
+        for block in self.key_transactions.chunk_by(|a, b| a.height == b.height) {
+            let height = block[0].height;
+            let header = headers
+                .get(height)
+                .filter(|_| height < self.header.height && previous < Some(height))
+                .ok_or(ProofError::UnknownKeyTransaction)?;
+            for proof in block {
+                if !proof.leads_to(header.merkle_root) {
+                    return Err(ProofError::UnknownKeyTransaction);
+                }
+                if height > 0 && !validators.iter().any(|key| proof.transaction.verify(key)) {
+                    return Err(ProofError::UnauthorisedKeyTransaction);
+                }
+            }
+            for proof in block {
+                let operation = &proof.transaction.operation;
+                apply_key_operation(&mut validators, operation, height, header.timestamp);
+            }
+            previous = Some(height);
+        }
+        Ok(validators)
+    }
+
+    /// Checks that the block was signed and committed by more than 2/3 of
+    /// `validators`, returning how many of them committed it
+    pub fn verify_certificate(&self, validators: &[PubKey]) -> Result<usize, ProofError> {
+        if !validators
+            .iter()
+            .any(|key| key.hash == self.header.signature_pub_key_hash)
+        {
+            return Err(ProofError::UnknownSigner);
+        }
+        let signers = self
+            .header
+            .certificate
+            .as_ref()
+            .filter(|certificate| {
+                certificate.height == self.header.height
+                    && certificate.block_hash == self.header.hash
+            })
+            .map_or(0, |certificate| certificate.valid_signers(validators).len());
+        if signers < quorum(validators.len()) {
+            return Err(ProofError::Uncertified);
+        }
+        Ok(signers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        BlockSigner,
+        consensus::{CommitCertificate, ValidatorSignature, Vote, VotePhase},
+        crypto,
+        keys::KeyOperation,
+    };
+
+    fn signer(creator: &str) -> BlockSigner {
+        let key = crypto::get_private_key();
+        let verifying_key = crypto::get_public_key(&key);
+        let pub_key = PubKey::from_verifying_key(creator, &verifying_key);
+        (key, verifying_key, pub_key)
+    }
+
+    /// A block at `height` registering `validator`, signed by `signer`
+    fn register(
+        signer: &BlockSigner,
+        prev_hash: &str,
+        height: usize,
+        validator: &BlockSigner,
+    ) -> Block {
+        let tx = KeyTransaction::new(
+            signer,
+            KeyOperation::Register {
+                creator: validator.2.creator.clone(),
+                pub_key: bincode::serialize(&validator.1).unwrap(),
+            },
+        );
+        Block::with_inner(
+            signer,
+            prev_hash,
+            BlockType::KeyManagement(vec![tx]),
+            height,
+        )
+    }
+
+    #[test]
+    fn test_inclusion_proof_verifies_single_result() {
+        let key = crypto::get_private_key();
+        let verifying_key = crypto::get_public_key(&key);
+        let signer = (key, verifying_key, PubKey::new_dummy());
+        let results: Vec<_> = (1..=5)
+            .map(|candidate| CandidateResult::new(1, 22113056303301, candidate, candidate * 10))
+            .collect();
+        let root = MerkleTree::from_election_results_proper(&results)
+            .get_root_hash()
+            .unwrap();
+        let block = Block::new(&signer, &"00".repeat(32), results.clone(), 1, root);
+        let signer_key = bincode::serialize(&verifying_key).unwrap();
+
+        let proof = InclusionProof::new(&block, &results[4], &signer_key, vec![]).unwrap();
+        assert_eq!(proof.verify(), Ok(()));
+        assert_eq!(proof.total_leaves, 5);
+
+        let mut forged = proof.clone();
+        forged.result.votes += 1;
+        assert_eq!(forged.verify(), Err(ProofError::ResultNotInLeaf));
+        forged.leaf = ProofLeaf::Result(forged.result.clone());
+        assert_eq!(forged.verify(), Err(ProofError::MerkleRootMismatch));
+
+        let mut resigned = proof.clone();
+        let other = crypto::get_public_key(&crypto::get_private_key());
+        resigned.signer_key = hex::encode(bincode::serialize(&other).unwrap());
+        assert_eq!(resigned.verify(), Err(ProofError::SignerMismatch));
+
+        let mut unsigned = proof.clone();
+        unsigned.header.hash_signature = proof.header.prev_hash.clone();
+        assert_eq!(unsigned.verify(), Err(ProofError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_certificate_is_checked_against_validators_from_genesis() {
+        let (first, second) = (signer("genesis"), signer("validator-2"));
+        let genesis = Block::genesis(&first, vec![]);
+        let keys = register(&first, &genesis.hash, 1, &second);
+        let results = vec![CandidateResult::new(1, 22113056303301, 1, 66)];
+        let mut block =
+            Block::with_inner(&first, &keys.hash, BlockType::Result(results.clone()), 2);
+        let precommits: Vec<_> = [&first, &second]
+            .into_iter()
+            .map(|signer| {
+                let vote = Vote::new(signer, 2, 0, Some(&block.hash), VotePhase::Precommit);
+                ValidatorSignature {
+                    validator: vote.validator,
+                    signature: vote.signature,
+                }
+            })
+            .collect();
+        block.certificate = Some(CommitCertificate {
+            height: 2,
+            round: 0,
+            block_hash: block.hash.clone(),
+            signatures: precommits.clone(),
+        });
+
+        let headers: Vec<_> = [&genesis, &keys, &block]
+            .map(|block| block.signed_header())
+            .into();
+        let key_transactions = [KeyProof::all(&genesis), KeyProof::all(&keys)].concat();
+        let signer_key = bincode::serialize(&first.1).unwrap();
+        let proof =
+            InclusionProof::new(&block, &results[0], &signer_key, key_transactions).unwrap();
+        assert_eq!(proof.verify(), Ok(()));
+        let validators = proof.validators(&headers).unwrap();
+        assert_eq!(validators.len(), 2);
+        assert_eq!(proof.verify_certificate(&validators), Ok(2));
+        assert_eq!(
+            proof.verify_certificate(&validators[1..]),
+            Err(ProofError::UnknownSigner)
+        );
+
+        let mut uncertified = proof.clone();
+        uncertified.header.certificate.as_mut().unwrap().signatures = precommits[..1].to_vec();
+        assert_eq!(
+            uncertified.verify_certificate(&validators),
+            Err(ProofError::Uncertified)
+        );
+
+        // Key transactions have to be part of the headers...
+        let outsider = signer("outsider");
+        let mut smuggled = proof.clone();
+        smuggled.key_transactions[1].transaction = register(&outsider, &genesis.hash, 1, &outsider)
+            .inner
+            .key_transactions()[0]
+            .clone();
+        assert_eq!(
+            smuggled.validators(&headers).err(),
+            Some(ProofError::UnknownKeyTransaction)
+        );
+
+        // ...and signed by a validator, so a forged branch cannot bring its own
+        let forged = register(&outsider, &genesis.hash, 1, &outsider);
+        let mut forged_headers = headers.clone();
+        forged_headers[1] = forged.signed_header();
+        let mut forged_proof = proof.clone();
+        forged_proof.key_transactions = [KeyProof::all(&genesis), KeyProof::all(&forged)].concat();
+        assert_eq!(
+            forged_proof.validators(&forged_headers).err(),
+            Some(ProofError::UnauthorisedKeyTransaction)
+        );
+    }
+}
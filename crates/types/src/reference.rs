@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+
+/// A gazetted record. Registers the record, or updates it when its code is
+/// already on chain. Records have to come after the ones they refer to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceData {
+    Position(String),
+    Party {
+        id: i64,
+        title: String,
+        logo: Option<String>,
+    },
+    County {
+        code: i64,
+        name: String,
+    },
+    Constituency {
+        code: i64,
+        county_code: i64,
+        name: String,
+    },
+    Ward {
+        code: i64,
+        constituency_code: i64,
+        name: String,
+    },
+    Station {
+        id: i64,
+        ward_code: i64,
+        reg_center_code: Option<i64>,
+        name: String,
+        registered_voters: i64,
+    },
+    Candidate {
+        id: i64,
+        name: String,
+        gender: String,
+        photo: Option<String>,
+        position_type: String,
+        party_id: i64,
+        voting_station: Option<i64>,
+    },
+}
@@ -1,5 +1,81 @@
+use std::collections::{BTreeMap, BTreeSet};
+
 use serde::{Deserialize, Serialize};
 
+/// Organisations whose submissions have to agree before a station's results
+/// are confirmed
+pub const REQUIRED_CONCURRENCE: usize = 2;
+
+/// How far independent submitters agree on a station's results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Confirmation {
+    /// Fewer than [`REQUIRED_CONCURRENCE`] organisations have reported
+    Pending,
+    /// Enough organisations reported the same votes for every candidate
+    Confirmed,
+    /// Two submissions give a candidate different votes
+    Disputed,
+}
+
+impl Confirmation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Confirmation::Pending => "pending",
+            Confirmation::Confirmed => "confirmed",
+            Confirmation::Disputed => "disputed",
+        }
+    }
+
+    /// The state of a station from its current results, given as the
+    /// organisation of the agent behind each submission, the candidate and
+    /// the votes. Results without an agent can dispute but not confirm.
+    pub fn of(results: &[(Option<String>, i64, i64)]) -> Self {
+        let mut candidates = BTreeMap::<i64, (BTreeSet<i64>, BTreeSet<&str>)>::new();
+        for (organisation, candidate_id, votes) in results {
+            let (counts, organisations) = candidates.entry(*candidate_id).or_default();
+            counts.insert(*votes);
+            organisations.extend(organisation.as_deref());
+        }
+        if candidates.values().any(|(counts, _)| counts.len() > 1) {
+            Confirmation::Disputed
+        } else if !candidates.is_empty()
+            && candidates
+                .values()
+                .all(|(_, organisations)| organisations.len() >= REQUIRED_CONCURRENCE)
+        {
+            Confirmation::Confirmed
+        } else {
+            Confirmation::Pending
+        }
+    }
+}
+
+/// Stations of an area by confirmation state
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(sqlx::FromRow))]
+pub struct ConfirmationCounts {
+    pub pending: i64,
+    pub confirmed: i64,
+    pub disputed: i64,
+}
+
+impl ConfirmationCounts {
+    /// The state of the area as a whole: disputed if any station is, and
+    /// confirmed once every station is
+    pub fn state(&self) -> Option<Confirmation> {
+        if self.disputed > 0 {
+            Some(Confirmation::Disputed)
+        } else if self.pending > 0 {
+            Some(Confirmation::Pending)
+        } else if self.confirmed > 0 {
+            Some(Confirmation::Confirmed)
+        } else {
+            None
+        }
+    }
+}
+
 #[cfg_attr(not(target_arch = "wasm32"), derive(sqlx::FromRow))]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StationResult {
@@ -110,7 +186,6 @@ pub struct Candidate {
     pub party_name: String,
 }
 
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(sqlx::FromRow))]
 pub struct GeneralResult {
@@ -120,6 +195,24 @@ pub struct GeneralResult {
     pub votes: u32,
     /// Standard deviation
     pub sd: u32,
+    /// Totals of the area's tally forms, `None` until a station reports them
+    #[cfg_attr(not(target_arch = "wasm32"), sqlx(skip))]
+    pub tally: Option<TallyTotals>,
+    /// Stations of the area by confirmation state, a single one for a station
+    #[cfg_attr(not(target_arch = "wasm32"), sqlx(skip))]
+    #[serde(default)]
+    pub confirmation: ConfirmationCounts,
+}
+
+/// Ballot accounting summed over the tally forms of an area
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(sqlx::FromRow))]
+pub struct TallyTotals {
+    pub rejected: i64,
+    pub disputed: i64,
+    pub objected: i64,
+    pub total_valid: i64,
+    pub total_cast: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -139,4 +232,48 @@ pub struct LastResultSummary {
     pub candidate2_votes: Option<u32>,
     pub candidate2_percentage: Option<f64>,
     pub sd: u32,
+    /// Ballot accounting from the station's tally form, if submitted
+    pub rejected: Option<i64>,
+    pub disputed: Option<i64>,
+    pub objected: Option<i64>,
+    pub total_cast: Option<i64>,
+    /// Confirmation state of the station, see [`Confirmation`]
+    pub confirmation: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submitted(organisation: &str, votes: &[(i64, i64)]) -> Vec<(Option<String>, i64, i64)> {
+        votes
+            .iter()
+            .map(|(candidate_id, votes)| (Some(organisation.to_string()), *candidate_id, *votes))
+            .collect()
+    }
+
+    #[test]
+    fn test_confirmation_needs_independent_agreement() {
+        let odm = submitted("ODM", &[(1, 66), (2, 21)]);
+        assert_eq!(Confirmation::of(&[]), Confirmation::Pending);
+        assert_eq!(Confirmation::of(&odm), Confirmation::Pending);
+
+        // A second agent of the same party does not count
+        let twice = [odm.clone(), odm.clone()].concat();
+        assert_eq!(Confirmation::of(&twice), Confirmation::Pending);
+
+        // Nor do results relayed by a validator without an agent's signature
+        let relayed = [odm.clone(), vec![(None, 1, 66), (None, 2, 21)]].concat();
+        assert_eq!(Confirmation::of(&relayed), Confirmation::Pending);
+
+        let agreed = [odm.clone(), submitted("UDA", &[(1, 66), (2, 21)])].concat();
+        assert_eq!(Confirmation::of(&agreed), Confirmation::Confirmed);
+
+        // A candidate only one organisation reported is not confirmed yet
+        let partial = [agreed.clone(), submitted("UDA", &[(3, 4)])].concat();
+        assert_eq!(Confirmation::of(&partial), Confirmation::Pending);
+
+        let diverged = [agreed, submitted("Observers", &[(1, 60), (2, 21)])].concat();
+        assert_eq!(Confirmation::of(&diverged), Confirmation::Disputed);
+    }
 }
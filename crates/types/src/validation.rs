@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+/// Why a block received from a peer was not added to the chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+pub enum BlockRejection {
+    #[error("expected height {expected}, got {found}")]
+    HeightMismatch { expected: usize, found: usize },
+    #[error("previous hash {found} does not match {expected}")]
+    PrevHashMismatch { expected: String, found: String },
+    #[error("header hashes to {expected}, block claims {found}")]
+    HashMismatch { expected: String, found: String },
+    #[error("merkle root does not match the block transactions")]
+    MerkleRootMismatch,
+    #[error("timestamp {0} is in the future")]
+    TimestampInFuture(i64),
+    #[error("timestamp {0} is older than the previous block")]
+    TimestampBeforeParent(i64),
+    #[error("signer {0} is not registered")]
+    UnknownSigner(String),
+    #[error("signer {0} was revoked")]
+    RevokedSigner(String),
+    #[error("invalid block signature")]
+    InvalidSignature,
+    #[error("key transaction not signed by a validator")]
+    InvalidKeyTransaction,
+    #[error("missing or invalid commit certificate")]
+    InvalidCertificate,
+    #[error("block is not a genesis block")]
+    NotGenesis,
+    #[error("attestation for station {station_id} does not match the chain")]
+    InvalidAttestation { station_id: i64 },
+    #[error("correction for station {station_id} does not replace its current results")]
+    InvalidCorrection { station_id: i64 },
+    #[error("reference data does not apply: {0}")]
+    InvalidReferenceData(String),
+    #[error("election {0} is invalid or already registered")]
+    InvalidElection(i64),
+    #[error("station {station_id} or its candidate is not part of election {election_id}")]
+    OutsideElection { election_id: i64, station_id: i64 },
+    #[error("tally summary for station {station_id} does not add up")]
+    InvalidSummary { station_id: i64 },
+    #[error("attachment {0} is not a SHA3-256 digest")]
+    InvalidAttachment(String),
+    #[error("agent {agent} may not submit results for station {station_id}")]
+    UnauthorisedSubmission { agent: String, station_id: i64 },
+    #[error("submission is not signed by agent {0}")]
+    InvalidSubmissionSignature(String),
+    #[error("agent key is invalid: {0}")]
+    InvalidAgent(String),
+    #[error("results are not signed by an agent")]
+    UnsignedResults,
+}
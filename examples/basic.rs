@@ -1,4 +1,6 @@
 use blockchain::BlockChain;
+use database::Database;
+use types::{Block, merkle::MerkleTree};
 
 #[tokio::main]
 // Example usage function
@@ -33,12 +35,16 @@ async fn main() {
     let pub_key = types::PubKey::new_dummy();
     let signer = (key.clone(), types::crypto::get_public_key(&key), pub_key);
 
-    let results = vec![types::CandidateResult::new(1, 1, 100)];
+    let results = vec![types::CandidateResult::new(1, 1, 1, 100)];
+    let root = MerkleTree::from_election_results_proper(&results)
+        .get_root_hash()
+        .unwrap();
     let block = Block::new(
         &signer,
         "1000000000000000000000000000000000000000000000000000000000000001",
         results,
         1,
+        root,
     );
 
     blockchain.announce_block(block).await.unwrap();
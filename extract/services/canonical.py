@@ -0,0 +1,87 @@
+"""
+Reference implementation of the chain's canonical encoding, see
+`crates/types/src/canonical.rs`, for auditing hashes without the node.
+"""
+
+import hashlib
+import struct
+from typing import List
+
+
+def i64(value: int) -> bytes:
+    return struct.pack("<q", value)
+
+
+def u64(value: int) -> bytes:
+    return struct.pack("<Q", value)
+
+
+def string(value: str) -> bytes:
+    data = value.encode("utf-8")
+    return u64(len(data)) + data
+
+
+def sequence(items: List[bytes]) -> bytes:
+    return u64(len(items)) + b"".join(items)
+
+
+def sha3(data: bytes) -> bytes:
+    return hashlib.sha3_256(data).digest()
+
+
+def encode_result(election_id: int, station_id: int, candidate_id: int, votes: int) -> bytes:
+    return i64(election_id) + i64(station_id) + i64(candidate_id) + i64(votes)
+
+
+def encode_header(
+    previous_hash: str,
+    merkle_root: str,
+    timestamp: int,
+    block_number: int,
+    validator_signature: str,
+) -> bytes:
+    """
+    The header a block's `hash` is computed over. Hashes are hex strings, the
+    fixed size ones are encoded as their raw 32 bytes.
+    """
+    return (
+        bytes.fromhex(previous_hash)
+        + bytes.fromhex(merkle_root)
+        + i64(timestamp)
+        + i64(block_number)
+        + string(validator_signature)
+    )
+
+
+def merkle_root(leaves: List[bytes]) -> bytes:
+    """
+    Root over leaf hashes, pairing the last node with itself on odd levels.
+    """
+    level = leaves
+    while len(level) > 1:
+        level = [
+            sha3(level[i] + (level[i + 1] if i + 1 < len(level) else level[i]))
+            for i in range(0, len(level), 2)
+        ]
+    return level[0] if level else bytes(32)
+
+
+if __name__ == "__main__":
+    # The vectors of `canonical::tests::test_canonical_vectors`
+    result = encode_result(1, 2, 3, 400)
+    assert sha3(result).hex() == "514b8c4e0c25307814517acf29112248401035da4cad30dbdde7739827082ef1"
+    header = encode_header("aa" * 32, "bb" * 32, 1_700_000_000, 7, "ab01")
+    assert sha3(header).hex() == "f872859332fd785048e0422a391688e62cf0a7751ee58c27157134f7736a08fc"
+    correction = (
+        i64(1)
+        + i64(2)
+        + u64(5)
+        + sequence([result])
+        + sequence([])
+        + string("typo")
+        + sequence([])
+    )
+    assert sha3(correction).hex() == "411e6280c6ba955180bf9db6505d293c8e479585adc9c38f327a2c6ccc4e2341"
+    leaves = [sha3(encode_result(1, 2, c, v)) for c, v in [(3, 400), (4, 10), (5, 0)]]
+    assert merkle_root(leaves).hex() == "9af1d108f73f21943070894db8e611ac6729717e84201ef3ca5f35c9822db669"
+    print("canonical vectors match")
@@ -1,95 +1,88 @@
+use std::{path::PathBuf, str::FromStr};
+
+use api::ui_handler;
+use axum::{Extension, Router};
 use blockchain::BlockChain;
-use database::{Database, SqlitePool};
-use types::p2p::P2PConfig;
+use clap::Parser;
+use database::SqlitePool;
+use sqlx::sqlite::SqliteConnectOptions;
+use types::config::{Config, Mode};
 
-#[tokio::main]
-async fn main() {
-    // TODO: This should come from genesis
-    let init_sql = r#"
-    INSERT INTO
-    positions
-Values
-    ("Mca"),
-    ("WomenRep"),
-    ("Mp"),
-    ("Senator"),
-    ("Governor"),
-    ("President");
+#[derive(Debug, Parser)]
+struct Cli {
+    #[arg(short, long, value_name = "FILE")]
+    config: PathBuf,
+}
 
-INSERT INTO
-    parties
-Values
-    (1, "ODM", ""),
-    (2, "PNU", "");
+/// Opens the database at `url`, creating it with `setup` on first start
+async fn open_database(url: &str, setup: &str) -> SqlitePool {
+    let options = SqliteConnectOptions::from_str(url)
+        .expect("Invalid database url")
+        .create_if_missing(true);
+    let pool = SqlitePool::connect_with(options)
+        .await
+        .expect("Failed to open database");
+    let tables: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    if tables == 0 {
+        sqlx::raw_sql(setup).execute(&pool).await.unwrap();
+    }
+    pool
+}
 
-INSERT INTO
-    counties
-VALUES
-    (22, "Kiambu"),
-    (45, "Kisii");
+#[tokio::main]
+async fn main() {
+    env_logger::init(); // Initialize the logger
+    log::info!("Starting an observer node...");
 
-INSERT INTO
-    constituencies
-VALUES
-    (113, 22, "Juja"),
-    (261, 45, "Bonchari");
+    let cli = Cli::parse();
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string(cli.config.as_path()).expect("Failed to read config file"),
+    )
+    .expect("Failed to parse config file");
+    let Some(Mode::Observer {
+        peer_addr,
+        genesis_hash,
+    }) = config.mode.clone()
+    else {
+        panic!(
+            "Config file is not for an observer, expected a `[mode]` with `mode = \"observer\"`"
+        );
+    };
+    let peer_addr = peer_addr.parse().expect("Invalid peer address");
 
-INSERT INTO
-    wards
-VALUES
-    (563, 113, "Kalimoni"),
-    (1301, 261, "Bomariba");
+    open_database(&config.main_db, database::MAIN_SETUP).await;
+    open_database(&config.private_db, database::PRIV_SETUP).await;
 
-INSERT INTO
-    stations
-VALUES
-    (
-        022113056303301,
-        563,
-        33,
-        "Athi Primary School",
-        533
-    );
+    let bind_addr = config
+        .node_addr
+        .clone()
+        .expect("Missing node_addr")
+        .parse()
+        .expect("Invalid node_addr");
+    let listener =
+        tokio::net::TcpListener::bind(config.http_addr.clone().expect("Missing http_addr"))
+            .await
+            .unwrap();
+    // The observer is read-only and keeps reconnecting to `peer_addr`
+    let mut blockchain = BlockChain::from_config(config).await;
+    blockchain
+        .bootstrap_from_peer(peer_addr, &genesis_hash)
+        .await
+        .expect("Failed to bootstrap from the genesis block");
+    let node = blockchain.start_p2p_server(bind_addr);
 
-INSERT INTO
-    stations
-VALUES
-    (
-        045261130100402,
-        1301,
-        4,
-        "Igonga Primary School",
-        685
+    let api_routes = api::run_read_only_api_server().layer(Extension(blockchain.clone()));
+    let server = axum::serve(
+        listener,
+        Router::new()
+            .nest("/api/v1", api_routes)
+            .fallback_service(ui_handler()),
     );
-INSERT INTO
-    candidates
-VALUES
-    (1, "Mwas", "M", "", "Mp", 1, 022113056303301),
-    (2, "Omosh", "M", "", "Mp", 2, 022113056303301);
-
-INSERT INTO
-    candidates
-VALUES
-    (3, "Adhis", "F", "", "Mp", 1, 045261130100402),
-    (4, "Juma", "F", "", "Mp", 2, 045261130100402);
-    "#;
-    env_logger::init(); // Initialize the logger
-    log::info!("Starting an observer node...");
-    let peer_addr = "127.0.0.1:9090".parse().unwrap();
-    let config = P2PConfig::default();
-    let chain_db = SqlitePool::connect_lazy("sqlite::memory:").unwrap();
-    let private_db = SqlitePool::connect_lazy("sqlite::memory:").unwrap();
-
-    let blockchain = BlockChain::new(Database::new(chain_db, private_db), Some(config));
-    sqlx::query(database::MAIN_SETUP)
-        .execute(&blockchain.db.chain_db)
-        .await
-        .unwrap();
-    sqlx::query(init_sql)
-        .execute(&blockchain.db.chain_db)
-        .await
-        .unwrap();
 
-    // Start the node and connect to a peer
-    blockchain.connect_to_peer(peer_addr).await.unwrap();
+    let (node, server) = tokio::join!(node, server);
+    node.expect("P2P server failed");
+    server.expect("HTTP server failed");
 }
@@ -4,7 +4,6 @@ use std::path::PathBuf;
 
 use axum::{Extension, Router};
 use blockchain::BlockChain;
-use database::{Database, SqlitePool};
 use types::config::Config;
 
 // cargo run --peers=1,2,3
@@ -28,30 +27,26 @@ async fn main() {
     )
     .expect("Failed to parse config file");
 
-    let bind_addr = config.node_addr.unwrap().parse().unwrap();
-    let blockchain = BlockChain::new(
-        Database::new(
-            SqlitePool::connect(&config.main_db).await.unwrap(),
-            SqlitePool::connect(&config.private_db).await.unwrap(),
-        ),
-        config.peer_config,
-    );
-    let listener = tokio::net::TcpListener::bind(config.http_addr.unwrap())
+    let bind_addr = config.node_addr.clone().unwrap().parse().unwrap();
+    let listener = tokio::net::TcpListener::bind(config.http_addr.clone().unwrap())
         .await
         .unwrap();
-    // Start the node and connect to a peer
+    let admin_addr = config.admin_addr.clone();
+    // Configured peers are added to the peer book and connected by the node
+    let blockchain = BlockChain::from_config(config).await;
     let node = blockchain.start_p2p_server(bind_addr);
 
-    if let Some(peers) = config.peers {
-        for peer in peers {
-            let blockchain = blockchain.clone();
-            tokio::spawn(async move {
-                blockchain
-                    .connect_to_peer(peer.1.parse().unwrap())
-                    .await
-                    .unwrap();
-            });
-        }
+    // Routes signing with the node key only answer on the admin address
+    if let Some(admin_addr) = admin_addr {
+        let admin_listener = tokio::net::TcpListener::bind(&admin_addr).await.unwrap();
+        log::info!("Serving the admin API at {admin_addr}");
+        let admin_routes = api::run_admin_api_server().layer(Extension(blockchain.clone()));
+        tokio::spawn(async move {
+            let admin = Router::new().nest("/api/v1", admin_routes);
+            if let Err(e) = axum::serve(admin_listener, admin).await {
+                log::error!("Admin API failed: {e}");
+            }
+        });
     }
 
     let api_routes = api::run_api_server().layer(Extension(blockchain.clone()));
@@ -1,3 +1,129 @@
-fn main() {
-    println!("Hello, world!");
+mod tally;
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use blockchain::BlockChain;
+use clap::Parser;
+use types::{
+    Block, BlockType,
+    attestation::{self, AttestationStatus},
+    config::{Config, Mode},
+    error::ChainError,
+};
+
+/// Seconds between two comparisons when the config does not say
+const DEFAULT_INTERVAL: u64 = 300;
+
+#[derive(Debug, Parser)]
+struct Cli {
+    #[arg(short, long, value_name = "FILE")]
+    config: PathBuf,
+}
+
+/// Compares the tallies with the chain and proposes an attestation block with
+/// the findings that changed since we last published them. Returns how many
+/// attestations were proposed.
+async fn verify(blockchain: &mut BlockChain, tallies: &Path) -> Result<usize, ChainError> {
+    let official = tally::load(tallies)?;
+    let stations: BTreeSet<(i64, i64)> = official
+        .iter()
+        .map(|t| (t.election_id, t.station_id))
+        .collect();
+    let mut chain = Vec::new();
+    for (election_id, station_id) in stations {
+        chain.extend(blockchain.station_votes(election_id, station_id).await?);
+    }
+
+    let signer = blockchain.get_private_key().await?;
+    let published: HashMap<(i64, i64, i64), _> = blockchain
+        .latest_attestations(&signer.2.hash)
+        .await?
+        .into_iter()
+        .map(|a| ((a.election_id, a.station_id, a.candidate_id), a))
+        .collect();
+    let attestations: Vec<_> = attestation::compare(&official, &chain)
+        .into_iter()
+        .filter(|a| {
+            published
+                .get(&(a.election_id, a.station_id, a.candidate_id))
+                .is_none_or(|p| {
+                    p.status != a.status.as_str()
+                        || p.official_votes != a.official_votes
+                        || p.chain_votes != a.chain_votes
+                })
+        })
+        .collect();
+    if attestations.is_empty() {
+        return Ok(0);
+    }
+
+    for status in [
+        AttestationStatus::Match,
+        AttestationStatus::Mismatch,
+        AttestationStatus::Missing,
+    ] {
+        let count = attestations.iter().filter(|a| a.status == status).count();
+        log::info!("🔎 {count} results {}", status.as_str());
+    }
+    let count = attestations.len();
+    let tip = blockchain.last_block().await?;
+    let block = Block::with_inner(
+        &signer,
+        &tip.hash,
+        BlockType::Attestation(attestations),
+        tip.height + 1,
+    );
+    blockchain.propose_block(block).await?;
+    Ok(count)
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init(); // Initialize the logger
+    log::info!("Starting a verification node...");
+
+    let cli = Cli::parse();
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string(cli.config.as_path()).expect("Failed to read config file"),
+    )
+    .expect("Failed to parse config file");
+    let Some(Mode::Verification { tallies, interval }) = config.mode.clone() else {
+        panic!(
+            "Config file is not for a verification node, expected a `[mode]` with `mode = \"verification\"`"
+        );
+    };
+    let tallies = PathBuf::from(tallies);
+    let interval = Duration::from_secs(interval.unwrap_or(DEFAULT_INTERVAL));
+
+    let bind_addr = config
+        .node_addr
+        .clone()
+        .expect("Missing node_addr")
+        .parse()
+        .expect("Invalid node_addr");
+    let mut blockchain = BlockChain::from_config(config).await;
+    let node = blockchain.clone();
+    tokio::spawn(async move {
+        if let Err(e) = node.start_p2p_server(bind_addr).await {
+            log::error!("P2P server failed: {e}");
+        }
+    });
+
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        // Results still arriving would show up as missing
+        if blockchain.sync_status().await.syncing {
+            continue;
+        }
+        match verify(&mut blockchain, &tallies).await {
+            Ok(0) => log::debug!("No new findings"),
+            Ok(count) => log::info!("📜 Proposed {count} attestations"),
+            Err(e) => log::error!("Verification failed: {e}"),
+        }
+    }
 }
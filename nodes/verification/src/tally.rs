@@ -0,0 +1,79 @@
+use std::path::Path;
+
+use types::{CandidateResult, error::ChainError};
+
+/// Reads official tallies from a JSON or CSV file, or every such file in a
+/// directory. JSON files hold an array of
+/// `{election_id, station_id, candidate_id, votes}`, CSV files have an
+/// `election_id,station_id,candidate_id,votes` header.
+pub fn load(path: &Path) -> Result<Vec<CandidateResult>, ChainError> {
+    if !path.is_dir() {
+        return load_file(path);
+    }
+    let mut entries: Vec<_> = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+
+    let mut tallies = Vec::new();
+    for entry in entries {
+        if matches!(extension(&entry), Some("json" | "csv")) {
+            tallies.extend(load_file(&entry)?);
+        }
+    }
+    Ok(tallies)
+}
+
+fn extension(path: &Path) -> Option<&str> {
+    path.extension().and_then(|ext| ext.to_str())
+}
+
+fn load_file(path: &Path) -> Result<Vec<CandidateResult>, ChainError> {
+    let invalid = |e: String| ChainError::Other(format!("Invalid tally {}: {e}", path.display()));
+    match extension(path) {
+        Some("json") => {
+            serde_json::from_slice(&std::fs::read(path)?).map_err(|e| invalid(e.to_string()))
+        }
+        Some("csv") => csv::Reader::from_path(path)
+            .and_then(|mut reader| reader.deserialize().collect())
+            .map_err(|e| invalid(e.to_string())),
+        _ => Err(invalid("expected a .json or .csv file".into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_json_and_csv_tallies() {
+        let dir = std::env::temp_dir().join(format!("tallies-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("juja.json"),
+            r#"[{"election_id": 1, "station_id": 22113056303301, "candidate_id": 1, "votes": 66}]"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("bonchari.csv"),
+            "election_id,station_id,candidate_id,votes\n2,45261130100402,3,40\n2,45261130100402,4,12\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let tallies = load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let rows: Vec<_> = tallies
+            .iter()
+            .map(|t| (t.election_id, t.station_id, t.candidate_id, t.votes))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (2, 45261130100402, 3, 40),
+                (2, 45261130100402, 4, 12),
+                (1, 22113056303301, 1, 66)
+            ]
+        );
+    }
+}